@@ -0,0 +1,137 @@
+//! API methods for managing API tokens.
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_auth_api::types::{Authid, Tokenname, Userid};
+use proxmox_network_types::ip_address::Cidr;
+use proxmox_router::Permission;
+use proxmox_schema::api;
+
+use crate::token_shadow::{self, ApiTokenSecret};
+use crate::types::ApiTokenShadowInfo;
+
+/// Fail unless `tokenid` refers to an API token in the user config.
+fn check_token_exists(tokenid: &Authid) -> Result<(), Error> {
+    let (config, _digest) = crate::user::config()?;
+    match config.sections.get(&tokenid.to_string()) {
+        Some((section_type, _)) if section_type == "token" => Ok(()),
+        _ => bail!("no such API token '{tokenid}'"),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            userid: {
+                type: Userid,
+            },
+            "token-name": {
+                type: Tokenname,
+            },
+            overlap: {
+                description: "Keep the previous secret valid for this many seconds.",
+                type: Integer,
+                minimum: 0,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        type: ApiTokenSecret,
+    },
+    protected: true,
+    access: {
+        description: "Users can regenerate the secrets of their own tokens.",
+        permission: &Permission::Or(&[
+            &Permission::Superuser,
+            &Permission::UserParam("userid"),
+        ]),
+    },
+)]
+/// Generate a new secret for an API token and return it.
+///
+/// Without `overlap`, the previous secret is invalidated immediately.
+pub fn regenerate_token_secret(
+    userid: Userid,
+    token_name: Tokenname,
+    overlap: Option<i64>,
+) -> Result<ApiTokenSecret, Error> {
+    let tokenid = Authid::from((userid, Some(token_name)));
+    check_token_exists(&tokenid)?;
+
+    token_shadow::regenerate_secret(&tokenid, overlap)
+}
+
+#[api(
+    input: {
+        properties: {
+            userid: {
+                type: Userid,
+            },
+            "token-name": {
+                type: Tokenname,
+            },
+        },
+    },
+    returns: {
+        type: ApiTokenShadowInfo,
+    },
+    protected: true,
+    access: {
+        description: "Users can read the information of their own tokens.",
+        permission: &Permission::Or(&[
+            &Permission::Superuser,
+            &Permission::UserParam("userid"),
+        ]),
+    },
+)]
+/// Get the active secrets, last usage and network restrictions of an API token.
+pub fn read_token_info(userid: Userid, token_name: Tokenname) -> Result<ApiTokenShadowInfo, Error> {
+    let tokenid = Authid::from((userid, Some(token_name)));
+    check_token_exists(&tokenid)?;
+
+    token_shadow::token_info(&tokenid)?
+        .ok_or_else(|| format_err!("no secret stored for API token '{tokenid}'"))
+}
+
+#[api(
+    input: {
+        properties: {
+            userid: {
+                type: Userid,
+            },
+            "token-name": {
+                type: Tokenname,
+            },
+            "allowed-cidrs": {
+                description: "Only allow using the token from these networks.",
+                type: Array,
+                optional: true,
+                items: {
+                    type: Cidr,
+                },
+            },
+        },
+    },
+    protected: true,
+    access: {
+        description: "Users can restrict their own tokens.",
+        permission: &Permission::Or(&[
+            &Permission::Superuser,
+            &Permission::UserParam("userid"),
+        ]),
+    },
+)]
+/// Restrict the networks an API token may be used from.
+///
+/// Without `allowed-cidrs`, or with an empty list, the restriction is removed.
+pub fn update_token_allowed_cidrs(
+    userid: Userid,
+    token_name: Tokenname,
+    allowed_cidrs: Option<Vec<Cidr>>,
+) -> Result<(), Error> {
+    let tokenid = Authid::from((userid, Some(token_name)));
+    check_token_exists(&tokenid)?;
+
+    token_shadow::set_allowed_cidrs(&tokenid, allowed_cidrs.unwrap_or_default())
+}
@@ -129,3 +129,7 @@ pub(crate) fn token_shadow() -> PathBuf {
 pub(crate) fn token_shadow_lock() -> PathBuf {
     conf_dir().join("token.shadow.lock")
 }
+
+pub(crate) fn token_usage() -> PathBuf {
+    conf_dir().join("token.usage")
+}
@@ -1,58 +1,330 @@
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{LazyLock, Mutex};
 
 use anyhow::{bail, format_err, Error};
 use serde::{Deserialize, Serialize};
 use serde_json::{from_value, Value};
 
 use proxmox_auth_api::types::Authid;
+use proxmox_network_types::ip_address::Cidr;
 use proxmox_product_config::{open_api_lockfile, replace_config, ApiLockGuard};
+use proxmox_schema::api;
 
-use crate::init::{token_shadow, token_shadow_lock};
+use crate::init::{token_shadow, token_shadow_lock, token_usage};
+use crate::types::{ApiTokenSecretInfo, ApiTokenShadowInfo};
 
+/// Maximum number of secrets a token can have at the same time.
+const MAX_TOKEN_SECRETS: usize = 2;
+
+/// Usage information is collected in memory and written to the usage file at most this often
+/// (in seconds), so that requests don't have to wait for file system I/O.
+const USAGE_FLUSH_INTERVAL: i64 = 60;
+
+#[api]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 /// ApiToken id / secret pair
 pub struct ApiTokenSecret {
     pub tokenid: Authid,
+    /// The API token secret.
     pub secret: String,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct HashedSecret {
+    hash: String,
+    /// Creation time, 0 if unknown for secrets stored in the legacy format.
+    #[serde(default)]
+    created: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expire: Option<i64>,
+}
+
+impl HashedSecret {
+    fn is_expired(&self, now: i64) -> bool {
+        self.expire.is_some_and(|expire| expire <= now)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ShadowEntry {
+    secrets: Vec<HashedSecret>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    allowed_cidrs: Vec<Cidr>,
+}
+
+/// Older shadow files only contain the hashed secret for each token. Tokens with a single,
+/// unrestricted secret are still written this way, so that older versions can read the file.
+///
+/// Older versions fail to parse the whole file if any token uses the new format, i.e. has more
+/// than one secret, an expiring secret or network restrictions. After a downgrade, every token
+/// is rejected until those are removed again, for example by setting a new secret.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ShadowFileEntry {
+    Legacy(String),
+    Entry(ShadowEntry),
+}
+
+impl From<ShadowEntry> for ShadowFileEntry {
+    fn from(mut entry: ShadowEntry) -> Self {
+        match entry.secrets.as_slice() {
+            [secret] if secret.expire.is_none() && entry.allowed_cidrs.is_empty() => {
+                ShadowFileEntry::Legacy(entry.secrets.remove(0).hash)
+            }
+            _ => ShadowFileEntry::Entry(entry),
+        }
+    }
+}
+
+impl From<ShadowFileEntry> for ShadowEntry {
+    fn from(entry: ShadowFileEntry) -> Self {
+        match entry {
+            ShadowFileEntry::Legacy(hash) => ShadowEntry {
+                secrets: vec![HashedSecret {
+                    hash,
+                    created: 0,
+                    expire: None,
+                }],
+                ..Default::default()
+            },
+            ShadowFileEntry::Entry(entry) => entry,
+        }
+    }
+}
+
+impl ShadowEntry {
+    fn check_client_ip(&self, client_ip: Option<&IpAddr>) -> Result<(), Error> {
+        if self.allowed_cidrs.is_empty() {
+            return Ok(());
+        }
+
+        match client_ip {
+            Some(ip)
+                if self
+                    .allowed_cidrs
+                    .iter()
+                    .any(|cidr| cidr.contains_address(ip)) =>
+            {
+                Ok(())
+            }
+            _ => bail!("API token not allowed from this address"),
+        }
+    }
+
+    fn verify(&self, secret: &str, client_ip: Option<&IpAddr>, now: i64) -> Result<(), Error> {
+        self.check_client_ip(client_ip)?;
+
+        let valid = self
+            .secrets
+            .iter()
+            .filter(|hashed| !hashed.is_expired(now))
+            .any(|hashed| proxmox_sys::crypt::verify_crypt_pw(secret, &hashed.hash).is_ok());
+
+        if !valid {
+            bail!("invalid API token");
+        }
+        Ok(())
+    }
+
+    /// Add a new secret. Existing secrets stay valid for `overlap` more seconds at most, the
+    /// oldest ones are dropped if there are too many.
+    fn rotate(&mut self, hash: String, overlap: Option<i64>, now: i64) {
+        self.secrets.retain(|hashed| !hashed.is_expired(now));
+
+        match overlap {
+            Some(overlap) if overlap > 0 => {
+                let expire = now + overlap;
+                for hashed in self.secrets.iter_mut() {
+                    hashed.expire = Some(hashed.expire.map_or(expire, |old| old.min(expire)));
+                }
+            }
+            _ => self.secrets.clear(),
+        }
+
+        self.secrets.push(HashedSecret {
+            hash,
+            created: now,
+            expire: None,
+        });
+
+        if self.secrets.len() > MAX_TOKEN_SECRETS {
+            self.secrets.sort_by_key(|hashed| hashed.created);
+            self.secrets.drain(..self.secrets.len() - MAX_TOKEN_SECRETS);
+        }
+    }
+}
+
 // Get exclusive lock
 fn lock_config() -> Result<ApiLockGuard, Error> {
     open_api_lockfile(token_shadow_lock(), None, true)
 }
 
-fn read_file() -> Result<HashMap<Authid, String>, Error> {
+fn read_file() -> Result<HashMap<Authid, ShadowEntry>, Error> {
     let json = proxmox_sys::fs::file_get_json(token_shadow(), Some(Value::Null))?;
 
     if json == Value::Null {
         Ok(HashMap::new())
     } else {
         // swallow serde error which might contain sensitive data
-        from_value(json)
-            .map_err(|_err| format_err!("unable to parse '{}'", token_shadow().display()))
+        let data: HashMap<Authid, ShadowFileEntry> = from_value(json)
+            .map_err(|_err| format_err!("unable to parse '{}'", token_shadow().display()))?;
+        Ok(data
+            .into_iter()
+            .map(|(tokenid, entry)| (tokenid, entry.into()))
+            .collect())
     }
 }
 
-fn write_file(data: HashMap<Authid, String>) -> Result<(), Error> {
+fn write_file(data: HashMap<Authid, ShadowEntry>) -> Result<(), Error> {
+    let data: HashMap<Authid, ShadowFileEntry> = data
+        .into_iter()
+        .map(|(tokenid, entry)| (tokenid, entry.into()))
+        .collect();
     let json = serde_json::to_vec(&data)?;
     replace_config(token_shadow(), &json)
 }
 
+/// Last usage of a token, kept in a separate file so that recording it neither needs the shadow
+/// file lock nor changes the shadow file.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TokenUsage {
+    last_used: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_client_ip: Option<IpAddr>,
+}
+
+/// Usage recorded by this process which was not written to the usage file yet.
+static PENDING_USAGE: LazyLock<Mutex<HashMap<Authid, TokenUsage>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static LAST_USAGE_FLUSH: AtomicI64 = AtomicI64::new(0);
+static USAGE_FLUSH_RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn read_usage_file() -> Result<HashMap<Authid, TokenUsage>, Error> {
+    let json = proxmox_sys::fs::file_get_json(token_usage(), Some(Value::Null))?;
+    if json == Value::Null {
+        return Ok(HashMap::new());
+    }
+    from_value(json).map_err(|err| format_err!("unable to parse token usage file - {err}"))
+}
+
+/// Merge `pending` into the usage file. Updates from different processes can race, in which
+/// case the older usage information of a token may win. This is acceptable for statistics.
+fn write_usage(pending: HashMap<Authid, TokenUsage>) -> Result<(), Error> {
+    let mut data = read_usage_file()?;
+    merge_usage(&mut data, pending);
+    replace_config(token_usage(), &serde_json::to_vec(&data)?)
+}
+
+fn merge_usage(data: &mut HashMap<Authid, TokenUsage>, pending: HashMap<Authid, TokenUsage>) {
+    for (tokenid, usage) in pending {
+        let entry = data.entry(tokenid).or_insert(usage);
+        if usage.last_used >= entry.last_used {
+            entry.last_used = usage.last_used;
+            if usage.last_client_ip.is_some() {
+                entry.last_client_ip = usage.last_client_ip;
+            }
+        }
+    }
+}
+
+/// Record the usage in memory and write it out in a background thread if it's time to do so.
+fn record_usage(tokenid: &Authid, client_ip: Option<&IpAddr>, now: i64) {
+    let mut pending = PENDING_USAGE.lock().unwrap();
+    let usage = pending.entry(tokenid.clone()).or_insert(TokenUsage {
+        last_used: now,
+        last_client_ip: None,
+    });
+    usage.last_used = now;
+    if let Some(client_ip) = client_ip {
+        usage.last_client_ip = Some(*client_ip);
+    }
+    drop(pending);
+
+    if now - LAST_USAGE_FLUSH.load(Ordering::Relaxed) < USAGE_FLUSH_INTERVAL
+        || USAGE_FLUSH_RUNNING.swap(true, Ordering::AcqRel)
+    {
+        return;
+    }
+    LAST_USAGE_FLUSH.store(now, Ordering::Relaxed);
+
+    let spawned = std::thread::Builder::new()
+        .name("token-usage".to_string())
+        .spawn(|| {
+            let pending = std::mem::take(&mut *PENDING_USAGE.lock().unwrap());
+            if let Err(err) = write_usage(pending) {
+                log::warn!("failed to record API token usage - {err}");
+            }
+            USAGE_FLUSH_RUNNING.store(false, Ordering::Release);
+        });
+    if let Err(err) = spawned {
+        log::warn!("failed to record API token usage - {err}");
+        USAGE_FLUSH_RUNNING.store(false, Ordering::Release);
+    }
+}
+
+fn token_usage_info(tokenid: &Authid) -> Option<TokenUsage> {
+    if let Some(usage) = PENDING_USAGE.lock().unwrap().get(tokenid) {
+        return Some(*usage);
+    }
+    match read_usage_file() {
+        Ok(mut data) => data.remove(tokenid),
+        Err(err) => {
+            log::warn!("{err}");
+            None
+        }
+    }
+}
+
+fn remove_usage(tokenid: &Authid) -> Result<(), Error> {
+    PENDING_USAGE.lock().unwrap().remove(tokenid);
+
+    let mut data = read_usage_file()?;
+    if data.remove(tokenid).is_some() {
+        replace_config(token_usage(), &serde_json::to_vec(&data)?)?;
+    }
+    Ok(())
+}
+
 /// Verifies that an entry for given tokenid / API token secret exists
 pub fn verify_secret(tokenid: &Authid, secret: &str) -> Result<(), Error> {
+    verify_secret_with_client_ip(tokenid, secret, None)
+}
+
+/// Verifies that an entry for given tokenid / API token secret exists and that the token may be
+/// used from `client_ip`. On success, the time and client address are recorded as last usage of
+/// the token, which is written to disk asynchronously.
+pub fn verify_secret_with_client_ip(
+    tokenid: &Authid,
+    secret: &str,
+    client_ip: Option<&IpAddr>,
+) -> Result<(), Error> {
     if !tokenid.is_token() {
         bail!("not an API token ID");
     }
 
+    let now = proxmox_time::epoch_i64();
+
     let data = read_file()?;
-    match data.get(tokenid) {
-        Some(hashed_secret) => proxmox_sys::crypt::verify_crypt_pw(secret, hashed_secret),
+    let entry = match data.get(tokenid) {
+        Some(entry) => entry,
         None => bail!("invalid API token"),
-    }
+    };
+    entry.verify(secret, client_ip, now)?;
+
+    record_usage(tokenid, client_ip, now);
+
+    Ok(())
 }
 
 /// Adds a new entry for the given tokenid / API token secret. The secret is stored as salted hash.
+///
+/// This replaces all existing secrets of the token.
 pub fn set_secret(tokenid: &Authid, secret: &str) -> Result<(), Error> {
     if !tokenid.is_token() {
         bail!("not an API token ID");
@@ -62,12 +334,93 @@ pub fn set_secret(tokenid: &Authid, secret: &str) -> Result<(), Error> {
 
     let mut data = read_file()?;
     let hashed_secret = proxmox_sys::crypt::encrypt_pw(secret)?;
-    data.insert(tokenid.clone(), hashed_secret);
+    data.entry(tokenid.clone())
+        .or_default()
+        .rotate(hashed_secret, None, proxmox_time::epoch_i64());
     write_file(data)?;
 
     Ok(())
 }
 
+/// Generates a new secret for the given tokenid and returns it.
+///
+/// If `overlap` is set, the previous secret stays valid for that many seconds, so that consumers
+/// of the token can be switched over without downtime. A token has at most two secrets, the
+/// oldest one is dropped if necessary.
+///
+/// Older versions cannot read the shadow file while any token has more than one secret or an
+/// expiring one.
+pub fn regenerate_secret(tokenid: &Authid, overlap: Option<i64>) -> Result<ApiTokenSecret, Error> {
+    if !tokenid.is_token() {
+        bail!("not an API token ID");
+    }
+
+    let _guard = lock_config()?;
+
+    let mut data = read_file()?;
+    let secret = proxmox_uuid::Uuid::generate().to_string();
+    let hashed_secret = proxmox_sys::crypt::encrypt_pw(&secret)?;
+    data.entry(tokenid.clone()).or_default().rotate(
+        hashed_secret,
+        overlap,
+        proxmox_time::epoch_i64(),
+    );
+    write_file(data)?;
+
+    Ok(ApiTokenSecret {
+        tokenid: tokenid.clone(),
+        secret,
+    })
+}
+
+/// Restricts the networks the given tokenid may be used from. An empty list removes the
+/// restriction.
+///
+/// Older versions cannot read the shadow file while any token is restricted.
+pub fn set_allowed_cidrs(tokenid: &Authid, allowed_cidrs: Vec<Cidr>) -> Result<(), Error> {
+    if !tokenid.is_token() {
+        bail!("not an API token ID");
+    }
+
+    let _guard = lock_config()?;
+
+    let mut data = read_file()?;
+    match data.get_mut(tokenid) {
+        Some(entry) => entry.allowed_cidrs = allowed_cidrs,
+        None => bail!("no such API token"),
+    }
+    write_file(data)?;
+
+    Ok(())
+}
+
+/// Returns the secret and usage information of the given tokenid.
+pub fn token_info(tokenid: &Authid) -> Result<Option<ApiTokenShadowInfo>, Error> {
+    let now = proxmox_time::epoch_i64();
+
+    let Some(entry) = read_file()?.remove(tokenid) else {
+        return Ok(None);
+    };
+    let usage = token_usage_info(tokenid);
+
+    Ok(Some(ApiTokenShadowInfo {
+        secrets: entry
+            .secrets
+            .into_iter()
+            .filter(|hashed| !hashed.is_expired(now))
+            .map(|hashed| ApiTokenSecretInfo {
+                created: (hashed.created > 0).then_some(hashed.created),
+                expire: hashed.expire,
+            })
+            .collect(),
+        last_used: usage.map(|usage| usage.last_used),
+        last_client_ip: usage
+            .and_then(|usage| usage.last_client_ip)
+            .map(|ip| ip.to_string()),
+        allowed_cidrs: entry.allowed_cidrs,
+    }))
+}
+
 /// Deletes the entry for the given tokenid.
 pub fn delete_secret(tokenid: &Authid) -> Result<(), Error> {
     if !tokenid.is_token() {
@@ -80,5 +433,135 @@ pub fn delete_secret(tokenid: &Authid) -> Result<(), Error> {
     data.remove(tokenid);
     write_file(data)?;
 
+    if let Err(err) = remove_usage(tokenid) {
+        log::warn!("failed to remove usage of API token '{tokenid}' - {err}");
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry_with_secrets(secrets: &[&str], now: i64) -> ShadowEntry {
+        let mut entry = ShadowEntry::default();
+        for secret in secrets {
+            let hash = proxmox_sys::crypt::encrypt_pw(secret).unwrap();
+            entry.rotate(hash, Some(60), now);
+        }
+        entry
+    }
+
+    #[test]
+    fn test_legacy_entry() {
+        let hash = proxmox_sys::crypt::encrypt_pw("secret").unwrap();
+        let data: HashMap<Authid, ShadowFileEntry> =
+            from_value(serde_json::json!({ "test@pam!token": hash })).unwrap();
+        let entry: ShadowEntry = data.into_values().next().unwrap().into();
+
+        entry.verify("secret", None, 0).unwrap();
+        assert!(entry.verify("wrong", None, 0).is_err());
+    }
+
+    #[test]
+    fn test_secret_rotation() {
+        let mut entry = entry_with_secrets(&["first", "second"], 100);
+        entry.verify("first", None, 159).unwrap();
+        entry.verify("second", None, 159).unwrap();
+        assert!(entry.verify("first", None, 160).is_err());
+        entry.verify("second", None, 1000).unwrap();
+
+        // only two secrets are kept
+        entry.rotate(
+            proxmox_sys::crypt::encrypt_pw("third").unwrap(),
+            Some(60),
+            110,
+        );
+        assert_eq!(entry.secrets.len(), 2);
+        assert!(entry.verify("first", None, 110).is_err());
+        entry.verify("second", None, 110).unwrap();
+        entry.verify("third", None, 110).unwrap();
+
+        // without overlap, previous secrets are invalidated immediately
+        entry.rotate(proxmox_sys::crypt::encrypt_pw("fourth").unwrap(), None, 120);
+        assert_eq!(entry.secrets.len(), 1);
+        assert!(entry.verify("third", None, 120).is_err());
+        entry.verify("fourth", None, 120).unwrap();
+    }
+
+    #[test]
+    fn test_shadow_file_format() {
+        // a single unrestricted secret is written in the legacy format
+        let entry = entry_with_secrets(&["secret"], 100);
+        let hash = entry.secrets[0].hash.clone();
+        let value = serde_json::to_value(ShadowFileEntry::from(entry)).unwrap();
+        assert_eq!(value, Value::String(hash));
+
+        let entry = entry_with_secrets(&["first", "second"], 100);
+        let value = serde_json::to_value(ShadowFileEntry::from(entry)).unwrap();
+        assert_eq!(value["secrets"].as_array().unwrap().len(), 2);
+
+        let mut entry = entry_with_secrets(&["secret"], 100);
+        entry.allowed_cidrs = vec!["192.0.2.0/24".parse().unwrap()];
+        let value = serde_json::to_value(ShadowFileEntry::from(entry)).unwrap();
+        assert_eq!(value["allowed-cidrs"][0], "192.0.2.0/24");
+    }
+
+    #[test]
+    fn test_merge_usage() {
+        let token: Authid = "test@pam!token".parse().unwrap();
+        let other: Authid = "test@pam!other".parse().unwrap();
+        let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+        let mut data = HashMap::from([(
+            token.clone(),
+            TokenUsage {
+                last_used: 100,
+                last_client_ip: Some(ip),
+            },
+        )]);
+        let usage = |last_used, last_client_ip| TokenUsage {
+            last_used,
+            last_client_ip,
+        };
+
+        // newer usage without a client address keeps the known address
+        merge_usage(
+            &mut data,
+            HashMap::from([
+                (token.clone(), usage(200, None)),
+                (other.clone(), usage(50, None)),
+            ]),
+        );
+        assert_eq!(data[&token].last_used, 200);
+        assert_eq!(data[&token].last_client_ip, Some(ip));
+        assert_eq!(data[&other].last_used, 50);
+
+        // older usage from another process does not overwrite newer information
+        merge_usage(
+            &mut data,
+            HashMap::from([(
+                token.clone(),
+                usage(150, Some("198.51.100.1".parse().unwrap())),
+            )]),
+        );
+        assert_eq!(data[&token].last_used, 200);
+        assert_eq!(data[&token].last_client_ip, Some(ip));
+    }
+
+    #[test]
+    fn test_allowed_cidrs() {
+        let mut entry = entry_with_secrets(&["secret"], 0);
+        entry.verify("secret", None, 0).unwrap();
+
+        entry.allowed_cidrs = vec!["192.0.2.0/24".parse().unwrap()];
+        entry
+            .verify("secret", Some(&"192.0.2.10".parse().unwrap()), 0)
+            .unwrap();
+        assert!(entry
+            .verify("secret", Some(&"198.51.100.1".parse().unwrap()), 0)
+            .is_err());
+        assert!(entry.verify("secret", None, 0).is_err());
+    }
+}
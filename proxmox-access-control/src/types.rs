@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 
 use proxmox_auth_api::types::{Authid, Userid, PROXMOX_TOKEN_ID_SCHEMA};
+use proxmox_network_types::ip_address::Cidr;
 use proxmox_schema::{
     api,
-    api_types::{COMMENT_SCHEMA, SINGLE_LINE_COMMENT_FORMAT},
+    api_types::{COMMENT_SCHEMA, IP_SCHEMA, SINGLE_LINE_COMMENT_FORMAT},
     BooleanSchema, IntegerSchema, Schema, StringSchema, Updater,
 };
 
@@ -128,6 +129,58 @@ impl ApiToken {
     }
 }
 
+#[api]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// An active secret of an API token. The secret itself is never exposed.
+pub struct ApiTokenSecretInfo {
+    /// Creation time of the secret (seconds since epoch), unknown for secrets created by older
+    /// versions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<i64>,
+    /// The secret is no longer accepted after this time (seconds since epoch).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire: Option<i64>,
+}
+
+#[api(
+    properties: {
+        secrets: {
+            type: Array,
+            items: {
+                type: ApiTokenSecretInfo,
+            },
+        },
+        "last-client-ip": {
+            schema: IP_SCHEMA,
+            optional: true,
+        },
+        "allowed-cidrs": {
+            type: Array,
+            optional: true,
+            items: {
+                type: Cidr,
+            },
+        },
+    }
+)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// Secret and usage information of an API token.
+pub struct ApiTokenShadowInfo {
+    /// The currently active secrets.
+    pub secrets: Vec<ApiTokenSecretInfo>,
+    /// Last time the token was used successfully (seconds since epoch).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<i64>,
+    /// The client address the token was last used from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_client_ip: Option<String>,
+    /// If not empty, the token may only be used from these networks.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub allowed_cidrs: Vec<Cidr>,
+}
+
 #[api(
     properties: {
         userid: {
@@ -1,5 +1,8 @@
 //! Provides the "/access/ticket" API call.
 
+use std::cell::Cell;
+use std::net::IpAddr;
+
 use anyhow::{bail, format_err, Error};
 use http::request::Parts;
 use http::Response;
@@ -14,10 +17,15 @@ use proxmox_router::{
 use proxmox_schema::{api, AllOfSchema, ApiType, ObjectSchema, ParameterSchema, ReturnType};
 use proxmox_tfa::api::TfaChallenge;
 
-use super::ApiTicket;
-use super::{auth_context, HMACKey};
+use super::login::{begin_attempt, finish_attempt, AttemptOutcome};
+use super::session::{check_session, generate_session_id};
+use super::{auth_context, extract_auth_data, AuthData, HMACKey};
+use super::{ApiTicket, LoginAttemptStore};
 use crate::ticket::Ticket;
-use crate::types::{Authid, CreateTicket, CreateTicketResponse, Userid};
+use crate::types::{
+    Authid, CreateTicket, CreateTicketResponse, LockoutPolicy, LoginAttempt, LoginResult,
+    LoginTfaMethod, LoginTicketType, SessionInfo, Userid,
+};
 
 #[allow(clippy::large_enum_variant)]
 enum AuthResult {
@@ -27,6 +35,9 @@ enum AuthResult {
     /// Successful authentication which requires a ticket to be created.
     CreateTicket,
 
+    /// Successful renewal of a session's ticket.
+    RenewSession(String),
+
     /// A partial ticket which requires a 2nd factor will be created.
     Partial(Box<TfaChallenge>),
 }
@@ -60,7 +71,9 @@ pub async fn create_ticket(
         .downcast_ref::<RestEnvironment>()
         .ok_or_else(|| format_err!("detected wrong RpcEnvironment type"))?;
 
-    handle_ticket_creation(create_params, env)
+    let user_agent = env.user_agent().map(str::to_owned);
+
+    handle_ticket_creation(create_params, env, user_agent)
         .await
         // remove the superfluous ticket_info to not confuse clients
         .map(|mut info| {
@@ -77,12 +90,16 @@ pub const API_METHOD_LOGOUT: ApiMethod = ApiMethod::new(
 .access(None, &Permission::World);
 
 fn logout_handler(
-    _parts: Parts,
+    parts: Parts,
     _param: Value,
     _info: &ApiMethod,
     _rpcenv: Box<dyn RpcEnvironment>,
 ) -> ApiResponseFuture {
     Box::pin(async move {
+        if let Err(err) = revoke_cookie_session(&parts.headers) {
+            log::error!("failed to revoke session on logout - {err}");
+        }
+
         // unset authentication cookie by setting an invalid one. needs the same `Path` and
         // `Secure` parameter to not be rejected by some browsers. also use the same `HttpOnly` and
         // `SameSite` parameters just in case.
@@ -97,6 +114,27 @@ fn logout_handler(
     })
 }
 
+/// Revoke the session of the ticket in the request's auth cookie, if any.
+fn revoke_cookie_session(headers: &http::HeaderMap) -> Result<(), Error> {
+    let auth_context = auth_context()?;
+    let Some(store) = auth_context.session_store() else {
+        return Ok(());
+    };
+    let Some(AuthData::User(data)) = extract_auth_data(auth_context, headers) else {
+        return Ok(());
+    };
+
+    let api_ticket = Ticket::<ApiTicket>::parse(&data.ticket)?.verify(
+        auth_context.keyring(),
+        auth_context.auth_prefix(),
+        None,
+    )?;
+    if let Some(session_id) = api_ticket.session_id() {
+        store.revoke_session(session_id)?;
+    }
+    Ok(())
+}
+
 pub const API_METHOD_CREATE_TICKET_HTTP_ONLY: ApiMethod = ApiMethod::new_full(
     &ApiHandler::AsyncHttpBodyParameters(&create_ticket_http_only),
     ParameterSchema::AllOf(&AllOfSchema::new(
@@ -145,7 +183,13 @@ fn create_ticket_http_only(
             .downcast_ref::<RestEnvironment>()
             .ok_or(format_err!("detected wrong RpcEnvironment type"))?;
 
-        let mut ticket_response = handle_ticket_creation(create_params, env).await?;
+        let user_agent = parts
+            .headers
+            .get(http::header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+
+        let mut ticket_response = handle_ticket_creation(create_params, env, user_agent).await?;
         let mut response =
             Response::builder().header(http::header::CONTENT_TYPE, "application/json");
 
@@ -186,12 +230,28 @@ fn create_ticket_http_only(
 async fn handle_ticket_creation(
     create_params: CreateTicket,
     env: &RestEnvironment,
+    user_agent: Option<String>,
 ) -> Result<CreateTicketResponse, Error> {
     let username = create_params.username;
     let password = create_params
         .password
         .ok_or(format_err!("no password provided"))?;
 
+    let login = LoginRecorder::new(
+        &username,
+        env,
+        create_params
+            .tfa_challenge
+            .as_ref()
+            .and_then(|_| LoginTfaMethod::from_response(&password)),
+    )?;
+
+    if let Err(err) = login.begin() {
+        login.record(LoginResult::Locked, None);
+        env.log_failed_auth(Some(username.to_string()), &err.to_string());
+        return Err(http_err!(UNAUTHORIZED, "permission check failed."));
+    }
+
     match authenticate_user(
         &username,
         &password,
@@ -203,15 +263,23 @@ async fn handle_ticket_creation(
     )
     .await
     {
-        Ok(AuthResult::Success) => Ok(CreateTicketResponse::new(username)),
-        Ok(AuthResult::CreateTicket) => {
+        Ok(AuthResult::Success) => {
+            login.record(LoginResult::Success, None);
+            Ok(CreateTicketResponse::new(username))
+        }
+        Ok(result @ (AuthResult::CreateTicket | AuthResult::RenewSession(_))) => {
             let auth_context = auth_context()?;
-            let api_ticket = ApiTicket::Full(username.clone());
+            let session_id = match result {
+                AuthResult::RenewSession(session_id) => Some(session_id),
+                _ => None,
+            };
+            let api_ticket = register_session(&username, session_id, env, user_agent)?;
             let mut ticket = Ticket::new(auth_context.auth_prefix(), &api_ticket)?;
             let csrfprevention_token =
                 assemble_csrf_prevention_token(auth_context.csrf_secret(), &username);
 
             env.log_auth(username.as_str());
+            login.record(LoginResult::Success, Some(LoginTicketType::Full));
 
             Ok(CreateTicketResponse {
                 username,
@@ -226,6 +294,8 @@ async fn handle_ticket_creation(
             let ticket = Ticket::new(auth_context.auth_prefix(), &api_ticket)?
                 .sign(auth_context.keyring(), Some(username.as_str()))?;
 
+            login.record(LoginResult::Success, Some(LoginTicketType::Partial));
+
             Ok(CreateTicketResponse {
                 username,
                 ticket: Some(ticket),
@@ -234,12 +304,166 @@ async fn handle_ticket_creation(
             })
         }
         Err(err) => {
+            login.record(LoginResult::Failure, None);
             env.log_failed_auth(Some(username.to_string()), &err.to_string());
             Err(http_err!(UNAUTHORIZED, "permission check failed."))
         }
     }
 }
 
+/// Create the data of a full ticket. If the [`AuthContext`] tracks sessions, this registers a
+/// new session or renews the existing one.
+///
+/// [`AuthContext`]: super::AuthContext
+fn register_session(
+    userid: &Userid,
+    session_id: Option<String>,
+    env: &RestEnvironment,
+    user_agent: Option<String>,
+) -> Result<ApiTicket, Error> {
+    let Some(store) = auth_context()?.session_store() else {
+        return Ok(ApiTicket::Full(userid.clone()));
+    };
+
+    let now = crate::time::epoch_i64();
+    let existing = match &session_id {
+        Some(id) => store
+            .list_sessions(Some(userid))?
+            .into_iter()
+            .find(|session| session.id == *id),
+        None => None,
+    };
+
+    let session = match existing {
+        Some(session) => SessionInfo {
+            renewed: now,
+            ..session
+        },
+        None => SessionInfo {
+            id: match session_id {
+                Some(id) => id,
+                None => generate_session_id()?,
+            },
+            userid: userid.clone(),
+            client_ip: env.get_client_ip().map(|sa| sa.ip().to_string()),
+            user_agent,
+            created: now,
+            renewed: now,
+        },
+    };
+    store.store_session(&session)?;
+
+    Ok(ApiTicket::Session(session.userid, session.id))
+}
+
+/// Helper to update the login audit log and lockout counters of the [`AuthContext`]'s
+/// [`LoginAttemptStore`] during ticket creation.
+///
+/// [`AuthContext`]: super::AuthContext
+struct LoginRecorder {
+    store: Option<(&'static dyn LoginAttemptStore, LockoutPolicy)>,
+    userid: Userid,
+    client_ip: Option<IpAddr>,
+    tfa_method: Option<LoginTfaMethod>,
+    now: i64,
+    // whether the attempt is registered and still needs to be finished
+    pending: Cell<bool>,
+}
+
+impl LoginRecorder {
+    fn new(
+        userid: &Userid,
+        env: &RestEnvironment,
+        tfa_method: Option<LoginTfaMethod>,
+    ) -> Result<Self, Error> {
+        let auth_context = auth_context()?;
+        Ok(Self {
+            store: auth_context
+                .login_attempt_store()
+                .map(|store| (store, auth_context.lockout_policy(userid.realm()))),
+            userid: userid.clone(),
+            client_ip: env.get_client_ip().map(|sa| sa.ip()),
+            tfa_method,
+            now: crate::time::epoch_i64(),
+            pending: Cell::new(false),
+        })
+    }
+
+    /// Register the attempt, fails if the user or client address is locked out.
+    fn begin(&self) -> Result<(), Error> {
+        match &self.store {
+            Some((store, policy)) => {
+                begin_attempt(*store, policy, &self.userid, self.client_ip, self.now)?;
+                self.pending.set(true);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record(&self, result: LoginResult, ticket_type: Option<LoginTicketType>) {
+        let Some((store, _)) = &self.store else {
+            return;
+        };
+
+        let attempt = LoginAttempt {
+            time: self.now,
+            userid: self.userid.clone(),
+            realm: self.userid.realm().to_owned(),
+            client_ip: self.client_ip.map(|ip| ip.to_string()),
+            result,
+            tfa_method: self.tfa_method,
+            ticket_type,
+        };
+        if let Err(err) = store.record_attempt(&attempt) {
+            log::error!(
+                "failed to record login attempt of '{}' - {err}",
+                self.userid
+            );
+        }
+
+        // A partial ticket still requires the second factor, so it neither counts as failure nor
+        // resets the failure counter. Locked attempts were never registered, so a lockout does not
+        // extend itself.
+        let outcome = match (result, ticket_type) {
+            (LoginResult::Locked, _) => return,
+            (LoginResult::Success, Some(LoginTicketType::Partial)) => AttemptOutcome::Neutral,
+            (LoginResult::Success, _) => AttemptOutcome::Reset,
+            (LoginResult::Failure, _) => AttemptOutcome::Failure,
+        };
+        self.finish(outcome);
+    }
+
+    fn finish(&self, outcome: AttemptOutcome) {
+        let Some((store, policy)) = &self.store else {
+            return;
+        };
+        if !self.pending.replace(false) {
+            return;
+        }
+        if let Err(err) = finish_attempt(
+            *store,
+            policy,
+            &self.userid,
+            self.client_ip,
+            outcome,
+            self.now,
+        ) {
+            log::error!(
+                "failed to update lockout state of '{}' - {err}",
+                self.userid
+            );
+        }
+    }
+}
+
+impl Drop for LoginRecorder {
+    fn drop(&mut self) {
+        // the attempt ended with an internal error, don't leave it registered
+        self.finish(AttemptOutcome::Neutral);
+    }
+}
+
 async fn authenticate_user(
     userid: &Userid,
     password: &str,
@@ -263,11 +487,16 @@ async fn authenticate_user(
 
     if password.starts_with(prefix) && password.as_bytes().get(prefix.len()).copied() == Some(b':')
     {
-        if let Ok(ticket_userid) = Ticket::<Userid>::parse(password)
+        if let Ok(api_ticket) = Ticket::<ApiTicket>::parse(password)
             .and_then(|ticket| ticket.verify(auth_context.keyring(), prefix, None))
         {
-            if *userid == ticket_userid {
-                return Ok(AuthResult::CreateTicket);
+            check_session(&api_ticket)?;
+            let session_id = api_ticket.session_id().map(str::to_owned);
+            if *userid == api_ticket.require_full()? {
+                return Ok(match session_id {
+                    Some(session_id) => AuthResult::RenewSession(session_id),
+                    None => AuthResult::CreateTicket,
+                });
             }
             bail!("ticket login failed - wrong userid");
         }
@@ -0,0 +1,93 @@
+//! Inspection and rotation of the ticket signing keys.
+//!
+//! Rotating the signing key replaces it with a newly generated key of the same kind. The previous
+//! key is kept to verify tickets signed with it until those have expired. Products persist the
+//! rotated keyring via [`AuthContext::keyring_rotated`](super::AuthContext::keyring_rotated()).
+
+use anyhow::Error;
+
+use proxmox_router::Permission;
+use proxmox_schema::api;
+
+use super::auth_context;
+use crate::auth_key::KeyInfo;
+use crate::types::AuthKeyInfo;
+use crate::TICKET_LIFETIME;
+
+impl From<KeyInfo> for AuthKeyInfo {
+    fn from(info: KeyInfo) -> Self {
+        Self {
+            expire: info.retired.map(|retired| retired + TICKET_LIFETIME),
+            id: info.id,
+            signing: info.signing,
+            created: info.created,
+            retired: info.retired,
+        }
+    }
+}
+
+/// Rotate the signing key and notify the [`AuthContext`](super::AuthContext).
+fn do_rotate() -> Result<AuthKeyInfo, Error> {
+    let auth_context = auth_context()?;
+    let keyring = auth_context.keyring();
+
+    let info = keyring.rotate()?;
+    auth_context.keyring_rotated(keyring)?;
+
+    Ok(info.into())
+}
+
+/// Rotate the signing key if it is older than the
+/// [rotation interval](super::AuthContext::auth_key_rotation_interval()).
+///
+/// This is meant to be called periodically, for instance from a daily job. Retired keys whose
+/// tickets have expired are removed in any case. Returns the new signing key if it was rotated.
+pub fn rotate_auth_key_if_due() -> Result<Option<AuthKeyInfo>, Error> {
+    let auth_context = auth_context()?;
+    let keyring = auth_context.keyring();
+
+    let interval = auth_context.auth_key_rotation_interval();
+    keyring.set_embed_key_id(interval.is_some());
+
+    match interval {
+        Some(interval) if keyring.rotation_due(interval) => do_rotate().map(Some),
+        _ => {
+            if keyring.prune() > 0 {
+                auth_context.keyring_rotated(keyring)?;
+            }
+            Ok(None)
+        }
+    }
+}
+
+#[api(
+    returns: {
+        description: "List of ticket keys, starting with the signing key.",
+        type: Array,
+        items: { type: AuthKeyInfo },
+    },
+    access: {
+        permission: &Permission::Superuser,
+    },
+)]
+/// List the keys used to sign and verify tickets.
+pub fn list_auth_keys() -> Result<Vec<AuthKeyInfo>, Error> {
+    Ok(auth_context()?
+        .keyring()
+        .key_info()
+        .into_iter()
+        .map(AuthKeyInfo::from)
+        .collect())
+}
+
+#[api(
+    returns: { type: AuthKeyInfo },
+    protected: true,
+    access: {
+        permission: &Permission::Superuser,
+    },
+)]
+/// Replace the ticket signing key with a new one. Existing tickets stay valid until they expire.
+pub fn rotate_auth_key() -> Result<AuthKeyInfo, Error> {
+    do_rotate()
+}
@@ -0,0 +1,534 @@
+//! Login audit log and brute-force lockout handling.
+//!
+//! Products can provide a [`LoginAttemptStore`] via their [`AuthContext`](super::AuthContext) to
+//! keep a queryable history of login attempts and to lock users or client addresses after too
+//! many failed attempts, as configured by the realm's [`LockoutPolicy`].
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_router::Permission;
+use proxmox_schema::api;
+
+use super::auth_context;
+use crate::types::{LockoutPolicy, LockoutThreshold, LoginAttempt, LoginAttemptFilter, Userid};
+
+/// What a lockout applies to.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "type", content = "id")]
+pub enum LockoutKey {
+    /// A user.
+    User(Userid),
+    /// A client address.
+    ClientIp(IpAddr),
+}
+
+impl fmt::Display for LockoutKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockoutKey::User(userid) => write!(f, "user '{userid}'"),
+            LockoutKey::ClientIp(ip) => write!(f, "client address '{ip}'"),
+        }
+    }
+}
+
+/// Failed attempt counter of a [`LockoutKey`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockoutState {
+    /// Number of failures within the current window.
+    pub failures: u32,
+
+    /// Number of attempts currently being verified. These count as failures until they are
+    /// finished, so that parallel attempts cannot exceed the limit.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub pending: u32,
+
+    /// Time of the first failure of the current window.
+    pub window_start: i64,
+
+    /// If set, the key is locked until this time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<i64>,
+}
+
+impl LockoutState {
+    /// Check whether the key is locked at time `now`.
+    pub fn is_locked(&self, now: i64) -> bool {
+        self.locked_until.is_some_and(|until| now < until)
+    }
+
+    fn is_unused(&self, now: i64) -> bool {
+        self.failures == 0 && self.pending == 0 && !self.is_locked(now)
+    }
+
+    fn start_window(&mut self, threshold: &LockoutThreshold, now: i64) {
+        if (self.failures == 0 && self.pending == 0)
+            || now - self.window_start >= threshold.window as i64
+        {
+            // pending attempts of an expired window are stale, e.g. after a crash
+            self.failures = 0;
+            self.pending = 0;
+            self.window_start = now;
+        }
+    }
+
+    /// Register an attempt at time `now`. Returns `false` if the key is locked or if the attempt
+    /// could exceed the limit together with the attempts still being verified.
+    fn begin_attempt(&mut self, threshold: &LockoutThreshold, now: i64) -> bool {
+        if self.is_locked(now) {
+            return false;
+        }
+        self.start_window(threshold, now);
+        if self.failures + self.pending >= threshold.max_failures {
+            return false;
+        }
+        self.pending += 1;
+        true
+    }
+
+    /// Finish an attempt registered with `begin_attempt`.
+    fn finish_attempt(&mut self, threshold: &LockoutThreshold, outcome: AttemptOutcome, now: i64) {
+        self.pending = self.pending.saturating_sub(1);
+        match outcome {
+            AttemptOutcome::Failure => self.add_failure(threshold, now),
+            AttemptOutcome::Reset => self.failures = 0,
+            AttemptOutcome::Neutral => (),
+        }
+    }
+
+    /// Account for a failed attempt at time `now`.
+    fn add_failure(&mut self, threshold: &LockoutThreshold, now: i64) {
+        self.start_window(threshold, now);
+
+        self.failures += 1;
+        if self.failures >= threshold.max_failures {
+            self.locked_until = Some(now + threshold.duration as i64);
+            self.failures = 0;
+        }
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// How a finished login attempt affects the lockout counters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum AttemptOutcome {
+    /// The attempt failed and counts towards the lockout.
+    Failure,
+    /// The attempt succeeded, resets the failure counter of the user.
+    Reset,
+    /// The attempt neither failed nor completed the login, e.g. a partial ticket still requiring
+    /// a second factor.
+    Neutral,
+}
+
+/// Storage for the login audit log and lockout counters.
+///
+/// Implementations need to take care of their own locking, since logins happen concurrently.
+pub trait LoginAttemptStore: Send + Sync {
+    /// Append an entry to the audit log.
+    fn record_attempt(&self, attempt: &LoginAttempt) -> Result<(), Error>;
+
+    /// Query the audit log. Entries are expected to be returned newest first.
+    fn query_attempts(&self, filter: &LoginAttemptFilter) -> Result<Vec<LoginAttempt>, Error>;
+
+    /// Get the current lockout state for a key.
+    fn lockout_state(&self, key: &LockoutKey) -> Result<Option<LockoutState>, Error>;
+
+    /// Atomically update the lockout state of a key. `update` gets the current state and returns
+    /// the new one, `None` removes it.
+    ///
+    /// No other update of the same key may happen between reading and writing the state, even
+    /// across processes sharing the store.
+    fn update_lockout_state(
+        &self,
+        key: &LockoutKey,
+        update: &mut dyn FnMut(Option<LockoutState>) -> Option<LockoutState>,
+    ) -> Result<(), Error>;
+
+    /// Set the lockout state of a key, `None` removes it.
+    fn set_lockout_state(
+        &self,
+        key: &LockoutKey,
+        state: Option<LockoutState>,
+    ) -> Result<(), Error> {
+        let mut state = Some(state);
+        self.update_lockout_state(key, &mut |_| state.take().flatten())
+    }
+}
+
+/// A [`LoginAttemptStore`] keeping everything in memory.
+///
+/// The audit log is limited to a fixed number of entries, older entries are dropped.
+pub struct MemoryLoginAttemptStore {
+    capacity: usize,
+    attempts: Mutex<VecDeque<LoginAttempt>>,
+    lockouts: Mutex<HashMap<LockoutKey, LockoutState>>,
+}
+
+impl MemoryLoginAttemptStore {
+    /// Create a new store keeping at most `capacity` audit log entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            attempts: Mutex::new(VecDeque::new()),
+            lockouts: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl LoginAttemptStore for MemoryLoginAttemptStore {
+    fn record_attempt(&self, attempt: &LoginAttempt) -> Result<(), Error> {
+        let mut attempts = self.attempts.lock().unwrap();
+        if attempts.len() >= self.capacity {
+            attempts.pop_front();
+        }
+        attempts.push_back(attempt.clone());
+        Ok(())
+    }
+
+    fn query_attempts(&self, filter: &LoginAttemptFilter) -> Result<Vec<LoginAttempt>, Error> {
+        let limit = filter.limit.map(|l| l as usize).unwrap_or(usize::MAX);
+        Ok(self
+            .attempts
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|attempt| filter.matches(attempt))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn lockout_state(&self, key: &LockoutKey) -> Result<Option<LockoutState>, Error> {
+        Ok(self.lockouts.lock().unwrap().get(key).cloned())
+    }
+
+    fn update_lockout_state(
+        &self,
+        key: &LockoutKey,
+        update: &mut dyn FnMut(Option<LockoutState>) -> Option<LockoutState>,
+    ) -> Result<(), Error> {
+        let mut lockouts = self.lockouts.lock().unwrap();
+        match update(lockouts.remove(key)) {
+            Some(state) => lockouts.insert(key.clone(), state),
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+fn lockout_keys<'a>(
+    policy: &'a LockoutPolicy,
+    userid: &Userid,
+    client_ip: Option<IpAddr>,
+) -> impl Iterator<Item = (LockoutKey, &'a LockoutThreshold)> {
+    let user = policy
+        .user
+        .as_ref()
+        .map(|threshold| (LockoutKey::User(userid.clone()), threshold));
+    let ip = policy
+        .client_ip
+        .as_ref()
+        .zip(client_ip)
+        .map(|(threshold, ip)| (LockoutKey::ClientIp(ip), threshold));
+    user.into_iter().chain(ip)
+}
+
+/// Register a login attempt, failing if the user or client address is locked out.
+///
+/// Checking and registering is a single atomic update per key, so that parallel attempts cannot
+/// get past the limit. Every successful call must be followed by [`finish_attempt`].
+pub(crate) fn begin_attempt(
+    store: &dyn LoginAttemptStore,
+    policy: &LockoutPolicy,
+    userid: &Userid,
+    client_ip: Option<IpAddr>,
+    now: i64,
+) -> Result<(), Error> {
+    let mut registered = Vec::new();
+
+    for (key, threshold) in lockout_keys(policy, userid, client_ip) {
+        let mut allowed = false;
+        let result = store.update_lockout_state(&key, &mut |state| {
+            let mut state = state.unwrap_or_default();
+            allowed = state.begin_attempt(threshold, now);
+            (!state.is_unused(now)).then_some(state)
+        });
+
+        if result.is_err() || !allowed {
+            // undo the registration of the other keys
+            for (key, threshold) in registered {
+                finish_key(store, &key, threshold, AttemptOutcome::Neutral, now)?;
+            }
+            result?;
+            bail!("{key} is locked due to too many failed login attempts");
+        }
+        registered.push((key, threshold));
+    }
+    Ok(())
+}
+
+/// Update the lockout counters after an attempt registered with [`begin_attempt`].
+///
+/// Failures are counted for both the user and the client address, a success only resets the
+/// counter of the user.
+pub(crate) fn finish_attempt(
+    store: &dyn LoginAttemptStore,
+    policy: &LockoutPolicy,
+    userid: &Userid,
+    client_ip: Option<IpAddr>,
+    outcome: AttemptOutcome,
+    now: i64,
+) -> Result<(), Error> {
+    for (key, threshold) in lockout_keys(policy, userid, client_ip) {
+        let outcome = match (&key, outcome) {
+            (LockoutKey::ClientIp(_), AttemptOutcome::Reset) => AttemptOutcome::Neutral,
+            (_, outcome) => outcome,
+        };
+        finish_key(store, &key, threshold, outcome, now)?;
+    }
+    Ok(())
+}
+
+fn finish_key(
+    store: &dyn LoginAttemptStore,
+    key: &LockoutKey,
+    threshold: &LockoutThreshold,
+    outcome: AttemptOutcome,
+    now: i64,
+) -> Result<(), Error> {
+    store.update_lockout_state(key, &mut |state| {
+        let mut state = state.unwrap_or_default();
+        state.finish_attempt(threshold, outcome, now);
+        (!state.is_unused(now)).then_some(state)
+    })
+}
+
+fn login_attempt_store() -> Result<&'static dyn LoginAttemptStore, Error> {
+    match auth_context()?.login_attempt_store() {
+        Some(store) => Ok(store),
+        None => bail!("login attempts are not recorded"),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            filter: {
+                type: LoginAttemptFilter,
+                flatten: true,
+            },
+        },
+    },
+    returns: {
+        description: "List of login attempts, newest first.",
+        type: Array,
+        items: { type: LoginAttempt },
+    },
+    access: {
+        permission: &Permission::Superuser,
+    },
+)]
+/// List recorded login attempts.
+pub fn list_login_attempts(filter: LoginAttemptFilter) -> Result<Vec<LoginAttempt>, Error> {
+    login_attempt_store()?.query_attempts(&filter)
+}
+
+#[api(
+    input: {
+        properties: {
+            userid: {
+                type: Userid,
+                optional: true,
+            },
+            "client-ip": {
+                schema: proxmox_schema::api_types::IP_SCHEMA,
+                optional: true,
+            },
+        },
+    },
+    protected: true,
+    access: {
+        permission: &Permission::Superuser,
+    },
+)]
+/// Remove the lockout of a user and/or a client address.
+pub fn unlock_login(userid: Option<Userid>, client_ip: Option<String>) -> Result<(), Error> {
+    if userid.is_none() && client_ip.is_none() {
+        bail!("neither a userid nor a client address specified");
+    }
+
+    let store = login_attempt_store()?;
+    if let Some(userid) = userid {
+        store.set_lockout_state(&LockoutKey::User(userid), None)?;
+    }
+    if let Some(client_ip) = client_ip {
+        store.set_lockout_state(&LockoutKey::ClientIp(client_ip.parse()?), None)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LoginResult;
+
+    fn policy() -> LockoutPolicy {
+        LockoutPolicy {
+            user: Some(LockoutThreshold {
+                max_failures: 3,
+                window: 60,
+                duration: 300,
+            }),
+            client_ip: Some(LockoutThreshold {
+                max_failures: 5,
+                window: 60,
+                duration: 600,
+            }),
+        }
+    }
+
+    fn attempt(
+        store: &dyn LoginAttemptStore,
+        userid: &Userid,
+        client_ip: Option<IpAddr>,
+        outcome: AttemptOutcome,
+        now: i64,
+    ) -> Result<(), Error> {
+        let policy = policy();
+        begin_attempt(store, &policy, userid, client_ip, now)?;
+        finish_attempt(store, &policy, userid, client_ip, outcome, now)
+    }
+
+    #[test]
+    fn test_user_lockout() {
+        let store = MemoryLoginAttemptStore::new(10);
+        let userid: Userid = "test@pam".parse().unwrap();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        for now in 0..3 {
+            attempt(&store, &userid, Some(ip), AttemptOutcome::Failure, now).unwrap();
+        }
+        assert!(attempt(&store, &userid, Some(ip), AttemptOutcome::Reset, 3).is_err());
+
+        // the client address is locked independently of the user
+        let other: Userid = "other@pam".parse().unwrap();
+        for now in 10..12 {
+            attempt(&store, &other, Some(ip), AttemptOutcome::Failure, now).unwrap();
+        }
+        assert!(attempt(&store, &other, Some(ip), AttemptOutcome::Failure, 12).is_err());
+        attempt(&store, &other, None, AttemptOutcome::Failure, 12).unwrap();
+
+        attempt(&store, &userid, None, AttemptOutcome::Reset, 302).unwrap();
+        assert!(attempt(&store, &userid, Some(ip), AttemptOutcome::Reset, 302).is_err());
+        attempt(&store, &userid, Some(ip), AttemptOutcome::Reset, 612).unwrap();
+    }
+
+    #[test]
+    fn test_failure_window() {
+        let store = MemoryLoginAttemptStore::new(10);
+        let userid: Userid = "test@pam".parse().unwrap();
+
+        attempt(&store, &userid, None, AttemptOutcome::Failure, 0).unwrap();
+        attempt(&store, &userid, None, AttemptOutcome::Failure, 1).unwrap();
+        // outside the window, the counter starts over
+        attempt(&store, &userid, None, AttemptOutcome::Failure, 100).unwrap();
+        attempt(&store, &userid, None, AttemptOutcome::Neutral, 100).unwrap();
+
+        // a successful login resets the counter
+        attempt(&store, &userid, None, AttemptOutcome::Reset, 101).unwrap();
+        attempt(&store, &userid, None, AttemptOutcome::Failure, 102).unwrap();
+        attempt(&store, &userid, None, AttemptOutcome::Failure, 103).unwrap();
+        attempt(&store, &userid, None, AttemptOutcome::Reset, 103).unwrap();
+        assert!(store
+            .lockout_state(&LockoutKey::User(userid))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_parallel_attempts() {
+        let store = MemoryLoginAttemptStore::new(10);
+        let policy = policy();
+        let userid: Userid = "test@pam".parse().unwrap();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        // attempts in flight count against the limit
+        for _ in 0..3 {
+            begin_attempt(&store, &policy, &userid, Some(ip), 0).unwrap();
+        }
+        assert!(begin_attempt(&store, &policy, &userid, Some(ip), 0).is_err());
+
+        // the rejected attempt did not stay registered for the client address
+        let key = LockoutKey::ClientIp(ip);
+        assert_eq!(store.lockout_state(&key).unwrap().unwrap().pending, 3);
+
+        for _ in 0..3 {
+            finish_attempt(
+                &store,
+                &policy,
+                &userid,
+                Some(ip),
+                AttemptOutcome::Failure,
+                1,
+            )
+            .unwrap();
+        }
+        let user = LockoutKey::User(userid.clone());
+        assert!(store.lockout_state(&user).unwrap().unwrap().is_locked(2));
+        assert_eq!(store.lockout_state(&key).unwrap().unwrap().failures, 3);
+
+        // pending attempts of an expired window are dropped
+        let other: Userid = "other@pam".parse().unwrap();
+        for _ in 0..3 {
+            begin_attempt(&store, &policy, &other, None, 10).unwrap();
+        }
+        begin_attempt(&store, &policy, &other, None, 100).unwrap();
+    }
+
+    #[test]
+    fn test_query_attempts() {
+        let store = MemoryLoginAttemptStore::new(3);
+        for time in 0..5 {
+            let userid: Userid = if time % 2 == 0 { "a@pam" } else { "b@pve" }
+                .parse()
+                .unwrap();
+            store
+                .record_attempt(&LoginAttempt {
+                    time,
+                    realm: userid.realm().to_owned(),
+                    userid,
+                    client_ip: None,
+                    result: LoginResult::Failure,
+                    tfa_method: None,
+                    ticket_type: None,
+                })
+                .unwrap();
+        }
+
+        let all = store.query_attempts(&Default::default()).unwrap();
+        assert_eq!(
+            all.iter().map(|a| a.time).collect::<Vec<_>>(),
+            vec![4, 3, 2]
+        );
+
+        let filter = LoginAttemptFilter {
+            userid: Some("a@pam".parse().unwrap()),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let list = store.query_attempts(&filter).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].time, 4);
+    }
+}
@@ -10,9 +10,12 @@ use proxmox_rest_server::{extract_cookie, AuthError};
 use proxmox_tfa::api::{OpenUserChallengeData, TfaConfig};
 
 use crate::auth_key::{HMACKey, Keyring};
-use crate::types::{Authid, RealmRef, Userid, UsernameRef};
+use crate::types::{Authid, LockoutPolicy, RealmRef, Userid, UsernameRef};
 
 mod access;
+mod keys;
+mod login;
+mod session;
 mod ticket;
 
 use crate::ticket::Ticket;
@@ -22,6 +25,18 @@ pub use access::{
     assemble_csrf_prevention_token, create_ticket, API_METHOD_CREATE_TICKET,
     API_METHOD_CREATE_TICKET_HTTP_ONLY, API_METHOD_LOGOUT,
 };
+pub use keys::{
+    list_auth_keys, rotate_auth_key, rotate_auth_key_if_due, API_METHOD_LIST_AUTH_KEYS,
+    API_METHOD_ROTATE_AUTH_KEY,
+};
+pub use login::{
+    list_login_attempts, unlock_login, LockoutKey, LockoutState, LoginAttemptStore,
+    MemoryLoginAttemptStore, API_METHOD_LIST_LOGIN_ATTEMPTS, API_METHOD_UNLOCK_LOGIN,
+};
+pub use session::{
+    list_sessions, revoke_sessions, FileSessionStore, MemorySessionStore, SessionStore,
+    API_METHOD_LIST_SESSIONS, API_METHOD_REVOKE_SESSIONS,
+};
 pub use ticket::{ApiTicket, PartialTicket};
 
 /// Authentication realms are used to manage users: authenticate, change password or remove.
@@ -75,6 +90,20 @@ pub trait AuthContext: Send + Sync {
     /// Verify a token secret.
     fn verify_token_secret(&self, token_id: &Authid, token_secret: &str) -> Result<(), Error>;
 
+    /// Verify a token secret used from a specific client address. Products supporting source
+    /// address restrictions for tokens should override this.
+    ///
+    /// Default: Ignores the address and calls [`verify_token_secret`](Self::verify_token_secret).
+    fn verify_token_secret_with_client_ip(
+        &self,
+        token_id: &Authid,
+        token_secret: &str,
+        client_ip: Option<&IpAddr>,
+    ) -> Result<(), Error> {
+        let _ = client_ip;
+        self.verify_token_secret(token_id, token_secret)
+    }
+
     /// Check path based tickets. (Used for terminal tickets).
     fn check_path_ticket(
         &self,
@@ -97,6 +126,44 @@ pub trait AuthContext: Send + Sync {
         static HOST_COOKIE: OnceLock<String> = OnceLock::new();
         HOST_COOKIE.get_or_init(|| format!("__Host-{}", self.auth_cookie_name()))
     }
+
+    /// Storage for the login audit log and lockout counters. Without a store, login attempts
+    /// are neither recorded nor locked out.
+    fn login_attempt_store(&self) -> Option<&'static dyn LoginAttemptStore> {
+        None
+    }
+
+    /// The lockout policy for logins to a realm. By default, nothing is locked out.
+    fn lockout_policy(&self, realm: &RealmRef) -> LockoutPolicy {
+        let _ = realm;
+        LockoutPolicy::default()
+    }
+
+    /// Registry of login sessions. Without a store, tickets carry no session ID and can only
+    /// expire, but not be revoked.
+    ///
+    /// Products running more than one daemon need a store shared between them, such as a
+    /// [`FileSessionStore`], as otherwise revocations are only seen by the daemon handling them.
+    fn session_store(&self) -> Option<&'static dyn SessionStore> {
+        None
+    }
+
+    /// Maximum age of the ticket signing key in seconds, after which
+    /// [`rotate_auth_key_if_due`] replaces it. By default, keys are never rotated automatically.
+    fn auth_key_rotation_interval(&self) -> Option<i64> {
+        None
+    }
+
+    /// Called after the [`keyring`](AuthContext::keyring()) was modified by a key rotation.
+    ///
+    /// Products need to persist the keyring's [entries](Keyring::entries()) here, so that the
+    /// rotation survives restarts. Other daemons sharing the keys do not see the rotation by
+    /// themselves, they need to load the persisted entries into their keyring with
+    /// [`Keyring::reload_entries`], e.g. when the file changed.
+    fn keyring_rotated(&self, keyring: &Keyring) -> Result<(), Error> {
+        let _ = keyring;
+        Ok(())
+    }
 }
 
 /// When verifying TFA challenges we need to be able to update the TFA config without interference
@@ -137,6 +204,16 @@ enum AuthData {
 pub fn http_check_auth(
     headers: &http::HeaderMap,
     method: &http::Method,
+) -> Result<String, AuthError> {
+    http_check_auth_with_client_ip(headers, method, None)
+}
+
+/// Like [`http_check_auth`], but passes the client address on to the token verification, see
+/// [`AuthContext::verify_token_secret_with_client_ip`].
+pub fn http_check_auth_with_client_ip(
+    headers: &http::HeaderMap,
+    method: &http::Method,
+    client_ip: Option<IpAddr>,
 ) -> Result<String, AuthError> {
     let auth_context = auth_context()?;
 
@@ -146,14 +223,14 @@ pub fn http_check_auth(
             let ticket = user_auth_data.ticket.clone();
             let ticket_lifetime = crate::TICKET_LIFETIME;
 
-            let userid: Userid = Ticket::<ApiTicket>::parse(&ticket)?
-                .verify_with_time_frame(
-                    auth_context.keyring(),
-                    auth_context.auth_prefix(),
-                    None,
-                    -300..ticket_lifetime,
-                )?
-                .require_full()?;
+            let api_ticket = Ticket::<ApiTicket>::parse(&ticket)?.verify_with_time_frame(
+                auth_context.keyring(),
+                auth_context.auth_prefix(),
+                None,
+                -300..ticket_lifetime,
+            )?;
+            session::check_session(&api_ticket)?;
+            let userid = api_ticket.require_full()?;
 
             let auth_id = Authid::from(userid.clone());
             if !auth_context.auth_id_is_active(&auth_id)? {
@@ -194,7 +271,11 @@ pub fn http_check_auth(
                 .decode_utf8()
                 .map_err(|_| format_err!("failed to decode API token header"))?;
 
-            auth_context.verify_token_secret(&tokenid, &tokensecret)?;
+            auth_context.verify_token_secret_with_client_ip(
+                &tokenid,
+                &tokensecret,
+                client_ip.as_ref(),
+            )?;
 
             Ok(tokenid.to_string())
         }
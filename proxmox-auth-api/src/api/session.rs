@@ -0,0 +1,556 @@
+//! Optional registry of login sessions.
+//!
+//! If the [`AuthContext`](super::AuthContext) provides a [`SessionStore`], every full ticket
+//! created via the ticket API carries a session ID. This allows listing the active sessions of
+//! users and revoking them before their tickets expire.
+
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use proxmox_router::Permission;
+use proxmox_schema::api;
+use proxmox_shared_memory::{Init, SharedMemory};
+use proxmox_sys::fs::CreateOptions;
+
+use super::{auth_context, ApiTicket};
+use crate::types::{SessionInfo, Userid};
+use crate::TICKET_LIFETIME;
+
+/// Storage for active sessions and revoked session IDs.
+///
+/// Implementations need to take care of their own locking. Products running multiple daemons
+/// need to share the revocation state between them, like a [`FileSessionStore`] does.
+pub trait SessionStore: Send + Sync {
+    /// Add a new session or update an existing one after its ticket was renewed.
+    fn store_session(&self, session: &SessionInfo) -> Result<(), Error>;
+
+    /// List the active sessions, optionally only those of a single user.
+    fn list_sessions(&self, userid: Option<&Userid>) -> Result<Vec<SessionInfo>, Error>;
+
+    /// Revoke a session. Returns the session if it was active.
+    fn revoke_session(&self, id: &str) -> Result<Option<SessionInfo>, Error>;
+
+    /// Check whether a session was revoked.
+    ///
+    /// This is called for every authenticated request, so it must be cheap.
+    fn is_revoked(&self, id: &str) -> bool;
+}
+
+/// Check whether a session has expired, meaning its ticket was not renewed in time.
+fn session_expired(session: &SessionInfo, now: i64) -> bool {
+    session.renewed + TICKET_LIFETIME < now
+}
+
+/// A [`SessionStore`] keeping everything in memory of the current process.
+///
+/// Revocations are neither visible to other daemons nor kept across restarts, so this is only
+/// suitable for single-process setups and tests. Use a [`FileSessionStore`] otherwise.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, SessionInfo>>,
+    /// Maps revoked session IDs to the time their tickets expire at the latest.
+    revoked: RwLock<HashMap<String, i64>>,
+}
+
+impl MemorySessionStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn store_session(&self, session: &SessionInfo) -> Result<(), Error> {
+        let now = crate::time::epoch_i64();
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| !session_expired(session, now));
+        sessions.insert(session.id.clone(), session.clone());
+
+        self.revoked
+            .write()
+            .unwrap()
+            .retain(|_, expire| *expire >= now);
+
+        Ok(())
+    }
+
+    fn list_sessions(&self, userid: Option<&Userid>) -> Result<Vec<SessionInfo>, Error> {
+        let now = crate::time::epoch_i64();
+
+        let mut list: Vec<SessionInfo> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| !session_expired(session, now))
+            .filter(|session| userid.is_none_or(|userid| session.userid == *userid))
+            .cloned()
+            .collect();
+        list.sort_by_key(|session| session.created);
+
+        Ok(list)
+    }
+
+    fn revoke_session(&self, id: &str) -> Result<Option<SessionInfo>, Error> {
+        let session = self.sessions.lock().unwrap().remove(id);
+
+        let expire = match &session {
+            Some(session) => session.renewed + TICKET_LIFETIME,
+            None => crate::time::epoch_i64() + TICKET_LIFETIME,
+        };
+        self.revoked.write().unwrap().insert(id.to_string(), expire);
+
+        Ok(session)
+    }
+
+    fn is_revoked(&self, id: &str) -> bool {
+        self.revoked.read().unwrap().contains_key(id)
+    }
+}
+
+/// On-disk format of a [`FileSessionStore`].
+#[derive(Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct SessionFile {
+    #[serde(default)]
+    sessions: HashMap<String, SessionInfo>,
+    /// Maps revoked session IDs to the time their tickets expire at the latest.
+    #[serde(default)]
+    revoked: HashMap<String, i64>,
+}
+
+impl SessionFile {
+    fn prune(&mut self, now: i64) {
+        self.sessions
+            .retain(|_, session| !session_expired(session, now));
+        self.revoked.retain(|_, expire| *expire >= now);
+    }
+}
+
+/// Identifies a version of the session file, so the revocation cache only needs to be reloaded
+/// after the file was replaced.
+#[derive(Clone, Copy, PartialEq)]
+enum FileVersion {
+    /// Inode, modification time and size of the file.
+    Stat(u64, i64, i64, u64),
+    /// The value of the change counter in shared memory.
+    Counter(u64),
+}
+
+const CHANGE_COUNTER_MAGIC: [u8; 8] = *b"pxsessc1";
+
+/// Shared memory counting the updates of a session file.
+#[repr(C)]
+struct ChangeCounter {
+    magic: [u8; 8],
+    counter: AtomicU64,
+    _padding: [u8; 4096 - 16],
+}
+
+impl Init for ChangeCounter {
+    fn initialize(this: &mut MaybeUninit<Self>) {
+        this.write(Self {
+            magic: CHANGE_COUNTER_MAGIC,
+            counter: AtomicU64::new(0),
+            _padding: [0; 4096 - 16],
+        });
+    }
+
+    fn check_type_magic(this: &MaybeUninit<Self>) -> Result<(), Error> {
+        // SAFETY: the magic is the first field and always initialized
+        let magic = unsafe { &(*this.as_ptr()).magic };
+        if *magic != CHANGE_COUNTER_MAGIC {
+            bail!("session change counter has an unexpected magic number");
+        }
+        Ok(())
+    }
+}
+
+/// A [`SessionStore`] keeping its state in a JSON file, so that sessions and revocations are
+/// shared between all daemons of a product and survive restarts.
+///
+/// Updates are serialized via a separate lock file. Revocation checks only re-read the file when
+/// it has changed. By default this requires a `stat()` per check, with
+/// [`with_change_counter`](FileSessionStore::with_change_counter()) it is a read of a counter in
+/// shared memory instead.
+pub struct FileSessionStore {
+    path: PathBuf,
+    lock_path: PathBuf,
+    options: CreateOptions,
+    change_counter: Option<SharedMemory<ChangeCounter>>,
+    revoked: RwLock<Option<(FileVersion, HashMap<String, i64>)>>,
+}
+
+impl FileSessionStore {
+    /// Create a store using the file at `path` and a lock file next to it. The `options` are
+    /// used when creating either of them.
+    pub fn new<P: Into<PathBuf>>(path: P, options: CreateOptions) -> Self {
+        let path = path.into();
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lck");
+
+        Self {
+            path,
+            lock_path: lock_path.into(),
+            options,
+            change_counter: None,
+            revoked: RwLock::new(None),
+        }
+    }
+
+    /// Count updates of the session file in shared memory at `path`, which must be on a tmpfs
+    /// like `/run`, and only re-read the file if the counter changed.
+    ///
+    /// All daemons sharing the session file must use the same counter, as changes made without
+    /// it are not noticed.
+    pub fn with_change_counter<P: AsRef<Path>>(mut self, path: P) -> Result<Self, Error> {
+        self.change_counter = Some(SharedMemory::open(path.as_ref(), self.options)?);
+        Ok(self)
+    }
+
+    fn read(&self) -> Result<SessionFile, Error> {
+        match proxmox_sys::fs::file_get_optional_contents(&self.path)? {
+            Some(data) => serde_json::from_slice(&data)
+                .map_err(|err| format_err!("failed to parse {:?} - {err}", self.path)),
+            None => Ok(SessionFile::default()),
+        }
+    }
+
+    /// Lock the file, apply `func` to its pruned contents and write them back.
+    fn update<F, R>(&self, func: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut SessionFile) -> R,
+    {
+        let _lock = proxmox_sys::fs::open_file_locked(
+            &self.lock_path,
+            Duration::from_secs(10),
+            true,
+            self.options,
+        )?;
+
+        let mut data = self.read()?;
+        data.prune(crate::time::epoch_i64());
+        let result = func(&mut data);
+
+        let raw = serde_json::to_vec(&data)?;
+        proxmox_sys::fs::replace_file(&self.path, &raw, self.options, false)?;
+        if let Some(shmem) = &self.change_counter {
+            shmem.data().counter.fetch_add(1, Ordering::AcqRel);
+        }
+
+        Ok(result)
+    }
+
+    fn file_version(&self) -> Result<Option<FileVersion>, Error> {
+        if let Some(shmem) = &self.change_counter {
+            let counter = shmem.data().counter.load(Ordering::Acquire);
+            return Ok(Some(FileVersion::Counter(counter)));
+        }
+
+        match std::fs::metadata(&self.path) {
+            Ok(stat) => Ok(Some(FileVersion::Stat(
+                stat.ino(),
+                stat.mtime(),
+                stat.mtime_nsec(),
+                stat.size(),
+            ))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => bail!("failed to stat {:?} - {err}", self.path),
+        }
+    }
+
+    fn check_revoked(&self, id: &str) -> Result<bool, Error> {
+        let Some(version) = self.file_version()? else {
+            return Ok(false);
+        };
+
+        if let Some((cached, revoked)) = &*self.revoked.read().unwrap() {
+            if *cached == version {
+                return Ok(revoked.contains_key(id));
+            }
+        }
+
+        let revoked = self.read()?.revoked;
+        let result = revoked.contains_key(id);
+        *self.revoked.write().unwrap() = Some((version, revoked));
+
+        Ok(result)
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn store_session(&self, session: &SessionInfo) -> Result<(), Error> {
+        self.update(|data| {
+            data.sessions.insert(session.id.clone(), session.clone());
+        })
+    }
+
+    fn list_sessions(&self, userid: Option<&Userid>) -> Result<Vec<SessionInfo>, Error> {
+        let now = crate::time::epoch_i64();
+
+        let mut list: Vec<SessionInfo> = self
+            .read()?
+            .sessions
+            .into_values()
+            .filter(|session| !session_expired(session, now))
+            .filter(|session| userid.is_none_or(|userid| session.userid == *userid))
+            .collect();
+        list.sort_by_key(|session| session.created);
+
+        Ok(list)
+    }
+
+    fn revoke_session(&self, id: &str) -> Result<Option<SessionInfo>, Error> {
+        self.update(|data| {
+            let session = data.sessions.remove(id);
+            let expire = match &session {
+                Some(session) => session.renewed + TICKET_LIFETIME,
+                None => crate::time::epoch_i64() + TICKET_LIFETIME,
+            };
+            data.revoked.insert(id.to_string(), expire);
+            session
+        })
+    }
+
+    fn is_revoked(&self, id: &str) -> bool {
+        // If the revocation state cannot be read, better reject the ticket than accept a revoked
+        // one.
+        self.check_revoked(id).unwrap_or_else(|err| {
+            log::error!("failed to check session revocation - {err}");
+            true
+        })
+    }
+}
+
+/// Generate a new random session ID.
+pub(crate) fn generate_session_id() -> Result<String, Error> {
+    let mut id = [0u8; 16];
+    openssl::rand::rand_bytes(&mut id)?;
+    Ok(id.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Fail if the ticket belongs to a revoked session.
+pub(crate) fn check_session(ticket: &ApiTicket) -> Result<(), Error> {
+    let Some(session_id) = ticket.session_id() else {
+        return Ok(());
+    };
+
+    if let Some(store) = auth_context()?.session_store() {
+        if store.is_revoked(session_id) {
+            bail!("session has been revoked");
+        }
+    }
+    Ok(())
+}
+
+fn session_store() -> Result<&'static dyn SessionStore, Error> {
+    match auth_context()?.session_store() {
+        Some(store) => Ok(store),
+        None => bail!("sessions are not tracked"),
+    }
+}
+
+#[api(
+    input: {
+        properties: {
+            userid: {
+                type: Userid,
+                optional: true,
+            },
+        },
+    },
+    returns: {
+        description: "List of active sessions.",
+        type: Array,
+        items: { type: SessionInfo },
+    },
+    access: {
+        description: "Users can list their own sessions.",
+        permission: &Permission::Or(&[
+            &Permission::Superuser,
+            &Permission::UserParam("userid"),
+        ]),
+    },
+)]
+/// List active sessions, optionally only those of a single user.
+pub fn list_sessions(userid: Option<Userid>) -> Result<Vec<SessionInfo>, Error> {
+    session_store()?.list_sessions(userid.as_ref())
+}
+
+#[api(
+    input: {
+        properties: {
+            userid: {
+                type: Userid,
+            },
+            id: {
+                type: String,
+                description: "The ID of the session to revoke.",
+                optional: true,
+            },
+        },
+    },
+    protected: true,
+    access: {
+        description: "Users can revoke their own sessions.",
+        permission: &Permission::Or(&[
+            &Permission::Superuser,
+            &Permission::UserParam("userid"),
+        ]),
+    },
+)]
+/// Revoke a session of a user, or all of the user's sessions if no session ID is given.
+pub fn revoke_sessions(userid: Userid, id: Option<String>) -> Result<(), Error> {
+    let store = session_store()?;
+
+    let sessions = store.list_sessions(Some(&userid))?;
+    match id {
+        Some(id) => {
+            if !sessions.iter().any(|session| session.id == id) {
+                bail!("no such session");
+            }
+            store.revoke_session(&id)?;
+        }
+        None => {
+            for session in sessions {
+                store.revoke_session(&session.id)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, userid: &str, renewed: i64) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            userid: userid.parse().unwrap(),
+            client_ip: None,
+            user_agent: None,
+            created: renewed,
+            renewed,
+        }
+    }
+
+    #[test]
+    fn test_memory_session_store() {
+        let now = crate::time::epoch_i64();
+        let store = MemorySessionStore::new();
+
+        store.store_session(&session("a", "a@pam", now)).unwrap();
+        store.store_session(&session("b", "b@pam", now)).unwrap();
+        store
+            .store_session(&session("c", "a@pam", now + 1))
+            .unwrap();
+        store
+            .store_session(&session("old", "a@pam", now - 2 * TICKET_LIFETIME))
+            .unwrap();
+
+        assert_eq!(store.list_sessions(None).unwrap().len(), 3);
+        let userid: Userid = "a@pam".parse().unwrap();
+        let ids: Vec<String> = store
+            .list_sessions(Some(&userid))
+            .unwrap()
+            .into_iter()
+            .map(|session| session.id)
+            .collect();
+        assert_eq!(ids, ["a", "c"]);
+
+        assert!(!store.is_revoked("a"));
+        assert!(store.revoke_session("a").unwrap().is_some());
+        assert!(store.is_revoked("a"));
+        assert!(!store.is_revoked("c"));
+        assert_eq!(store.list_sessions(Some(&userid)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_file_session_store() {
+        let dir =
+            std::env::temp_dir().join(format!("proxmox-auth-sessions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sessions.json");
+        let _ = std::fs::remove_file(&path);
+
+        let now = crate::time::epoch_i64();
+        let store = FileSessionStore::new(&path, CreateOptions::new());
+        // a second instance, as used by another daemon
+        let other = FileSessionStore::new(&path, CreateOptions::new());
+
+        assert!(!store.is_revoked("a"));
+        store.store_session(&session("a", "a@pam", now)).unwrap();
+        store.store_session(&session("b", "b@pam", now)).unwrap();
+        store
+            .store_session(&session("old", "a@pam", now - 2 * TICKET_LIFETIME))
+            .unwrap();
+
+        assert_eq!(other.list_sessions(None).unwrap().len(), 2);
+        assert!(!other.is_revoked("a"));
+
+        assert!(store.revoke_session("a").unwrap().is_some());
+        assert!(other.is_revoked("a"));
+        assert!(!other.is_revoked("b"));
+
+        // the state survives a restart
+        let restarted = FileSessionStore::new(&path, CreateOptions::new());
+        assert!(restarted.is_revoked("a"));
+        let userid: Userid = "b@pam".parse().unwrap();
+        assert_eq!(restarted.list_sessions(Some(&userid)).unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_file_session_store_change_counter() {
+        // proxmox-shared-memory skips the tmpfs check for directories named 'shmemtest'
+        let dir = std::env::temp_dir()
+            .join(format!(
+                "proxmox-auth-sessions-counter-{}",
+                std::process::id()
+            ))
+            .join("shmemtest");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sessions.json");
+        let counter = dir.join("sessions.counter");
+
+        let now = crate::time::epoch_i64();
+        let open = || {
+            FileSessionStore::new(&path, CreateOptions::new())
+                .with_change_counter(&counter)
+                .unwrap()
+        };
+        let store = open();
+        let other = open();
+
+        store.store_session(&session("a", "a@pam", now)).unwrap();
+        assert!(!other.is_revoked("a"));
+        assert!(store.revoke_session("a").unwrap().is_some());
+        assert!(other.is_revoked("a"));
+        assert!(open().is_revoked("a"));
+
+        let _ = std::fs::remove_dir_all(dir.parent().unwrap());
+    }
+
+    #[test]
+    fn test_session_ticket_data() {
+        let ticket: ApiTicket = "!session!0123abcd!user@pam".parse().unwrap();
+        assert_eq!(ticket.session_id(), Some("0123abcd"));
+        assert_eq!(ticket.to_string(), "!session!0123abcd!user@pam");
+        assert_eq!(ticket.require_full().unwrap(), "user@pam");
+
+        let ticket: ApiTicket = "user@pam".parse().unwrap();
+        assert_eq!(ticket.session_id(), None);
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::fmt;
 
-use anyhow::{bail, Error};
+use anyhow::{bail, format_err, Error};
 use serde::{Deserialize, Serialize};
 
 use proxmox_tfa::api::TfaChallenge;
@@ -24,22 +24,32 @@ pub struct PartialTicket {
 pub enum ApiTicket {
     Full(Userid),
     Partial(Box<TfaChallenge>),
+    /// A full ticket belonging to a session of the [`SessionStore`](super::SessionStore).
+    Session(Userid, String),
 }
 
 impl ApiTicket {
     /// Require the ticket to be a full ticket, otherwise error with a meaningful error message.
     pub fn require_full(self) -> Result<Userid, Error> {
         match self {
-            ApiTicket::Full(userid) => Ok(userid),
+            ApiTicket::Full(userid) | ApiTicket::Session(userid, _) => Ok(userid),
             ApiTicket::Partial(_) => bail!("access denied - second login factor required"),
         }
     }
 
+    /// The session ID, if the ticket belongs to a session.
+    pub fn session_id(&self) -> Option<&str> {
+        match self {
+            ApiTicket::Session(_, session_id) => Some(session_id),
+            _ => None,
+        }
+    }
+
     /// Expect the ticket to contain a tfa challenge, otherwise error with a meaningful error
     /// message.
     pub fn require_partial(self) -> Result<Box<TfaChallenge>, Error> {
         match self {
-            ApiTicket::Full(_) => bail!("invalid tfa challenge"),
+            ApiTicket::Full(_) | ApiTicket::Session(..) => bail!("invalid tfa challenge"),
             ApiTicket::Partial(challenge) => Ok(challenge),
         }
     }
@@ -53,6 +63,7 @@ impl fmt::Display for ApiTicket {
                 let data = serde_json::to_string(partial).map_err(|_| fmt::Error)?;
                 write!(f, "!tfa!{}", data)
             }
+            ApiTicket::Session(userid, session_id) => write!(f, "!session!{session_id}!{userid}"),
         }
     }
 }
@@ -63,6 +74,11 @@ impl std::str::FromStr for ApiTicket {
     fn from_str(s: &str) -> Result<Self, Error> {
         if let Some(tfa_ticket) = s.strip_prefix("!tfa!") {
             Ok(ApiTicket::Partial(serde_json::from_str(tfa_ticket)?))
+        } else if let Some(session_ticket) = s.strip_prefix("!session!") {
+            let (session_id, userid) = session_ticket
+                .split_once('!')
+                .ok_or_else(|| format_err!("invalid session ticket"))?;
+            Ok(ApiTicket::Session(userid.parse()?, session_id.to_string()))
         } else {
             Ok(ApiTicket::Full(s.parse()?))
         }
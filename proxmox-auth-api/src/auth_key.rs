@@ -1,11 +1,15 @@
 //! Auth key handling.
 
+use std::sync::RwLock;
+
 use anyhow::{bail, format_err, Error};
 use openssl::hash::MessageDigest;
 use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
 use openssl::rsa::Rsa;
 use openssl::sign::{Signer, Verifier};
 
+use crate::TICKET_LIFETIME;
+
 /// A private auth key used for API ticket signing and verification.
 #[derive(Clone)]
 pub struct PrivateKey {
@@ -210,6 +214,10 @@ impl HMACKey {
     // TODO: remove once all dependent products had a major version release (PBS)
     #[cfg(feature = "api")]
     pub(crate) fn as_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.as_raw_bytes()
+    }
+
+    fn as_raw_bytes(&self) -> Result<Vec<u8>, Error> {
         // workaround to get access to the the bytes behind the key.
         self.key
             .raw_private_key()
@@ -217,24 +225,202 @@ impl HMACKey {
     }
 }
 
+/// Derive a short, stable key ID from key material.
+fn key_id_from_bytes(data: &[u8]) -> String {
+    openssl::sha::sha256(data)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
 enum SigningKey {
     Private(PrivateKey),
     Hmac(HMACKey),
 }
 
+impl SigningKey {
+    fn key_id(&self) -> Option<String> {
+        match self {
+            SigningKey::Private(key) => key.key.public_key_to_der().ok(),
+            SigningKey::Hmac(key) => key.as_raw_bytes().ok(),
+        }
+        .map(|data| key_id_from_bytes(&data))
+    }
+
+    /// Generate a new key of the same kind.
+    fn generate_successor(&self) -> Result<Self, Error> {
+        Ok(match self {
+            SigningKey::Private(key) if key.key.id() == Id::ED25519 => {
+                SigningKey::Private(PrivateKey::generate_ec()?)
+            }
+            SigningKey::Private(_) => SigningKey::Private(PrivateKey::generate_rsa()?),
+            SigningKey::Hmac(_) => SigningKey::Hmac(HMACKey::generate()?),
+        })
+    }
+
+    fn into_verification_key(self) -> Result<VerificationKey, Error> {
+        Ok(match self {
+            SigningKey::Private(key) => VerificationKey::Public(key.public_key()?),
+            SigningKey::Hmac(key) => VerificationKey::Hmac(key),
+        })
+    }
+}
+
 enum VerificationKey {
     Public(PublicKey),
     Hmac(HMACKey),
 }
 
+impl VerificationKey {
+    fn key_id(&self) -> Option<String> {
+        match self {
+            VerificationKey::Public(key) => key.key.public_key_to_der().ok(),
+            VerificationKey::Hmac(key) => key.as_raw_bytes().ok(),
+        }
+        .map(|data| key_id_from_bytes(&data))
+    }
+}
+
+/// A key in a [`Keyring`] along with its metadata.
+struct Entry<K> {
+    id: Option<String>,
+    created: Option<i64>,
+    retired: Option<i64>,
+    key: K,
+}
+
+impl Entry<SigningKey> {
+    fn new_signing(key: SigningKey, created: Option<i64>) -> Self {
+        Self {
+            id: key.key_id(),
+            created,
+            retired: None,
+            key,
+        }
+    }
+}
+
+impl Entry<VerificationKey> {
+    fn new_verification(key: VerificationKey) -> Self {
+        Self {
+            id: key.key_id(),
+            created: None,
+            retired: None,
+            key,
+        }
+    }
+}
+
+impl<K> Entry<K> {
+    /// Retired keys are kept until all tickets they signed have expired.
+    fn is_expired(&self, now: i64) -> bool {
+        self.retired
+            .is_some_and(|retired| retired + TICKET_LIFETIME < now)
+    }
+
+    fn info(&self, signing: bool) -> KeyInfo {
+        KeyInfo {
+            id: self.id.clone(),
+            signing,
+            created: self.created,
+            retired: self.retired,
+        }
+    }
+}
+
+/// Metadata of a key in a [`Keyring`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyInfo {
+    /// The ID of the key, which is embedded into tickets signed with it.
+    pub id: Option<String>,
+
+    /// Whether this is the key used to sign new tickets.
+    pub signing: bool,
+
+    /// The time the key was created, if known.
+    pub created: Option<i64>,
+
+    /// The time the key was replaced by a new signing key. Retired keys are only used to verify
+    /// tickets until those expired.
+    pub retired: Option<i64>,
+}
+
+/// Key data of a [`KeyringEntry`].
+#[derive(Clone)]
+pub enum KeyringKey {
+    Private(PrivateKey),
+    Public(PublicKey),
+    Hmac(HMACKey),
+}
+
+/// A key of a [`Keyring`] along with its metadata. Used to persist and restore a keyring with
+/// [`Keyring::entries`] and [`Keyring::from_entries`].
+#[derive(Clone)]
+pub struct KeyringEntry {
+    pub info: KeyInfo,
+    pub key: KeyringKey,
+}
+
+#[derive(Default)]
+struct KeyringInner {
+    signing_key: Option<Entry<SigningKey>>,
+    public_keys: Vec<Entry<VerificationKey>>,
+    /// Always embed the ID of the signing key in signatures, see [`Keyring::set_embed_key_id`].
+    embed_key_id: bool,
+}
+
+impl KeyringInner {
+    fn from_entries<I>(entries: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = KeyringEntry>,
+    {
+        let now = crate::time::epoch_i64();
+        let mut inner = KeyringInner::default();
+
+        for entry in entries {
+            if entry.info.signing {
+                if inner.signing_key.is_some() {
+                    bail!("keyring contains multiple signing keys");
+                }
+                let key = match entry.key {
+                    KeyringKey::Private(key) => SigningKey::Private(key),
+                    KeyringKey::Hmac(key) => SigningKey::Hmac(key),
+                    KeyringKey::Public(_) => bail!("cannot sign tickets with a public key"),
+                };
+                inner.signing_key = Some(Entry::new_signing(key, entry.info.created));
+                continue;
+            }
+
+            let key = match entry.key {
+                KeyringKey::Private(key) => VerificationKey::Public(key.public_key()?),
+                KeyringKey::Public(key) => VerificationKey::Public(key),
+                KeyringKey::Hmac(key) => VerificationKey::Hmac(key),
+            };
+            let entry = Entry {
+                created: entry.info.created,
+                retired: entry.info.retired,
+                ..Entry::new_verification(key)
+            };
+            if !entry.is_expired(now) {
+                inner.public_keys.push(entry);
+            }
+        }
+
+        Ok(inner)
+    }
+}
+
 /// A key ring for authentication.
 ///
 /// This can hold one active signing key for new tickets (either an HMAC secret or an asymmetric
 /// key), and optionally multiple public keys and HMAC secrets for verifying them in order to
 /// support key rollover.
+///
+/// Keys are identified by an ID derived from the key material, which is embedded into signed
+/// tickets. The signing key can be rotated via [`rotate`](Keyring::rotate()), in which case the
+/// previous key is kept for verification until all tickets signed with it have expired.
 pub struct Keyring {
-    signing_key: Option<SigningKey>,
-    public_keys: Vec<VerificationKey>,
+    inner: RwLock<KeyringInner>,
 }
 
 impl Default for Keyring {
@@ -256,40 +442,184 @@ impl Keyring {
         HMACKey::generate().map(Self::with_hmac_key)
     }
 
-    pub fn new() -> Self {
+    fn from_inner(inner: KeyringInner) -> Self {
         Self {
-            signing_key: None,
-            public_keys: Vec::new(),
+            inner: RwLock::new(inner),
         }
     }
 
+    pub fn new() -> Self {
+        Self::from_inner(KeyringInner::default())
+    }
+
     pub fn with_public_key(key: PublicKey) -> Self {
-        Self {
+        Self::from_inner(KeyringInner {
             signing_key: None,
-            public_keys: vec![VerificationKey::Public(key)],
-        }
+            public_keys: vec![Entry::new_verification(VerificationKey::Public(key))],
+            ..Default::default()
+        })
     }
 
     pub fn with_private_key(key: PrivateKey) -> Self {
-        Self {
-            signing_key: Some(SigningKey::Private(key)),
+        Self::from_inner(KeyringInner {
+            signing_key: Some(Entry::new_signing(SigningKey::Private(key), None)),
             public_keys: Vec::new(),
-        }
+            ..Default::default()
+        })
     }
 
     pub fn with_hmac_key(key: HMACKey) -> Self {
-        Self {
-            signing_key: Some(SigningKey::Hmac(key)),
+        Self::from_inner(KeyringInner {
+            signing_key: Some(Entry::new_signing(SigningKey::Hmac(key), None)),
             public_keys: Vec::new(),
-        }
+            ..Default::default()
+        })
+    }
+
+    /// Restore a keyring from entries previously returned by [`entries`](Keyring::entries()).
+    ///
+    /// Retired keys whose tickets have all expired are dropped.
+    pub fn from_entries<I>(entries: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = KeyringEntry>,
+    {
+        KeyringInner::from_entries(entries).map(Self::from_inner)
+    }
+
+    /// Replace all keys with the provided entries, like [`from_entries`](Keyring::from_entries()),
+    /// but in place.
+    ///
+    /// This lets daemons pick up a rotation persisted by another daemon sharing the keys. On
+    /// error, the keyring is left unchanged.
+    pub fn reload_entries<I>(&self, entries: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = KeyringEntry>,
+    {
+        let reloaded = KeyringInner::from_entries(entries)?;
+        let mut inner = self.inner.write().unwrap();
+        inner.signing_key = reloaded.signing_key;
+        inner.public_keys = reloaded.public_keys;
+        Ok(())
     }
 
     pub fn add_public_key(&mut self, key: PublicKey) {
-        self.public_keys.push(VerificationKey::Public(key));
+        self.inner
+            .get_mut()
+            .unwrap()
+            .public_keys
+            .push(Entry::new_verification(VerificationKey::Public(key)));
     }
 
     pub fn add_hmac_key(&mut self, key: HMACKey) {
-        self.public_keys.push(VerificationKey::Hmac(key));
+        self.inner
+            .get_mut()
+            .unwrap()
+            .public_keys
+            .push(Entry::new_verification(VerificationKey::Hmac(key)));
+    }
+
+    /// Get the metadata of all keys, starting with the signing key.
+    pub fn key_info(&self) -> Vec<KeyInfo> {
+        let inner = self.inner.read().unwrap();
+        inner
+            .signing_key
+            .iter()
+            .map(|entry| entry.info(true))
+            .chain(inner.public_keys.iter().map(|entry| entry.info(false)))
+            .collect()
+    }
+
+    /// Get all keys along with their metadata, for instance to persist a rotated keyring.
+    pub fn entries(&self) -> Vec<KeyringEntry> {
+        let inner = self.inner.read().unwrap();
+        let signing = inner.signing_key.iter().map(|entry| KeyringEntry {
+            info: entry.info(true),
+            key: match &entry.key {
+                SigningKey::Private(key) => KeyringKey::Private(key.clone()),
+                SigningKey::Hmac(key) => KeyringKey::Hmac(key.clone()),
+            },
+        });
+        let verification = inner.public_keys.iter().map(|entry| KeyringEntry {
+            info: entry.info(false),
+            key: match &entry.key {
+                VerificationKey::Public(key) => KeyringKey::Public(key.clone()),
+                VerificationKey::Hmac(key) => KeyringKey::Hmac(key.clone()),
+            },
+        });
+        signing.chain(verification).collect()
+    }
+
+    /// Always embed the ID of the signing key in tickets.
+    ///
+    /// By default, the ID is only embedded while the keyring holds more than one key, so that
+    /// tickets of a keyring which was never rotated keep the format older verifiers understand.
+    /// Products rotating their keys can enable this to always get the ID.
+    pub fn set_embed_key_id(&self, enable: bool) {
+        self.inner.write().unwrap().embed_key_id = enable;
+    }
+
+    /// Check whether the signing key is older than `max_age` seconds. Keys of unknown age are
+    /// always due for rotation.
+    pub fn rotation_due(&self, max_age: i64) -> bool {
+        let now = crate::time::epoch_i64();
+        self.inner
+            .read()
+            .unwrap()
+            .signing_key
+            .as_ref()
+            .is_some_and(|entry| entry.created.is_none_or(|created| now - created >= max_age))
+    }
+
+    /// Replace the signing key with a newly generated key of the same kind.
+    ///
+    /// The previous signing key is kept for verification until all tickets signed with it have
+    /// expired. Returns the metadata of the new signing key.
+    pub fn rotate(&self) -> Result<KeyInfo, Error> {
+        let key = match &self.inner.read().unwrap().signing_key {
+            Some(entry) => entry.key.generate_successor()?,
+            None => bail!("no signing key available to rotate"),
+        };
+        self.rotate_to(key)
+    }
+
+    /// Replace the signing key with the provided key, see [`rotate`](Keyring::rotate()).
+    pub fn rotate_with(&self, key: KeyringKey) -> Result<KeyInfo, Error> {
+        let key = match key {
+            KeyringKey::Private(key) => SigningKey::Private(key),
+            KeyringKey::Hmac(key) => SigningKey::Hmac(key),
+            KeyringKey::Public(_) => bail!("cannot sign tickets with a public key"),
+        };
+        self.rotate_to(key)
+    }
+
+    fn rotate_to(&self, key: SigningKey) -> Result<KeyInfo, Error> {
+        let now = crate::time::epoch_i64();
+        let new_entry = Entry::new_signing(key, Some(now));
+        let info = new_entry.info(true);
+
+        let mut inner = self.inner.write().unwrap();
+        if let Some(old) = inner.signing_key.take() {
+            let retired = Entry {
+                id: old.id,
+                created: old.created,
+                retired: Some(now),
+                key: old.key.into_verification_key()?,
+            };
+            inner.public_keys.push(retired);
+        }
+        inner.signing_key = Some(new_entry);
+        inner.public_keys.retain(|entry| !entry.is_expired(now));
+
+        Ok(info)
+    }
+
+    /// Drop retired keys whose tickets have all expired. Returns the number of removed keys.
+    pub fn prune(&self) -> usize {
+        let now = crate::time::epoch_i64();
+        let mut inner = self.inner.write().unwrap();
+        let count = inner.public_keys.len();
+        inner.public_keys.retain(|entry| !entry.is_expired(now));
+        count - inner.public_keys.len()
     }
 
     pub fn verify(
@@ -297,6 +627,18 @@ impl Keyring {
         digest: MessageDigest,
         signature: &[u8],
         data: &[u8],
+    ) -> Result<bool, Error> {
+        self.verify_with_key_id(None, digest, signature, data)
+    }
+
+    /// Verify a signature. If a key ID is provided, only the key with that ID is tried, otherwise
+    /// all keys are tried in turn.
+    pub fn verify_with_key_id(
+        &self,
+        key_id: Option<&str>,
+        digest: MessageDigest,
+        signature: &[u8],
+        data: &[u8],
     ) -> Result<bool, Error> {
         fn verify_with<P: HasPublic>(
             key: &PKeyRef<P>,
@@ -317,8 +659,13 @@ impl Keyring {
                 .map_err(|err| format_err!("openssl error verifying data - {err}"))
         }
 
-        if let Some(key) = &self.signing_key {
-            match key {
+        let matches_id =
+            |id: &Option<String>| key_id.is_none_or(|key_id| id.as_deref() == Some(key_id));
+
+        let inner = self.inner.read().unwrap();
+
+        if let Some(entry) = inner.signing_key.as_ref().filter(|e| matches_id(&e.id)) {
+            match &entry.key {
                 SigningKey::Private(key) if verify_with(&key.key, digest, signature, data)? => {
                     return Ok(true)
                 }
@@ -327,8 +674,12 @@ impl Keyring {
             }
         }
 
-        for key in &self.public_keys {
-            match key {
+        let now = crate::time::epoch_i64();
+        for entry in &inner.public_keys {
+            if !matches_id(&entry.id) || entry.is_expired(now) {
+                continue;
+            }
+            match &entry.key {
                 VerificationKey::Public(key) if verify_with(&key.key, digest, signature, data)? => {
                     return Ok(true)
                 }
@@ -342,15 +693,120 @@ impl Keyring {
         Ok(false)
     }
 
-    pub(crate) fn sign(&self, digest: MessageDigest, data: &[u8]) -> Result<Vec<u8>, Error> {
-        let signing_key = self
+    /// Sign data with the signing key. Returns the signature along with the ID of the key if it
+    /// needs to be embedded, see [`set_embed_key_id`](Keyring::set_embed_key_id()).
+    pub(crate) fn sign(
+        &self,
+        digest: MessageDigest,
+        data: &[u8],
+    ) -> Result<(Option<String>, Vec<u8>), Error> {
+        let inner = self.inner.read().unwrap();
+        let signing_key = inner
             .signing_key
             .as_ref()
             .ok_or_else(|| format_err!("no private key available for signing"))?;
 
-        match signing_key {
-            SigningKey::Private(key) => key.sign(digest, data),
-            SigningKey::Hmac(key) => key.sign(digest, data),
-        }
+        let signature = match &signing_key.key {
+            SigningKey::Private(key) => key.sign(digest, data)?,
+            SigningKey::Hmac(key) => key.sign(digest, data)?,
+        };
+        let key_id = if inner.embed_key_id || !inner.public_keys.is_empty() {
+            signing_key.id.clone()
+        } else {
+            None
+        };
+        Ok((key_id, signature))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sign_and_verify(signer: &Keyring, verifier: &Keyring) -> bool {
+        let (key_id, signature) = signer.sign(MessageDigest::sha256(), b"data").unwrap();
+        verifier
+            .verify_with_key_id(
+                key_id.as_deref(),
+                MessageDigest::sha256(),
+                &signature,
+                b"data",
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_keyring_rotation() {
+        let keyring = Keyring::generate_new_ec().expect("failed to generate EC key for testing");
+        assert!(keyring.rotation_due(3600));
+
+        // the ID of a single key is not embedded
+        let old_id = keyring.key_info()[0].id.clone();
+        let (key_id, old_signature) = keyring.sign(MessageDigest::sha256(), b"data").unwrap();
+        assert!(old_id.is_some());
+        assert!(key_id.is_none());
+
+        let info = keyring.rotate().expect("failed to rotate keyring");
+        assert!(info.signing);
+        assert_ne!(info.id, old_id);
+        assert!(!keyring.rotation_due(3600));
+
+        // the retired key still verifies old signatures, but only with the matching id
+        let keys = keyring.key_info();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1].id, old_id);
+        assert!(keys[1].retired.is_some());
+        assert!(keyring
+            .verify_with_key_id(
+                old_id.as_deref(),
+                MessageDigest::sha256(),
+                &old_signature,
+                b"data"
+            )
+            .unwrap());
+        assert!(!keyring
+            .verify_with_key_id(
+                info.id.as_deref(),
+                MessageDigest::sha256(),
+                &old_signature,
+                b"data"
+            )
+            .unwrap());
+        assert_eq!(
+            keyring.sign(MessageDigest::sha256(), b"data").unwrap().0,
+            info.id
+        );
+        assert!(sign_and_verify(&keyring, &keyring));
+
+        // persisting and restoring keeps the ids
+        let restored = Keyring::from_entries(keyring.entries()).unwrap();
+        assert_eq!(restored.key_info(), keyring.key_info());
+        assert!(sign_and_verify(&keyring, &restored));
+
+        // expired keys are dropped
+        let mut entries = keyring.entries();
+        entries[1].info.retired = Some(crate::time::epoch_i64() - TICKET_LIFETIME - 1);
+        let restored = Keyring::from_entries(entries).unwrap();
+        assert_eq!(restored.key_info().len(), 1);
+    }
+
+    #[test]
+    fn test_keyring_hmac_rotation() {
+        let keyring =
+            Keyring::generate_new_hmac().expect("failed to generate HMAC key for testing");
+        let old = Keyring::from_entries(keyring.entries()).unwrap();
+
+        keyring.rotate().expect("failed to rotate keyring");
+        assert!(sign_and_verify(&old, &keyring));
+        assert!(!sign_and_verify(&keyring, &old));
+
+        // another daemon picks up the rotation
+        old.reload_entries(keyring.entries()).unwrap();
+        assert_eq!(old.key_info(), keyring.key_info());
+        assert!(sign_and_verify(&keyring, &old));
+        assert!(old
+            .reload_entries(keyring.entries().into_iter().chain(keyring.entries()))
+            .is_err());
+        assert_eq!(old.key_info(), keyring.key_info());
     }
 }
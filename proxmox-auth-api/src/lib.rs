@@ -24,7 +24,7 @@ pub use api::set_auth_context;
 mod auth_key;
 
 #[cfg(any(feature = "api", feature = "ticket"))]
-pub use auth_key::{HMACKey, Keyring, PrivateKey, PublicKey};
+pub use auth_key::{HMACKey, KeyInfo, Keyring, KeyringEntry, KeyringKey, PrivateKey, PublicKey};
 
 #[cfg(feature = "ticket")]
 pub mod ticket;
@@ -38,11 +38,13 @@ impl std::str::FromStr for Empty {
 
 /// An API ticket consists of a ticket type (prefix), type-dependent data, optional additional
 /// authenticaztion data, a timestamp and a signature. We store these values in the form
-/// `<prefix>:<stringified data>:<timestamp>::<signature>`.
+/// `<prefix>:<stringified data>:<timestamp>::<signature>`, or
+/// `<prefix>:<stringified data>:<timestamp>::<key id>:<signature>` if the signing key has an ID.
 ///
 /// The signature is made over the string consisting of prefix, data, timestamp and aad joined
 /// together by colons. If there is no additional authentication data it will be skipped together
-/// with the colon separating it from the timestamp.
+/// with the colon separating it from the timestamp. The key ID is not part of the signed data, it
+/// only serves as a hint about which key of a [`Keyring`] to verify the ticket with.
 pub struct Ticket<T>
 where
     T: ToString + std::str::FromStr,
@@ -51,6 +53,7 @@ where
     data: String,
     time: i64,
     signature: Option<Vec<u8>>,
+    key_id: Option<String>,
     _type_marker: PhantomData<fn() -> T>,
 }
 
@@ -66,6 +69,7 @@ where
             data: data.to_string(),
             time: crate::time::epoch_i64(),
             signature: None,
+            key_id: None,
             _type_marker: PhantomData,
         })
     }
@@ -80,6 +84,11 @@ where
         self.time
     }
 
+    /// Get the ID of the key the ticket was signed with, if any.
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
     /// Get the raw string data contained in the ticket. The `verify` method will call `parse()`
     /// this in the end, so using this method directly is discouraged as it does not verify the
     /// signature.
@@ -117,18 +126,19 @@ where
     /// Sign the ticket.
     pub fn sign(&mut self, keyring: &Keyring, aad: Option<&str>) -> Result<String, Error> {
         let mut output = self.ticket_data();
-        let signature = keyring
+        let (key_id, signature) = keyring
             .sign(MessageDigest::sha256(), &self.verification_data(aad))
             .map_err(|err| format_err!("error signing ticket: {}", err))?;
 
         use std::fmt::Write;
-        write!(
-            &mut output,
-            "::{}",
-            proxmox_base64::encode_no_pad(&signature),
-        )?;
+        output.push_str("::");
+        if let Some(key_id) = &key_id {
+            write!(&mut output, "{key_id}:")?;
+        }
+        write!(&mut output, "{}", proxmox_base64::encode_no_pad(&signature))?;
 
         self.signature = Some(signature);
+        self.key_id = key_id;
 
         Ok(output)
     }
@@ -159,7 +169,8 @@ where
             bail!("invalid ticket - expired");
         }
 
-        let is_valid = keyring.verify(
+        let is_valid = keyring.verify_with_key_id(
+            self.key_id.as_deref(),
             MessageDigest::sha256(),
             signature,
             &self.verification_data(aad),
@@ -214,10 +225,17 @@ where
             .ok_or_else(|| format_err!("ticket without signature"))?;
         // <prefix>:<data>:<time>::signature - the 4th `.next()` swallows the first colon in the
         // double-colon!
-        if !remainder.starts_with(':') {
-            bail!("ticket without signature separator");
-        }
-        let signature = proxmox_base64::decode_no_pad(&remainder[1..])
+        let remainder = remainder
+            .strip_prefix(':')
+            .ok_or_else(|| format_err!("ticket without signature separator"))?;
+
+        // the signature may be preceded by the ID of the key used to create it
+        let (key_id, signature) = match remainder.split_once(':') {
+            Some((key_id, signature)) => (Some(key_id.to_string()), signature),
+            None => (None, remainder),
+        };
+
+        let signature = proxmox_base64::decode_no_pad(signature)
             .map_err(|err| format_err!("ticket with bad signature: {}", err))?;
 
         Ok(Self {
@@ -225,6 +243,7 @@ where
             data: data.into_owned(),
             time,
             signature: Some(signature),
+            key_id,
             _type_marker: PhantomData,
         })
     }
@@ -337,4 +356,69 @@ mod test {
             false
         });
     }
+
+    #[test]
+    fn test_tickets_single_key_format() {
+        let keyring = Keyring::generate_new_ec().expect("failed to generate EC key for testing");
+        let userid = Testid("root".to_string());
+
+        // without rotation, tickets keep the `<data>::<signature>` format
+        let ticket = Ticket::new("PREFIX", &userid)
+            .expect("failed to create Ticket struct")
+            .sign(&keyring, None)
+            .expect("failed to sign test ticket");
+        let (_, signature) = ticket.split_once("::").unwrap();
+        assert!(!signature.contains(':'));
+        let parsed = Ticket::<Testid>::parse(&ticket).expect("failed to parse test ticket");
+        assert!(parsed.key_id().is_none());
+        parsed.verify(&keyring, "PREFIX", None).unwrap();
+
+        keyring.set_embed_key_id(true);
+        let ticket = Ticket::new("PREFIX", &userid)
+            .unwrap()
+            .sign(&keyring, None)
+            .unwrap();
+        let parsed = Ticket::<Testid>::parse(&ticket).unwrap();
+        assert_eq!(parsed.key_id(), keyring.key_info()[0].id.as_deref());
+    }
+
+    #[test]
+    fn test_tickets_key_rotation() {
+        let keyring = Keyring::generate_new_ec().expect("failed to generate EC key for testing");
+        keyring.set_embed_key_id(true);
+        let userid = Testid("root".to_string());
+
+        let old_ticket = Ticket::new("PREFIX", &userid)
+            .expect("failed to create Ticket struct")
+            .sign(&keyring, None)
+            .expect("failed to sign test ticket");
+        let parsed = Ticket::<Testid>::parse(&old_ticket).expect("failed to parse test ticket");
+        assert_eq!(parsed.key_id(), keyring.key_info()[0].id.as_deref());
+
+        keyring.rotate().expect("failed to rotate keyring");
+
+        // tickets signed with the retired key stay valid
+        let check: Testid = Ticket::<Testid>::parse(&old_ticket)
+            .unwrap()
+            .verify(&keyring, "PREFIX", None)
+            .expect("failed to verify ticket after key rotation");
+        assert_eq!(check, userid);
+
+        // tickets without a key id are checked against all keys
+        let legacy = old_ticket.replacen(&format!("::{}:", parsed.key_id().unwrap()), "::", 1);
+        let parsed = Ticket::<Testid>::parse(&legacy).expect("failed to parse legacy ticket");
+        assert!(parsed.key_id().is_none());
+        parsed
+            .verify(&keyring, "PREFIX", None)
+            .expect("failed to verify ticket without key id");
+
+        // ... until the retired key expires
+        let mut entries = keyring.entries();
+        entries[1].info.retired = Some(crate::time::epoch_i64() - crate::TICKET_LIFETIME - 1);
+        let keyring = Keyring::from_entries(entries).unwrap();
+        Ticket::<Testid>::parse(&old_ticket)
+            .unwrap()
+            .verify(&keyring, "PREFIX", None)
+            .expect_err("ticket of an expired key verified successfully");
+    }
 }
@@ -741,6 +741,266 @@ impl CreateTicketResponse {
     }
 }
 
+#[api]
+/// The outcome of a login attempt.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoginResult {
+    /// The user was authenticated successfully.
+    Success,
+    /// Authentication failed.
+    Failure,
+    /// The attempt was rejected because the user or client address was locked out.
+    Locked,
+}
+
+#[api]
+/// The second factor used in a login attempt.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoginTfaMethod {
+    /// A TOTP code.
+    Totp,
+    /// A HOTP code.
+    Hotp,
+    /// A U2F challenge response.
+    U2f,
+    /// A Yubico OTP.
+    Yubico,
+    /// A WebAuthn challenge response.
+    Webauthn,
+    /// A recovery key.
+    Recovery,
+}
+
+impl LoginTfaMethod {
+    /// Determine the method from a TFA challenge response as passed to the ticket API.
+    pub fn from_response(response: &str) -> Option<Self> {
+        let (method, _) = response.split_once(':')?;
+        Some(match method {
+            "totp" => Self::Totp,
+            "hotp" => Self::Hotp,
+            "u2f" => Self::U2f,
+            "yubico" => Self::Yubico,
+            "webauthn" => Self::Webauthn,
+            "recovery" => Self::Recovery,
+            _ => return None,
+        })
+    }
+}
+
+#[api]
+/// The kind of ticket handed out after a login attempt.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoginTicketType {
+    /// A full ticket.
+    Full,
+    /// A partial ticket which still requires a second factor.
+    Partial,
+}
+
+#[api(
+    properties: {
+        "client-ip": {
+            schema: proxmox_schema::api_types::IP_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+/// An entry of the login audit log.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LoginAttempt {
+    /// Time of the attempt (epoch).
+    pub time: i64,
+
+    /// The user trying to log in.
+    pub userid: Userid,
+
+    /// The realm of the user.
+    pub realm: Realm,
+
+    /// The address the request came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+
+    /// The outcome of the attempt.
+    pub result: LoginResult,
+
+    /// The second factor used, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tfa_method: Option<LoginTfaMethod>,
+
+    /// The ticket handed out, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket_type: Option<LoginTicketType>,
+}
+
+#[api(
+    properties: {
+        "client-ip": {
+            schema: proxmox_schema::api_types::IP_SCHEMA,
+            optional: true,
+        },
+        limit: {
+            optional: true,
+            minimum: 1,
+        },
+    },
+)]
+/// Filter for querying the login audit log.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LoginAttemptFilter {
+    /// Only list attempts of this user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub userid: Option<Userid>,
+
+    /// Only list attempts for this realm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub realm: Option<Realm>,
+
+    /// Only list attempts from this address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+
+    /// Only list attempts with this outcome.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<LoginResult>,
+
+    /// Only list attempts made at or after this time (epoch).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<i64>,
+
+    /// Only list attempts made before this time (epoch).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+
+    /// Return at most this many entries, newest first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+impl LoginAttemptFilter {
+    /// Check whether an entry matches this filter. The `limit` is not considered here.
+    pub fn matches(&self, attempt: &LoginAttempt) -> bool {
+        self.userid.as_ref().is_none_or(|u| *u == attempt.userid)
+            && self.realm.as_ref().is_none_or(|r| *r == attempt.realm)
+            && self
+                .client_ip
+                .as_ref()
+                .is_none_or(|ip| Some(ip) == attempt.client_ip.as_ref())
+            && self.result.is_none_or(|r| r == attempt.result)
+            && self.since.is_none_or(|t| attempt.time >= t)
+            && self.until.is_none_or(|t| attempt.time < t)
+    }
+}
+
+#[api(
+    properties: {
+        "max-failures": {
+            minimum: 1,
+        },
+    },
+)]
+/// Lockout threshold for failed login attempts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockoutThreshold {
+    /// Number of failed attempts after which logins are locked.
+    pub max_failures: u32,
+
+    /// Time window in seconds in which failures are counted.
+    pub window: u64,
+
+    /// Duration of the lockout in seconds.
+    pub duration: u64,
+}
+
+#[api(
+    properties: {
+        user: {
+            type: LockoutThreshold,
+            optional: true,
+        },
+        "client-ip": {
+            type: LockoutThreshold,
+            optional: true,
+        },
+    },
+)]
+/// Lockout policy of a realm.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockoutPolicy {
+    /// Lock a user after too many failed attempts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<LockoutThreshold>,
+
+    /// Lock a client address after too many failed attempts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<LockoutThreshold>,
+}
+
+#[api(
+    properties: {
+        "client-ip": {
+            schema: proxmox_schema::api_types::IP_SCHEMA,
+            optional: true,
+        },
+    },
+)]
+/// An active login session.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SessionInfo {
+    /// The session ID.
+    pub id: String,
+
+    /// The user owning the session.
+    pub userid: Userid,
+
+    /// The address the session was created from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+
+    /// The user agent the session was created with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// Creation time of the session (epoch).
+    pub created: i64,
+
+    /// Time the session's ticket was last renewed (epoch).
+    pub renewed: i64,
+}
+
+#[api]
+/// A ticket signing or verification key.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuthKeyInfo {
+    /// The key ID embedded into tickets signed with this key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    /// Whether this key is used to sign new tickets.
+    pub signing: bool,
+
+    /// Creation time of the key (epoch), if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<i64>,
+
+    /// Time the key was replaced by a newer signing key (epoch).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retired: Option<i64>,
+
+    /// Time after which a retired key is no longer used to verify tickets (epoch).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire: Option<i64>,
+}
+
 #[test]
 fn test_token_id() {
     let userid: Userid = "test@pam".parse().expect("parsing Userid failed");
@@ -769,6 +1029,24 @@ fn test_token_id() {
     assert_eq!(auth_id.to_string(), "test@pam!bar".to_string());
 }
 
+#[test]
+fn test_login_tfa_method() {
+    for (response, method) in [
+        ("totp:123456", LoginTfaMethod::Totp),
+        ("hotp:123456", LoginTfaMethod::Hotp),
+        (
+            "yubico:cccccckdvvulethkhtvkrtbeukiettkbdkvlguhkivlr",
+            LoginTfaMethod::Yubico,
+        ),
+        ("webauthn:{}", LoginTfaMethod::Webauthn),
+        ("recovery:abcd-ef01", LoginTfaMethod::Recovery),
+    ] {
+        assert_eq!(LoginTfaMethod::from_response(response), Some(method));
+    }
+    assert_eq!(LoginTfaMethod::from_response("password"), None);
+    assert_eq!(LoginTfaMethod::from_response("other:123"), None);
+}
+
 serde_plain::derive_deserialize_from_fromstr!(Userid, "valid user id");
 serde_plain::derive_serialize_from_display!(Userid);
 
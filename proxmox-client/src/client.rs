@@ -24,6 +24,9 @@ use crate::error::ParseFingerprintError;
 use crate::{Error, Token};
 
 use super::{HttpApiClient, HttpApiResponse, HttpApiResponseStream};
+use super::{HttpApiUploadClient, UploadStream};
+
+const JSON_CONTENT_TYPE: &str = "application/json";
 
 /// See [`set_verify_callback`](openssl::ssl::SslContextBuilder::set_verify_callback()).
 pub type TlsCallback = dyn Fn(bool, &mut x509::X509StoreContextRef) -> bool + Send + Sync + 'static;
@@ -66,7 +69,7 @@ impl TlsOptions {
 /// A Proxmox API client base backed by a [`proxmox_http::client::Client`].
 pub struct Client {
     api_url: Uri,
-    auth: Mutex<Option<Arc<AuthenticationKind>>>,
+    auth: Arc<Mutex<Option<Arc<AuthenticationKind>>>>,
     client: Arc<proxmox_http::client::Client>,
     pve_compat: bool,
     cookie_name: Option<String>,
@@ -89,7 +92,7 @@ impl Client {
     pub fn with_client(api_url: Uri, client: Arc<proxmox_http::client::Client>) -> Self {
         Self {
             api_url,
-            auth: Mutex::new(None),
+            auth: Arc::new(Mutex::new(None)),
             client,
             pve_compat: false,
             cookie_name: None,
@@ -137,6 +140,12 @@ impl Client {
         Ok(Self::with_client(api_url, Arc::new(client)))
     }
 
+    /// Share the authentication state with another client, so that logging in, refreshing the
+    /// ticket or logging out via either of them affects both.
+    pub(crate) fn share_authentication(&mut self, other: &Client) {
+        self.auth = Arc::clone(&other.auth);
+    }
+
     /// Get the underlying client object.
     pub fn http_client(&self) -> &Arc<proxmox_http::client::Client> {
         &self.client
@@ -220,7 +229,8 @@ impl Client {
         auth: Arc<AuthenticationKind>,
         method: Method,
         uri: Uri,
-        json_body: Option<String>,
+        // the content type and the body
+        body: Option<(&str, Body)>,
         // send an `Accept: application/json-seq` header.
         streaming: bool,
         cookie_name: &Option<String>,
@@ -233,10 +243,10 @@ impl Client {
             request = request.header(http::header::ACCEPT, "application/json-seq");
         }
 
-        let request = if let Some(body) = json_body {
+        let request = if let Some((content_type, body)) = body {
             request
-                .header(http::header::CONTENT_TYPE, "application/json")
-                .body(body.into())
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(body)
         } else {
             request.body(Body::empty())
         }
@@ -274,19 +284,12 @@ impl Client {
         auth: Arc<AuthenticationKind>,
         method: Method,
         uri: Uri,
-        json_body: Option<String>,
+        body: Option<(&str, Body)>,
         cookie_name: &Option<String>,
     ) -> Result<HttpApiResponse, Error> {
-        let (response, body) = Self::send_authenticated_request(
-            client,
-            auth,
-            method,
-            uri,
-            json_body,
-            false,
-            cookie_name,
-        )
-        .await?;
+        let (response, body) =
+            Self::send_authenticated_request(client, auth, method, uri, body, false, cookie_name)
+                .await?;
         let body = read_body(body).await?;
 
         let content_type = match response.headers.get(http::header::CONTENT_TYPE) {
@@ -337,7 +340,7 @@ impl Client {
         Ok(())
     }
 
-    async fn do_login_request(
+    pub(crate) async fn do_login_request(
         &self,
         request: proxmox_login::Request,
     ) -> Result<(Option<Ticket>, Vec<u8>), Error> {
@@ -486,11 +489,11 @@ impl HttpApiClient for Client {
             .transpose();
 
         Box::pin(async move {
-            let params = params?;
+            let body = params?.map(|params| (JSON_CONTENT_TYPE, params.into()));
             let auth = self.login_auth()?;
             let uri = self.build_uri(path_and_query)?;
             let client = Arc::clone(&self.client);
-            Self::authenticated_request(client, auth, method, uri, params, &self.cookie_name).await
+            Self::authenticated_request(client, auth, method, uri, body, &self.cookie_name).await
         })
     }
 
@@ -511,7 +514,7 @@ impl HttpApiClient for Client {
             .transpose();
 
         Box::pin(async move {
-            let params = params?;
+            let body = params?.map(|params| (JSON_CONTENT_TYPE, params.into()));
             let auth = self.login_auth()?;
             let uri = self.build_uri(path_and_query)?;
             let client = Arc::clone(&self.client);
@@ -520,7 +523,7 @@ impl HttpApiClient for Client {
                 auth,
                 method,
                 uri,
-                params,
+                body,
                 true,
                 &self.cookie_name,
             )
@@ -545,6 +548,27 @@ impl HttpApiClient for Client {
     }
 }
 
+impl HttpApiUploadClient for Client {
+    fn upload_request<'a, S>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        content_type: &'a str,
+        body: S,
+    ) -> Self::ResponseFuture<'a>
+    where
+        S: UploadStream,
+    {
+        Box::pin(async move {
+            let auth = self.login_auth()?;
+            let uri = self.build_uri(path_and_query)?;
+            let client = Arc::clone(&self.client);
+            let body = Some((content_type, Body::wrap_stream(body)));
+            Self::authenticated_request(client, auth, method, uri, body, &self.cookie_name).await
+        })
+    }
+}
+
 fn verify_fingerprint(chain: &x509::X509StoreContextRef, expected_fingerprint: &[u8]) -> bool {
     let Some(cert) = chain.current_cert() else {
         log::error!("no certificate in chain?");
@@ -25,6 +25,24 @@ mod client;
 #[cfg(feature = "hyper-client")]
 pub use client::{Client, TlsOptions};
 
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(feature = "upload")]
+mod upload;
+#[cfg(feature = "upload")]
+pub use upload::{HttpApiUploadClient, UploadStream};
+
+#[cfg(feature = "task")]
+mod task;
+#[cfg(feature = "task")]
+pub use task::{Task, TaskExitStatus, TaskLogLine, TaskStatus};
+
+#[cfg(feature = "hyper-client")]
+mod multi_client;
+#[cfg(feature = "hyper-client")]
+pub use multi_client::{Endpoint, EndpointStatus, MultiClient, RetryPolicy};
+
 /// HTTP client backend trait. This should be implemented for a HTTP client capable of making
 /// *authenticated* API requests to a proxmox HTTP API.
 pub trait HttpApiClient {
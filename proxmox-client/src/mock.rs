@@ -0,0 +1,646 @@
+//! In-memory [`HttpApiClient`] implementations for testing API consumers without a server.
+//!
+//! A [`MockClient`] answers requests from a list of [`Exchange`]s, which can either be scripted
+//! in a test or loaded from a JSON fixture previously captured via a [`RecordingClient`].
+
+use std::error::Error as StdError;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use http::{Method, StatusCode};
+use http_body_util::{BodyExt, Full};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Error, HttpApiClient, HttpApiResponse, HttpApiResponseStream};
+use crate::{HttpApiUploadClient, UploadStream};
+
+/// The body type of streamed responses of the clients in this module.
+pub type MockBody = Full<Bytes>;
+
+/// A request expected by a [`MockClient`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MockRequest {
+    /// The HTTP method.
+    #[serde(with = "method_serde")]
+    pub method: Method,
+
+    /// The path and query of the request.
+    pub path: String,
+
+    /// The JSON encoded parameters, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+
+    /// The raw request body of uploads, see [`HttpApiUploadClient`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload: Option<Vec<u8>>,
+}
+
+impl MockRequest {
+    /// A request without parameters.
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            body: None,
+            upload: None,
+        }
+    }
+
+    /// Expect the request to be made with these parameters.
+    pub fn body(mut self, body: impl Serialize) -> Self {
+        self.body = Some(serde_json::to_value(body).expect("failed to serialize mock request"));
+        self
+    }
+
+    /// Expect the request to be an upload of this data.
+    pub fn upload(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.upload = Some(data.into());
+        self
+    }
+
+    fn matches(
+        &self,
+        method: &Method,
+        path: &str,
+        body: Option<&Value>,
+        upload: Option<&[u8]>,
+    ) -> bool {
+        self.method == *method
+            && self.path == path
+            && self.body.as_ref() == body
+            && self.upload.as_deref() == upload
+    }
+}
+
+/// A response served by a [`MockClient`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MockResponse {
+    /// The HTTP status code.
+    pub status: u16,
+
+    /// The `Content-Type` header, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+
+    /// The response body.
+    #[serde(default)]
+    pub body: String,
+}
+
+impl MockResponse {
+    /// A raw response.
+    pub fn new(status: StatusCode, content_type: Option<&str>, body: impl Into<String>) -> Self {
+        Self {
+            status: status.as_u16(),
+            content_type: content_type.map(str::to_string),
+            body: body.into(),
+        }
+    }
+
+    /// A successful API response returning `data` in the format of the `extjs` formatter.
+    pub fn json(data: impl Serialize) -> Self {
+        let data = serde_json::to_value(data).expect("failed to serialize mock response");
+        let body = serde_json::json!({ "data": data, "success": true });
+        Self::new(StatusCode::OK, Some("application/json"), body.to_string())
+    }
+
+    /// A successful API response without any data.
+    pub fn nodata() -> Self {
+        Self::json(Value::Null)
+    }
+
+    /// A successful response for a streaming API call, encoding each item as a `json-seq`
+    /// record.
+    pub fn json_seq<I, T>(items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Serialize,
+    {
+        let mut body = String::new();
+        for item in items {
+            body.push('\x1e');
+            body.push_str(&serde_json::to_string(&item).expect("failed to serialize mock record"));
+            body.push('\n');
+        }
+        Self::new(StatusCode::OK, Some("application/json-seq"), body)
+    }
+
+    /// An error response.
+    pub fn error(status: StatusCode, message: impl Into<String>) -> Self {
+        Self::new(status, None, message)
+    }
+
+    /// Turn the response into the result a real client would produce.
+    fn into_result(self) -> Result<(u16, Option<String>, Vec<u8>), Error> {
+        let status = StatusCode::from_u16(self.status).map_err(|err| {
+            Error::Internal("invalid status code in mock response", Box::new(err))
+        })?;
+
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(Error::Unauthorized);
+        }
+        if !status.is_success() {
+            return Err(Error::Api(status, self.body));
+        }
+
+        Ok((self.status, self.content_type, self.body.into_bytes()))
+    }
+}
+
+/// A request along with the response it is answered with.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Exchange {
+    pub request: MockRequest,
+    pub response: MockResponse,
+}
+
+/// Read a list of exchanges from a JSON fixture file.
+pub fn load_fixture<P: AsRef<Path>>(path: P) -> Result<Vec<Exchange>, Error> {
+    let data = std::fs::read(path)
+        .map_err(|err| Error::Internal("failed to read fixture", Box::new(err)))?;
+    serde_json::from_slice(&data)
+        .map_err(|err| Error::Internal("failed to parse fixture", Box::new(err)))
+}
+
+/// Write a list of exchanges to a JSON fixture file.
+pub fn save_fixture<P: AsRef<Path>>(path: P, exchanges: &[Exchange]) -> Result<(), Error> {
+    let data = serde_json::to_vec_pretty(exchanges)
+        .map_err(|err| Error::Internal("failed to serialize fixture", Box::new(err)))?;
+    std::fs::write(path, data)
+        .map_err(|err| Error::Internal("failed to write fixture", Box::new(err)))
+}
+
+fn serialize_params<T: Serialize>(params: Option<T>) -> Result<Option<Value>, Error> {
+    params
+        .map(|params| {
+            serde_json::to_value(params)
+                .map_err(|err| Error::Internal("failed to serialize parameters", Box::new(err)))
+        })
+        .transpose()
+}
+
+/// An [`HttpApiClient`] answering requests from a list of [`Exchange`]s.
+///
+/// Each request is answered by the first exchange not used yet with a matching method, path and
+/// body. Requests without a matching exchange fail.
+///
+/// ```
+/// # use proxmox_client::mock::{MockClient, MockRequest, MockResponse};
+/// # use proxmox_client::HttpApiClient;
+/// # async fn example() -> Result<(), proxmox_client::Error> {
+/// let client = MockClient::new();
+/// client.expect(
+///     MockRequest::new(http::Method::GET, "/api2/extjs/version"),
+///     MockResponse::json(serde_json::json!({ "version": "3.0" })),
+/// );
+///
+/// let version = client
+///     .get("/api2/extjs/version")
+///     .await?
+///     .expect_json::<serde_json::Value>()?
+///     .data;
+/// assert_eq!(version["version"], "3.0");
+/// client.assert_done();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct MockClient {
+    exchanges: Mutex<Vec<(Exchange, bool)>>,
+}
+
+impl MockClient {
+    /// A client without any expected requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A client answering requests from a list of exchanges.
+    pub fn with_exchanges(exchanges: Vec<Exchange>) -> Self {
+        Self {
+            exchanges: Mutex::new(exchanges.into_iter().map(|ex| (ex, false)).collect()),
+        }
+    }
+
+    /// A client replaying a fixture captured via [`RecordingClient::save`].
+    pub fn from_fixture<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        load_fixture(path).map(Self::with_exchanges)
+    }
+
+    /// Expect a request and answer it with the provided response.
+    pub fn expect(&self, request: MockRequest, response: MockResponse) -> &Self {
+        self.exchanges
+            .lock()
+            .unwrap()
+            .push((Exchange { request, response }, false));
+        self
+    }
+
+    /// Get the exchanges which were not used to answer a request yet.
+    pub fn unused(&self) -> Vec<Exchange> {
+        self.exchanges
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, used)| !used)
+            .map(|(exchange, _)| exchange.clone())
+            .collect()
+    }
+
+    /// Panic if any expected request was not made.
+    pub fn assert_done(&self) {
+        let unused = self.unused();
+        if !unused.is_empty() {
+            let requests: Vec<_> = unused
+                .iter()
+                .map(|ex| format!("{} {}", ex.request.method, ex.request.path))
+                .collect();
+            panic!("expected requests were not made: {}", requests.join(", "));
+        }
+    }
+
+    fn respond(
+        &self,
+        method: &Method,
+        path: &str,
+        body: Option<&Value>,
+        upload: Option<&[u8]>,
+    ) -> Result<(u16, Option<String>, Vec<u8>), Error> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let (exchange, used) = exchanges
+            .iter_mut()
+            .find(|(ex, used)| !used && ex.request.matches(method, path, body, upload))
+            .ok_or_else(|| Error::BadApi(format!("unexpected request: {method} {path}"), None))?;
+        *used = true;
+        exchange.response.clone().into_result()
+    }
+}
+
+impl HttpApiClient for MockClient {
+    type ResponseFuture<'a> =
+        Pin<Box<dyn Future<Output = Result<HttpApiResponse, Error>> + Send + 'a>>;
+
+    type ResponseStreamFuture<'a> =
+        Pin<Box<dyn Future<Output = Result<HttpApiResponseStream<Self::Body>, Error>> + Send + 'a>>;
+
+    type Body = MockBody;
+
+    fn request<'a, T>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        params: Option<T>,
+    ) -> Self::ResponseFuture<'a>
+    where
+        T: Serialize + 'a,
+    {
+        let result = serialize_params(params)
+            .and_then(|params| self.respond(&method, path_and_query, params.as_ref(), None))
+            .map(|(status, content_type, body)| HttpApiResponse {
+                status,
+                content_type,
+                body,
+            });
+        Box::pin(std::future::ready(result))
+    }
+
+    fn streaming_request<'a, T>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        params: Option<T>,
+    ) -> Self::ResponseStreamFuture<'a>
+    where
+        T: Serialize + 'a,
+    {
+        let result = serialize_params(params)
+            .and_then(|params| self.respond(&method, path_and_query, params.as_ref(), None))
+            .map(|(status, content_type, body)| HttpApiResponseStream {
+                status,
+                content_type,
+                body: Some(Full::new(Bytes::from(body))),
+            });
+        Box::pin(std::future::ready(result))
+    }
+}
+
+impl HttpApiUploadClient for MockClient {
+    fn upload_request<'a, S>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        _content_type: &'a str,
+        body: S,
+    ) -> Self::ResponseFuture<'a>
+    where
+        S: UploadStream,
+    {
+        Box::pin(async move {
+            let data = body
+                .try_fold(Vec::new(), |mut data, chunk| async move {
+                    data.extend_from_slice(&chunk);
+                    Ok(data)
+                })
+                .await
+                .map_err(|err| Error::Client(err.into()))?;
+            let (status, content_type, body) =
+                self.respond(&method, path_and_query, None, Some(&data))?;
+            Ok(HttpApiResponse {
+                status,
+                content_type,
+                body,
+            })
+        })
+    }
+}
+
+/// An [`HttpApiClient`] passing requests on to another client and recording the exchanges, so
+/// they can be saved as a fixture for a [`MockClient`].
+///
+/// Streamed responses are read to completion before being passed on.
+pub struct RecordingClient<C> {
+    client: C,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl<C> RecordingClient<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            exchanges: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Access the wrapped client, for instance to log in.
+    pub fn inner(&self) -> &C {
+        &self.client
+    }
+
+    /// Get the exchanges recorded so far.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges.lock().unwrap().clone()
+    }
+
+    /// Save the exchanges recorded so far to a JSON fixture file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        save_fixture(path, &self.exchanges.lock().unwrap())
+    }
+
+    fn record(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+        result: &Result<(u16, Option<String>, Vec<u8>), Error>,
+    ) -> Result<(), Error> {
+        let response = match result {
+            Ok((status, content_type, data)) => MockResponse {
+                status: *status,
+                content_type: content_type.clone(),
+                body: String::from_utf8(data.clone())
+                    .map_err(|_| Error::Other("cannot record non-utf8 response"))?,
+            },
+            Err(Error::Unauthorized) => MockResponse::error(StatusCode::UNAUTHORIZED, ""),
+            Err(Error::Api(status, message)) => MockResponse::error(*status, message.as_str()),
+            // connection errors and the like cannot be replayed
+            Err(_) => return Ok(()),
+        };
+
+        self.exchanges.lock().unwrap().push(Exchange {
+            request: MockRequest {
+                method,
+                path: path.to_string(),
+                body,
+                upload: None,
+            },
+            response,
+        });
+
+        Ok(())
+    }
+}
+
+impl<C> HttpApiClient for RecordingClient<C>
+where
+    C: HttpApiClient,
+    C::Body: http_body::Body + Unpin,
+    <C::Body as http_body::Body>::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
+{
+    type ResponseFuture<'a>
+        = Pin<Box<dyn Future<Output = Result<HttpApiResponse, Error>> + 'a>>
+    where
+        Self: 'a;
+
+    type ResponseStreamFuture<'a>
+        = Pin<Box<dyn Future<Output = Result<HttpApiResponseStream<Self::Body>, Error>> + 'a>>
+    where
+        Self: 'a;
+
+    type Body = MockBody;
+
+    fn request<'a, T>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        params: Option<T>,
+    ) -> Self::ResponseFuture<'a>
+    where
+        T: Serialize + 'a,
+    {
+        let params = serialize_params(params);
+
+        Box::pin(async move {
+            let params = params?;
+            let result = self
+                .client
+                .request(method.clone(), path_and_query, params.as_ref())
+                .await
+                .map(|response| (response.status, response.content_type, response.body));
+
+            self.record(method, path_and_query, params, &result)?;
+
+            let (status, content_type, body) = result?;
+            Ok(HttpApiResponse {
+                status,
+                content_type,
+                body,
+            })
+        })
+    }
+
+    fn streaming_request<'a, T>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        params: Option<T>,
+    ) -> Self::ResponseStreamFuture<'a>
+    where
+        T: Serialize + 'a,
+    {
+        let params = serialize_params(params);
+
+        Box::pin(async move {
+            let params = params?;
+            let result = match self
+                .client
+                .streaming_request(method.clone(), path_and_query, params.as_ref())
+                .await
+            {
+                Ok(response) => {
+                    let body = match response.body {
+                        Some(body) => body
+                            .collect()
+                            .await
+                            .map_err(|err| Error::Client(err.into()))?
+                            .to_bytes()
+                            .to_vec(),
+                        None => Vec::new(),
+                    };
+                    Ok((response.status, response.content_type, body))
+                }
+                Err(err) => Err(err),
+            };
+
+            self.record(method, path_and_query, params, &result)?;
+
+            let (status, content_type, body) = result?;
+            Ok(HttpApiResponseStream {
+                status,
+                content_type,
+                body: Some(Full::new(Bytes::from(body))),
+            })
+        })
+    }
+}
+
+mod method_serde {
+    use http::Method;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(method: &Method, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(method.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Method, D::Error> {
+        let method = String::deserialize(deserializer)?;
+        method.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoopWaker;
+
+    impl std::task::Wake for NoopWaker {
+        fn wake(self: std::sync::Arc<Self>) {}
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        // the mock client's futures are always ready
+        let mut future = std::pin::pin!(future);
+        let waker = std::task::Waker::from(std::sync::Arc::new(NoopWaker));
+        match future
+            .as_mut()
+            .poll(&mut std::task::Context::from_waker(&waker))
+        {
+            std::task::Poll::Ready(output) => output,
+            std::task::Poll::Pending => panic!("mock future not ready"),
+        }
+    }
+
+    #[test]
+    fn test_mock_client() {
+        let client = MockClient::new();
+        client
+            .expect(
+                MockRequest::new(Method::POST, "/api2/extjs/nodes/node1/tasks")
+                    .body(serde_json::json!({ "type": "foo" })),
+                MockResponse::json("UPID:node1"),
+            )
+            .expect(
+                MockRequest::new(Method::GET, "/api2/extjs/version"),
+                MockResponse::error(StatusCode::INTERNAL_SERVER_ERROR, "oops"),
+            );
+
+        // the body must match
+        assert!(
+            block_on(client.post("/api2/extjs/nodes/node1/tasks", &serde_json::json!({}))).is_err(),
+            "request with wrong body was answered"
+        );
+
+        let upid: String = block_on(client.post(
+            "/api2/extjs/nodes/node1/tasks",
+            &serde_json::json!({ "type": "foo" }),
+        ))
+        .unwrap()
+        .expect_json()
+        .unwrap()
+        .data;
+        assert_eq!(upid, "UPID:node1");
+
+        match block_on(client.get("/api2/extjs/version")) {
+            Err(Error::Api(status, message)) => {
+                assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+                assert_eq!(message, "oops");
+            }
+            _ => panic!("expected an api error"),
+        }
+
+        // every exchange is only used once
+        assert!(block_on(client.get("/api2/extjs/version")).is_err());
+        client.assert_done();
+    }
+
+    #[test]
+    fn test_mock_upload() {
+        let client = MockClient::new();
+        client.expect(
+            MockRequest::new(Method::POST, "/api2/extjs/upload").upload("hello world"),
+            MockResponse::nodata(),
+        );
+
+        let body = futures::stream::iter(["hello", " ", "world"].map(|s| Ok(Bytes::from(s))));
+        block_on(client.upload_request(
+            Method::POST,
+            "/api2/extjs/upload",
+            "application/octet-stream",
+            body,
+        ))
+        .unwrap()
+        .nodata()
+        .unwrap();
+        client.assert_done();
+    }
+
+    #[cfg(feature = "hyper-client")]
+    #[test]
+    fn test_recording_client_type() {
+        fn assert_api_client<C: HttpApiClient>() {}
+        assert_api_client::<RecordingClient<crate::Client>>();
+    }
+
+    #[test]
+    fn test_fixture_format() {
+        let exchange = Exchange {
+            request: MockRequest::new(Method::GET, "/api2/extjs/stream"),
+            response: MockResponse::json_seq([1, 2]),
+        };
+        let fixture = serde_json::to_string(&[&exchange]).unwrap();
+        assert_eq!(
+            fixture,
+            r#"[{"request":{"method":"GET","path":"/api2/extjs/stream"},"response":{"status":200,"content-type":"application/json-seq","body":"\u001e1\n\u001e2\n"}}]"#
+        );
+
+        let parsed: Vec<Exchange> = serde_json::from_str(&fixture).unwrap();
+        assert_eq!(parsed, [exchange]);
+    }
+}
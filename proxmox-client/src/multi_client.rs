@@ -0,0 +1,627 @@
+//! A client failing over between multiple endpoints serving the same API.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use http::{Method, StatusCode, Uri};
+use serde::Serialize;
+use serde_json::Value;
+
+use proxmox_http::{Body, HttpOptions};
+use proxmox_login::ticket::Validity;
+use proxmox_login::{Login, SecondFactorChallenge, TicketResult};
+
+use crate::auth::AuthenticationKind;
+use crate::{Client, Error, HttpApiClient, HttpApiResponse, HttpApiResponseStream, TlsOptions};
+
+/// An API endpoint of a [`MultiClient`], for instance a single node of a cluster.
+pub struct Endpoint {
+    api_url: Uri,
+    tls_options: TlsOptions,
+    http_options: HttpOptions,
+}
+
+impl Endpoint {
+    /// An endpoint using the default TLS and HTTP options.
+    pub fn new(api_url: Uri) -> Self {
+        Self {
+            api_url,
+            tls_options: TlsOptions::default(),
+            http_options: HttpOptions::default(),
+        }
+    }
+
+    /// Set the TLS options, for instance to pin the endpoint's certificate fingerprint.
+    pub fn tls_options(mut self, tls_options: TlsOptions) -> Self {
+        self.tls_options = tls_options;
+        self
+    }
+
+    /// Set the HTTP options.
+    pub fn http_options(mut self, http_options: HttpOptions) -> Self {
+        self.http_options = http_options;
+        self
+    }
+}
+
+/// Controls how failed requests are retried.
+///
+/// A request failing due to a connection error, or with a status of 502, 503 or 504 as returned
+/// by proxies in front of an unavailable API, is first passed on to the other endpoints. Once all endpoints failed, the request is retried with an exponential
+/// backoff. Only idempotent requests are retried at all, other requests fail immediately.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// How often to retry a request after all endpoints failed.
+    pub max_retries: u32,
+
+    /// Delay before the first retry, doubled for every further retry.
+    pub initial_backoff: Duration,
+
+    /// Upper limit for the delay between retries.
+    pub max_backoff: Duration,
+
+    /// How long to avoid an endpoint after a request to it failed.
+    pub failure_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            failure_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which only fails over to other endpoints, but never retries.
+    pub fn no_retry() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// The health of an endpoint as tracked by the [`MultiClient`].
+#[derive(Clone, Debug)]
+pub struct EndpointStatus {
+    /// The endpoint's API url.
+    pub api_url: Uri,
+
+    /// Whether requests are currently sent to this endpoint.
+    pub healthy: bool,
+
+    /// The number of consecutive failed requests.
+    pub failures: u32,
+}
+
+#[derive(Default)]
+struct Health {
+    failures: u32,
+    failed_at: Option<Instant>,
+}
+
+impl Health {
+    fn is_healthy(&self, now: Instant, failure_timeout: Duration) -> bool {
+        self.failed_at
+            .is_none_or(|failed_at| now.duration_since(failed_at) >= failure_timeout)
+    }
+}
+
+struct EndpointClient {
+    client: Client,
+    health: Mutex<Health>,
+}
+
+/// A Proxmox API client failing over between multiple endpoints serving the same API, usually
+/// the nodes of a cluster.
+///
+/// Requests go to the first healthy endpoint in the order the endpoints were provided. Endpoints
+/// failing with connection errors or gateway errors are avoided until the
+/// [`failure_timeout`](RetryPolicy::failure_timeout) passed. The authentication is shared by all
+/// endpoints, so logging in once is enough.
+pub struct MultiClient {
+    endpoints: Vec<EndpointClient>,
+    retry_policy: RetryPolicy,
+    pve_compat: bool,
+    /// The endpoint a TFA challenge was received from.
+    tfa_endpoint: Mutex<Option<usize>>,
+}
+
+impl MultiClient {
+    /// Create a client for a list of endpoints.
+    pub fn new(endpoints: Vec<Endpoint>) -> Result<Self, Error> {
+        let clients = endpoints
+            .into_iter()
+            .map(|endpoint| {
+                Client::with_options(
+                    endpoint.api_url,
+                    endpoint.tls_options,
+                    endpoint.http_options,
+                )
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Self::with_clients(clients)
+    }
+
+    /// Create a client from already configured clients for each endpoint.
+    ///
+    /// The clients' current authentication is replaced by the one of the first client.
+    pub fn with_clients(mut clients: Vec<Client>) -> Result<Self, Error> {
+        let Some((first, others)) = clients.split_first_mut() else {
+            return Err(Error::Other("no API endpoints provided"));
+        };
+        for client in others {
+            client.share_authentication(first);
+        }
+
+        Ok(Self {
+            endpoints: clients
+                .into_iter()
+                .map(|client| EndpointClient {
+                    client,
+                    health: Mutex::new(Health::default()),
+                })
+                .collect(),
+            retry_policy: RetryPolicy::default(),
+            pve_compat: false,
+            tfa_endpoint: Mutex::new(None),
+        })
+    }
+
+    /// Set the retry policy.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Enable Proxmox VE login API compatibility, see [`Client::set_pve_compatibility`].
+    pub fn set_pve_compatibility(&mut self, compatibility: bool) {
+        self.pve_compat = compatibility;
+        for endpoint in &mut self.endpoints {
+            endpoint.client.set_pve_compatibility(compatibility);
+        }
+    }
+
+    pub fn set_cookie_name(&mut self, cookie_name: &str) {
+        for endpoint in &mut self.endpoints {
+            endpoint.client.set_cookie_name(cookie_name);
+        }
+    }
+
+    /// Get the health of all endpoints.
+    pub fn endpoint_status(&self) -> Vec<EndpointStatus> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                let health = endpoint.health.lock().unwrap();
+                EndpointStatus {
+                    api_url: endpoint.client.api_url().clone(),
+                    healthy: health.is_healthy(now, self.retry_policy.failure_timeout),
+                    failures: health.failures,
+                }
+            })
+            .collect()
+    }
+
+    /// The client shared authentication operations are performed with.
+    fn main_client(&self) -> &Client {
+        &self.endpoints[0].client
+    }
+
+    /// Get a reference to the current authentication information.
+    pub fn authentication(&self) -> Option<Arc<AuthenticationKind>> {
+        self.main_client().authentication()
+    }
+
+    /// Replace the currently used authentication.
+    pub fn set_authentication(&self, auth: impl Into<AuthenticationKind>) {
+        self.main_client().set_authentication(auth);
+    }
+
+    /// Get a serialized version of the ticket if one is used, see [`Client::serialize_ticket`].
+    pub fn serialize_ticket(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.main_client().serialize_ticket()
+    }
+
+    /// Drop the current authentication information.
+    pub fn logout(&self) {
+        self.main_client().logout();
+    }
+
+    /// Check to see if we need to refresh the ticket, see [`Client::ticket_validity`].
+    pub fn ticket_validity(&self) -> Result<Validity, Error> {
+        self.main_client().ticket_validity()
+    }
+
+    /// If the ticket expires soon, attempt to refresh it.
+    pub async fn maybe_refresh_ticket(&self) -> Result<(), Error> {
+        if let Validity::Refresh = self.ticket_validity()? {
+            self.refresh_ticket().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Attempt to refresh the current ticket via the first available endpoint.
+    pub async fn refresh_ticket(&self) -> Result<(), Error> {
+        self.with_failover(true, |index| self.endpoints[index].client.refresh_ticket())
+            .await
+    }
+
+    /// Attempt to login via the first available endpoint.
+    ///
+    /// The login's API url is replaced by the one of the endpoint. If a 2nd factor is required,
+    /// `Some` is returned and the login needs to be finished via [`login_tfa`](Self::login_tfa).
+    pub async fn login(&self, login: Login) -> Result<Option<SecondFactorChallenge>, Error> {
+        let mut login = login.pve_compatibility(self.pve_compat);
+
+        let (index, ticket, api_response) = self
+            .with_failover(true, |index| {
+                let client = &self.endpoints[index].client;
+                login.set_url(client.api_url().to_string().trim_end_matches('/'));
+                let request = login.request();
+                async move {
+                    let (ticket, body) = client.do_login_request(request).await?;
+                    Ok((index, ticket, body))
+                }
+            })
+            .await?;
+
+        Ok(
+            match login.response_with_cookie_ticket(ticket, &api_response)? {
+                TicketResult::TfaRequired(challenge) => {
+                    *self.tfa_endpoint.lock().unwrap() = Some(index);
+                    Some(challenge)
+                }
+                TicketResult::Full(auth) | TicketResult::HttpOnly(auth) => {
+                    self.set_authentication(auth);
+                    None
+                }
+            },
+        )
+    }
+
+    /// Attempt to finish a 2nd factor login.
+    ///
+    /// This is sent to the endpoint the challenge was received from.
+    pub async fn login_tfa(
+        &self,
+        challenge: SecondFactorChallenge,
+        challenge_response: proxmox_login::Request,
+    ) -> Result<(), Error> {
+        let index = self
+            .tfa_endpoint
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or(Error::Other("no pending TFA challenge"))?;
+
+        self.endpoints[index]
+            .client
+            .login_tfa(challenge, challenge_response)
+            .await
+    }
+
+    /// Pick the next endpoint to send a request to, preferring healthy endpoints in their
+    /// configured order, then the ones which failed the longest time ago.
+    fn pick_endpoint(&self, tried: &[bool]) -> Option<usize> {
+        let now = Instant::now();
+        let health: Vec<_> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.health.lock().unwrap().failed_at)
+            .collect();
+
+        pick_endpoint(&health, tried, now, self.retry_policy.failure_timeout)
+    }
+
+    fn mark_success(&self, index: usize) {
+        *self.endpoints[index].health.lock().unwrap() = Health::default();
+    }
+
+    fn mark_failure(&self, index: usize, err: &Error) {
+        let mut health = self.endpoints[index].health.lock().unwrap();
+        health.failures += 1;
+        health.failed_at = Some(Instant::now());
+
+        log::warn!(
+            "request to {} failed, trying next endpoint - {err}",
+            self.endpoints[index].client.api_url(),
+        );
+    }
+
+    /// Run a request, failing over to other endpoints and retrying it according to the retry
+    /// policy.
+    ///
+    /// If `retry` is false, a failed request is not retried at all. The endpoint is still marked
+    /// as failed, so subsequent requests go elsewhere.
+    async fn with_failover<F, Fut, R>(&self, retry: bool, mut call: F) -> Result<R, Error>
+    where
+        F: FnMut(usize) -> Fut,
+        Fut: Future<Output = Result<R, Error>>,
+    {
+        let mut tried = vec![false; self.endpoints.len()];
+        let mut retries = 0;
+        let mut last_error = None;
+
+        loop {
+            let Some(index) = self.pick_endpoint(&tried) else {
+                if retries >= self.retry_policy.max_retries {
+                    return Err(last_error.unwrap_or(Error::Other("no API endpoints available")));
+                }
+                tokio::time::sleep(self.retry_policy.backoff(retries)).await;
+                retries += 1;
+                tried.fill(false);
+                continue;
+            };
+            tried[index] = true;
+
+            match call(index).await {
+                Err(err) if is_retryable(&err) => {
+                    self.mark_failure(index, &err);
+                    if !retry {
+                        return Err(err);
+                    }
+                    last_error = Some(err);
+                }
+                result => {
+                    // any other result means the endpoint is reachable and working
+                    self.mark_success(index);
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn serialize_params<T: Serialize>(params: Option<T>) -> Result<Option<Value>, Error> {
+        params
+            .map(|params| {
+                serde_json::to_value(params)
+                    .map_err(|err| Error::internal("failed to serialize parameters", err))
+            })
+            .transpose()
+    }
+}
+
+fn pick_endpoint(
+    failed_at: &[Option<Instant>],
+    tried: &[bool],
+    now: Instant,
+    failure_timeout: Duration,
+) -> Option<usize> {
+    let untried = || {
+        failed_at
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !tried[*index])
+    };
+
+    untried()
+        .find(|(_, failed_at)| {
+            failed_at.is_none_or(|failed_at| now.duration_since(failed_at) >= failure_timeout)
+        })
+        .or_else(|| untried().min_by_key(|(_, failed_at)| **failed_at))
+        .map(|(index, _)| index)
+}
+
+/// Connection errors and unavailable endpoints may go away when using a different endpoint or
+/// after waiting a bit. Other errors, including internal server errors, come from the API itself
+/// and would happen on any endpoint.
+fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::Client(_) => true,
+        Error::Anyhow(err) => err
+            .chain()
+            .any(|err| err.is::<hyper::Error>() || err.is::<std::io::Error>()),
+        Error::Api(status, _) => matches!(
+            *status,
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ),
+        _ => false,
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+
+impl HttpApiClient for MultiClient {
+    type ResponseFuture<'a> =
+        Pin<Box<dyn Future<Output = Result<HttpApiResponse, Error>> + Send + 'a>>;
+
+    type ResponseStreamFuture<'a> =
+        Pin<Box<dyn Future<Output = Result<HttpApiResponseStream<Self::Body>, Error>> + Send + 'a>>;
+
+    type Body = Body;
+
+    fn request<'a, T>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        params: Option<T>,
+    ) -> Self::ResponseFuture<'a>
+    where
+        T: Serialize + 'a,
+    {
+        let params = Self::serialize_params(params);
+
+        Box::pin(async move {
+            let params = params?;
+            let retry = is_idempotent(&method);
+            self.with_failover(retry, |index| {
+                self.endpoints[index].client.request(
+                    method.clone(),
+                    path_and_query,
+                    params.as_ref(),
+                )
+            })
+            .await
+        })
+    }
+
+    fn streaming_request<'a, T>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        params: Option<T>,
+    ) -> Self::ResponseStreamFuture<'a>
+    where
+        T: Serialize + 'a,
+    {
+        let params = Self::serialize_params(params);
+
+        Box::pin(async move {
+            let params = params?;
+            let retry = is_idempotent(&method);
+            self.with_failover(retry, |index| {
+                self.endpoints[index].client.streaming_request(
+                    method.clone(),
+                    path_and_query,
+                    params.as_ref(),
+                )
+            })
+            .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(5), policy.max_backoff);
+        assert_eq!(policy.backoff(40), policy.max_backoff);
+    }
+
+    #[test]
+    fn test_pick_endpoint() {
+        let timeout = Duration::from_secs(60);
+        let now = Instant::now() + Duration::from_secs(3600);
+        let recent = Some(now - Duration::from_secs(10));
+        let older = Some(now - Duration::from_secs(20));
+        let expired = Some(now - Duration::from_secs(120));
+
+        // healthy endpoints are preferred in their configured order
+        let failed_at = [recent, None, expired];
+        assert_eq!(
+            pick_endpoint(&failed_at, &[false; 3], now, timeout),
+            Some(1)
+        );
+        assert_eq!(
+            pick_endpoint(&failed_at, &[false, true, false], now, timeout),
+            Some(2)
+        );
+
+        // without healthy endpoints, the one failing the longest time ago is used
+        let failed_at = [recent, older, recent];
+        assert_eq!(
+            pick_endpoint(&failed_at, &[false; 3], now, timeout),
+            Some(1)
+        );
+        assert_eq!(
+            pick_endpoint(&failed_at, &[false, true, false], now, timeout),
+            Some(0)
+        );
+
+        assert_eq!(pick_endpoint(&failed_at, &[true; 3], now, timeout), None);
+    }
+
+    fn test_client() -> MultiClient {
+        let endpoints = ["https://node1:8006", "https://node2:8006"]
+            .into_iter()
+            .map(|url| Endpoint::new(url.parse().unwrap()))
+            .collect();
+        MultiClient::new(endpoints)
+            .unwrap()
+            .with_retry_policy(RetryPolicy::no_retry())
+    }
+
+    /// Run a request failing with `error` on the first endpoint and succeeding on the second one.
+    /// Returns the result and the endpoints which were called.
+    async fn run_failover(
+        client: &MultiClient,
+        error: fn() -> Error,
+    ) -> (Result<usize, Error>, Vec<usize>) {
+        let calls = Mutex::new(Vec::new());
+        let result = client
+            .with_failover(true, |index| {
+                calls.lock().unwrap().push(index);
+                std::future::ready(if index == 0 { Err(error()) } else { Ok(index) })
+            })
+            .await;
+        (result, calls.into_inner().unwrap())
+    }
+
+    fn healthy(client: &MultiClient) -> Vec<bool> {
+        client
+            .endpoint_status()
+            .into_iter()
+            .map(|status| status.healthy)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let client = test_client();
+        let (result, calls) = run_failover(&client, || {
+            Error::Client(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into())
+        })
+        .await;
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(calls, [0, 1]);
+        assert_eq!(healthy(&client), [false, true]);
+
+        let client = test_client();
+        let (result, calls) =
+            run_failover(&client, || Error::api(StatusCode::SERVICE_UNAVAILABLE, "")).await;
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(calls, [0, 1]);
+        assert_eq!(healthy(&client), [false, true]);
+    }
+
+    #[tokio::test]
+    async fn test_no_failover_on_api_errors() {
+        let client = test_client();
+        let (result, calls) = run_failover(&client, || {
+            Error::api(StatusCode::INTERNAL_SERVER_ERROR, "something broke")
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(Error::Api(StatusCode::INTERNAL_SERVER_ERROR, _))
+        ));
+        assert_eq!(calls, [0]);
+        assert_eq!(healthy(&client), [true, true]);
+
+        let client = test_client();
+        let (result, calls) =
+            run_failover(&client, || Error::Anyhow(anyhow::format_err!("bad data"))).await;
+        assert!(result.is_err());
+        assert_eq!(calls, [0]);
+        assert_eq!(healthy(&client), [true, true]);
+    }
+}
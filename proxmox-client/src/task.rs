@@ -0,0 +1,315 @@
+//! Helpers for worker tasks started via the API.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use futures::Stream;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+
+use proxmox_schema::upid::UPID;
+
+use crate::{ApiPathBuilder, Error, HttpApiClient};
+
+/// Number of log lines fetched at once when following a task log.
+const LOG_CHUNK_SIZE: u64 = 500;
+
+/// The exit status of a finished task, mirroring the `TaskState` of the REST server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TaskExitStatus {
+    /// The task ended with an undefined state.
+    Unknown,
+    /// The task ended and there were no errors or warnings.
+    OK,
+    /// The task had `count` amount of warnings and no errors.
+    Warning { count: u64 },
+    /// The task ended with the error described in `message`.
+    Error { message: String },
+}
+
+impl TaskExitStatus {
+    /// Parse the `exitstatus` reported by the task status API.
+    pub fn parse(s: &str) -> Self {
+        if s.is_empty() || s == "unknown" {
+            Self::Unknown
+        } else if s == "OK" {
+            Self::OK
+        } else if let Some(count) = s
+            .strip_prefix("WARNINGS: ")
+            .and_then(|count| count.parse().ok())
+        {
+            Self::Warning { count }
+        } else {
+            let message = s.strip_prefix("ERROR: ").unwrap_or(s).to_string();
+            Self::Error { message }
+        }
+    }
+}
+
+impl fmt::Display for TaskExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => f.write_str("unknown"),
+            Self::OK => f.write_str("OK"),
+            Self::Warning { count } => write!(f, "WARNINGS: {count}"),
+            Self::Error { message } => f.write_str(message),
+        }
+    }
+}
+
+/// The state of a task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// The task is still running.
+    Running,
+    /// The task finished with an exit status.
+    Stopped(TaskExitStatus),
+}
+
+impl TaskStatus {
+    pub fn is_running(&self) -> bool {
+        matches!(self, Self::Running)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawTaskStatus {
+    status: String,
+    #[serde(default)]
+    exitstatus: Option<String>,
+}
+
+/// A line of a task log.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct TaskLogLine {
+    /// The line number, starting at 1. This is also the offset to continue reading the log at.
+    #[serde(rename = "n")]
+    pub line: u64,
+
+    /// The text of the line.
+    #[serde(rename = "t")]
+    pub text: String,
+}
+
+/// A worker task running on a remote node, identified by its [`UPID`].
+///
+/// ```no_run
+/// # use proxmox_client::{Error, HttpApiClient};
+/// # async fn example<C: HttpApiClient>(client: &C, upid: &str) -> Result<(), Error> {
+/// use futures::TryStreamExt;
+/// use proxmox_client::Task;
+///
+/// let task = Task::from_upid_str(client, upid)?;
+/// let mut log = std::pin::pin!(task.follow_log(0));
+/// while let Some(line) = log.try_next().await? {
+///     println!("{}", line.text);
+/// }
+/// println!("task finished: {}", task.wait(None).await?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Task<'a, C> {
+    client: &'a C,
+    upid: UPID,
+    path: String,
+    poll_interval: Duration,
+}
+
+impl<C> Clone for Task<'_, C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client,
+            upid: self.upid.clone(),
+            path: self.path.clone(),
+            poll_interval: self.poll_interval,
+        }
+    }
+}
+
+impl<'a, C> Task<'a, C>
+where
+    C: HttpApiClient,
+{
+    /// Access a task via a client.
+    pub fn new(client: &'a C, upid: UPID) -> Self {
+        let path = format!(
+            "/api2/extjs/nodes/{}/tasks/{}",
+            utf8_percent_encode(&upid.node, NON_ALPHANUMERIC),
+            utf8_percent_encode(&upid.to_string(), NON_ALPHANUMERIC),
+        );
+
+        Self {
+            client,
+            upid,
+            path,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Access a task via a client, parsing the UPID as returned by API calls starting a worker.
+    pub fn from_upid_str(client: &'a C, upid: &str) -> Result<Self, Error> {
+        let upid = upid.parse().map_err(Error::Anyhow)?;
+        Ok(Self::new(client, upid))
+    }
+
+    /// Change the interval at which the task status and log are polled. Defaults to 1 second.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Get the task's UPID.
+    pub fn upid(&self) -> &UPID {
+        &self.upid
+    }
+
+    /// Query the current status of the task.
+    pub async fn status(&self) -> Result<TaskStatus, Error> {
+        let status = self
+            .client
+            .get(&format!("{}/status", self.path))
+            .await?
+            .expect_json::<RawTaskStatus>()?
+            .data;
+
+        Ok(match status.status.as_str() {
+            "running" => TaskStatus::Running,
+            "stopped" => TaskStatus::Stopped(TaskExitStatus::parse(
+                status.exitstatus.as_deref().unwrap_or_default(),
+            )),
+            other => {
+                return Err(Error::BadApi(
+                    format!("unknown task status '{other}'"),
+                    None,
+                ))
+            }
+        })
+    }
+
+    /// Wait for the task to finish and return its exit status.
+    ///
+    /// If a `timeout` is provided and the task does not finish in time, an error is returned. The
+    /// task keeps running in that case.
+    pub async fn wait(&self, timeout: Option<Duration>) -> Result<TaskExitStatus, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            if let TaskStatus::Stopped(exit_status) = self.status().await? {
+                return Ok(exit_status);
+            }
+
+            let delay = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => remaining.min(self.poll_interval),
+                    _ => return Err(Error::Other("timeout waiting for task to finish")),
+                },
+                None => self.poll_interval,
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Read up to `limit` lines of the task log, starting after `start` lines.
+    pub async fn read_log(&self, start: u64, limit: u64) -> Result<Vec<TaskLogLine>, Error> {
+        let path = ApiPathBuilder::new(format!("{}/log", self.path))
+            .arg("start", start)
+            .arg("limit", limit)
+            .build();
+
+        Ok(self
+            .client
+            .get(&path)
+            .await?
+            .expect_json::<Vec<TaskLogLine>>()?
+            .data)
+    }
+
+    /// Follow the task log, starting after `start` lines, until the task finished.
+    ///
+    /// New lines are polled at the [poll interval](Self::poll_interval) while the task is
+    /// running. The stream ends once the task stopped and all of its log was read.
+    pub fn follow_log(&self, start: u64) -> impl Stream<Item = Result<TaskLogLine, Error>> + 'a
+    where
+        C: 'a,
+    {
+        struct State<'a, C> {
+            task: Task<'a, C>,
+            start: u64,
+            buffer: VecDeque<TaskLogLine>,
+            stopped: bool,
+        }
+
+        let state = State {
+            task: self.clone(),
+            start,
+            buffer: VecDeque::new(),
+            stopped: false,
+        };
+
+        futures::stream::try_unfold(state, |mut state| async move {
+            loop {
+                if let Some(line) = state.buffer.pop_front() {
+                    return Ok(Some((line, state)));
+                }
+
+                let lines = state.task.read_log(state.start, LOG_CHUNK_SIZE).await?;
+                if let Some(last) = lines.last() {
+                    state.start = last.line;
+                    state.buffer.extend(lines);
+                    continue;
+                }
+
+                if state.stopped {
+                    return Ok(None);
+                }
+
+                if state.task.status().await?.is_running() {
+                    tokio::time::sleep(state.task.poll_interval).await;
+                } else {
+                    // read once more to catch lines written right before the task stopped
+                    state.stopped = true;
+                }
+            }
+        })
+    }
+
+    /// Request the task to be aborted.
+    pub async fn abort(&self) -> Result<(), Error> {
+        self.client.delete(&self.path).await?.nodata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TaskExitStatus;
+
+    #[test]
+    fn test_parse_exit_status() {
+        for (text, status) in [
+            ("OK", TaskExitStatus::OK),
+            ("unknown", TaskExitStatus::Unknown),
+            ("", TaskExitStatus::Unknown),
+            ("WARNINGS: 3", TaskExitStatus::Warning { count: 3 }),
+            (
+                "ERROR: interrupted by signal",
+                TaskExitStatus::Error {
+                    message: "interrupted by signal".to_string(),
+                },
+            ),
+            (
+                "command 'foo' failed",
+                TaskExitStatus::Error {
+                    message: "command 'foo' failed".to_string(),
+                },
+            ),
+        ] {
+            assert_eq!(TaskExitStatus::parse(text), status);
+        }
+
+        assert_eq!(
+            TaskExitStatus::parse(&TaskExitStatus::Warning { count: 1 }.to_string()),
+            TaskExitStatus::Warning { count: 1 }
+        );
+    }
+}
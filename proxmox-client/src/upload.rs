@@ -0,0 +1,86 @@
+//! Requests sending a raw request body, like file uploads.
+
+use bytes::Bytes;
+use futures::Stream;
+use http::Method;
+
+use crate::HttpApiClient;
+
+/// A stream of raw request body data, see [`HttpApiUploadClient::upload_request`].
+pub trait UploadStream: Stream<Item = Result<Bytes, std::io::Error>> + Send + 'static {}
+
+impl<S> UploadStream for S where S: Stream<Item = Result<Bytes, std::io::Error>> + Send + 'static {}
+
+/// An [`HttpApiClient`] which can also send raw request bodies, as required by API endpoints
+/// reading the request body themselves, like uploads.
+pub trait HttpApiUploadClient: HttpApiClient {
+    /// An *authenticated* asynchronous request with a path and query component (no hostname),
+    /// streaming `body` with the provided content type. The response body is read to completion.
+    ///
+    /// Since the body is consumed, such requests cannot be retried.
+    ///
+    /// For this request, authentication headers should be set!
+    fn upload_request<'a, S>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        content_type: &'a str,
+        body: S,
+    ) -> Self::ResponseFuture<'a>
+    where
+        S: UploadStream;
+}
+
+impl<C> HttpApiUploadClient for &C
+where
+    C: HttpApiUploadClient,
+{
+    fn upload_request<'a, S>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        content_type: &'a str,
+        body: S,
+    ) -> Self::ResponseFuture<'a>
+    where
+        S: UploadStream,
+    {
+        C::upload_request(self, method, path_and_query, content_type, body)
+    }
+}
+
+impl<C> HttpApiUploadClient for std::sync::Arc<C>
+where
+    C: HttpApiUploadClient,
+{
+    fn upload_request<'a, S>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        content_type: &'a str,
+        body: S,
+    ) -> Self::ResponseFuture<'a>
+    where
+        S: UploadStream,
+    {
+        C::upload_request(self, method, path_and_query, content_type, body)
+    }
+}
+
+impl<C> HttpApiUploadClient for std::rc::Rc<C>
+where
+    C: HttpApiUploadClient,
+{
+    fn upload_request<'a, S>(
+        &'a self,
+        method: Method,
+        path_and_query: &'a str,
+        content_type: &'a str,
+        body: S,
+    ) -> Self::ResponseFuture<'a>
+    where
+        S: UploadStream,
+    {
+        C::upload_request(self, method, path_and_query, content_type, body)
+    }
+}
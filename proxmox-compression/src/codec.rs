@@ -0,0 +1,288 @@
+//! Shared implementation of the [AsyncRead] and [AsyncWrite] compression wrappers
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::ready;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use proxmox_lang::io_format_err;
+
+/// Result of a single [Codec] operation
+pub(crate) struct Progress {
+    /// bytes consumed from the input
+    pub read: usize,
+    /// bytes written to the output
+    pub written: usize,
+    /// the end of the (compressed) stream was reached
+    pub done: bool,
+}
+
+/// A raw compression or decompression operation working on buffers
+pub(crate) trait Codec {
+    /// Process data from 'input' and place the result in 'output'
+    fn process(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<Progress>;
+
+    /// Finish the stream after all input was processed, called until `done` is set
+    fn finish(&mut self, output: &mut [u8]) -> io::Result<Progress>;
+}
+
+/// Runs a [Codec] on the data read from an [AsyncRead]
+pub(crate) struct CodecReader<R, C> {
+    inner: R,
+    codec: C,
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R, C> CodecReader<R, C> {
+    pub fn new(inner: R, codec: C, buffer_size: usize) -> Self {
+        Self {
+            inner,
+            codec,
+            buffer: vec![0u8; buffer_size].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin, C: Codec + Unpin> AsyncRead for CodecReader<R, C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.done || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.start == this.end && !this.eof {
+                let mut input = ReadBuf::new(&mut this.buffer[..]);
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut input))?;
+                this.start = 0;
+                this.end = input.filled().len();
+                this.eof = this.end == 0;
+            }
+
+            let output = buf.initialize_unfilled();
+            let progress = if this.start < this.end {
+                this.codec
+                    .process(&this.buffer[this.start..this.end], output)?
+            } else {
+                this.codec.finish(output)?
+            };
+
+            this.start += progress.read;
+            buf.advance(progress.written);
+            this.done = progress.done;
+
+            if progress.written > 0 || progress.done {
+                return Poll::Ready(Ok(()));
+            } else if progress.read == 0 {
+                return Poll::Ready(Err(io_format_err!("compression stream made no progress")));
+            }
+        }
+    }
+}
+
+/// Runs a [Codec] on the data written to it and writes the result to an [AsyncWrite]
+///
+/// The output is only complete after shutting the writer down.
+pub(crate) struct CodecWriter<W, C> {
+    inner: W,
+    codec: C,
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    done: bool,
+}
+
+impl<W, C> CodecWriter<W, C> {
+    pub fn new(inner: W, codec: C, buffer_size: usize) -> Self {
+        Self {
+            inner,
+            codec,
+            buffer: vec![0u8; buffer_size].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin, C> CodecWriter<W, C> {
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.start < self.end {
+            let written = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &self.buffer[self.start..self.end])
+            )?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.start += written;
+        }
+        self.start = 0;
+        self.end = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin, C: Codec + Unpin> AsyncWrite for CodecWriter<W, C> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            ready!(this.poll_write_buffer(cx))?;
+
+            if data.is_empty() {
+                return Poll::Ready(Ok(0));
+            } else if this.done {
+                return Poll::Ready(Err(io_format_err!(
+                    "trailing data after end of compressed stream"
+                )));
+            }
+
+            let progress = this.codec.process(data, &mut this.buffer[..])?;
+            this.end = progress.written;
+            this.done = progress.done;
+
+            if progress.read > 0 {
+                return Poll::Ready(Ok(progress.read));
+            } else if progress.written == 0 && !progress.done {
+                return Poll::Ready(Err(io_format_err!("compression stream made no progress")));
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            ready!(this.poll_write_buffer(cx))?;
+            if this.done {
+                break;
+            }
+            let progress = this.codec.finish(&mut this.buffer[..])?;
+            this.end = progress.written;
+            this.done = progress.done;
+        }
+
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Defines a public newtype around [CodecReader] with the given [Codec]
+macro_rules! codec_reader {
+    ($(#[$attr:meta])* $name:ident, $codec:ty) => {
+        $(#[$attr])*
+        pub struct $name<R> {
+            inner: $crate::codec::CodecReader<R, $codec>,
+        }
+
+        impl<R> $name<R> {
+            /// Returns a reference to the wrapped reader
+            pub fn get_ref(&self) -> &R {
+                self.inner.get_ref()
+            }
+
+            /// Returns the wrapped reader
+            pub fn into_inner(self) -> R {
+                self.inner.into_inner()
+            }
+        }
+
+        impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for $name<R> {
+            fn poll_read(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+                buf: &mut tokio::io::ReadBuf<'_>,
+            ) -> std::task::Poll<std::io::Result<()>> {
+                std::pin::Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+            }
+        }
+    };
+}
+
+/// Defines a public newtype around [CodecWriter] with the given [Codec]
+macro_rules! codec_writer {
+    ($(#[$attr:meta])* $name:ident, $codec:ty) => {
+        $(#[$attr])*
+        pub struct $name<W> {
+            inner: $crate::codec::CodecWriter<W, $codec>,
+        }
+
+        impl<W> $name<W> {
+            /// Returns a reference to the wrapped writer
+            pub fn get_ref(&self) -> &W {
+                self.inner.get_ref()
+            }
+
+            /// Returns the wrapped writer
+            pub fn into_inner(self) -> W {
+                self.inner.into_inner()
+            }
+        }
+
+        impl<W: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for $name<W> {
+            fn poll_write(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+                data: &[u8],
+            ) -> std::task::Poll<std::io::Result<usize>> {
+                std::pin::Pin::new(&mut self.get_mut().inner).poll_write(cx, data)
+            }
+
+            fn poll_flush(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<std::io::Result<()>> {
+                std::pin::Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<std::io::Result<()>> {
+                std::pin::Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+            }
+        }
+    };
+}
+
+pub(crate) use codec_reader;
+pub(crate) use codec_writer;
@@ -12,6 +12,8 @@ use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use proxmox_io::ByteBuffer;
 use proxmox_lang::io_format_err;
 
+use crate::codec::{codec_reader, codec_writer, Codec, CodecReader, CodecWriter, Progress};
+
 pub enum Level {
     Fastest,
     Best,
@@ -69,7 +71,7 @@ impl<T> DeflateEncoderBuilder<T> {
         self
     }
 
-    pub fn build(self) -> DeflateEncoder<T> {
+    fn compressor(&self) -> Compress {
         let level = match self.level {
             Level::Fastest => Compression::fast(),
             Level::Best => Compression::best(),
@@ -77,9 +79,13 @@ impl<T> DeflateEncoderBuilder<T> {
             Level::Precise(val) => Compression::new(val),
         };
 
+        Compress::new(level, self.is_zlib)
+    }
+
+    pub fn build(self) -> DeflateEncoder<T> {
         DeflateEncoder {
+            compressor: self.compressor(),
             inner: self.inner,
-            compressor: Compress::new(level, self.is_zlib),
             buffer: ByteBuffer::with_capacity(self.buffer_size),
             input_buffer: Bytes::new(),
             state: EncoderState::Reading,
@@ -88,6 +94,26 @@ impl<T> DeflateEncoderBuilder<T> {
     }
 }
 
+impl<T: AsyncRead> DeflateEncoderBuilder<T> {
+    /// Build an [AsyncRead] returning the compressed data of the wrapped reader
+    pub fn build_reader(self) -> DeflateEncoderReader<T> {
+        let compressor = self.compressor();
+        DeflateEncoderReader {
+            inner: CodecReader::new(self.inner, compressor, self.buffer_size),
+        }
+    }
+}
+
+impl<T: AsyncWrite> DeflateEncoderBuilder<T> {
+    /// Build an [AsyncWrite] compressing the written data into the wrapped writer
+    pub fn build_writer(self) -> DeflateEncoderWriter<T> {
+        let compressor = self.compressor();
+        DeflateEncoderWriter {
+            inner: CodecWriter::new(self.inner, compressor, self.buffer_size),
+        }
+    }
+}
+
 impl<T> DeflateEncoder<T> {
     pub fn new(inner: T) -> Self {
         Self::builder(inner).build()
@@ -137,6 +163,55 @@ impl<T> DeflateEncoder<T> {
     }
 }
 
+impl Codec for Compress {
+    fn process(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<Progress> {
+        let old_in = self.total_in();
+        let old_out = self.total_out();
+        self.compress(input, output, FlushCompress::None)?;
+        Ok(Progress {
+            read: (self.total_in() - old_in) as usize,
+            written: (self.total_out() - old_out) as usize,
+            done: false,
+        })
+    }
+
+    fn finish(&mut self, output: &mut [u8]) -> io::Result<Progress> {
+        let old_out = self.total_out();
+        let res = self.compress(&[][..], output, FlushCompress::Finish)?;
+        Ok(Progress {
+            read: 0,
+            written: (self.total_out() - old_out) as usize,
+            done: res == flate2::Status::StreamEnd,
+        })
+    }
+}
+
+codec_reader!(
+    /// An [AsyncRead] returning the compressed data of the wrapped reader
+    DeflateEncoderReader,
+    Compress
+);
+
+impl<R: AsyncRead> DeflateEncoderReader<R> {
+    pub fn new(inner: R) -> Self {
+        DeflateEncoder::builder(inner).build_reader()
+    }
+}
+
+codec_writer!(
+    /// An [AsyncWrite] compressing the written data into the wrapped writer
+    ///
+    /// The compressed stream is only complete after shutting the writer down.
+    DeflateEncoderWriter,
+    Compress
+);
+
+impl<W: AsyncWrite> DeflateEncoderWriter<W> {
+    pub fn new(inner: W) -> Self {
+        DeflateEncoder::builder(inner).build_writer()
+    }
+}
+
 impl DeflateEncoder<Vec<u8>> {
     // assume small files
     pub async fn compress_vec<R>(&mut self, reader: &mut R, size_hint: usize) -> Result<(), Error>
@@ -8,7 +8,12 @@ use flate2::{Decompress, FlushDecompress};
 use futures::ready;
 use futures::stream::Stream;
 
+use tokio::io::{AsyncRead, AsyncWrite};
+
 use proxmox_io::ByteBuffer;
+use proxmox_lang::io_format_err;
+
+use crate::codec::{codec_reader, codec_writer, Codec, CodecReader, CodecWriter, Progress};
 
 #[derive(Eq, PartialEq)]
 enum DecoderState {
@@ -45,8 +50,8 @@ impl<T> DeflateDecoderBuilder<T> {
 
     pub fn build(self) -> DeflateDecoder<T> {
         DeflateDecoder {
-            inner: self.inner,
             decompressor: Decompress::new(self.is_zlib),
+            inner: self.inner,
             buffer: ByteBuffer::with_capacity(self.buffer_size),
             input_buffer: Bytes::new(),
             state: DecoderState::Reading,
@@ -54,6 +59,24 @@ impl<T> DeflateDecoderBuilder<T> {
     }
 }
 
+impl<T: AsyncRead> DeflateDecoderBuilder<T> {
+    /// Build an [AsyncRead] returning the decompressed data of the wrapped reader
+    pub fn build_reader(self) -> DeflateDecoderReader<T> {
+        DeflateDecoderReader {
+            inner: CodecReader::new(self.inner, Decompress::new(self.is_zlib), self.buffer_size),
+        }
+    }
+}
+
+impl<T: AsyncWrite> DeflateDecoderBuilder<T> {
+    /// Build an [AsyncWrite] decompressing the written data into the wrapped writer
+    pub fn build_writer(self) -> DeflateDecoderWriter<T> {
+        DeflateDecoderWriter {
+            inner: CodecWriter::new(self.inner, Decompress::new(self.is_zlib), self.buffer_size),
+        }
+    }
+}
+
 impl<T> DeflateDecoder<T> {
     pub fn new(inner: T) -> Self {
         Self::builder(inner).build()
@@ -85,6 +108,60 @@ impl<T> DeflateDecoder<T> {
     }
 }
 
+impl Codec for Decompress {
+    fn process(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<Progress> {
+        let old_in = self.total_in();
+        let old_out = self.total_out();
+        let res = self.decompress(input, output, FlushDecompress::None)?;
+        Ok(Progress {
+            read: (self.total_in() - old_in) as usize,
+            written: (self.total_out() - old_out) as usize,
+            done: res == flate2::Status::StreamEnd,
+        })
+    }
+
+    fn finish(&mut self, output: &mut [u8]) -> io::Result<Progress> {
+        let old_out = self.total_out();
+        let res = self.decompress(&[][..], output, FlushDecompress::Finish)?;
+        let written = (self.total_out() - old_out) as usize;
+        if written == 0 && res == flate2::Status::BufError {
+            return Err(io_format_err!("unexpected end of compressed stream"));
+        }
+        Ok(Progress {
+            read: 0,
+            written,
+            done: res == flate2::Status::StreamEnd,
+        })
+    }
+}
+
+codec_reader!(
+    /// An [AsyncRead] returning the decompressed data of the wrapped reader
+    DeflateDecoderReader,
+    Decompress
+);
+
+impl<R: AsyncRead> DeflateDecoderReader<R> {
+    pub fn new(inner: R) -> Self {
+        DeflateDecoder::builder(inner).build_reader()
+    }
+}
+
+codec_writer!(
+    /// An [AsyncWrite] decompressing the written data into the wrapped writer
+    ///
+    /// Shutting the writer down writes the remaining data and fails if the compressed stream
+    /// was incomplete.
+    DeflateDecoderWriter,
+    Decompress
+);
+
+impl<W: AsyncWrite> DeflateDecoderWriter<W> {
+    pub fn new(inner: W) -> Self {
+        DeflateDecoder::builder(inner).build_writer()
+    }
+}
+
 impl<T, O, E> Stream for DeflateDecoder<T>
 where
     T: Stream<Item = Result<O, E>> + Unpin,
@@ -132,6 +209,12 @@ where
                     }
                     if res == flate2::Status::StreamEnd {
                         this.state = DecoderState::Finished;
+                    } else if res == flate2::Status::BufError {
+                        // no progress possible without more input
+                        this.state = DecoderState::Finished;
+                        return Poll::Ready(Some(Err(anyhow::format_err!(
+                            "unexpected end of compressed stream"
+                        ))));
                     }
                 }
                 DecoderState::Finished => return Poll::Ready(None),
@@ -1,8 +1,12 @@
 mod compression;
 mod decompression;
 
-pub use compression::{DeflateEncoder, DeflateEncoderBuilder, Level};
-pub use decompression::{DeflateDecoder, DeflateDecoderBuilder};
+pub use compression::{
+    DeflateEncoder, DeflateEncoderBuilder, DeflateEncoderReader, DeflateEncoderWriter, Level,
+};
+pub use decompression::{
+    DeflateDecoder, DeflateDecoderBuilder, DeflateDecoderReader, DeflateDecoderWriter,
+};
 
 const BUFFER_SIZE: usize = 8192;
 
@@ -103,6 +107,59 @@ si aliquod aeternum et infinitum impendere."#;
         assert_eq!(decoded, BODY.as_bytes());
     }
 
+    #[tokio::test]
+    async fn test_async_io_against_flate2() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        for is_zlib in [false, true] {
+            let mut encoded = Vec::new();
+            DeflateEncoder::builder(BODY.as_bytes())
+                .zlib(is_zlib)
+                .buffer_size(BUFFER_SIZE)
+                .build_reader()
+                .read_to_end(&mut encoded)
+                .await
+                .unwrap();
+            assert_eq!(flate2_decode(&encoded, is_zlib).unwrap(), BODY.as_bytes());
+
+            let mut decoded = Vec::new();
+            let mut writer = DeflateDecoder::builder(&mut decoded)
+                .zlib(is_zlib)
+                .buffer_size(BUFFER_SIZE)
+                .build_writer();
+            writer.write_all(&encoded).await.unwrap();
+            writer.shutdown().await.unwrap();
+            assert_eq!(decoded, BODY.as_bytes());
+
+            let mut encoded = Vec::new();
+            let mut writer = DeflateEncoder::builder(&mut encoded)
+                .zlib(is_zlib)
+                .buffer_size(BUFFER_SIZE)
+                .build_writer();
+            writer.write_all(BODY.as_bytes()).await.unwrap();
+            writer.shutdown().await.unwrap();
+
+            let mut decoded = Vec::new();
+            DeflateDecoder::builder(&encoded[..])
+                .zlib(is_zlib)
+                .buffer_size(BUFFER_SIZE)
+                .build_reader()
+                .read_to_end(&mut decoded)
+                .await
+                .unwrap();
+            assert_eq!(decoded, BODY.as_bytes());
+
+            let mut decoded = Vec::new();
+            let truncated = &encoded[..encoded.len() / 2];
+            assert!(DeflateDecoder::builder(truncated)
+                .zlib(is_zlib)
+                .build_reader()
+                .read_to_end(&mut decoded)
+                .await
+                .is_err());
+        }
+    }
+
     fn flate2_encode(bytes: &[u8], is_zlib: bool) -> Result<Vec<u8>, std::io::Error> {
         if is_zlib {
             let mut e = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
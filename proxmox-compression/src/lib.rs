@@ -1,9 +1,11 @@
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
 pub use deflate::{
-    DeflateDecoder, DeflateDecoderBuilder, DeflateEncoder, DeflateEncoderBuilder, Level,
+    DeflateDecoder, DeflateDecoderBuilder, DeflateDecoderReader, DeflateDecoderWriter,
+    DeflateEncoder, DeflateEncoderBuilder, DeflateEncoderReader, DeflateEncoderWriter, Level,
 };
 
+mod codec;
 mod deflate;
 pub mod tar;
 pub mod zip;
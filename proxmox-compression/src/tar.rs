@@ -1,16 +1,222 @@
 //! tar helper
 use std::collections::HashMap;
+use std::ffi::{CString, OsString};
 use std::io;
-use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::path::{Component, Path, PathBuf};
 use std::str;
 
-use anyhow::Error;
+use anyhow::{bail, format_err, Error};
+use nix::errno::Errno;
+use nix::sys::stat::{Mode, SFlag, UtimensatFlags};
+use nix::sys::time::TimeSpec;
+use nix::unistd::{Gid, Uid};
 
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use tar::{EntryType, Header};
 
+use proxmox_sys::fs::acl::{self, ACLTag, ACLXAttrBuffer};
+use proxmox_sys::fs::xattr;
+
+const PAX_XATTR_PREFIX: &str = "SCHILY.xattr.";
+const PAX_ACL_ACCESS: &str = "SCHILY.acl.access";
+const PAX_ACL_DEFAULT: &str = "SCHILY.acl.default";
+
+// upper limit for the size of extension headers we read into memory
+const MAX_EXTENSION_SIZE: u64 = 16 * 1024 * 1024;
+
+/// PAX extended header records of a tar entry
+///
+/// Stores what does not fit into a tar header, like extended attributes (`SCHILY.xattr.*`),
+/// POSIX ACLs (`SCHILY.acl.*`), timestamps with nanosecond precision and long paths.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PaxExtensions {
+    records: Vec<(String, Vec<u8>)>,
+}
+
+impl PaxExtensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Sets a record, replacing an existing one with the same key
+    pub fn set<V: Into<Vec<u8>>>(&mut self, key: &str, value: V) {
+        let value = value.into();
+        match self.records.iter_mut().find(|(k, _)| k == key) {
+            Some((_, old)) => *old = value,
+            None => self.records.push((key.to_string(), value)),
+        }
+    }
+
+    /// Returns the value of a record
+    ///
+    /// Empty values are treated as missing, they unset records of global headers.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.records
+            .iter()
+            .find(|(k, v)| k == key && !v.is_empty())
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Iterates over all records in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.records.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    pub fn set_mtime(&mut self, secs: i64, nsecs: u32) {
+        self.set("mtime", format_pax_time(secs, nsecs));
+    }
+
+    /// The modification time as seconds and nanoseconds since the epoch
+    pub fn mtime(&self) -> Option<(i64, u32)> {
+        parse_pax_time(self.get("mtime")?)
+    }
+
+    pub fn set_atime(&mut self, secs: i64, nsecs: u32) {
+        self.set("atime", format_pax_time(secs, nsecs));
+    }
+
+    /// The access time as seconds and nanoseconds since the epoch
+    pub fn atime(&self) -> Option<(i64, u32)> {
+        parse_pax_time(self.get("atime")?)
+    }
+
+    /// Adds an extended attribute, `name` includes the namespace (e.g. `user.foo`)
+    pub fn add_xattr(&mut self, name: &str, value: &[u8]) {
+        self.set(&format!("{PAX_XATTR_PREFIX}{name}"), value);
+    }
+
+    /// Iterates over the extended attributes as name and value
+    pub fn xattrs(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.iter()
+            .filter_map(|(k, v)| Some((k.strip_prefix(PAX_XATTR_PREFIX)?, v)))
+    }
+
+    /// Sets the access ACL in short text form (e.g. `user::rw-,group::r--,other::r--`)
+    pub fn set_acl_access(&mut self, acl: &str) {
+        self.set(PAX_ACL_ACCESS, acl);
+    }
+
+    pub fn acl_access(&self) -> Option<&str> {
+        str::from_utf8(self.get(PAX_ACL_ACCESS)?).ok()
+    }
+
+    /// Sets the default ACL of a directory in short text form
+    pub fn set_acl_default(&mut self, acl: &str) {
+        self.set(PAX_ACL_DEFAULT, acl);
+    }
+
+    pub fn acl_default(&self) -> Option<&str> {
+        str::from_utf8(self.get(PAX_ACL_DEFAULT)?).ok()
+    }
+
+    fn merge(&mut self, other: &PaxExtensions) {
+        for (key, value) in other.iter() {
+            self.set(key, value);
+        }
+    }
+
+    fn get_u64(&self, key: &str) -> Result<Option<u64>, Error> {
+        match self.get(key) {
+            Some(value) => {
+                Ok(Some(str::from_utf8(value)?.parse().map_err(|err| {
+                    format_err!("invalid pax record '{key}' - {err}")
+                })?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Encodes the records as '<length> <key>=<value>\n', the length includes itself
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (key, value) in self.iter() {
+            let rest = key.len() + value.len() + 3;
+            let mut len = rest + 1;
+            while len != rest + len.to_string().len() {
+                len = rest + len.to_string().len();
+            }
+            data.extend_from_slice(format!("{len} {key}=").as_bytes());
+            data.extend_from_slice(value);
+            data.push(b'\n');
+        }
+        data
+    }
+
+    fn parse(mut data: &[u8]) -> Result<Self, Error> {
+        let mut extensions = Self::new();
+        while !data.is_empty() && data[0] != 0 {
+            let space = data
+                .iter()
+                .position(|b| *b == b' ')
+                .ok_or_else(|| format_err!("invalid pax record - missing length"))?;
+            let len: usize = str::from_utf8(&data[..space])?
+                .parse()
+                .map_err(|err| format_err!("invalid pax record length - {err}"))?;
+            if len <= space + 1 || len > data.len() || data[len - 1] != b'\n' {
+                bail!("invalid pax record length {len}");
+            }
+
+            let record = &data[space + 1..len - 1];
+            let equal = record
+                .iter()
+                .position(|b| *b == b'=')
+                .ok_or_else(|| format_err!("invalid pax record - missing '='"))?;
+            extensions.set(str::from_utf8(&record[..equal])?, &record[equal + 1..]);
+
+            data = &data[len..];
+        }
+        Ok(extensions)
+    }
+}
+
+fn format_pax_time(secs: i64, nsecs: u32) -> String {
+    let (sign, secs, nsecs) = if secs < 0 && nsecs > 0 {
+        ("-", -(secs + 1), 1_000_000_000 - nsecs)
+    } else {
+        ("", secs, nsecs)
+    };
+    if nsecs == 0 {
+        format!("{secs}")
+    } else {
+        let fraction = format!("{nsecs:09}");
+        format!("{sign}{secs}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+fn parse_pax_time(value: &[u8]) -> Option<(i64, u32)> {
+    let value = str::from_utf8(value).ok()?;
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let (secs, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let secs: i64 = secs.parse().ok()?;
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nsecs = fraction
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0u32, |nsecs, digit| nsecs * 10 + (digit - b'0') as u32);
+
+    if negative && nsecs > 0 {
+        Some((-secs - 1, 1_000_000_000 - nsecs))
+    } else if negative {
+        Some((-secs, 0))
+    } else {
+        Some((secs, nsecs))
+    }
+}
+
 /// An async Builder for tar archives based on [tar::Builder]
 ///
 /// Wraps an inner [AsyncWrite] struct to write into.
@@ -88,6 +294,67 @@ impl<W: AsyncWrite + Unpin> Builder<W> {
         self.add(header, tokio::io::empty()).await
     }
 
+    /// Adds a new entry with PAX extended header records to this archive.
+    ///
+    /// Paths not fitting into the header are stored as PAX record instead of a GNU extension
+    /// header.
+    pub async fn add_entry_with_extensions<P, R>(
+        &mut self,
+        header: &mut Header,
+        path: P,
+        data: R,
+        extensions: &PaxExtensions,
+    ) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        R: AsyncRead + Unpin,
+    {
+        let mut extensions = extensions.clone();
+        let path = relative_path(path.as_ref());
+        set_pax_path(header, &path, &mut extensions)?;
+        self.add_pax_header(&path, &extensions).await?;
+        header.set_cksum();
+        self.add(header, data).await
+    }
+
+    /// Adds a new link (symbolic or hard) entry with PAX extended header records to this
+    /// archive.
+    ///
+    /// Paths and link targets not fitting into the header are stored as PAX record instead of a
+    /// GNU extension header.
+    pub async fn add_link_with_extensions<P: AsRef<Path>, T: AsRef<Path>>(
+        &mut self,
+        header: &mut Header,
+        path: P,
+        target: T,
+        extensions: &PaxExtensions,
+    ) -> io::Result<()> {
+        let mut extensions = extensions.clone();
+        let path = relative_path(path.as_ref());
+        set_pax_path(header, &path, &mut extensions)?;
+
+        if let Err(err) = header.set_link_name(target.as_ref()) {
+            let link_name = target.as_ref().as_os_str().as_bytes();
+            if link_name.len() < header.as_old().linkname.len() {
+                return Err(err);
+            }
+            extensions.set("linkpath", link_name);
+        }
+
+        self.add_pax_header(&path, &extensions).await?;
+        header.set_cksum();
+        self.add(header, tokio::io::empty()).await
+    }
+
+    async fn add_pax_header(&mut self, path: &Path, extensions: &PaxExtensions) -> io::Result<()> {
+        if extensions.is_empty() {
+            return Ok(());
+        }
+        let data = extensions.encode();
+        let header = get_pax_header(path, data.len() as u64);
+        append_data(&mut self.inner, &header, &mut &data[..]).await
+    }
+
     /// Finish the archive and flush the underlying writer
     ///
     /// Consumes the Builder. This must be called when finishing the archive.
@@ -131,19 +398,49 @@ fn get_gnu_header(size: u64, entry_type: EntryType) -> Header {
     header
 }
 
-// tries to set the path in header, or add a gnu header with 'LongName'
-async fn append_path_header<W>(dst: &mut W, header: &mut Header, path: &Path) -> io::Result<()>
-where
-    W: AsyncWrite + Unpin,
-{
+fn get_pax_header(path: &Path, size: u64) -> Header {
+    let mut header = Header::new_ustar();
+    let mut name = b"PaxHeaders/".to_vec();
+    name.extend_from_slice(path.file_name().unwrap_or_default().as_bytes());
+    let name_field = &mut header.as_old_mut().name;
+    let len = name.len().min(name_field.len());
+    name_field[..len].copy_from_slice(&name[..len]);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header.set_size(size);
+    header.set_entry_type(EntryType::XHeader);
+    header.set_cksum();
+    header
+}
+
+fn relative_path(path: &Path) -> PathBuf {
     let mut relpath = PathBuf::new();
-    let components = path.components();
-    for comp in components {
+    for comp in path.components() {
         if Component::RootDir == comp {
             continue;
         }
         relpath.push(comp);
     }
+    relpath
+}
+
+// the longest prefix of 'data' that fits into 'max' bytes and is valid utf-8
+fn truncated_path(data: &[u8], max: usize) -> &str {
+    let data = &data[..data.len().min(max)];
+    match str::from_utf8(data) {
+        Ok(truncated) => truncated,
+        Err(err) => str::from_utf8(&data[..err.valid_up_to()]).unwrap(),
+    }
+}
+
+// tries to set the path in header, or add a gnu header with 'LongName'
+async fn append_path_header<W>(dst: &mut W, header: &mut Header, path: &Path) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let relpath = relative_path(path);
     // try to set the path directly, fallback to gnu extension header otherwise
     if let Err(err) = header.set_path(&relpath) {
         let data = relpath.as_os_str().as_bytes();
@@ -157,16 +454,52 @@ where
         append_data(dst, &extension, &mut ext_data).await?;
 
         // add the path as far as we can
-        let truncated = match str::from_utf8(&data[..max]) {
-            Ok(truncated) => truncated,
-            Err(err) => str::from_utf8(&data[..err.valid_up_to()]).unwrap(),
-        };
-        header.set_path(truncated)?;
+        header.set_path(truncated_path(data, max))?;
+    }
+    Ok(())
+}
+
+// tries to set the path in header, or add it as pax record
+fn set_pax_path(
+    header: &mut Header,
+    path: &Path,
+    extensions: &mut PaxExtensions,
+) -> io::Result<()> {
+    if let Err(err) = header.set_path(path) {
+        let data = path.as_os_str().as_bytes();
+        let max = header.as_old().name.len();
+        if data.len() < max {
+            return Err(err);
+        }
+        extensions.set("path", data);
+        header.set_path(truncated_path(data, max))?;
     }
     Ok(())
 }
 
+/// Tar a local directory and write the archive to target, see [zip_directory] for the layout.
+///
+/// Extended attributes, POSIX ACLs and modification times with nanosecond precision are stored in
+/// PAX extended headers. Access times are not stored, so that archives of unchanged directories
+/// are identical, see [tar_directory_with_atime].
+///
+/// [zip_directory]: crate::zip::zip_directory
 pub async fn tar_directory<W>(target: W, source: &Path) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    tar_directory_impl(target, source, false).await
+}
+
+/// Like [tar_directory], but also stores access times.
+pub async fn tar_directory_with_atime<W>(target: W, source: &Path) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    tar_directory_impl(target, source, true).await
+}
+
+async fn tar_directory_impl<W>(target: W, source: &Path, store_atime: bool) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin + Send,
 {
@@ -201,6 +534,14 @@ where
             header.set_size(0);
             let dev = metadata.dev();
 
+            let mut extensions = PaxExtensions::new();
+            if metadata.mtime_nsec() != 0 {
+                extensions.set_mtime(metadata.mtime(), metadata.mtime_nsec() as u32);
+            }
+            if store_atime {
+                extensions.set_atime(metadata.atime(), metadata.atime_nsec() as u32);
+            }
+
             let file_type = entry.file_type();
 
             if file_type.is_file() {
@@ -210,7 +551,12 @@ where
                         if let Some(target) = map.get(&ino) {
                             header.set_entry_type(tar::EntryType::Link);
                             encoder
-                                .add_link(&mut header, entry_path_no_base, target)
+                                .add_link_with_extensions(
+                                    &mut header,
+                                    entry_path_no_base,
+                                    target,
+                                    &extensions,
+                                )
                                 .await?;
                             return Ok(());
                         } else {
@@ -223,22 +569,30 @@ where
                     }
                 }
                 let file = tokio::fs::File::open(entry.path()).await?;
+                add_fs_extensions(file.as_raw_fd(), false, entry.path(), &mut extensions);
                 header.set_size(metadata.size());
                 header.set_cksum();
                 encoder
-                    .add_entry(&mut header, entry_path_no_base, file)
+                    .add_entry_with_extensions(&mut header, entry_path_no_base, file, &extensions)
                     .await?;
             } else if file_type.is_dir() {
+                let dir = std::fs::File::open(entry.path())?;
+                add_fs_extensions(dir.as_raw_fd(), true, entry.path(), &mut extensions);
                 header.set_entry_type(EntryType::Directory);
                 header.set_cksum();
                 encoder
-                    .add_entry(&mut header, entry_path_no_base, tokio::io::empty())
+                    .add_entry_with_extensions(
+                        &mut header,
+                        entry_path_no_base,
+                        tokio::io::empty(),
+                        &extensions,
+                    )
                     .await?;
             } else if file_type.is_symlink() {
                 let target = std::fs::read_link(entry.path())?;
                 header.set_entry_type(EntryType::Symlink);
                 encoder
-                    .add_link(&mut header, entry_path_no_base, target)
+                    .add_link_with_extensions(&mut header, entry_path_no_base, target, &extensions)
                     .await?;
             } else if file_type.is_block_device() {
                 header.set_entry_type(EntryType::Block);
@@ -246,7 +600,12 @@ where
                 header.set_device_minor(unsafe { libc::minor(dev) })?;
                 header.set_cksum();
                 encoder
-                    .add_entry(&mut header, entry_path_no_base, tokio::io::empty())
+                    .add_entry_with_extensions(
+                        &mut header,
+                        entry_path_no_base,
+                        tokio::io::empty(),
+                        &extensions,
+                    )
                     .await?;
             } else if file_type.is_char_device() {
                 header.set_entry_type(EntryType::Char);
@@ -254,7 +613,12 @@ where
                 header.set_device_minor(unsafe { libc::minor(dev) })?;
                 header.set_cksum();
                 encoder
-                    .add_entry(&mut header, entry_path_no_base, tokio::io::empty())
+                    .add_entry_with_extensions(
+                        &mut header,
+                        entry_path_no_base,
+                        tokio::io::empty(),
+                        &extensions,
+                    )
                     .await?;
             } else if file_type.is_fifo() {
                 header.set_entry_type(EntryType::Fifo);
@@ -262,7 +626,12 @@ where
                 header.set_device_minor(0)?;
                 header.set_cksum();
                 encoder
-                    .add_entry(&mut header, entry_path_no_base, tokio::io::empty())
+                    .add_entry_with_extensions(
+                        &mut header,
+                        entry_path_no_base,
+                        tokio::io::empty(),
+                        &extensions,
+                    )
                     .await?;
             }
             // ignore other file_types
@@ -279,3 +648,978 @@ where
     }
     Ok(())
 }
+
+// adds the extended attributes and ACLs of an open file or directory, the entry is kept without
+// them if they cannot be read
+fn add_fs_extensions(fd: RawFd, is_dir: bool, path: &Path, extensions: &mut PaxExtensions) {
+    let mut with_fs_extensions = extensions.clone();
+    match read_fs_extensions(fd, is_dir, &mut with_fs_extensions) {
+        Ok(()) => *extensions = with_fs_extensions,
+        Err(err) => eprintln!(
+            "tar: could not read extended attributes of '{}': {err}",
+            path.display()
+        ),
+    }
+}
+
+// collects the extended attributes and ACLs of an open file or directory
+fn read_fs_extensions(
+    fd: RawFd,
+    is_dir: bool,
+    extensions: &mut PaxExtensions,
+) -> Result<(), Error> {
+    let names = match xattr::flistxattr(fd) {
+        Ok(names) => names,
+        Err(Errno::EOPNOTSUPP) => return Ok(()),
+        Err(err) => return Err(format_err!("failed to list xattrs - {err}")),
+    };
+
+    for name in &names {
+        if xattr::is_acl(name) || !xattr::is_valid_xattr_name(name) {
+            continue;
+        }
+        let Ok(name_str) = name.to_str() else {
+            continue;
+        };
+        match xattr::fgetxattr(fd, name) {
+            Ok(value) => extensions.add_xattr(name_str, &value),
+            Err(Errno::ENODATA) => continue, // removed in the meantime
+            Err(err) => bail!("failed to read xattr {name_str} - {err}"),
+        }
+    }
+
+    match xattr::fgetxattr(fd, xattr::XATTR_ACL_ACCESS) {
+        Ok(data) => extensions.set_acl_access(&acl_xattr_to_text(&data)?),
+        Err(Errno::ENODATA | Errno::EOPNOTSUPP) => {}
+        Err(err) => bail!("failed to read access ACL - {err}"),
+    }
+
+    if is_dir {
+        match xattr::fgetxattr(fd, xattr::XATTR_ACL_DEFAULT) {
+            Ok(data) => extensions.set_acl_default(&acl_xattr_to_text(&data)?),
+            Err(Errno::ENODATA | Errno::EOPNOTSUPP) => {}
+            Err(err) => bail!("failed to read default ACL - {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+// converts the 'system.posix_acl_*' xattr format to the short text form of acl_to_text
+fn acl_xattr_to_text(data: &[u8]) -> Result<String, Error> {
+    if data.len() < 4 || !data[4..].chunks_exact(8).remainder().is_empty() {
+        bail!("invalid ACL xattr size {}", data.len());
+    }
+    let version = u32::from_le_bytes(data[..4].try_into().unwrap());
+    if version != acl::ACL_EA_VERSION {
+        bail!("unsupported ACL xattr version {version}");
+    }
+
+    let mut entries = Vec::new();
+    for entry in data[4..].chunks_exact(8) {
+        let tag = u16::from_le_bytes([entry[0], entry[1]]) as ACLTag;
+        let perms = u16::from_le_bytes([entry[2], entry[3]]);
+        let id = u32::from_le_bytes(entry[4..].try_into().unwrap());
+
+        let prefix = match tag {
+            acl::ACL_USER_OBJ => "user::".to_string(),
+            acl::ACL_USER => format!("user:{id}:"),
+            acl::ACL_GROUP_OBJ => "group::".to_string(),
+            acl::ACL_GROUP => format!("group:{id}:"),
+            acl::ACL_MASK => "mask::".to_string(),
+            acl::ACL_OTHER => "other::".to_string(),
+            _ => bail!("unknown ACL tag {tag}"),
+        };
+        let mut text = prefix;
+        for (bit, c) in [
+            (acl::ACL_READ, 'r'),
+            (acl::ACL_WRITE, 'w'),
+            (acl::ACL_EXECUTE, 'x'),
+        ] {
+            text.push(if perms as u32 & bit != 0 { c } else { '-' });
+        }
+        entries.push(text);
+    }
+
+    Ok(entries.join(","))
+}
+
+// converts ACLs in text form (comma or newline separated, as written by star and GNU tar) to
+// the 'system.posix_acl_*' xattr format
+fn acl_text_to_xattr(text: &str) -> Result<Vec<u8>, Error> {
+    let mut entries = Vec::new();
+
+    for entry in text.split([',', '\n']) {
+        let entry = entry.split('#').next().unwrap_or_default().trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let parts: Vec<&str> = entry.split(':').collect();
+        if parts.len() < 3 {
+            bail!("invalid ACL entry '{entry}'");
+        }
+        let (tag, qualifier, perms) = (parts[0], parts[1], parts[2]);
+        // star appends the numeric id to named entries
+        let id = parts.get(3).copied().unwrap_or(qualifier);
+
+        let (tag, qualifier) = match (tag, qualifier.is_empty()) {
+            ("user" | "u", true) => (acl::ACL_USER_OBJ, None),
+            ("user" | "u", false) => (acl::ACL_USER, Some(acl_qualifier(id, true)?)),
+            ("group" | "g", true) => (acl::ACL_GROUP_OBJ, None),
+            ("group" | "g", false) => (acl::ACL_GROUP, Some(acl_qualifier(id, false)?)),
+            ("mask" | "m", _) => (acl::ACL_MASK, None),
+            ("other" | "o", _) => (acl::ACL_OTHER, None),
+            _ => bail!("invalid ACL entry '{entry}'"),
+        };
+
+        let mut permissions = 0;
+        for c in perms.chars() {
+            permissions |= match c {
+                'r' => acl::ACL_READ,
+                'w' => acl::ACL_WRITE,
+                'x' => acl::ACL_EXECUTE,
+                '-' => 0,
+                _ => bail!("invalid ACL permissions '{perms}'"),
+            };
+        }
+
+        entries.push((tag, qualifier, permissions as u64));
+    }
+
+    // the kernel expects the entries sorted by tag and qualifier
+    entries.sort_by_key(|(tag, qualifier, _)| (*tag, *qualifier));
+
+    let mut buffer = ACLXAttrBuffer::new(acl::ACL_EA_VERSION);
+    for (tag, qualifier, permissions) in entries {
+        buffer.add_entry(tag, qualifier, permissions);
+    }
+    Ok(buffer.as_mut_slice().to_vec())
+}
+
+fn acl_qualifier(name: &str, is_user: bool) -> Result<u64, Error> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    let id = if is_user {
+        nix::unistd::User::from_name(name)?.map(|user| user.uid.as_raw())
+    } else {
+        nix::unistd::Group::from_name(name)?.map(|group| group.gid.as_raw())
+    };
+    id.map(u64::from)
+        .ok_or_else(|| format_err!("unknown user or group '{name}' in ACL"))
+}
+
+/// An entry of a tar archive read by [Archive]
+///
+/// Contains the header and the PAX extended header records of the entry, which already
+/// include the records of global headers.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    header: Header,
+    path: PathBuf,
+    link_name: Option<PathBuf>,
+    extensions: PaxExtensions,
+    size: u64,
+}
+
+impl Entry {
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn entry_type(&self) -> EntryType {
+        self.header.entry_type()
+    }
+
+    /// The path of the entry, from PAX or GNU extension headers if present
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The target of links, from PAX or GNU extension headers if present
+    pub fn link_name(&self) -> Option<&Path> {
+        self.link_name.as_deref()
+    }
+
+    pub fn extensions(&self) -> &PaxExtensions {
+        &self.extensions
+    }
+
+    /// Size of the content
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The modification time as seconds and nanoseconds since the epoch
+    pub fn mtime(&self) -> io::Result<(i64, u32)> {
+        match self.extensions.mtime() {
+            Some(mtime) => Ok(mtime),
+            None => Ok((self.header.mtime()? as i64, 0)),
+        }
+    }
+
+    pub fn uid(&self) -> Result<u64, Error> {
+        match self.extensions.get_u64("uid")? {
+            Some(uid) => Ok(uid),
+            None => Ok(self.header.uid()?),
+        }
+    }
+
+    pub fn gid(&self) -> Result<u64, Error> {
+        match self.extensions.get_u64("gid")? {
+            Some(gid) => Ok(gid),
+            None => Ok(self.header.gid()?),
+        }
+    }
+}
+
+/// An async reader for tar archives
+///
+/// Understands PAX extended headers and GNU long name and link headers, as written by
+/// [Builder].
+/// # Example
+///
+/// ```no_run
+/// use proxmox_compression::tar::Archive;
+///
+/// # async fn foo() -> Result<(), anyhow::Error> {
+/// let file = tokio::fs::File::open("foo.tar").await?;
+/// let mut archive = Archive::new(file);
+///
+/// while let Some(entry) = archive.next_entry().await? {
+///     println!("{} ({} bytes)", entry.path().display(), entry.size());
+///     if entry.path().ends_with("foo.txt") {
+///         let mut content = Vec::new();
+///         archive.read_data(&mut content).await?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Archive<R: AsyncRead + Unpin> {
+    inner: R,
+    data_remaining: u64,
+    padding: u64,
+    global_extensions: PaxExtensions,
+}
+
+impl<R: AsyncRead + Unpin> Archive<R> {
+    /// Takes an AsyncReader as source
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            data_remaining: 0,
+            padding: 0,
+            global_extensions: PaxExtensions::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // returns None at the end of the source
+    async fn read_header(&mut self) -> io::Result<Option<Header>> {
+        let mut header = Header::new_old();
+        let buffer = header.as_mut_bytes();
+        let mut pos = 0;
+        while pos < buffer.len() {
+            match self.inner.read(&mut buffer[pos..]).await? {
+                0 if pos == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                read => pos += read,
+            }
+        }
+        Ok(Some(header))
+    }
+
+    fn set_data_size(&mut self, size: u64) {
+        self.data_remaining = size;
+        self.padding = (512 - size % 512) % 512;
+    }
+
+    async fn skip_data(&mut self) -> io::Result<()> {
+        let len = self.data_remaining + self.padding;
+        let mut data = (&mut self.inner).take(len);
+        if tokio::io::copy(&mut data, &mut tokio::io::sink()).await? != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.data_remaining = 0;
+        self.padding = 0;
+        Ok(())
+    }
+
+    async fn read_extension_data(&mut self) -> Result<Vec<u8>, Error> {
+        if self.data_remaining > MAX_EXTENSION_SIZE {
+            bail!("extension header too large ({} bytes)", self.data_remaining);
+        }
+        let mut data = Vec::new();
+        self.read_data(&mut data).await?;
+        Ok(data)
+    }
+
+    /// Returns the next entry of the archive, or None at its end
+    ///
+    /// Skips the remaining content of the previous entry.
+    pub async fn next_entry(&mut self) -> Result<Option<Entry>, Error> {
+        let mut extensions = PaxExtensions::new();
+        let mut long_name = None;
+        let mut long_link = None;
+
+        loop {
+            self.skip_data().await?;
+
+            let header = match self.read_header().await? {
+                Some(header) if header.as_bytes().iter().any(|b| *b != 0) => header,
+                _ => return Ok(None),
+            };
+
+            let mut check = header.clone();
+            check.set_cksum();
+            if check.cksum()? != header.cksum()? {
+                bail!("invalid tar header checksum");
+            }
+
+            self.set_data_size(header.entry_size()?);
+
+            match header.entry_type() {
+                EntryType::XHeader => {
+                    extensions.merge(&PaxExtensions::parse(&self.read_extension_data().await?)?);
+                }
+                EntryType::XGlobalHeader => {
+                    let global = PaxExtensions::parse(&self.read_extension_data().await?)?;
+                    self.global_extensions.merge(&global);
+                }
+                EntryType::GNULongName => {
+                    long_name = Some(trim_nul(self.read_extension_data().await?));
+                }
+                EntryType::GNULongLink => {
+                    long_link = Some(trim_nul(self.read_extension_data().await?));
+                }
+                _ => {
+                    let mut all_extensions = self.global_extensions.clone();
+                    all_extensions.merge(&extensions);
+
+                    if let Some(size) = all_extensions.get_u64("size")? {
+                        self.set_data_size(size);
+                    }
+
+                    let path = all_extensions
+                        .get("path")
+                        .map(<[u8]>::to_vec)
+                        .or(long_name)
+                        .unwrap_or_else(|| header.path_bytes().into_owned());
+                    let link_name = all_extensions
+                        .get("linkpath")
+                        .map(<[u8]>::to_vec)
+                        .or(long_link)
+                        .or_else(|| header.link_name_bytes().map(|name| name.into_owned()));
+
+                    return Ok(Some(Entry {
+                        header,
+                        path: OsString::from_vec(path).into(),
+                        link_name: link_name.map(|name| OsString::from_vec(name).into()),
+                        extensions: all_extensions,
+                        size: self.data_remaining,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Writes the content of the current entry to 'target'
+    ///
+    /// Returns the number of bytes written, which is 0 if the content was already read.
+    pub async fn read_data<W>(&mut self, target: &mut W) -> io::Result<u64>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let len = self.data_remaining;
+        let mut data = (&mut self.inner).take(len);
+        if tokio::io::copy(&mut data, target).await? != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.data_remaining = 0;
+        Ok(len)
+    }
+
+    /// Extracts all entries of the archive below 'target'
+    ///
+    /// Entries with paths leaving 'target' are refused. Permissions, timestamps, extended
+    /// attributes and ACLs are restored, the ownership only when running as root. Failing
+    /// entries are reported and skipped, an error is returned at the end if there were any.
+    pub async fn extract_to_directory(&mut self, target: &Path) -> Result<(), Error> {
+        tokio::fs::create_dir_all(target).await?;
+
+        let is_root = nix::unistd::geteuid().is_root();
+        let mut directories = Vec::new();
+        let mut errors = 0;
+
+        while let Some(entry) = self.next_entry().await? {
+            if let Err(err) = self
+                .extract_entry(&entry, target, is_root, &mut directories)
+                .await
+            {
+                eprintln!(
+                    "tar: error extracting '{}': {}",
+                    entry.path().display(),
+                    err
+                );
+                errors += 1;
+            }
+        }
+
+        // directory metadata is restored last, as extracting their content changes it
+        for (path, entry) in directories.into_iter().rev() {
+            let mut flags = libc::O_DIRECTORY;
+            if path != target {
+                flags |= libc::O_NOFOLLOW;
+            }
+            let result = std::fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(flags)
+                .open(&path)
+                .map_err(Error::from)
+                .and_then(|dir| restore_metadata(dir.as_raw_fd(), &path, &entry, is_root));
+            if let Err(err) = result {
+                eprintln!("tar: error restoring '{}': {}", path.display(), err);
+                errors += 1;
+            }
+        }
+
+        if errors > 0 {
+            bail!("failed to extract {errors} entries");
+        }
+        Ok(())
+    }
+
+    async fn extract_entry(
+        &mut self,
+        entry: &Entry,
+        target: &Path,
+        is_root: bool,
+        directories: &mut Vec<(PathBuf, Entry)>,
+    ) -> Result<(), Error> {
+        let path = safe_target_path(target, entry.path())?;
+
+        let entry_type = entry.entry_type();
+        if entry_type == EntryType::Directory {
+            // an earlier symlink entry must not redirect the directory or its metadata
+            if path != target {
+                if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+                    if metadata.file_type().is_symlink() {
+                        bail!("refusing to extract directory over a symlink");
+                    }
+                }
+            }
+            tokio::fs::create_dir_all(&path).await?;
+            directories.push((path, entry.clone()));
+            return Ok(());
+        }
+
+        if path == target {
+            bail!("invalid path for {entry_type:?} entry");
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        match entry_type {
+            EntryType::Regular | EntryType::Continuous => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .custom_flags(libc::O_NOFOLLOW)
+                    .open(&path)
+                    .await?;
+                self.read_data(&mut file).await?;
+                file.flush().await?;
+                let file = file.into_std().await;
+                restore_metadata(file.as_raw_fd(), &path, entry, is_root)?;
+            }
+            EntryType::Link => {
+                let link_name = entry
+                    .link_name()
+                    .ok_or_else(|| format_err!("missing hardlink target"))?;
+                let link_target = safe_target_path(target, link_name)?;
+                remove_existing(&path)?;
+                std::fs::hard_link(link_target, &path)?;
+            }
+            EntryType::Symlink => {
+                let link_name = entry
+                    .link_name()
+                    .ok_or_else(|| format_err!("missing symlink target"))?;
+                remove_existing(&path)?;
+                std::os::unix::fs::symlink(link_name, &path)?;
+                restore_path_metadata(&path, entry, is_root, false)?;
+            }
+            EntryType::Block | EntryType::Char | EntryType::Fifo => {
+                let kind = match entry_type {
+                    EntryType::Block => SFlag::S_IFBLK,
+                    EntryType::Char => SFlag::S_IFCHR,
+                    _ => SFlag::S_IFIFO,
+                };
+                let major = entry.header().device_major()?.unwrap_or(0);
+                let minor = entry.header().device_minor()?.unwrap_or(0);
+                remove_existing(&path)?;
+                nix::sys::stat::mknod(
+                    &path,
+                    kind,
+                    Mode::from_bits_truncate(0o600),
+                    libc::makedev(major, minor),
+                )?;
+                restore_path_metadata(&path, entry, is_root, true)?;
+            }
+            other => bail!("unsupported entry type {other:?}"),
+        }
+
+        Ok(())
+    }
+}
+
+fn trim_nul(mut data: Vec<u8>) -> Vec<u8> {
+    while data.last() == Some(&0) {
+        data.pop();
+    }
+    data
+}
+
+// joins 'path' to 'target', refusing paths leaving 'target' or passing through symlinks
+fn safe_target_path(target: &Path, path: &Path) -> Result<PathBuf, Error> {
+    let mut result = target.to_path_buf();
+    let mut components = path.components().peekable();
+    while let Some(comp) = components.next() {
+        match comp {
+            Component::Normal(name) => result.push(name),
+            Component::RootDir | Component::CurDir => continue,
+            _ => bail!(
+                "refusing to extract '{}' outside of the target",
+                path.display()
+            ),
+        }
+        // only the last component may be a symlink
+        if components.peek().is_some() {
+            if let Ok(metadata) = std::fs::symlink_metadata(&result) {
+                if metadata.file_type().is_symlink() {
+                    bail!("refusing to extract '{}' through a symlink", path.display());
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn remove_existing(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn pax_timespec(time: Option<(i64, u32)>) -> TimeSpec {
+    match time {
+        Some((secs, nsecs)) => TimeSpec::new(secs, nsecs as i64),
+        None => TimeSpec::UTIME_OMIT,
+    }
+}
+
+// restores ownership, mode, xattrs, ACLs and times of an extracted file or directory
+fn restore_metadata(fd: RawFd, path: &Path, entry: &Entry, is_root: bool) -> Result<(), Error> {
+    if is_root {
+        let uid = Uid::from_raw(entry.uid()? as u32);
+        let gid = Gid::from_raw(entry.gid()? as u32);
+        nix::unistd::fchown(fd, Some(uid), Some(gid))?;
+    }
+
+    let mode = Mode::from_bits_truncate(entry.header().mode()? & 0o7777);
+    nix::sys::stat::fchmod(fd, mode)?;
+
+    // failing to restore xattrs and ACLs (e.g. due to missing privileges or support of the
+    // file system) only warrants a warning
+    for (name, value) in entry.extensions().xattrs() {
+        let result = CString::new(name)
+            .map_err(Error::from)
+            .and_then(|name| Ok(xattr::fsetxattr(fd, &name, value)?));
+        if let Err(err) = result {
+            eprintln!(
+                "tar: could not restore xattr {name} of '{}': {err}",
+                path.display()
+            );
+        }
+    }
+
+    let acls = [
+        (entry.extensions().acl_access(), xattr::XATTR_ACL_ACCESS),
+        (entry.extensions().acl_default(), xattr::XATTR_ACL_DEFAULT),
+    ];
+    for (acl, xattr_name) in acls {
+        let Some(acl) = acl else {
+            continue;
+        };
+        let result =
+            acl_text_to_xattr(acl).and_then(|data| Ok(xattr::fsetxattr(fd, xattr_name, &data)?));
+        if let Err(err) = result {
+            eprintln!("tar: could not restore ACL of '{}': {err}", path.display());
+        }
+    }
+
+    let mtime = entry.mtime()?;
+    nix::sys::stat::futimens(
+        fd,
+        &pax_timespec(entry.extensions().atime()),
+        &pax_timespec(Some(mtime)),
+    )?;
+
+    Ok(())
+}
+
+// restores ownership, mode and times of symlinks and special files
+fn restore_path_metadata(
+    path: &Path,
+    entry: &Entry,
+    is_root: bool,
+    chmod: bool,
+) -> Result<(), Error> {
+    if is_root {
+        let uid = Uid::from_raw(entry.uid()? as u32);
+        let gid = Gid::from_raw(entry.gid()? as u32);
+        nix::unistd::fchownat(
+            None,
+            path,
+            Some(uid),
+            Some(gid),
+            nix::fcntl::AtFlags::AT_SYMLINK_NOFOLLOW,
+        )?;
+    }
+
+    if chmod {
+        let mode = Mode::from_bits_truncate(entry.header().mode()? & 0o7777);
+        nix::sys::stat::fchmodat(
+            None,
+            path,
+            mode,
+            nix::sys::stat::FchmodatFlags::FollowSymlink,
+        )?;
+    }
+
+    let mtime = entry.mtime()?;
+    nix::sys::stat::utimensat(
+        None,
+        path,
+        &pax_timespec(entry.extensions().atime()),
+        &pax_timespec(Some(mtime)),
+        UtimensatFlags::NoFollowSymlink,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use super::*;
+
+    const MTIME: i64 = 1_700_000_000;
+
+    async fn read_entries(archive: Vec<u8>) -> Vec<(Entry, Vec<u8>)> {
+        let mut archive = Archive::new(Cursor::new(archive));
+        let mut entries = Vec::new();
+        while let Some(entry) = archive.next_entry().await.unwrap() {
+            let mut content = Vec::new();
+            archive.read_data(&mut content).await.unwrap();
+            entries.push((entry, content));
+        }
+        entries
+    }
+
+    fn test_header(entry_type: EntryType, size: u64, mode: u32) -> Header {
+        let mut header = Header::new_ustar();
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(mode);
+        header.set_mtime(MTIME as u64);
+        header.set_uid(nix::unistd::geteuid().as_raw() as u64);
+        header.set_gid(nix::unistd::getegid().as_raw() as u64);
+        header
+    }
+
+    #[test]
+    fn test_pax_records() {
+        let mut extensions = PaxExtensions::new();
+        extensions.add_xattr("user.foo", b"bar\nbaz=\0");
+        extensions.set_acl_access("user::rw-,group::r--,other::---");
+        // the record is 98 bytes without its length, which needs three digits
+        extensions.set("comment", "x".repeat(88));
+        extensions.set_mtime(MTIME, 500);
+
+        let data = extensions.encode();
+        assert!(data.starts_with(b"35 SCHILY.xattr.user.foo=bar\nbaz=\0\n"));
+        assert!(str::from_utf8(&data)
+            .unwrap()
+            .contains(&format!("101 comment={}\n", "x".repeat(88))));
+
+        let parsed = PaxExtensions::parse(&data).unwrap();
+        assert_eq!(parsed, extensions);
+        assert_eq!(
+            parsed.xattrs().collect::<Vec<_>>(),
+            [("user.foo", &b"bar\nbaz=\0"[..])]
+        );
+        assert_eq!(parsed.mtime(), Some((MTIME, 500)));
+        assert_eq!(parsed.atime(), None);
+
+        assert!(PaxExtensions::parse(b"10 foo=bar\n").is_err());
+        assert!(PaxExtensions::parse(b"11 foobar\n").is_err());
+    }
+
+    #[test]
+    fn test_pax_time() {
+        for (secs, nsecs, text) in [
+            (MTIME, 0, "1700000000"),
+            (MTIME, 120_000_000, "1700000000.12"),
+            (0, 1, "0.000000001"),
+            (-1, 500_000_000, "-0.5"),
+            (-2, 0, "-2"),
+        ] {
+            assert_eq!(format_pax_time(secs, nsecs), text);
+            assert_eq!(parse_pax_time(text.as_bytes()), Some((secs, nsecs)));
+        }
+        assert_eq!(parse_pax_time(b"1.1234567891"), Some((1, 123_456_789)));
+        assert_eq!(parse_pax_time(b"1.x"), None);
+    }
+
+    #[test]
+    fn test_acl_conversion() {
+        let text = "user::rwx,user:1000:r-x,group::r--,group:100:rw-,mask::rwx,other::---";
+        let xattr = acl_text_to_xattr(text).unwrap();
+        assert_eq!(xattr.len(), 4 + 6 * 8);
+        assert_eq!(acl_xattr_to_text(&xattr).unwrap(), text);
+
+        // star style with newlines, numeric ids and unsorted entries
+        let xattr = acl_text_to_xattr("u::rw-\no::r--\ng::r--\nu:foo:rw-:1000\nm::rw-\n").unwrap();
+        assert_eq!(
+            acl_xattr_to_text(&xattr).unwrap(),
+            "user::rw-,user:1000:rw-,group::r--,mask::rw-,other::r--"
+        );
+
+        assert!(acl_text_to_xattr("user::rwz").is_err());
+        assert!(acl_text_to_xattr("nobody::rwx").is_err());
+        assert!(acl_xattr_to_text(&[2, 0, 0, 0, 1]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pax_roundtrip() {
+        let long_path = format!("{}/file", "directory".repeat(30));
+        let long_target = "target".repeat(30);
+
+        let mut extensions = PaxExtensions::new();
+        extensions.set_mtime(MTIME, 123_456_789);
+        extensions.add_xattr("user.comment", b"hello");
+
+        let mut tar = Builder::new(Vec::new());
+        let mut header = test_header(EntryType::Regular, 5, 0o640);
+        tar.add_entry_with_extensions(&mut header, &long_path, &b"12345"[..], &extensions)
+            .await
+            .unwrap();
+        let mut header = test_header(EntryType::Symlink, 0, 0o777);
+        tar.add_link_with_extensions(&mut header, "link", &long_target, &PaxExtensions::new())
+            .await
+            .unwrap();
+        let archive = tar.finish().await.unwrap();
+
+        let entries = read_entries(archive).await;
+        assert_eq!(entries.len(), 2);
+
+        let (entry, content) = &entries[0];
+        assert_eq!(entry.path(), Path::new(&long_path));
+        assert_eq!(entry.mtime().unwrap(), (MTIME, 123_456_789));
+        assert_eq!(
+            entry.extensions().xattrs().collect::<Vec<_>>(),
+            [("user.comment", &b"hello"[..])]
+        );
+        assert_eq!(content, b"12345");
+
+        let (entry, content) = &entries[1];
+        assert_eq!(entry.entry_type(), EntryType::Symlink);
+        assert_eq!(entry.path(), Path::new("link"));
+        assert_eq!(entry.link_name(), Some(Path::new(&long_target)));
+        assert_eq!(entry.mtime().unwrap(), (MTIME, 0));
+        assert!(content.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gnu_longname() {
+        let long_path = "a".repeat(200);
+
+        let mut tar = Builder::new(Vec::new());
+        let mut header = Header::new_gnu();
+        header.set_size(3);
+        tar.add_entry(&mut header, &long_path, &b"foo"[..])
+            .await
+            .unwrap();
+        let mut header = Header::new_gnu();
+        header.set_size(3);
+        tar.add_entry(&mut header, "short", &b"bar"[..])
+            .await
+            .unwrap();
+        let archive = tar.finish().await.unwrap();
+
+        // skipping the content of the first entry must work too
+        let mut reader = Archive::new(Cursor::new(archive.clone()));
+        reader.next_entry().await.unwrap().unwrap();
+        let entry = reader.next_entry().await.unwrap().unwrap();
+        assert_eq!(entry.path(), Path::new("short"));
+        assert!(reader.next_entry().await.unwrap().is_none());
+
+        let entries = read_entries(archive).await;
+        assert_eq!(entries[0].0.path(), Path::new(&long_path));
+        assert_eq!(entries[0].1, b"foo");
+        assert_eq!(entries[1].1, b"bar");
+
+        let mut corrupted = reader.into_inner().into_inner();
+        corrupted[1024] ^= 1;
+        let mut reader = Archive::new(Cursor::new(corrupted));
+        assert!(reader.next_entry().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_extract_to_directory() {
+        let target = std::env::temp_dir().join(format!("tar-extract-test-{}", std::process::id()));
+
+        let mut tar = Builder::new(Vec::new());
+
+        let mut header = test_header(EntryType::Directory, 0, 0o750);
+        let mut extensions = PaxExtensions::new();
+        extensions.set_mtime(MTIME, 5);
+        tar.add_entry_with_extensions(&mut header, "dir", tokio::io::empty(), &extensions)
+            .await
+            .unwrap();
+
+        let mut header = test_header(EntryType::Regular, 7, 0o4751);
+        let mut extensions = PaxExtensions::new();
+        extensions.set_mtime(MTIME + 10, 987_654_321);
+        extensions.set_atime(MTIME + 20, 0);
+        tar.add_entry_with_extensions(&mut header, "dir/file", &b"content"[..], &extensions)
+            .await
+            .unwrap();
+
+        let mut header = test_header(EntryType::Symlink, 0, 0o777);
+        tar.add_link(&mut header, "dir/symlink", "file")
+            .await
+            .unwrap();
+
+        let mut header = test_header(EntryType::Link, 0, 0o644);
+        tar.add_link(&mut header, "hardlink", "dir/file")
+            .await
+            .unwrap();
+
+        let archive = tar.finish().await.unwrap();
+        Archive::new(Cursor::new(archive))
+            .extract_to_directory(&target)
+            .await
+            .unwrap();
+
+        let file = target.join("dir/file");
+        let metadata = std::fs::metadata(&file).unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"content");
+        assert_eq!(metadata.mode() & 0o7777, 0o4751);
+        assert_eq!(
+            (metadata.mtime(), metadata.mtime_nsec()),
+            (MTIME + 10, 987_654_321)
+        );
+        assert_eq!(metadata.atime(), MTIME + 20);
+        assert_eq!(metadata.nlink(), 2);
+        assert_eq!(
+            metadata.ino(),
+            std::fs::metadata(target.join("hardlink")).unwrap().ino()
+        );
+
+        let symlink = target.join("dir/symlink");
+        assert_eq!(std::fs::read_link(&symlink).unwrap(), Path::new("file"));
+        assert_eq!(std::fs::symlink_metadata(&symlink).unwrap().mtime(), MTIME);
+
+        let metadata = std::fs::metadata(target.join("dir")).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o750);
+        assert_eq!((metadata.mtime(), metadata.mtime_nsec()), (MTIME, 5));
+
+        // paths leaving the target and paths through symlinks are refused
+        let mut tar = Builder::new(Vec::new());
+        for path in ["../evil", "dir/symlink/evil"] {
+            let mut header = test_header(EntryType::Regular, 4, 0o644);
+            // bypass the path sanitizing of the tar crate
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_cksum();
+            tar.add(&header, &b"evil"[..]).await.unwrap();
+        }
+        let archive = tar.finish().await.unwrap();
+        assert!(Archive::new(Cursor::new(archive))
+            .extract_to_directory(&target)
+            .await
+            .is_err());
+        assert!(!target.parent().unwrap().join("evil").exists());
+        assert!(!target.join("dir/evil").exists());
+
+        // access times are only archived on request
+        let mut archive = Vec::new();
+        tar_directory(&mut archive, &target.join("dir"))
+            .await
+            .unwrap();
+        let entries = read_entries(archive).await;
+        assert!(entries.len() >= 2);
+        assert!(entries
+            .iter()
+            .all(|(entry, _)| entry.extensions().atime().is_none()));
+
+        let mut archive = Vec::new();
+        tar_directory_with_atime(&mut archive, &target.join("dir"))
+            .await
+            .unwrap();
+        let entries = read_entries(archive).await;
+        assert!(entries
+            .iter()
+            .all(|(entry, _)| entry.extensions().atime().is_some()));
+
+        std::fs::remove_dir_all(&target).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_extract_directory_over_symlink() {
+        let base = std::env::temp_dir().join(format!("tar-symlink-test-{}", std::process::id()));
+        let target = base.join("target");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::set_permissions(&outside, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let before = std::fs::metadata(&outside).unwrap();
+
+        // a symlink 'd' pointing outside of the target, followed by a directory 'd'
+        let mut tar = Builder::new(Vec::new());
+        let mut header = test_header(EntryType::Symlink, 0, 0o777);
+        tar.add_link(&mut header, "d", &outside).await.unwrap();
+        let mut header = test_header(EntryType::Directory, 0, 0o700);
+        let mut extensions = PaxExtensions::new();
+        extensions.set_mtime(MTIME, 0);
+        tar.add_entry_with_extensions(&mut header, "d", tokio::io::empty(), &extensions)
+            .await
+            .unwrap();
+        let archive = tar.finish().await.unwrap();
+
+        assert!(Archive::new(Cursor::new(archive))
+            .extract_to_directory(&target)
+            .await
+            .is_err());
+
+        let after = std::fs::metadata(&outside).unwrap();
+        assert_eq!(after.mode(), before.mode());
+        assert_eq!(after.mtime(), before.mtime());
+        assert!(std::fs::symlink_metadata(target.join("d"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,28 +1,33 @@
 //! ZIP Helper
 //!
-//! Provides an interface to create a ZIP File from ZipEntries
-//! for a more detailed description of the ZIP format, see:
+//! Provides an interface to create a ZIP File from ZipEntries and to read
+//! and extract existing ZIP Files.
+//! For a more detailed description of the ZIP format, see:
 //! <https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT>
 
 use std::convert::TryInto;
-use std::ffi::OsString;
-use std::io;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, SeekFrom};
 use std::mem::size_of;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{format_err, Error, Result};
+use anyhow::{bail, format_err, Error, Result};
+use bytes::Bytes;
 use endian_trait::Endian;
 use futures::ready;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use futures::stream::{Stream, StreamExt};
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf,
+};
 
 use crc32fast::Hasher;
-use proxmox_time::gmtime;
+use proxmox_time::{gmtime, timegm};
 
-use crate::deflate::{DeflateEncoder, Level};
+use crate::deflate::{DeflateDecoder, DeflateEncoder, Level};
 
 const LOCAL_FH_SIG: u32 = 0x04034B50;
 const LOCAL_FF_SIG: u32 = 0x08074B50;
@@ -34,6 +39,10 @@ const VERSION_MADE_BY: u16 = 0x032d;
 const ZIP64_EOCD_RECORD: u32 = 0x06064B50;
 const ZIP64_EOCD_LOCATOR: u32 = 0x07064B50;
 
+const COMPRESSION_STORED: u16 = 0;
+const COMPRESSION_DEFLATE: u16 = 8;
+
+const LFH_GPF_ENCRYPTED_BIT: u16 = 1 << 0;
 const LFH_GENERAL_PURPOSE_FLAGS: u16 = 1 << 3; // we place crc32 in the data descriptor
 const LFH_GPF_EFS_BIT: u16 = 1 << 11; // EFS, marks filename & comment as UTF-8
 
@@ -55,7 +64,7 @@ fn epoch_to_dos(epoch: i64) -> (u16, u16) {
     };
 
     let seconds = (gmtime.tm_sec / 2) & 0b11111;
-    let minutes = gmtime.tm_min & 0xb111111;
+    let minutes = gmtime.tm_min & 0b111111;
     let hours = gmtime.tm_hour & 0b11111;
     let time: u16 = ((hours << 11) | (minutes << 5) | (seconds)) as u16;
 
@@ -71,6 +80,29 @@ fn epoch_to_dos(epoch: i64) -> (u16, u16) {
     (date, time)
 }
 
+// inverse of epoch_to_dos, invalid dates map to 0
+fn dos_to_epoch(date: u16, time: u16) -> i64 {
+    let mut tm = match gmtime(0) {
+        Ok(tm) => tm,
+        Err(_) => return 0,
+    };
+
+    tm.tm_sec = ((time & 0b11111) * 2) as i32;
+    tm.tm_min = ((time >> 5) & 0b111111) as i32;
+    tm.tm_hour = (time >> 11) as i32;
+
+    let day = (date & 0b11111) as i32;
+    let month = ((date >> 5) & 0b1111) as i32;
+    if day == 0 || month == 0 || month > 12 {
+        return 0;
+    }
+    tm.tm_mday = day;
+    tm.tm_mon = month - 1;
+    tm.tm_year = (date >> 9) as i32 + 1980 - 1900;
+
+    timegm(&mut tm).unwrap_or(0)
+}
+
 #[derive(Endian)]
 #[repr(C, packed)]
 struct Zip64Field {
@@ -191,6 +223,29 @@ where
     output.write_all(data).await
 }
 
+fn parse_struct<E: Endian>(data: &[u8]) -> io::Result<E> {
+    if data.len() < size_of::<E>() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "unexpected end of zip structure",
+        ));
+    }
+
+    // only used for the packed integer structs above, every bit pattern is valid
+    let data = unsafe { std::ptr::read_unaligned(data.as_ptr() as *const E) };
+    Ok(data.from_le())
+}
+
+async fn read_struct<E, T>(input: &mut T) -> io::Result<E>
+where
+    T: AsyncRead + ?Sized + Unpin,
+    E: Endian,
+{
+    let mut data = vec![0u8; size_of::<E>()];
+    input.read_exact(&mut data).await?;
+    parse_struct(&data)
+}
+
 /// Represents an Entry in a ZIP File
 ///
 /// used to add to a ZipEncoder
@@ -685,3 +740,722 @@ where
 
     encoder.finish().await
 }
+
+/// Represents an Entry of an existing ZIP File
+///
+/// returned by ZipDecoder
+#[derive(Clone, Debug)]
+pub struct ZipFileEntry {
+    filename: OsString,
+    mtime: i64,
+    mode: u16,
+    crc32: u32,
+    uncompressed_size: u64,
+    compressed_size: u64,
+    offset: u64,
+    compression: u16,
+    flags: u16,
+}
+
+impl ZipFileEntry {
+    fn from_central_directory_header(
+        header: &CentralDirectoryFileHeader,
+        filename: OsString,
+        extra_fields: &[u8],
+    ) -> Result<Self, Error> {
+        let external_flags = header.external_flags;
+        let is_dir = filename.as_bytes().ends_with(b"/") || external_flags & (1 << 4) != 0;
+
+        // the upper byte of 'version made by' is 3 for unix
+        let mut mode = if header.version_made_by >> 8 == 3 {
+            (external_flags >> 16) as u16
+        } else {
+            0
+        };
+
+        if mode as u32 & libc::S_IFMT == 0 {
+            if mode & 0o7777 == 0 {
+                mode = if is_dir { 0o755 } else { 0o644 };
+            }
+            mode |= if is_dir { libc::S_IFDIR } else { libc::S_IFREG } as u16;
+        }
+
+        let mut entry = Self {
+            filename,
+            mtime: dos_to_epoch(header.date, header.time),
+            mode,
+            crc32: header.crc32,
+            uncompressed_size: header.uncompressed_size as u64,
+            compressed_size: header.compressed_size as u64,
+            offset: header.offset as u64,
+            compression: header.compression,
+            flags: header.flags,
+        };
+        entry.parse_extra_fields(extra_fields)?;
+
+        Ok(entry)
+    }
+
+    fn parse_extra_fields(&mut self, mut extra_fields: &[u8]) -> Result<(), Error> {
+        while extra_fields.len() >= 4 {
+            let field_type = u16::from_le_bytes([extra_fields[0], extra_fields[1]]);
+            let field_size = u16::from_le_bytes([extra_fields[2], extra_fields[3]]) as usize;
+            let data = match extra_fields.get(4..4 + field_size) {
+                Some(data) => data,
+                None => bail!("invalid extra field in '{:?}'", self.filename),
+            };
+            extra_fields = &extra_fields[4 + field_size..];
+
+            match field_type {
+                // zip64 extended information, only contains the values set to 0xFFFFFFFF
+                0x0001 => {
+                    let mut values = data
+                        .chunks_exact(8)
+                        .map(|value| u64::from_le_bytes(value.try_into().unwrap()));
+                    for field in [
+                        &mut self.uncompressed_size,
+                        &mut self.compressed_size,
+                        &mut self.offset,
+                    ] {
+                        if *field == 0xFFFFFFFF {
+                            *field = values.next().ok_or_else(|| {
+                                format_err!("incomplete zip64 field in '{:?}'", self.filename)
+                            })?;
+                        }
+                    }
+                }
+                // extended timestamp, starts with the modification time if flag bit 0 is set
+                0x5455 if data.len() >= 5 && data[0] & 1 != 0 => {
+                    self.mtime = i32::from_le_bytes(data[1..5].try_into().unwrap()) as i64;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The path of the entry, as stored in the archive
+    pub fn filename(&self) -> &Path {
+        Path::new(&self.filename)
+    }
+
+    /// Modification time in seconds since the epoch
+    pub fn mtime(&self) -> i64 {
+        self.mtime
+    }
+
+    /// File type and permissions, synthesized from the entry type if the
+    /// archive does not contain unix permissions
+    pub fn mode(&self) -> u16 {
+        self.mode
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode as u32 & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode as u32 & libc::S_IFMT == libc::S_IFREG
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    pub fn uncompressed_size(&self) -> u64 {
+        self.uncompressed_size
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    // the path relative to an extraction target, refuses paths leaving it
+    fn relative_path(&self) -> Result<PathBuf, Error> {
+        let mut path = PathBuf::new();
+        for comp in self.filename().components() {
+            match comp {
+                Component::Normal(name) => path.push(name),
+                Component::CurDir => {}
+                _ => bail!(
+                    "refusing to extract '{}' outside of the target directory",
+                    self.filename().display()
+                ),
+            }
+        }
+        Ok(path)
+    }
+}
+
+// streams the raw data of an entry, at most 'remaining' bytes
+struct EntryData<'a, R: ?Sized> {
+    reader: &'a mut R,
+    remaining: u64,
+    buffer: Vec<u8>,
+}
+
+impl<R> Stream for EntryData<'_, R>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        let len = this.remaining.min(this.buffer.len() as u64) as usize;
+        let mut buf = ReadBuf::new(&mut this.buffer[..len]);
+        ready!(Pin::new(&mut *this.reader).poll_read(cx, &mut buf))?;
+        let read = buf.filled().len();
+        if read == 0 {
+            this.remaining = 0;
+            return Poll::Ready(Some(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected end of zip entry data",
+            ))));
+        }
+        this.remaining -= read as u64;
+
+        Poll::Ready(Some(Ok(Bytes::copy_from_slice(buf.filled()))))
+    }
+}
+
+enum EntryContent<'a, R: ?Sized> {
+    Stored(EntryData<'a, R>),
+    Deflated(DeflateDecoder<EntryData<'a, R>>),
+}
+
+/// Stream of the uncompressed content of a ZIP entry
+///
+/// The size and CRC32 are verified at the end of the data, a mismatch
+/// results in an error as last item.
+pub struct ZipEntryReader<'a, R: ?Sized> {
+    content: EntryContent<'a, R>,
+    hasher: Hasher,
+    size: u64,
+    crc32: u32,
+    uncompressed_size: u64,
+    finished: bool,
+}
+
+impl<R> ZipEntryReader<'_, R>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    fn check(&self) -> Result<(), Error> {
+        if self.size != self.uncompressed_size {
+            bail!(
+                "size mismatch (expected {}, got {})",
+                self.uncompressed_size,
+                self.size
+            );
+        }
+        let crc32 = self.hasher.clone().finalize();
+        if crc32 != self.crc32 {
+            bail!(
+                "crc32 mismatch (expected {:08x}, got {:08x})",
+                self.crc32,
+                crc32
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<R> Stream for ZipEntryReader<'_, R>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(None);
+        }
+
+        let next = match &mut this.content {
+            EntryContent::Stored(data) => ready!(data.poll_next_unpin(cx)).map(|res| Ok(res?)),
+            EntryContent::Deflated(decoder) => ready!(decoder.poll_next_unpin(cx)),
+        };
+
+        match next {
+            Some(Ok(bytes)) => {
+                this.hasher.update(&bytes);
+                this.size += bytes.len() as u64;
+                if this.size > this.uncompressed_size {
+                    this.finished = true;
+                    return Poll::Ready(Some(this.check()).map(|res| res.map(|_| bytes)));
+                }
+                Poll::Ready(Some(Ok(bytes)))
+            }
+            Some(Err(err)) => {
+                this.finished = true;
+                Poll::Ready(Some(Err(err)))
+            }
+            None => {
+                this.finished = true;
+                Poll::Ready(this.check().err().map(Err))
+            }
+        }
+    }
+}
+
+/// Reads a ZIP archive from a seekable reader
+///
+/// The central directory is parsed on creation, the content of the entries
+/// is decompressed on the fly when reading them.
+/// Example:
+/// ```no_run
+/// use anyhow::{Error, Result};
+/// use futures::stream::TryStreamExt;
+/// use tokio::fs::File;
+///
+/// use proxmox_compression::zip::ZipDecoder;
+///
+/// //#[tokio::main]
+/// async fn main_() -> Result<(), Error> {
+///     let source = File::open("foo.zip").await?;
+///
+///     let mut zip = ZipDecoder::new(source).await?;
+///     for (index, entry) in zip.entries().to_vec().iter().enumerate() {
+///         println!("{} ({} bytes)", entry.filename().display(), entry.uncompressed_size());
+///         let mut content = zip.read_entry(index).await?;
+///         while let Some(data) = content.try_next().await? {
+///             // ...
+///         }
+///     }
+///
+///     zip.extract_to_directory("foo".as_ref()).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct ZipDecoder<R> {
+    source: R,
+    entries: Vec<ZipFileEntry>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> ZipDecoder<R> {
+    pub async fn new(mut source: R) -> Result<Self, Error> {
+        let (count, directory_size, directory_offset) =
+            Self::find_central_directory(&mut source).await?;
+
+        source.seek(SeekFrom::Start(directory_offset)).await?;
+        let mut directory = vec![0u8; directory_size.try_into()?];
+        source.read_exact(&mut directory).await?;
+
+        let header_size = size_of::<CentralDirectoryFileHeader>();
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        for _ in 0..count {
+            let header: CentralDirectoryFileHeader = parse_struct(&directory[pos..])?;
+            if header.signature != CENTRAL_DIRECTORY_FH_SIG {
+                bail!("invalid central directory file header signature");
+            }
+            pos += header_size;
+
+            let filename_end = pos + header.filename_len as usize;
+            let extra_end = filename_end + header.extra_field_len as usize;
+            let end = extra_end + header.comment_len as usize;
+            if end > directory.len() {
+                bail!("central directory entry exceeds the central directory");
+            }
+
+            let filename = OsStr::from_bytes(&directory[pos..filename_end]).to_owned();
+            entries.push(ZipFileEntry::from_central_directory_header(
+                &header,
+                filename,
+                &directory[filename_end..extra_end],
+            )?);
+            pos = end;
+        }
+
+        Ok(Self { source, entries })
+    }
+
+    // returns entry count, size and offset of the central directory
+    async fn find_central_directory(source: &mut R) -> Result<(u64, u64, u64), Error> {
+        let file_size = source.seek(SeekFrom::End(0)).await?;
+        let eocd_size = size_of::<EndOfCentralDir>();
+        if file_size < eocd_size as u64 {
+            bail!("file too small for a zip archive");
+        }
+
+        // the end of central directory record is followed by a comment of at most 64KiB
+        let tail_size = file_size.min((eocd_size + u16::MAX as usize) as u64);
+        let tail_offset = file_size - tail_size;
+        let mut tail = vec![0u8; tail_size as usize];
+        source.seek(SeekFrom::Start(tail_offset)).await?;
+        source.read_exact(&mut tail).await?;
+
+        let signature = END_OF_CENTRAL_DIR.to_le_bytes();
+        let pos = (0..=(tail.len() - eocd_size))
+            .rev()
+            .find(|&pos| {
+                let comment_len = u16::from_le_bytes([tail[pos + 20], tail[pos + 21]]) as usize;
+                tail[pos..pos + 4] == signature && pos + eocd_size + comment_len <= tail.len()
+            })
+            .ok_or_else(|| format_err!("end of central directory not found"))?;
+
+        let eocd: EndOfCentralDir = parse_struct(&tail[pos..])?;
+        let eocd_offset = tail_offset + pos as u64;
+        let mut count = eocd.total_record_count as u64;
+        let mut directory_size = eocd.directory_size as u64;
+        let mut directory_offset = eocd.directory_offset as u64;
+
+        let locator_size = size_of::<Zip64EOCDLocator>() as u64;
+        if eocd_offset >= locator_size {
+            source
+                .seek(SeekFrom::Start(eocd_offset - locator_size))
+                .await?;
+            let locator: Zip64EOCDLocator = read_struct(source).await?;
+            if locator.signature == ZIP64_EOCD_LOCATOR {
+                source.seek(SeekFrom::Start(locator.offset)).await?;
+                let record: Zip64EOCDRecord = read_struct(source).await?;
+                if record.signature != ZIP64_EOCD_RECORD {
+                    bail!("invalid zip64 end of central directory signature");
+                }
+                count = record.total_record_count;
+                directory_size = record.directory_size;
+                directory_offset = record.directory_offset;
+            }
+        }
+
+        if directory_offset.saturating_add(directory_size) > eocd_offset {
+            bail!("central directory exceeds the archive");
+        }
+
+        Ok((count, directory_size, directory_offset))
+    }
+
+    /// The entries of the archive, in the order of the central directory
+    pub fn entries(&self) -> &[ZipFileEntry] {
+        &self.entries
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Returns a stream of the uncompressed content of the entry at 'index'
+    pub async fn read_entry(&mut self, index: usize) -> Result<ZipEntryReader<'_, R>, Error> {
+        let entry = self
+            .entries
+            .get(index)
+            .ok_or_else(|| format_err!("no zip entry with index {}", index))?;
+
+        if entry.flags & LFH_GPF_ENCRYPTED_BIT != 0 {
+            bail!(
+                "'{:?}' is encrypted, which is not supported",
+                entry.filename
+            );
+        }
+
+        self.source.seek(SeekFrom::Start(entry.offset)).await?;
+        let header: LocalFileHeader = read_struct(&mut self.source).await?;
+        if header.signature != LOCAL_FH_SIG {
+            bail!(
+                "invalid local file header signature for '{:?}'",
+                entry.filename
+            );
+        }
+        let skip = header.filename_len as i64 + header.extra_field_len as i64;
+        self.source.seek(SeekFrom::Current(skip)).await?;
+
+        let data = EntryData {
+            reader: &mut self.source,
+            remaining: entry.compressed_size,
+            buffer: vec![0u8; 64 * 1024],
+        };
+
+        let content = match entry.compression {
+            COMPRESSION_STORED => EntryContent::Stored(data),
+            // directories and the like have no compressed data at all
+            COMPRESSION_DEFLATE if entry.compressed_size == 0 => EntryContent::Stored(data),
+            COMPRESSION_DEFLATE => EntryContent::Deflated(DeflateDecoder::new(data)),
+            other => bail!(
+                "unsupported compression method {} for '{:?}'",
+                other,
+                entry.filename
+            ),
+        };
+
+        Ok(ZipEntryReader {
+            content,
+            hasher: Hasher::new(),
+            size: 0,
+            crc32: entry.crc32,
+            uncompressed_size: entry.uncompressed_size,
+            finished: false,
+        })
+    }
+
+    /// Writes the uncompressed content of the entry at 'index' to 'target',
+    /// returns the number of bytes written
+    pub async fn extract_entry<W>(&mut self, index: usize, target: &mut W) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let mut reader = self.read_entry(index).await?;
+        let mut size = 0;
+        while let Some(data) = reader.next().await {
+            let data = data?;
+            target.write_all(&data).await?;
+            size += data.len() as u64;
+        }
+        Ok(size)
+    }
+
+    /// Extracts all files and directories of the archive below 'target'
+    ///
+    /// Entries with absolute paths or paths leaving 'target' are refused, entries
+    /// which are neither files nor directories are skipped. Permissions (without
+    /// setuid, setgid and sticky bits) and modification times are restored.
+    pub async fn extract_to_directory(&mut self, target: &Path) -> Result<(), Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        tokio::fs::create_dir_all(target).await?;
+
+        let mut directories = Vec::new();
+
+        for index in 0..self.entries.len() {
+            let entry = &self.entries[index];
+            let path = target.join(entry.relative_path()?);
+            let (mode, mtime) = (entry.mode, entry.mtime);
+
+            if entry.is_dir() {
+                tokio::fs::create_dir_all(&path).await?;
+                // restored after all content was written
+                directories.push((path, mode, mtime));
+                continue;
+            } else if !entry.is_file() {
+                eprintln!("zip: skipping unsupported entry '{}'", path.display());
+                continue;
+            }
+
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(&path)
+                .await
+                .map_err(|err| format_err!("could not create '{}': {}", path.display(), err))?;
+
+            self.extract_entry(index, &mut file)
+                .await
+                .map_err(|err| format_err!("could not extract '{}': {}", path.display(), err))?;
+            file.flush().await?;
+
+            let file = file.into_std().await;
+            file.set_permissions(std::fs::Permissions::from_mode(mode as u32 & 0o777))?;
+            file.set_modified(epoch_to_system_time(mtime))?;
+        }
+
+        for (path, mode, mtime) in directories.into_iter().rev() {
+            let dir = std::fs::File::open(&path)?;
+            dir.set_modified(epoch_to_system_time(mtime))?;
+            dir.set_permissions(std::fs::Permissions::from_mode(mode as u32 & 0o777))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn epoch_to_system_time(epoch: i64) -> SystemTime {
+    if epoch >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(epoch as u64)
+    } else {
+        SystemTime::UNIX_EPOCH - Duration::from_secs(epoch.unsigned_abs())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use futures::stream::TryStreamExt;
+
+    use super::*;
+
+    const MTIME: i64 = 1_700_000_000;
+
+    fn test_content(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    async fn encode(entries: Vec<(ZipEntry, Option<Vec<u8>>)>) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut encoder = ZipEncoder::new(&mut archive);
+        for (entry, content) in entries {
+            encoder.add_entry(entry, content.as_deref()).await.unwrap();
+        }
+        encoder.finish().await.unwrap();
+        archive
+    }
+
+    async fn read_all<R>(decoder: &mut ZipDecoder<R>, index: usize) -> Result<Vec<u8>, Error>
+    where
+        R: AsyncRead + AsyncSeek + Unpin,
+    {
+        let mut content = Vec::new();
+        let mut reader = decoder.read_entry(index).await?;
+        while let Some(data) = reader.try_next().await? {
+            content.extend_from_slice(&data);
+        }
+        Ok(content)
+    }
+
+    #[test]
+    fn test_dos_time() {
+        for epoch in [315532800, MTIME, 4354819198] {
+            let (date, time) = epoch_to_dos(epoch);
+            assert_eq!(dos_to_epoch(date, time), epoch);
+        }
+        assert_eq!(dos_to_epoch(0, 0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_zip_roundtrip() {
+        let large = test_content(1024 * 1024);
+        let archive = encode(vec![
+            (ZipEntry::new("foo", MTIME, 0o40750, false), None),
+            (
+                ZipEntry::new("foo/bar.txt", MTIME + 2, 0o100640, true),
+                Some(b"hello world\n".to_vec()),
+            ),
+            (
+                ZipEntry::new("foo/empty", MTIME, 0o100600, true),
+                Some(Vec::new()),
+            ),
+            (
+                ZipEntry::new("large", MTIME, 0o100755, true),
+                Some(large.clone()),
+            ),
+        ])
+        .await;
+
+        let mut decoder = ZipDecoder::new(Cursor::new(archive)).await.unwrap();
+
+        let entries: Vec<_> = decoder
+            .entries()
+            .iter()
+            .map(|e| (e.filename().to_owned(), e.mode(), e.mtime(), e.is_dir()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (PathBuf::from("foo/"), 0o40750, MTIME, true),
+                (PathBuf::from("foo/bar.txt"), 0o100640, MTIME + 2, false),
+                (PathBuf::from("foo/empty"), 0o100600, MTIME, false),
+                (PathBuf::from("large"), 0o100755, MTIME, false),
+            ]
+        );
+
+        assert!(read_all(&mut decoder, 0).await.unwrap().is_empty());
+        assert_eq!(read_all(&mut decoder, 1).await.unwrap(), b"hello world\n");
+        assert!(read_all(&mut decoder, 2).await.unwrap().is_empty());
+        assert_eq!(read_all(&mut decoder, 3).await.unwrap(), large);
+        assert!(read_all(&mut decoder, 4).await.is_err());
+
+        decoder.entries[1].crc32 ^= 1;
+        let err = read_all(&mut decoder, 1).await.unwrap_err();
+        assert!(err.to_string().contains("crc32 mismatch"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_zip64_eocd() {
+        let count = u16::MAX as usize + 10;
+        let entries = (0..count)
+            .map(|i| (ZipEntry::new(format!("{i}"), MTIME, 0o40755, false), None))
+            .collect();
+        let archive = encode(entries).await;
+
+        let decoder = ZipDecoder::new(Cursor::new(archive)).await.unwrap();
+        assert_eq!(decoder.entries().len(), count);
+        assert_eq!(
+            decoder.entries()[count - 1].filename(),
+            Path::new(&format!("{}/", count - 1))
+        );
+    }
+
+    #[test]
+    fn test_zip64_extra_field() {
+        let mut entry = ZipFileEntry {
+            filename: "test".into(),
+            mtime: 0,
+            mode: 0o100644,
+            crc32: 0,
+            uncompressed_size: 0xFFFFFFFF,
+            compressed_size: 10,
+            offset: 0xFFFFFFFF,
+            compression: COMPRESSION_DEFLATE,
+            flags: 0,
+        };
+
+        let mut extra = Vec::new();
+        extra.extend_from_slice(&0x5455u16.to_le_bytes());
+        extra.extend_from_slice(&5u16.to_le_bytes());
+        extra.push(1);
+        extra.extend_from_slice(&(MTIME as i32).to_le_bytes());
+        extra.extend_from_slice(&1u16.to_le_bytes());
+        extra.extend_from_slice(&16u16.to_le_bytes());
+        extra.extend_from_slice(&(1u64 << 33).to_le_bytes());
+        extra.extend_from_slice(&(1u64 << 34).to_le_bytes());
+
+        entry.parse_extra_fields(&extra).unwrap();
+        assert_eq!(entry.uncompressed_size, 1 << 33);
+        assert_eq!(entry.compressed_size, 10);
+        assert_eq!(entry.offset, 1 << 34);
+        assert_eq!(entry.mtime, MTIME);
+
+        entry.compressed_size = 0xFFFFFFFF;
+        assert!(entry.parse_extra_fields(&extra[9..21]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_extract_to_directory() {
+        use std::os::unix::fs::MetadataExt;
+
+        let target = std::env::temp_dir().join(format!("zip-extract-test-{}", std::process::id()));
+
+        let archive = encode(vec![
+            (ZipEntry::new("dir", MTIME, 0o40700, false), None),
+            (
+                ZipEntry::new("dir/file", MTIME + 10, 0o104751, true),
+                Some(b"content".to_vec()),
+            ),
+        ])
+        .await;
+        let mut decoder = ZipDecoder::new(Cursor::new(archive)).await.unwrap();
+        decoder.extract_to_directory(&target).await.unwrap();
+
+        let file = target.join("dir/file");
+        assert_eq!(std::fs::read(&file).unwrap(), b"content");
+        let metadata = std::fs::metadata(&file).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o751);
+        assert_eq!(metadata.mtime(), MTIME + 10);
+        let metadata = std::fs::metadata(target.join("dir")).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o700);
+        assert_eq!(metadata.mtime(), MTIME);
+
+        let mut evil = ZipEntry::new("evil", MTIME, 0o100644, true);
+        evil.filename = "../evil".into();
+        let archive = encode(vec![(evil, Some(b"evil".to_vec()))]).await;
+        let mut decoder = ZipDecoder::new(Cursor::new(archive)).await.unwrap();
+        assert!(decoder.extract_to_directory(&target).await.is_err());
+        assert!(!target.parent().unwrap().join("evil").exists());
+
+        std::fs::remove_dir_all(&target).unwrap();
+    }
+}
@@ -7,9 +7,13 @@ use anyhow::{format_err, Error};
 use bytes::Bytes;
 use futures::ready;
 use futures::stream::Stream;
-use zstd::stream::raw::{Encoder, Operation, OutBuffer};
+use tokio::io::{AsyncRead, AsyncWrite};
+use zstd::stream::raw::{CParameter, DParameter, Decoder, Encoder, Operation, OutBuffer};
 
 use proxmox_io::ByteBuffer;
+use proxmox_lang::io_format_err;
+
+use crate::codec::{codec_reader, codec_writer, Codec, CodecReader, CodecWriter, Progress};
 
 const BUFFER_SIZE: usize = 8192;
 
@@ -32,6 +36,94 @@ pub struct ZstdEncoder<'a, T> {
     state: EncoderState,
 }
 
+/// Builder for the zstd compression variants
+///
+/// Created with [ZstdEncoder::builder].
+pub struct ZstdEncoderBuilder<'a, T> {
+    inner: T,
+    level: i32,
+    dictionary: Option<&'a [u8]>,
+    workers: u32,
+    checksum: bool,
+    buffer_size: usize,
+}
+
+impl<'a, T> ZstdEncoderBuilder<'a, T> {
+    /// The compression level, defaults to 3
+    pub const fn level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Compress with the given dictionary, it must also be used for decompression
+    pub const fn dictionary(mut self, dictionary: &'a [u8]) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Number of worker threads for compression, 0 (the default) compresses in the calling
+    /// thread. Fails on build if the zstd library has no multi-threading support.
+    pub const fn workers(mut self, workers: u32) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Add a checksum of the content to each frame
+    pub const fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub const fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    fn compressor(&self) -> Result<Encoder<'static>, io::Error> {
+        let mut compressor = match self.dictionary {
+            Some(dictionary) => Encoder::with_dictionary(self.level, dictionary)?,
+            None => Encoder::new(self.level)?,
+        };
+        if self.workers > 0 {
+            compressor.set_parameter(CParameter::NbWorkers(self.workers))?;
+        }
+        if self.checksum {
+            compressor.set_parameter(CParameter::ChecksumFlag(true))?;
+        }
+        Ok(compressor)
+    }
+
+    pub fn build(self) -> Result<ZstdEncoder<'a, T>, io::Error> {
+        Ok(ZstdEncoder {
+            compressor: self.compressor()?,
+            inner: self.inner,
+            buffer: ByteBuffer::with_capacity(self.buffer_size),
+            input_buffer: Bytes::new(),
+            state: EncoderState::Reading,
+        })
+    }
+}
+
+impl<T: AsyncRead> ZstdEncoderBuilder<'_, T> {
+    /// Build an [AsyncRead] returning the compressed data of the wrapped reader
+    pub fn build_reader(self) -> Result<ZstdEncoderReader<T>, io::Error> {
+        let compressor = self.compressor()?;
+        Ok(ZstdEncoderReader {
+            inner: CodecReader::new(self.inner, compressor, self.buffer_size),
+        })
+    }
+}
+
+impl<T: AsyncWrite> ZstdEncoderBuilder<'_, T> {
+    /// Build an [AsyncWrite] compressing the written data into the wrapped writer
+    pub fn build_writer(self) -> Result<ZstdEncoderWriter<T>, io::Error> {
+        let compressor = self.compressor()?;
+        Ok(ZstdEncoderWriter {
+            inner: CodecWriter::new(self.inner, compressor, self.buffer_size),
+        })
+    }
+}
+
 impl<T, O, E> ZstdEncoder<'_, T>
 where
     T: Stream<Item = Result<O, E>> + Unpin,
@@ -45,13 +137,20 @@ where
 
     /// Returns a new [ZstdEncoder] with the given level
     pub fn with_quality(inner: T, level: i32) -> Result<Self, io::Error> {
-        Ok(Self {
+        ZstdEncoder::builder(inner).level(level).build()
+    }
+}
+
+impl<'a, T> ZstdEncoder<'a, T> {
+    pub fn builder(inner: T) -> ZstdEncoderBuilder<'a, T> {
+        ZstdEncoderBuilder {
             inner,
-            compressor: Encoder::new(level)?,
-            buffer: ByteBuffer::with_capacity(BUFFER_SIZE),
-            input_buffer: Bytes::new(),
-            state: EncoderState::Reading,
-        })
+            level: 3,
+            dictionary: None,
+            workers: 0,
+            checksum: false,
+            buffer_size: BUFFER_SIZE,
+        }
     }
 }
 
@@ -72,7 +171,7 @@ impl<T> ZstdEncoder<'_, T> {
 
     fn finish(&mut self) -> Result<usize, io::Error> {
         let mut outbuf = OutBuffer::around(self.buffer.get_free_mut_slice());
-        let res = self.compressor.finish(&mut outbuf, true);
+        let res = Operation::finish(&mut self.compressor, &mut outbuf, true);
         let size = outbuf.pos();
         // drop(outbuf);
         self.buffer.add_size(size);
@@ -132,3 +231,415 @@ where
         }
     }
 }
+
+impl Codec for Encoder<'_> {
+    fn process(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<Progress> {
+        let status = self.run_on_buffers(input, output)?;
+        Ok(Progress {
+            read: status.bytes_read,
+            written: status.bytes_written,
+            done: false,
+        })
+    }
+
+    fn finish(&mut self, output: &mut [u8]) -> io::Result<Progress> {
+        let mut outbuf = OutBuffer::around(output);
+        let remaining = Operation::finish(self, &mut outbuf, true)?;
+        Ok(Progress {
+            read: 0,
+            written: outbuf.pos(),
+            done: remaining == 0,
+        })
+    }
+}
+
+codec_reader!(
+    /// An [AsyncRead] returning the zstd compressed data of the wrapped reader
+    ZstdEncoderReader,
+    Encoder<'static>
+);
+
+impl<R: AsyncRead> ZstdEncoderReader<R> {
+    /// Returns a new [ZstdEncoderReader] with default level 3
+    pub fn new(inner: R) -> Result<Self, io::Error> {
+        ZstdEncoder::builder(inner).build_reader()
+    }
+}
+
+codec_writer!(
+    /// An [AsyncWrite] zstd compressing the written data into the wrapped writer
+    ///
+    /// The compressed stream is only complete after shutting the writer down.
+    ZstdEncoderWriter,
+    Encoder<'static>
+);
+
+impl<W: AsyncWrite> ZstdEncoderWriter<W> {
+    /// Returns a new [ZstdEncoderWriter] with default level 3
+    pub fn new(inner: W) -> Result<Self, io::Error> {
+        ZstdEncoder::builder(inner).build_writer()
+    }
+}
+
+// zstd decompression, which may consist of multiple frames
+struct Decompress {
+    decoder: Decoder<'static>,
+    frame_finished: bool,
+}
+
+impl Codec for Decompress {
+    fn process(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<Progress> {
+        let status = self.decoder.run_on_buffers(input, output)?;
+        self.frame_finished = status.remaining == 0;
+        Ok(Progress {
+            read: status.bytes_read,
+            written: status.bytes_written,
+            done: false,
+        })
+    }
+
+    fn finish(&mut self, output: &mut [u8]) -> io::Result<Progress> {
+        let status = self.decoder.run_on_buffers(&[][..], output)?;
+        if status.bytes_written == 0 && !self.frame_finished {
+            return Err(io_format_err!("unexpected end of zstd stream"));
+        }
+        Ok(Progress {
+            read: 0,
+            written: status.bytes_written,
+            done: status.bytes_written == 0,
+        })
+    }
+}
+
+/// Builder for the zstd decompression variants
+///
+/// Created with [ZstdDecoder::builder].
+pub struct ZstdDecoderBuilder<'a, T> {
+    inner: T,
+    dictionary: Option<&'a [u8]>,
+    window_log_max: Option<u32>,
+    buffer_size: usize,
+}
+
+impl<'a, T> ZstdDecoderBuilder<'a, T> {
+    /// Decompress with the dictionary used for compression
+    pub const fn dictionary(mut self, dictionary: &'a [u8]) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Allow windows of up to 2^window_log_max bytes, needed for data compressed with large
+    /// windows (e.g. long distance matching)
+    pub const fn window_log_max(mut self, window_log_max: u32) -> Self {
+        self.window_log_max = Some(window_log_max);
+        self
+    }
+
+    pub const fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    fn decompressor(&self) -> Result<Decompress, io::Error> {
+        let mut decoder = match self.dictionary {
+            Some(dictionary) => Decoder::with_dictionary(dictionary)?,
+            None => Decoder::new()?,
+        };
+        if let Some(window_log_max) = self.window_log_max {
+            decoder.set_parameter(DParameter::WindowLogMax(window_log_max))?;
+        }
+        Ok(Decompress {
+            decoder,
+            frame_finished: true,
+        })
+    }
+
+    pub fn build(self) -> Result<ZstdDecoder<T>, io::Error> {
+        Ok(ZstdDecoder {
+            decompressor: self.decompressor()?,
+            inner: self.inner,
+            buffer: ByteBuffer::with_capacity(self.buffer_size),
+            input_buffer: Bytes::new(),
+            state: DecoderState::Reading,
+        })
+    }
+}
+
+impl<T: AsyncRead> ZstdDecoderBuilder<'_, T> {
+    /// Build an [AsyncRead] returning the decompressed data of the wrapped reader
+    pub fn build_reader(self) -> Result<ZstdDecoderReader<T>, io::Error> {
+        let decompressor = self.decompressor()?;
+        Ok(ZstdDecoderReader {
+            inner: CodecReader::new(self.inner, decompressor, self.buffer_size),
+        })
+    }
+}
+
+impl<T: AsyncWrite> ZstdDecoderBuilder<'_, T> {
+    /// Build an [AsyncWrite] decompressing the written data into the wrapped writer
+    pub fn build_writer(self) -> Result<ZstdDecoderWriter<T>, io::Error> {
+        let decompressor = self.decompressor()?;
+        Ok(ZstdDecoderWriter {
+            inner: CodecWriter::new(self.inner, decompressor, self.buffer_size),
+        })
+    }
+}
+
+#[derive(Eq, PartialEq)]
+enum DecoderState {
+    Reading,
+    Writing,
+    Flushing,
+    Finished,
+}
+
+/// An async ZstdDecoder that implements [Stream] for another [Stream]
+///
+/// Counterpart of [ZstdEncoder], concatenated frames are decompressed as one stream.
+pub struct ZstdDecoder<T> {
+    inner: T,
+    decompressor: Decompress,
+    buffer: ByteBuffer,
+    input_buffer: Bytes,
+    state: DecoderState,
+}
+
+impl<T, O, E> ZstdDecoder<T>
+where
+    T: Stream<Item = Result<O, E>> + Unpin,
+    O: Into<Bytes>,
+    E: Into<Error>,
+{
+    /// Returns a new [ZstdDecoder]
+    pub fn new(inner: T) -> Result<Self, io::Error> {
+        Self::builder(inner).build()
+    }
+}
+
+impl<T> ZstdDecoder<T> {
+    pub fn builder<'a>(inner: T) -> ZstdDecoderBuilder<'a, T> {
+        ZstdDecoderBuilder {
+            inner,
+            dictionary: None,
+            window_log_max: None,
+            buffer_size: BUFFER_SIZE,
+        }
+    }
+
+    /// Returns the wrapped [Stream]
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn take_buffer(&mut self) -> Bytes {
+        self.buffer.remove_data(self.buffer.len()).to_vec().into()
+    }
+}
+
+impl<T, O, E> Stream for ZstdDecoder<T>
+where
+    T: Stream<Item = Result<O, E>> + Unpin,
+    O: Into<Bytes>,
+    E: Into<Error>,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.state {
+                DecoderState::Reading => {
+                    if let Some(res) = ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                        let buf = res.map_err(Into::into)?;
+                        this.input_buffer = buf.into();
+                        this.state = DecoderState::Writing;
+                    } else {
+                        this.state = DecoderState::Flushing;
+                    }
+                }
+                DecoderState::Writing => {
+                    let progress = this
+                        .decompressor
+                        .process(&this.input_buffer[..], this.buffer.get_free_mut_slice())?;
+                    this.buffer.add_size(progress.written);
+                    let _ = this.input_buffer.split_to(progress.read);
+
+                    if this.input_buffer.is_empty() {
+                        this.state = DecoderState::Reading;
+                    }
+                    if this.buffer.is_full()
+                        || (this.input_buffer.is_empty() && !this.buffer.is_empty())
+                    {
+                        return Poll::Ready(Some(Ok(this.take_buffer())));
+                    }
+                }
+                DecoderState::Flushing => {
+                    let progress = this.decompressor.finish(this.buffer.get_free_mut_slice())?;
+                    this.buffer.add_size(progress.written);
+                    if progress.done {
+                        this.state = DecoderState::Finished;
+                    }
+                    if !this.buffer.is_empty() {
+                        return Poll::Ready(Some(Ok(this.take_buffer())));
+                    }
+                }
+                DecoderState::Finished => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+codec_reader!(
+    /// An [AsyncRead] returning the decompressed data of the wrapped zstd reader
+    ZstdDecoderReader,
+    Decompress
+);
+
+impl<R: AsyncRead> ZstdDecoderReader<R> {
+    pub fn new(inner: R) -> Result<Self, io::Error> {
+        ZstdDecoder::builder(inner).build_reader()
+    }
+}
+
+codec_writer!(
+    /// An [AsyncWrite] decompressing the written zstd data into the wrapped writer
+    ///
+    /// Shutting the writer down writes the remaining data and fails if the compressed stream
+    /// was incomplete.
+    ZstdDecoderWriter,
+    Decompress
+);
+
+impl<W: AsyncWrite> ZstdDecoderWriter<W> {
+    pub fn new(inner: W) -> Result<Self, io::Error> {
+        ZstdDecoder::builder(inner).build_writer()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    const BUFFER_SIZE: usize = 25;
+    const BODY: &str = r#"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do
+eiusmod tempor incididunt ut labore et dolore magnam aliquam quaerat voluptatem. Ut
+enim aeque doleamus animo, cum corpore dolemus, fieri tamen permagna accessio potest,
+si aliquod aeternum et infinitum impendere."#;
+
+    fn chunker(content: &[u8]) -> Vec<Result<Vec<u8>, io::Error>> {
+        content.chunks(10).map(|chunk| Ok(chunk.to_vec())).collect()
+    }
+
+    async fn collect<S: Stream<Item = Result<Bytes, Error>> + Unpin>(
+        mut stream: S,
+    ) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        while let Some(data) = stream.next().await {
+            buf.extend_from_slice(&data?);
+        }
+        Ok(buf)
+    }
+
+    #[tokio::test]
+    async fn test_encoder_against_decoder() {
+        let stream = futures::stream::iter(chunker(BODY.as_bytes()));
+        let encoder = ZstdEncoder::builder(stream)
+            .buffer_size(BUFFER_SIZE)
+            .build()
+            .unwrap();
+        let decoder = ZstdDecoder::builder(encoder)
+            .buffer_size(BUFFER_SIZE * 2)
+            .build()
+            .unwrap();
+
+        assert_eq!(collect(decoder).await.unwrap(), BODY.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_decoder_against_zstd() {
+        // two concatenated frames are decoded as one stream
+        let mut encoded = zstd::bulk::compress(BODY.as_bytes(), 3).unwrap();
+        encoded.extend(zstd::bulk::compress(BODY.as_bytes(), 19).unwrap());
+
+        let stream = futures::stream::iter(chunker(&encoded));
+        let decoder = ZstdDecoder::builder(stream)
+            .buffer_size(BUFFER_SIZE)
+            .build()
+            .unwrap();
+        assert_eq!(collect(decoder).await.unwrap(), BODY.repeat(2).as_bytes());
+
+        let stream = futures::stream::iter(chunker(&encoded[..encoded.len() - 5]));
+        let decoder = ZstdDecoder::new(stream).unwrap();
+        assert!(collect(decoder).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_async_io_with_dictionary() {
+        let dictionary = BODY.as_bytes();
+
+        let mut encoded = Vec::new();
+        let mut writer = ZstdEncoder::builder(&mut encoded)
+            .dictionary(dictionary)
+            .checksum(true)
+            .buffer_size(BUFFER_SIZE)
+            .build_writer()
+            .unwrap();
+        writer.write_all(BODY.as_bytes()).await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let plain = zstd::bulk::compress(BODY.as_bytes(), 3).unwrap();
+        assert!(encoded.len() < plain.len());
+
+        let mut decoded = Vec::new();
+        ZstdDecoder::builder(&encoded[..])
+            .dictionary(dictionary)
+            .buffer_size(BUFFER_SIZE)
+            .build_reader()
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, BODY.as_bytes());
+
+        let mut decoded = Vec::new();
+        assert!(ZstdDecoderReader::new(&encoded[..])
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_async_io_against_zstd() {
+        let mut encoded = Vec::new();
+        ZstdEncoder::builder(BODY.as_bytes())
+            .level(10)
+            .buffer_size(BUFFER_SIZE)
+            .build_reader()
+            .unwrap()
+            .read_to_end(&mut encoded)
+            .await
+            .unwrap();
+        assert_eq!(zstd::decode_all(&encoded[..]).unwrap(), BODY.as_bytes());
+
+        let mut decoded = Vec::new();
+        let mut writer = ZstdDecoder::builder(&mut decoded)
+            .buffer_size(BUFFER_SIZE)
+            .build_writer()
+            .unwrap();
+        writer.write_all(&encoded).await.unwrap();
+        writer.shutdown().await.unwrap();
+        assert_eq!(decoded, BODY.as_bytes());
+
+        let mut writer = ZstdDecoderWriter::new(Vec::new()).unwrap();
+        writer
+            .write_all(&encoded[..encoded.len() / 2])
+            .await
+            .unwrap();
+        assert!(writer.shutdown().await.is_err());
+    }
+}
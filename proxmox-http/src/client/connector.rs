@@ -6,7 +6,7 @@ use std::task::{Context, Poll};
 use futures::*;
 use http::Uri;
 use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
-use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::connect::{Connection, HttpConnector};
 use hyper_util::rt::TokioIo;
 use openssl::ssl::SslConnector;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
@@ -15,9 +15,11 @@ use tokio_openssl::SslStream;
 
 use proxmox_sys::linux::socket::set_tcp_keepalive;
 
-use crate::proxy_config::ProxyConfig;
+use crate::proxy_config::{ProxyConfig, ProxyType};
 use crate::uri::build_authority;
 
+use super::socks5;
+use super::stats::ConnectionStats;
 use super::tls::MaybeTlsStream;
 use crate::{RateLimitedStream, ShareableRateLimit};
 
@@ -31,6 +33,8 @@ pub struct HttpsConnector<T = GaiResolver> {
     tcp_keepalive: u32,
     read_limiter: Option<SharedRateLimit>,
     write_limiter: Option<SharedRateLimit>,
+    http2: bool,
+    stats: Option<ConnectionStats>,
 }
 
 impl<T> HttpsConnector<T> {
@@ -47,9 +51,22 @@ impl<T> HttpsConnector<T> {
             tcp_keepalive,
             read_limiter: None,
             write_limiter: None,
+            http2: false,
+            stats: None,
         }
     }
 
+    /// Offer HTTP/2 via ALPN on TLS connections.
+    ///
+    /// The hyper client needs to support HTTP/2 to make use of it.
+    pub fn set_http2(&mut self, http2: bool) {
+        self.http2 = http2;
+    }
+
+    pub(crate) fn set_stats(&mut self, stats: ConnectionStats) {
+        self.stats = Some(stats);
+    }
+
     pub fn set_proxy(&mut self, proxy: ProxyConfig) {
         self.proxy = Some(proxy);
     }
@@ -66,9 +83,14 @@ impl<T> HttpsConnector<T> {
         tcp_stream: S,
         ssl_connector: &SslConnector,
         host: &str,
+        http2: bool,
     ) -> Result<MaybeTlsStream<S>, Error> {
         let config = ssl_connector.configure()?;
-        let mut conn: SslStream<S> = SslStream::new(config.into_ssl(host)?, tcp_stream)?;
+        let mut ssl = config.into_ssl(host)?;
+        if http2 {
+            ssl.set_alpn_protos(b"\x02h2\x08http/1.1")?;
+        }
+        let mut conn: SslStream<S> = SslStream::new(ssl, tcp_stream)?;
         Pin::new(&mut conn).connect().await?;
         Ok(MaybeTlsStream::Secured(conn))
     }
@@ -154,8 +176,15 @@ where
         let keepalive = self.tcp_keepalive;
         let read_limiter = self.read_limiter.clone();
         let write_limiter = self.write_limiter.clone();
+        let http2 = self.http2;
+        let stats = self.stats.clone().map(|stats| (stats, host.clone(), port));
 
-        if let Some(ref proxy) = self.proxy {
+        let proxy = self
+            .proxy
+            .as_ref()
+            .filter(|proxy| !proxy.is_bypassed(&host, port));
+
+        let future = if let Some(proxy) = proxy {
             let use_connect = is_https || proxy.force_connect;
 
             let proxy_authority = match build_authority(&proxy.host, proxy.port) {
@@ -175,7 +204,41 @@ where
 
             let authorization = proxy.authorization.clone();
 
-            if use_connect {
+            if proxy.proxy_type.is_socks() {
+                let resolve_remote = proxy.proxy_type == ProxyType::Socks5h;
+                async move {
+                    let tcp_stream = connector
+                        .call(proxy_uri)
+                        .await
+                        .map_err(|err| {
+                            format_err!("error connecting to {} - {}", proxy_authority, err)
+                        })?
+                        .into_inner();
+
+                    let _ = set_tcp_keepalive(&tcp_stream, keepalive);
+
+                    let mut tcp_stream =
+                        RateLimitedStream::with_limiter(tcp_stream, read_limiter, write_limiter);
+
+                    socks5::connect(
+                        &mut tcp_stream,
+                        &host,
+                        port,
+                        authorization.as_deref(),
+                        resolve_remote,
+                    )
+                    .await?;
+
+                    if is_https {
+                        Self::secure_stream(tcp_stream, &ssl_connector, &host, http2)
+                            .await
+                            .map(TokioIo::new)
+                    } else {
+                        Ok(TokioIo::new(MaybeTlsStream::Normal(tcp_stream)))
+                    }
+                }
+                .boxed()
+            } else if use_connect {
                 async move {
                     use std::fmt::Write as _;
                     let tcp_stream = connector
@@ -207,7 +270,7 @@ where
                     Self::parse_connect_response(&mut tcp_stream).await?;
 
                     if is_https {
-                        Self::secure_stream(tcp_stream, &ssl_connector, &host)
+                        Self::secure_stream(tcp_stream, &ssl_connector, &host, http2)
                             .await
                             .map(TokioIo::new)
                     } else {
@@ -249,7 +312,7 @@ where
                     RateLimitedStream::with_limiter(tcp_stream, read_limiter, write_limiter);
 
                 if is_https {
-                    Self::secure_stream(tcp_stream, &ssl_connector, &host)
+                    Self::secure_stream(tcp_stream, &ssl_connector, &host, http2)
                         .await
                         .map(TokioIo::new)
                 } else {
@@ -257,6 +320,24 @@ where
                 }
             }
             .boxed()
+        };
+
+        match stats {
+            None => future,
+            Some((stats, host, port)) => async move {
+                let result = future.await;
+                stats.update(&host, port, |stats| match &result {
+                    Ok(stream) => {
+                        stats.connections += 1;
+                        if stream.inner().connected().is_negotiated_h2() {
+                            stats.http2_connections += 1;
+                        }
+                    }
+                    Err(_) => stats.connect_errors += 1,
+                });
+                result
+            }
+            .boxed(),
         }
     }
 }
@@ -18,6 +18,14 @@ mod simple;
 #[cfg(feature = "client")]
 pub use simple::Client;
 
+#[cfg(feature = "client")]
+mod socks5;
+
+#[cfg(feature = "client")]
+mod stats;
+#[cfg(feature = "client")]
+pub use stats::HostStats;
+
 #[cfg(feature = "client")]
 pub mod tls;
 
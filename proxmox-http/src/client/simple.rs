@@ -15,10 +15,11 @@ use http_body_util::{BodyDataStream, BodyExt};
 use hyper_util::client::legacy::connect::dns::GaiResolver;
 use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::client::legacy::Client as HyperClient;
-use hyper_util::rt::TokioExecutor;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
 use openssl::ssl::{SslConnector, SslMethod};
 
-use crate::client::HttpsConnector;
+use crate::client::stats::ConnectionStats;
+use crate::client::{HostStats, HttpsConnector};
 use crate::Body;
 use crate::HttpOptions;
 
@@ -26,6 +27,7 @@ use crate::HttpOptions;
 pub struct Client {
     client: HyperClient<HttpsConnector<GaiResolver>, Body>,
     options: HttpOptions,
+    stats: ConnectionStats,
 }
 
 impl Client {
@@ -41,7 +43,8 @@ impl Client {
     }
 
     pub fn with_ssl_connector(ssl_connector: SslConnector, options: HttpOptions) -> Self {
-        let connector = HttpConnector::new();
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(options.connect_timeout);
         let mut https = HttpsConnector::with_connector(
             connector,
             ssl_connector,
@@ -50,10 +53,33 @@ impl Client {
         if let Some(ref proxy_config) = options.proxy_config {
             https.set_proxy(proxy_config.clone());
         }
+        https.set_http2(options.http2);
 
-        let client =
-            HyperClient::builder(TokioExecutor::new()).build::<HttpsConnector, Body>(https);
-        Self { client, options }
+        let stats = ConnectionStats::default();
+        https.set_stats(stats.clone());
+
+        let mut builder = HyperClient::builder(TokioExecutor::new());
+        builder
+            .pool_timer(TokioTimer::new())
+            .timer(TokioTimer::new());
+        if let Some(max_idle) = options.pool_max_idle_per_host {
+            builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = options.pool_idle_timeout {
+            builder.pool_idle_timeout(idle_timeout);
+        }
+
+        let client = builder.build::<HttpsConnector, Body>(https);
+        Self {
+            client,
+            options,
+            stats,
+        }
+    }
+
+    /// Connection and request statistics, keyed by `host:port`.
+    pub fn connection_stats(&self) -> HashMap<String, HostStats> {
+        self.stats.snapshot()
     }
 
     pub fn set_user_agent(&mut self, user_agent: &str) -> Result<(), Error> {
@@ -62,6 +88,13 @@ impl Client {
     }
 
     fn add_proxy_headers(&self, request: &mut Request<Body>) -> Result<(), Error> {
+        let uri = request.uri();
+        if let (Some(proxy_config), Some(host)) = (&self.options.proxy_config, uri.host()) {
+            if proxy_config.is_bypassed(host, uri.port_u16().unwrap_or(80)) {
+                return Ok(());
+            }
+        }
+
         if request.uri().scheme() != Some(&http::uri::Scheme::HTTPS) {
             if let Some(ref authorization) = self.options.get_proxy_authorization() {
                 request.headers_mut().insert(
@@ -86,7 +119,23 @@ impl Client {
 
         self.add_proxy_headers(&mut request)?;
 
-        let encoded_response = self.client.request(request).map_err(Error::from).await?;
+        let uri = request.uri();
+        let host = uri.host().unwrap_or_default().to_string();
+        let port = uri.port_u16().unwrap_or(match uri.scheme() {
+            Some(scheme) if *scheme == http::uri::Scheme::HTTPS => 443,
+            _ => 80,
+        });
+        let active_request = self.stats.start_request(&host, port);
+
+        let response = self.client.request(request).map_err(Error::from);
+        let encoded_response = match self.options.request_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, response)
+                .await
+                .map_err(|_| format_err!("request to {host}:{port} timed out"))??,
+            None => response.await?,
+        };
+        active_request.succeeded();
+
         decode_response(encoded_response).await
     }
 
@@ -312,6 +361,7 @@ mod test {
     use super::*;
 
     use std::io::Write;
+    use std::time::Duration;
 
     const BODY: &str = r#"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do
 eiusmod tempor incididunt ut labore et dolore magnam aliquam quaerat voluptatem. Ut
@@ -337,6 +387,59 @@ si aliquod aeternum et infinitum impendere."#;
         );
     }
 
+    #[tokio::test]
+    async fn test_connection_stats() {
+        // get a free port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let client = Client::new();
+        let uri = format!("http://127.0.0.1:{port}/");
+        assert!(client.get_string(&uri, None).await.is_err());
+
+        let stats = client.connection_stats();
+        let stats = &stats[&format!("127.0.0.1:{port}")];
+        assert_eq!(stats.connections, 0);
+        assert_eq!(stats.connect_errors, 1);
+        assert_eq!(stats.requests, 1);
+        assert_eq!(stats.active_requests, 0);
+        assert_eq!(stats.failed_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        const LIMIT: Duration = Duration::from_secs(5);
+
+        // accepts connections, but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = Client::with_options(HttpOptions {
+            request_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        let uri = format!("http://127.0.0.1:{port}/");
+        let result = tokio::time::timeout(LIMIT, client.get_string(&uri, None))
+            .await
+            .expect("request timeout did not fire");
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+
+        // a TEST-NET-1 address, which is not routed
+        let client = Client::with_options(HttpOptions {
+            connect_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        let result = tokio::time::timeout(LIMIT, client.get_string("http://192.0.2.1/", None))
+            .await
+            .expect("connect timeout did not fire");
+        assert!(result.is_err());
+
+        drop(listener);
+    }
+
     fn encode_deflate(bytes: &[u8]) -> Result<Vec<u8>, std::io::Error> {
         use flate2::write::ZlibEncoder;
         use flate2::Compression;
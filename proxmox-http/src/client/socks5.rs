@@ -0,0 +1,213 @@
+//! SOCKS5 client handshake, see RFC1928 and RFC1929 for username/password authentication.
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{bail, format_err, Error};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 5;
+const AUTH_VERSION: u8 = 1;
+
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NOT_ACCEPTABLE: u8 = 0xff;
+
+const CMD_CONNECT: u8 = 0x01;
+
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+fn reply_error(code: u8) -> &'static str {
+    match code {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// Ask the SOCKS5 server connected via `stream` to connect to `host` and `port`.
+///
+/// `authorization` is expected as `user:password`. With `resolve_remote` host names are passed to
+/// the proxy, otherwise they are resolved locally.
+pub(crate) async fn connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    authorization: Option<&str>,
+    resolve_remote: bool,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut request = vec![VERSION, CMD_CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(ip) => push_addr(&mut request, ip),
+        Err(_) if resolve_remote => {
+            let len = u8::try_from(host.len())
+                .map_err(|_| format_err!("host name too long for SOCKS5 - {host}"))?;
+            request.extend([ATYP_DOMAIN, len]);
+            request.extend(host.as_bytes());
+        }
+        Err(_) => {
+            let addr: SocketAddr = tokio::net::lookup_host((host, port))
+                .await?
+                .next()
+                .ok_or_else(|| format_err!("failed to resolve '{host}'"))?;
+            push_addr(&mut request, addr.ip());
+        }
+    }
+    request.extend(port.to_be_bytes());
+
+    authenticate(stream, authorization).await?;
+
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        bail!("invalid SOCKS5 reply version {}", reply[0]);
+    }
+    if reply[1] != 0 {
+        bail!("SOCKS5 connect failed - {}", reply_error(reply[1]));
+    }
+
+    // skip the bound address
+    let addr_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => stream.read_u8().await? as usize,
+        other => bail!("invalid SOCKS5 address type {other}"),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+fn push_addr(request: &mut Vec<u8>, ip: IpAddr) {
+    match ip {
+        IpAddr::V4(ip) => {
+            request.push(ATYP_IPV4);
+            request.extend(ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(ATYP_IPV6);
+            request.extend(ip.octets());
+        }
+    }
+}
+
+async fn authenticate<S>(stream: &mut S, authorization: Option<&str>) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let methods: &[u8] = match authorization {
+        Some(_) => &[METHOD_NO_AUTH, METHOD_USERNAME_PASSWORD],
+        None => &[METHOD_NO_AUTH],
+    };
+    let mut greeting = vec![VERSION, methods.len() as u8];
+    greeting.extend(methods);
+    stream.write_all(&greeting).await?;
+    stream.flush().await?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != VERSION {
+        bail!("invalid SOCKS5 version {}", choice[0]);
+    }
+
+    match (choice[1], authorization) {
+        (METHOD_NO_AUTH, _) => Ok(()),
+        (METHOD_USERNAME_PASSWORD, Some(authorization)) => {
+            let (user, password) = authorization.split_once(':').unwrap_or((authorization, ""));
+            let user_len =
+                u8::try_from(user.len()).map_err(|_| format_err!("SOCKS5 user name too long"))?;
+            let password_len = u8::try_from(password.len())
+                .map_err(|_| format_err!("SOCKS5 password too long"))?;
+
+            let mut request = vec![AUTH_VERSION, user_len];
+            request.extend(user.as_bytes());
+            request.push(password_len);
+            request.extend(password.as_bytes());
+            stream.write_all(&request).await?;
+            stream.flush().await?;
+
+            let mut reply = [0u8; 2];
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                bail!("SOCKS5 authentication failed");
+            }
+            Ok(())
+        }
+        (METHOD_NOT_ACCEPTABLE, _) => bail!("SOCKS5 proxy accepted no authentication method"),
+        (other, _) => bail!("SOCKS5 proxy selected unsupported authentication method {other}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_socks5_connect() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        let proxy = async move {
+            let mut buf = [0u8; 4];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [5, 2, 0, 2]);
+            server.write_all(&[5, 2]).await.unwrap();
+
+            let mut auth = [0u8; 11];
+            server.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x04user\x04pass");
+            server.write_all(&[1, 0]).await.unwrap();
+
+            let mut request = [0u8; 18];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request, b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+            server
+                .write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x1f, 0x90])
+                .await
+                .unwrap();
+        };
+
+        let connect = connect(&mut client, "example.com", 443, Some("user:pass"), true);
+
+        let ((), result) = tokio::join!(proxy, connect);
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_refused() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        let proxy = async move {
+            let mut buf = [0u8; 3];
+            server.read_exact(&mut buf).await.unwrap();
+            server.write_all(&[5, 0]).await.unwrap();
+
+            let mut request = [0u8; 10];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request, b"\x05\x01\x00\x01\x0a\x00\x00\x02\x00\x50");
+            server.write_all(&[5, 5, 0, 1]).await.unwrap();
+        };
+
+        let connect = connect(&mut client, "10.0.0.2", 80, None, false);
+
+        let ((), result) = tokio::join!(proxy, connect);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "SOCKS5 connect failed - connection refused"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Per-host connection statistics of the async [`Client`](super::Client).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Connection and request counters for a single `host:port`.
+///
+/// Comparing `connections` to `requests` shows how well connections get reused from the pool,
+/// `active_requests` how many requests currently wait for their response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostStats {
+    /// Number of connections established.
+    pub connections: u64,
+    /// Number of connections which negotiated HTTP/2.
+    pub http2_connections: u64,
+    /// Number of failed connection attempts.
+    pub connect_errors: u64,
+    /// Number of requests sent.
+    pub requests: u64,
+    /// Number of requests currently waiting for a response.
+    pub active_requests: u64,
+    /// Number of requests which failed, timed out or were cancelled.
+    pub failed_requests: u64,
+}
+
+/// Shared statistics, keyed by `host:port`.
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionStats {
+    hosts: Arc<Mutex<HashMap<String, HostStats>>>,
+}
+
+impl ConnectionStats {
+    pub(crate) fn update(&self, host: &str, port: u16, func: impl FnOnce(&mut HostStats)) {
+        let mut hosts = self.hosts.lock().unwrap();
+        func(hosts.entry(format!("{host}:{port}")).or_default());
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<String, HostStats> {
+        self.hosts.lock().unwrap().clone()
+    }
+
+    /// Count a new request, which stays active until the returned guard is dropped.
+    pub(crate) fn start_request(&self, host: &str, port: u16) -> ActiveRequest {
+        self.update(host, port, |stats| {
+            stats.requests += 1;
+            stats.active_requests += 1;
+        });
+        ActiveRequest {
+            stats: self.clone(),
+            host: host.to_string(),
+            port,
+            failed: true,
+        }
+    }
+}
+
+pub(crate) struct ActiveRequest {
+    stats: ConnectionStats,
+    host: String,
+    port: u16,
+    failed: bool,
+}
+
+impl ActiveRequest {
+    pub(crate) fn succeeded(mut self) {
+        self.failed = false;
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        let failed = self.failed;
+        self.stats.update(&self.host, self.port, |stats| {
+            stats.active_requests -= 1;
+            if failed {
+                stats.failed_requests += 1;
+            }
+        });
+    }
+}
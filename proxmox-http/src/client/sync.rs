@@ -2,11 +2,12 @@ use std::collections::HashMap;
 use std::io::Read;
 use std::time::Duration;
 
-use anyhow::Error;
+use anyhow::{bail, Error};
 use http::Response;
 
 use crate::HttpClient;
 use crate::HttpOptions;
+use crate::ProxyConfig;
 
 #[derive(Default)]
 /// Blocking HTTP client for usage with [`HttpClient`].
@@ -30,7 +31,7 @@ impl Client {
         }
     }
 
-    fn agent(&self) -> Result<ureq::Agent, Error> {
+    fn agent(&self, uri: &str) -> Result<ureq::Agent, Error> {
         let mut builder = ureq::Agent::config_builder()
             .tls_config(
                 ureq::tls::TlsConfig::builder()
@@ -42,15 +43,33 @@ impl Client {
                 "proxmox-sync-http-client/",
                 env!("CARGO_PKG_VERSION")
             )))
-            .timeout_global(self.timeout);
+            .timeout_global(self.timeout.or(self.options.request_timeout))
+            .timeout_connect(self.options.connect_timeout);
 
         if let Some(proxy_config) = &self.options.proxy_config {
-            builder = builder.proxy(Some(ureq::Proxy::new(&proxy_config.to_proxy_string()?)?));
+            if !Self::is_bypassed(proxy_config, uri) {
+                if proxy_config.proxy_type.is_socks() {
+                    bail!("SOCKS5 proxies are not supported by the blocking HTTP client");
+                }
+                builder = builder.proxy(Some(ureq::Proxy::new(&proxy_config.to_proxy_string()?)?));
+            }
         }
 
         Ok(builder.build().into())
     }
 
+    fn is_bypassed(proxy_config: &ProxyConfig, uri: &str) -> bool {
+        let Ok(uri) = uri.parse::<http::Uri>() else {
+            return false;
+        };
+        let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+            Some("https") => 443,
+            _ => 80,
+        });
+        uri.host()
+            .is_some_and(|host| proxy_config.is_bypassed(host, port))
+    }
+
     fn add_headers(
         mut req: http::request::Builder,
         content_type: Option<&str>,
@@ -93,7 +112,7 @@ impl HttpClient<String, String> for Client {
         uri: &str,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<http::Response<String>, Error> {
-        let agent = self.agent()?;
+        let agent = self.agent(uri)?;
         let req = http::Request::get(uri);
         let req = Self::add_headers(req, None, extra_headers);
         let req = req.body(ureq::SendBody::none())?;
@@ -111,7 +130,7 @@ impl HttpClient<String, String> for Client {
         content_type: Option<&str>,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<http::Response<String>, Error> {
-        let agent = self.agent()?;
+        let agent = self.agent(uri)?;
         let req = http::Request::post(uri);
         let req = Self::add_headers(req, content_type, extra_headers);
 
@@ -126,7 +145,7 @@ impl HttpClient<String, String> for Client {
     fn request(&self, request: http::Request<String>) -> Result<http::Response<String>, Error> {
         let (parts, body) = request.into_parts();
 
-        let agent = self.agent()?;
+        let agent = self.agent(&parts.uri.to_string())?;
         let mut req = http::Request::post(parts.method.as_str());
 
         for header in parts.headers.keys() {
@@ -148,7 +167,7 @@ impl HttpClient<&[u8], Vec<u8>> for Client {
         uri: &str,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<http::Response<Vec<u8>>, Error> {
-        let agent = self.agent()?;
+        let agent = self.agent(uri)?;
         let req = http::Request::get(uri);
         let req = Self::add_headers(req, None, extra_headers);
         let req = req.body(ureq::SendBody::none())?;
@@ -166,7 +185,7 @@ impl HttpClient<&[u8], Vec<u8>> for Client {
         content_type: Option<&str>,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<http::Response<Vec<u8>>, Error> {
-        let agent = self.agent()?;
+        let agent = self.agent(uri)?;
         let req = http::Request::post(uri);
         let req = Self::add_headers(req, content_type, extra_headers);
 
@@ -181,7 +200,7 @@ impl HttpClient<&[u8], Vec<u8>> for Client {
     fn request(&self, request: http::Request<&[u8]>) -> Result<http::Response<Vec<u8>>, Error> {
         let (parts, body) = request.into_parts();
 
-        let agent = self.agent()?;
+        let agent = self.agent(&parts.uri.to_string())?;
         let mut req = http::Request::post(parts.method.as_str());
 
         for header in parts.headers.keys() {
@@ -203,7 +222,7 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
         uri: &str,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<http::Response<Box<dyn Read>>, Error> {
-        let agent = self.agent()?;
+        let agent = self.agent(uri)?;
         let req = http::Request::get(uri);
         let req = Self::add_headers(req, None, extra_headers);
         let req = req.body(ureq::SendBody::none())?;
@@ -221,7 +240,7 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
         content_type: Option<&str>,
         extra_headers: Option<&HashMap<String, String>>,
     ) -> Result<http::Response<Box<dyn Read>>, Error> {
-        let agent = self.agent()?;
+        let agent = self.agent(uri)?;
         let req = http::Request::post(uri);
         let req = Self::add_headers(req, content_type, extra_headers);
 
@@ -239,7 +258,7 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
     ) -> Result<http::Response<Box<dyn Read>>, Error> {
         let (parts, body) = request.into_parts();
 
-        let agent = self.agent()?;
+        let agent = self.agent(&parts.uri.to_string())?;
         let mut req = http::Request::post(parts.method.as_str());
 
         for header in parts.headers.keys() {
@@ -254,3 +273,21 @@ impl HttpClient<Box<dyn Read>, Box<dyn Read>> for Client {
             .map(Self::convert_response_to_reader)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_socks_proxy_rejected() {
+        let mut proxy_config = ProxyConfig::parse_proxy_url("socks5://proxy.example.com").unwrap();
+        proxy_config.no_proxy = "localhost".parse().unwrap();
+        let client = Client::new(HttpOptions {
+            proxy_config: Some(proxy_config),
+            ..Default::default()
+        });
+
+        assert!(client.agent("https://www.example.com/").is_err());
+        assert!(client.agent("https://localhost/").is_ok());
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use crate::ProxyConfig;
 
 /// Options for an HTTP client.
@@ -9,12 +11,22 @@ pub struct HttpOptions {
     pub user_agent: Option<String>,
     /// TCP keepalive time, defaults to 7200
     pub tcp_keepalive: Option<u32>,
+    /// Offer HTTP/2 via ALPN on TLS connections, HTTP/1.1 is used otherwise
+    pub http2: bool,
+    /// Maximum number of idle connections kept per host, unlimited by default
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Close idle pooled connections after this time, defaults to 90 seconds
+    pub pool_idle_timeout: Option<Duration>,
+    /// Timeout for a single request until the response headers are received
+    pub request_timeout: Option<Duration>,
+    /// Timeout for establishing the TCP connection
+    pub connect_timeout: Option<Duration>,
 }
 
 impl HttpOptions {
     pub fn get_proxy_authorization(&self) -> Option<String> {
         if let Some(ref proxy_config) = self.proxy_config {
-            if !proxy_config.force_connect {
+            if !proxy_config.force_connect && !proxy_config.proxy_type.is_socks() {
                 return proxy_config.authorization.clone();
             }
         }
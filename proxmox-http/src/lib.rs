@@ -11,7 +11,7 @@ pub mod uri;
 #[cfg(feature = "http-helpers")]
 pub mod proxy_config;
 #[cfg(feature = "http-helpers")]
-pub use proxy_config::ProxyConfig;
+pub use proxy_config::{NoProxy, ProxyConfig, ProxyType};
 
 #[cfg(feature = "http-helpers")]
 mod http_options;
@@ -0,0 +1,218 @@
+//! Client side of the WebSocket handshake
+
+use anyhow::{bail, format_err, Error};
+use http::header::{
+    HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY,
+    SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use http::{Request, Response, StatusCode};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+
+use super::{accept_key, DeflateParams, WebSocket, WebSocketConfig, PERMESSAGE_DEFLATE};
+use crate::client::Client;
+use crate::Body;
+
+/// Prepares an upgrade request and validates the server's response to it.
+///
+/// Example usage:
+/// ```no_run
+/// # use anyhow::Error;
+/// # use proxmox_http::client::Client;
+/// # use proxmox_http::websocket::*;
+/// # async fn code(client: &Client, vnc: tokio::net::TcpStream) -> Result<(), Error> {
+/// let request = http::Request::get("https://localhost:8006/api2/json/nodes/node/vncwebsocket")
+///     .body(proxmox_http::Body::empty())?;
+/// let handshake = ClientHandshake::new(WebSocketConfig::default())?.protocol("binary");
+/// let connection = handshake.connect(client, request).await?;
+/// connection.websocket.serve_connection(connection.stream, vnc).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClientHandshake {
+    key: String,
+    protocols: Vec<String>,
+    config: WebSocketConfig,
+}
+
+/// An established client WebSocket connection.
+pub struct ClientConnection {
+    /// Used to serve the connection, configured for the client side.
+    pub websocket: WebSocket,
+    /// The subprotocol selected by the server, if any.
+    pub protocol: Option<String>,
+    /// The upgraded connection to the server.
+    pub stream: TokioIo<Upgraded>,
+}
+
+impl ClientHandshake {
+    /// Creates a new handshake with a random key.
+    pub fn new(config: WebSocketConfig) -> Result<Self, Error> {
+        let mut key = [0u8; 16];
+        openssl::rand::rand_bytes(&mut key)?;
+
+        Ok(Self {
+            key: proxmox_base64::encode(key),
+            protocols: Vec::new(),
+            config,
+        })
+    }
+
+    /// Offers a subprotocol to the server. Can be called multiple times, in order of preference.
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.protocols.push(protocol.into());
+        self
+    }
+
+    /// The `Sec-WebSocket-Key` sent to the server.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Adds the headers required for the upgrade to a request.
+    pub fn prepare_request<B>(&self, request: &mut Request<B>) -> Result<(), Error> {
+        let headers = request.headers_mut();
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        headers.insert(SEC_WEBSOCKET_KEY, HeaderValue::from_str(&self.key)?);
+        if !self.protocols.is_empty() {
+            headers.insert(
+                SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_str(&self.protocols.join(", "))?,
+            );
+        }
+        if self.config.permessage_deflate {
+            headers.insert(
+                SEC_WEBSOCKET_EXTENSIONS,
+                HeaderValue::from_static(PERMESSAGE_DEFLATE),
+            );
+        }
+        Ok(())
+    }
+
+    /// Validates the server's response to the upgrade request.
+    ///
+    /// Returns the [`WebSocket`] to serve the connection with and the selected subprotocol.
+    pub fn validate_response<B>(
+        &self,
+        response: &Response<B>,
+    ) -> Result<(WebSocket, Option<String>), Error> {
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            bail!(
+                "websocket upgrade failed - got status '{}'",
+                response.status()
+            );
+        }
+
+        let headers = response.headers();
+        let header = |name| -> Result<Option<&str>, Error> {
+            match headers.get(&name) {
+                Some(value) => Ok(Some(value.to_str()?)),
+                None => Ok(None),
+            }
+        };
+
+        if !header(UPGRADE)?.is_some_and(|value| value.eq_ignore_ascii_case("websocket")) {
+            bail!("invalid or missing Upgrade header");
+        }
+
+        let connection_upgrade = headers.get_all(CONNECTION).iter().any(|value| {
+            value.to_str().is_ok_and(|value| {
+                value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+            })
+        });
+        if !connection_upgrade {
+            bail!("invalid or missing Connection header");
+        }
+
+        if header(SEC_WEBSOCKET_ACCEPT)? != Some(accept_key(&self.key).as_str()) {
+            bail!("invalid or missing websocket accept key");
+        }
+
+        let protocol = match header(SEC_WEBSOCKET_PROTOCOL)? {
+            Some(protocol) if self.protocols.iter().any(|offered| offered == protocol) => {
+                Some(protocol.to_string())
+            }
+            Some(protocol) => bail!("server selected unrequested subprotocol '{protocol}'"),
+            None => None,
+        };
+
+        let deflate = match header(SEC_WEBSOCKET_EXTENSIONS)? {
+            Some(_) if !self.config.permessage_deflate => {
+                bail!("server responded with unrequested extensions")
+            }
+            Some(extensions) => DeflateParams::from_response(extensions)?,
+            None => None,
+        };
+
+        // only enables masking, every frame gets a new random key
+        let websocket = WebSocket {
+            mask: Some([0; 4]),
+            config: self.config.clone(),
+            deflate,
+            client: true,
+        };
+
+        Ok((websocket, protocol))
+    }
+
+    /// Sends the upgrade request via `client` and waits for the upgrade to complete.
+    pub async fn connect(
+        &self,
+        client: &Client,
+        mut request: Request<Body>,
+    ) -> Result<ClientConnection, Error> {
+        self.prepare_request(&mut request)?;
+
+        let mut response = client.request(request).await?;
+        let (websocket, protocol) = self.validate_response(&response)?;
+
+        let upgraded = hyper::upgrade::on(&mut response)
+            .await
+            .map_err(|err| format_err!("websocket upgrade failed - {err}"))?;
+
+        Ok(ClientConnection {
+            websocket,
+            protocol,
+            stream: TokioIo::new(upgraded),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handshake() {
+        let config = WebSocketConfig {
+            permessage_deflate: true,
+            ..Default::default()
+        };
+        let handshake = ClientHandshake::new(config.clone())
+            .unwrap()
+            .protocol("binary");
+
+        let mut request = Request::get("http://localhost/").body(()).unwrap();
+        handshake.prepare_request(&mut request).unwrap();
+
+        let (_, response) = WebSocket::with_config(request.headers().clone(), config).unwrap();
+        let (websocket, protocol) = handshake.validate_response(&response).unwrap();
+        assert_eq!(protocol.as_deref(), Some("binary"));
+        assert_eq!(websocket.deflate(), Some(DeflateParams::default()));
+        assert!(websocket.mask.is_some());
+
+        let other = ClientHandshake::new(WebSocketConfig::default()).unwrap();
+        assert!(other.validate_response(&response).is_err());
+
+        let mut response = response;
+        let headers = response.headers_mut();
+        headers.insert(CONNECTION, HeaderValue::from_static("keep-alive, upgrade"));
+        assert!(handshake.validate_response(&response).is_ok());
+        response.headers_mut().remove(CONNECTION);
+        assert!(handshake.validate_response(&response).is_err());
+    }
+}
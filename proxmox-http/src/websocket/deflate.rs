@@ -0,0 +1,326 @@
+//! The permessage-deflate extension, see RFC7692
+//!
+//! Only the default LZ77 window size of 15 bits is supported for compression, offers requiring a
+//! smaller window on our side get declined.
+
+use std::io;
+
+use anyhow::{bail, Error};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+use super::{WebSocketError, WebSocketErrorKind};
+
+/// The name of the extension as used in the `Sec-WebSocket-Extensions` header.
+pub const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+
+/// Every compressed message is terminated by an empty deflate block, which is not transmitted.
+const DEFLATE_TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Negotiated parameters of the permessage-deflate extension.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeflateParams {
+    /// The server resets its compression context after every message.
+    pub server_no_context_takeover: bool,
+    /// The client resets its compression context after every message.
+    pub client_no_context_takeover: bool,
+}
+
+impl DeflateParams {
+    /// Pick the first acceptable permessage-deflate offer of a client's
+    /// `Sec-WebSocket-Extensions` header.
+    pub fn negotiate(extensions: &str) -> Option<Self> {
+        extensions
+            .split(',')
+            .filter_map(|extension| parse_extension(extension).ok().flatten())
+            .find_map(|params| {
+                let mut result = Self::default();
+                for (name, value) in params {
+                    match (name, value) {
+                        ("server_no_context_takeover", None) => {
+                            result.server_no_context_takeover = true
+                        }
+                        ("client_no_context_takeover", None) => {
+                            result.client_no_context_takeover = true
+                        }
+                        // we don't need to limit the client's window
+                        ("client_max_window_bits", _) => (),
+                        ("server_max_window_bits", Some("15")) => (),
+                        _ => return None,
+                    }
+                }
+                Some(result)
+            })
+    }
+
+    /// Parse the server's response to our permessage-deflate offer.
+    ///
+    /// Returns `None` if the server did not accept the extension.
+    pub fn from_response(extensions: &str) -> Result<Option<Self>, Error> {
+        let mut result = None;
+        for extension in extensions.split(',') {
+            let Some(params) = parse_extension(extension)? else {
+                bail!(
+                    "server responded with unrequested extension '{}'",
+                    extension.trim()
+                );
+            };
+            if result.is_some() {
+                bail!("server accepted {PERMESSAGE_DEFLATE} more than once");
+            }
+
+            let mut deflate = Self::default();
+            for (name, value) in params {
+                match (name, value) {
+                    ("server_no_context_takeover", None) => {
+                        deflate.server_no_context_takeover = true
+                    }
+                    ("client_no_context_takeover", None) => {
+                        deflate.client_no_context_takeover = true
+                    }
+                    // a smaller window of the server does not matter for decompression
+                    ("server_max_window_bits", Some(bits)) if is_window_bits(bits) => (),
+                    _ => bail!("unsupported {PERMESSAGE_DEFLATE} parameter '{name}'"),
+                }
+            }
+            result = Some(deflate);
+        }
+        Ok(result)
+    }
+
+    /// The value for the `Sec-WebSocket-Extensions` header.
+    pub fn header_value(&self) -> String {
+        let mut value = PERMESSAGE_DEFLATE.to_string();
+        if self.server_no_context_takeover {
+            value.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            value.push_str("; client_no_context_takeover");
+        }
+        value
+    }
+}
+
+fn is_window_bits(bits: &str) -> bool {
+    matches!(bits.parse::<u8>(), Ok(8..=15))
+}
+
+/// Extension parameters with their optional values.
+type ExtensionParams<'a> = Vec<(&'a str, Option<&'a str>)>;
+
+/// Parse a single extension of an extension list. Returns `None` for extensions other than
+/// permessage-deflate.
+fn parse_extension(extension: &str) -> Result<Option<ExtensionParams<'_>>, Error> {
+    let mut parts = extension.split(';').map(str::trim);
+    if parts.next() != Some(PERMESSAGE_DEFLATE) {
+        return Ok(None);
+    }
+
+    let mut params = ExtensionParams::new();
+    for part in parts {
+        let (name, value) = match part.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (part, None),
+        };
+        if params.iter().any(|(existing, _)| *existing == name) {
+            bail!("duplicate {PERMESSAGE_DEFLATE} parameter '{name}'");
+        }
+        params.push((name, value));
+    }
+    Ok(Some(params))
+}
+
+/// Compresses outgoing messages.
+pub(crate) struct Deflater {
+    compress: Compress,
+    no_context_takeover: bool,
+}
+
+impl Deflater {
+    pub(crate) fn new(no_context_takeover: bool) -> Self {
+        Self {
+            compress: Compress::new(Compression::default(), false),
+            no_context_takeover,
+        }
+    }
+
+    /// Compress a complete message.
+    pub(crate) fn compress(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(data.len() / 2 + 64);
+        let start = self.compress.total_in();
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity());
+            }
+            self.compress
+                .compress_vec(&data[consumed..], &mut output, FlushCompress::Sync)
+                .map_err(io::Error::other)?;
+            // the flush is complete once all input is consumed and there was space left
+            if self.compress.total_in() - start == data.len() as u64
+                && output.len() < output.capacity()
+            {
+                break;
+            }
+        }
+
+        if output.ends_with(&DEFLATE_TRAILER) {
+            output.truncate(output.len() - DEFLATE_TRAILER.len());
+        }
+        if self.no_context_takeover {
+            self.compress.reset();
+        }
+        Ok(output)
+    }
+}
+
+/// Decompresses incoming messages, which may be fed in pieces.
+pub(crate) struct Inflater {
+    decompress: Decompress,
+    /// Decompressed size of the current message so far.
+    message_len: usize,
+}
+
+impl Inflater {
+    pub(crate) fn new() -> Self {
+        Self {
+            decompress: Decompress::new(false),
+            message_len: 0,
+        }
+    }
+
+    /// Decompress a part of a message, appending the result to `output`.
+    ///
+    /// Fails once the decompressed message exceeds `max_size` bytes.
+    pub(crate) fn inflate(
+        &mut self,
+        mut data: &[u8],
+        output: &mut Vec<u8>,
+        max_size: usize,
+    ) -> Result<(), WebSocketError> {
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity().max(4096));
+            }
+            let before = self.decompress.total_in();
+            let output_len = output.len();
+            let status = self
+                .decompress
+                .decompress_vec(data, output, FlushDecompress::Sync)
+                .map_err(|_| {
+                    WebSocketError::new(
+                        WebSocketErrorKind::InvalidPayload,
+                        "invalid compressed message",
+                    )
+                })?;
+            let consumed = (self.decompress.total_in() - before) as usize;
+            data = &data[consumed..];
+
+            self.message_len += output.len() - output_len;
+            if self.message_len > max_size {
+                return Err(WebSocketError::new(
+                    WebSocketErrorKind::MessageTooBig,
+                    "decompressed message too big",
+                ));
+            }
+
+            if status == Status::StreamEnd {
+                // a final block ends the deflate stream, following messages start a new one
+                self.decompress.reset(false);
+            }
+            if output.len() < output.capacity()
+                && (data.is_empty() || (consumed == 0 && output.len() == output_len))
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Finish a message after all of its data was passed to [`inflate`](Self::inflate).
+    pub(crate) fn finish(
+        &mut self,
+        output: &mut Vec<u8>,
+        max_size: usize,
+    ) -> Result<(), WebSocketError> {
+        let result = self.inflate(&DEFLATE_TRAILER, output, max_size);
+        self.message_len = 0;
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(DeflateParams::negotiate("x-webkit-deflate-frame"), None);
+        assert_eq!(
+            DeflateParams::negotiate("permessage-deflate; client_max_window_bits"),
+            Some(DeflateParams::default())
+        );
+        assert_eq!(
+            DeflateParams::negotiate(
+                "permessage-deflate; server_max_window_bits=10, \
+                 permessage-deflate; client_no_context_takeover"
+            ),
+            Some(DeflateParams {
+                server_no_context_takeover: false,
+                client_no_context_takeover: true,
+            })
+        );
+        assert_eq!(DeflateParams::negotiate("permessage-deflate; foo"), None);
+
+        let params = DeflateParams {
+            server_no_context_takeover: true,
+            client_no_context_takeover: false,
+        };
+        assert_eq!(
+            DeflateParams::from_response(&params.header_value()).unwrap(),
+            Some(params)
+        );
+        assert!(
+            DeflateParams::from_response("permessage-deflate; client_max_window_bits=9").is_err()
+        );
+        assert!(DeflateParams::from_response("foo").is_err());
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        for no_context_takeover in [false, true] {
+            let mut deflater = Deflater::new(no_context_takeover);
+            let mut inflater = Inflater::new();
+
+            for message in [&b"hello hello hello"[..], b"", &[7u8; 100_000]] {
+                let compressed = deflater.compress(message).unwrap();
+                let mut output = Vec::new();
+                // feed the data in pieces
+                for chunk in compressed.chunks(7) {
+                    inflater.inflate(chunk, &mut output, usize::MAX).unwrap();
+                }
+                inflater.finish(&mut output, usize::MAX).unwrap();
+                assert_eq!(output, message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inflate_errors() {
+        let mut deflater = Deflater::new(false);
+        let compressed = deflater.compress(&[0u8; 100_000]).unwrap();
+
+        let mut inflater = Inflater::new();
+        let mut output = Vec::new();
+        let err = inflater
+            .inflate(&compressed, &mut output, 50_000)
+            .unwrap_err();
+        assert!(matches!(err.kind, WebSocketErrorKind::MessageTooBig));
+        assert!(output.len() < 2 * 50_000 + 4096);
+
+        let mut inflater = Inflater::new();
+        let mut output = Vec::new();
+        let err = inflater
+            .inflate(&[0xff; 16], &mut output, usize::MAX)
+            .unwrap_err();
+        assert!(matches!(err.kind, WebSocketErrorKind::InvalidPayload));
+    }
+}
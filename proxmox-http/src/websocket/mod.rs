@@ -7,13 +7,15 @@ use std::cmp::min;
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, format_err, Error};
 use futures::select;
 use http::header::{
-    HeaderMap, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
-    SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE,
+    HeaderMap, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_EXTENSIONS,
+    SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE,
 };
 use http::{Response, StatusCode};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
@@ -27,6 +29,15 @@ use proxmox_lang::io_format_err;
 
 use crate::Body;
 
+mod deflate;
+pub use deflate::{DeflateParams, PERMESSAGE_DEFLATE};
+use deflate::{Deflater, Inflater};
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+pub use client::{ClientConnection, ClientHandshake};
+
 // see RFC6455 section 7.4.1
 #[derive(Debug, Clone, Copy)]
 #[repr(u16)]
@@ -34,7 +45,9 @@ pub enum WebSocketErrorKind {
     Normal = 1000,
     ProtocolError = 1002,
     InvalidData = 1003,
+    InvalidPayload = 1007,
     Other = 1008,
+    MessageTooBig = 1009,
     Unexpected = 1011,
 }
 
@@ -189,7 +202,20 @@ pub fn create_frame(
     data: &[u8],
     frametype: OpCode,
 ) -> Result<Vec<u8>, WebSocketError> {
-    let first_byte = 0b10000000 | (frametype as u8);
+    build_frame(mask, data, frametype, false)
+}
+
+/// Like [`create_frame`], optionally setting the RSV1 bit marking a compressed message.
+fn build_frame(
+    mask: Option<[u8; 4]>,
+    data: &[u8],
+    frametype: OpCode,
+    compressed: bool,
+) -> Result<Vec<u8>, WebSocketError> {
+    let mut first_byte = 0b10000000 | (frametype as u8);
+    if compressed {
+        first_byte |= 0b01000000;
+    }
     let len = data.len();
     if (frametype as u8) & 0b00001000 > 0 && len > 125 {
         return Err(WebSocketError::new(
@@ -226,6 +252,20 @@ pub fn create_frame(
     Ok(buf)
 }
 
+/// Returns a new random masking key if masking is enabled by `mask`.
+///
+/// Clients need to use a fresh, unpredictable key for every frame (RFC 6455, section 5.3).
+fn fresh_mask(mask: Option<[u8; 4]>) -> io::Result<Option<[u8; 4]>> {
+    match mask {
+        Some(_) => {
+            let mut mask = [0u8; 4];
+            openssl::rand::rand_bytes(&mut mask).map_err(io::Error::other)?;
+            Ok(Some(mask))
+        }
+        None => Ok(None),
+    }
+}
+
 /// Wrap (encapsulate) an `AsyncWrite`er into a WebSocket transparently
 ///
 /// Send websocket frames to anything accepting AsyncWrite.
@@ -248,25 +288,38 @@ pub struct WebSocketWriter<W: AsyncWrite + Unpin> {
     writer: W,
     mask: Option<[u8; 4]>,
     frame: Option<(Vec<u8>, usize, usize)>,
+    deflater: Option<Deflater>,
 }
 
 impl<W: AsyncWrite + Unpin> WebSocketWriter<W> {
-    /// Create a new WebSocketWriter which will use the given mask, if any, creating Binary frames
+    /// Create a new WebSocketWriter creating Binary frames, which are masked if a mask is given.
+    ///
+    /// Every frame is masked with a new random key, the given mask only enables masking.
     pub fn new(mask: Option<[u8; 4]>, writer: W) -> WebSocketWriter<W> {
         WebSocketWriter {
             writer,
             mask,
             frame: None,
+            deflater: None,
         }
     }
 
+    /// Compress every written message with the negotiated permessage-deflate extension.
+    ///
+    /// With `no_context_takeover`, the compression context is reset after every message.
+    pub fn enable_deflate(mut self, no_context_takeover: bool) -> Self {
+        self.deflater = Some(Deflater::new(no_context_takeover));
+        self
+    }
+
+    /// Send a control frame, masked with a new random key if `mask` is set.
     pub async fn send_control_frame(
         &mut self,
         mask: Option<[u8; 4]>,
         opcode: OpCode,
         data: &[u8],
     ) -> Result<(), Error> {
-        let frame = create_frame(mask, data, opcode).map_err(Error::from)?;
+        let frame = create_frame(fresh_mask(mask)?, data, opcode).map_err(Error::from)?;
         self.writer.write_all(&frame).await.map_err(Error::from)
     }
 }
@@ -277,7 +330,15 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for WebSocketWriter<W> {
 
         if this.frame.is_none() {
             // create frame buf
-            let frame = match create_frame(this.mask, buf, OpCode::Binary) {
+            let mask = fresh_mask(this.mask)?;
+            let frame = match this.deflater.as_mut() {
+                Some(deflater) => {
+                    let data = deflater.compress(buf)?;
+                    build_frame(mask, &data, OpCode::Binary, true)
+                }
+                None => create_frame(mask, buf, OpCode::Binary),
+            };
+            let frame = match frame {
                 Ok(f) => f,
                 Err(e) => {
                     return Poll::Ready(Err(io::Error::other(e)));
@@ -369,18 +430,32 @@ impl FrameHeader {
     /// # }
     /// ```
     pub fn try_from_bytes(data: &[u8]) -> Result<Option<FrameHeader>, WebSocketError> {
+        Ok(Self::parse(data, false)?.map(|(header, _)| header))
+    }
+
+    /// Parses a header, additionally returning the RSV1 bit if `allow_compressed` is set.
+    fn parse(
+        data: &[u8],
+        allow_compressed: bool,
+    ) -> Result<Option<(FrameHeader, bool)>, WebSocketError> {
         let len = data.len();
         if len < 2 {
             return Ok(None);
         }
 
-        // we do not support extensions
-        if data[0] & 0b01110000 > 0 {
+        // the only supported extension is permessage-deflate, which uses RSV1
+        let reserved = if allow_compressed {
+            0b00110000
+        } else {
+            0b01110000
+        };
+        if data[0] & reserved > 0 {
             return Err(WebSocketError::new(
                 WebSocketErrorKind::ProtocolError,
                 "Extensions not supported",
             ));
         }
+        let compressed = data[0] & 0b01000000 != 0;
 
         let fin = data[0] & 0b10000000 != 0;
         let frametype = match data[0] & 0b1111 {
@@ -449,13 +524,21 @@ impl FrameHeader {
             None
         };
 
-        Ok(Some(FrameHeader {
+        if compressed && (frametype.is_control() || frametype == OpCode::Continuation) {
+            return Err(WebSocketError::new(
+                WebSocketErrorKind::ProtocolError,
+                "RSV1 is only allowed on the first frame of a data message",
+            ));
+        }
+
+        let header = FrameHeader {
             fin,
             mask,
             frametype,
             payload_len,
             header_len: payload_offset,
-        }))
+        };
+        Ok(Some((header, compressed)))
     }
 }
 
@@ -475,6 +558,14 @@ pub struct WebSocketReader<R: AsyncRead> {
     read_buffer: Option<ByteBuffer>,
     header: Option<FrameHeader>,
     state: ReaderState<R>,
+    inflater: Option<Inflater>,
+    /// Whether the data message currently being read is compressed.
+    compressed: bool,
+    /// Decompressed data not yet returned.
+    inflated: Vec<u8>,
+    inflated_pos: usize,
+    max_message_size: usize,
+    last_activity: Option<Arc<Mutex<Instant>>>,
 }
 
 impl<R: AsyncRead> WebSocketReader<R> {
@@ -498,8 +589,33 @@ impl<R: AsyncRead> WebSocketReader<R> {
             read_buffer: Some(ByteBuffer::with_capacity(capacity)),
             header: None,
             state: ReaderState::NoData,
+            inflater: None,
+            compressed: false,
+            inflated: Vec::new(),
+            inflated_pos: 0,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            last_activity: None,
         }
     }
+
+    /// Accept messages compressed with the negotiated permessage-deflate extension.
+    pub fn enable_deflate(mut self) -> Self {
+        self.inflater = Some(Inflater::new());
+        self
+    }
+
+    /// Limit the decompressed size of compressed messages, defaults to
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`].
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// Record the time any frame was received.
+    fn track_activity(mut self, last_activity: Arc<Mutex<Instant>>) -> Self {
+        self.last_activity = Some(last_activity);
+        self
+    }
 }
 
 struct ReadResult<R> {
@@ -525,6 +641,18 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
         let this = Pin::get_mut(self);
 
         loop {
+            if this.inflated_pos < this.inflated.len() {
+                let data = &this.inflated[this.inflated_pos..];
+                let len = min(buf.remaining(), data.len());
+                buf.put_slice(&data[..len]);
+                this.inflated_pos += len;
+                if this.inflated_pos == this.inflated.len() {
+                    this.inflated.clear();
+                    this.inflated_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+
             match &mut this.state {
                 ReaderState::NoData => {
                     let mut reader = match this.reader.take() {
@@ -574,20 +702,32 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
                     let mut header = match this.header.take() {
                         Some(header) => header,
                         None => {
-                            let header = match FrameHeader::try_from_bytes(&read_buffer[..]) {
-                                Ok(Some(header)) => header,
-                                Ok(None) => {
-                                    this.state = ReaderState::NoData;
-                                    this.read_buffer = Some(read_buffer);
-                                    continue;
-                                }
-                                Err(err) => {
-                                    if let Err(err) = this.sender.send(Err(err.clone())) {
+                            let allow_compressed = this.inflater.is_some();
+                            let header =
+                                match FrameHeader::parse(&read_buffer[..], allow_compressed) {
+                                    Ok(Some((header, compressed))) => {
+                                        if !header.is_control_frame()
+                                            && header.frametype != OpCode::Continuation
+                                        {
+                                            this.compressed = compressed;
+                                        }
+                                        if let Some(last_activity) = &this.last_activity {
+                                            *last_activity.lock().unwrap() = Instant::now();
+                                        }
+                                        header
+                                    }
+                                    Ok(None) => {
+                                        this.state = ReaderState::NoData;
+                                        this.read_buffer = Some(read_buffer);
+                                        continue;
+                                    }
+                                    Err(err) => {
+                                        if let Err(err) = this.sender.send(Err(err.clone())) {
+                                            return Poll::Ready(Err(io::Error::other(err)));
+                                        }
                                         return Poll::Ready(Err(io::Error::other(err)));
                                     }
-                                    return Poll::Ready(Err(io::Error::other(err)));
-                                }
-                            };
+                                };
 
                             read_buffer.consume(header.header_len as usize);
                             header
@@ -616,14 +756,37 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
                         continue;
                     }
 
-                    let len = min(buf.remaining(), min(header.payload_len, read_buffer.len()));
+                    let compressed = this.compressed && this.inflater.is_some();
+
+                    let len = if compressed {
+                        min(header.payload_len, read_buffer.len())
+                    } else {
+                        min(buf.remaining(), min(header.payload_len, read_buffer.len()))
+                    };
 
                     let mut data = read_buffer.remove_data(len);
                     mask_bytes(header.mask, &mut data);
-                    buf.put_slice(&data);
 
                     header.payload_len -= len;
 
+                    if compressed {
+                        // unwrap: checked above
+                        let inflater = this.inflater.as_mut().unwrap();
+                        let mut result =
+                            inflater.inflate(&data, &mut this.inflated, this.max_message_size);
+                        if result.is_ok() && header.payload_len == 0 && header.fin {
+                            result = inflater.finish(&mut this.inflated, this.max_message_size);
+                        }
+                        if let Err(err) = result {
+                            if let Err(err) = this.sender.send(Err(err.clone())) {
+                                return Poll::Ready(Err(io::Error::other(err)));
+                            }
+                            return Poll::Ready(Err(io::Error::other(err)));
+                        }
+                    } else {
+                        buf.put_slice(&data);
+                    }
+
                     if header.payload_len > 0 {
                         this.header = Some(header);
                     }
@@ -635,7 +798,7 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
                     };
                     this.read_buffer = Some(read_buffer);
 
-                    if len > 0 {
+                    if len > 0 && !compressed {
                         return Poll::Ready(Ok(()));
                     }
                 }
@@ -647,15 +810,53 @@ impl<R: AsyncRead + Unpin + Send + 'static> AsyncRead for WebSocketReader<R> {
 /// Global Identifier for WebSockets, see RFC6455
 pub const MAGIC_WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
+/// Computes the `Sec-WebSocket-Accept` value for a `Sec-WebSocket-Key`.
+fn accept_key(key: &str) -> String {
+    let mut sha1 = openssl::sha::Sha1::new();
+    let data = format!("{}{}", key, MAGIC_WEBSOCKET_GUID);
+    sha1.update(data.as_bytes());
+    proxmox_base64::encode(sha1.finish())
+}
+
+/// The default limit for the decompressed size of compressed messages.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Optional WebSocket features, all disabled by default.
+#[derive(Clone, Debug, Default)]
+pub struct WebSocketConfig {
+    /// Negotiate the permessage-deflate extension.
+    pub permessage_deflate: bool,
+    /// Limit for the decompressed size of compressed messages, defaults to
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`].
+    pub max_message_size: Option<usize>,
+    /// Send a ping after the connection was idle for this long.
+    pub ping_interval: Option<Duration>,
+    /// Close the connection if nothing was received from the peer for this long.
+    pub idle_timeout: Option<Duration>,
+}
+
 /// Provides methods for connecting one WebSocket endpoint with another
 pub struct WebSocket {
+    /// Mask sent frames, as clients must. Each frame gets a new random key, see
+    /// [`WebSocketWriter::new`].
     pub mask: Option<[u8; 4]>,
+    config: WebSocketConfig,
+    deflate: Option<DeflateParams>,
+    client: bool,
 }
 
 impl WebSocket {
     /// Returns a new WebSocket instance and the correct WebSocket response derived from the
     /// upgrade request's headers
     pub fn new(headers: HeaderMap<HeaderValue>) -> Result<(Self, Response<Body>), Error> {
+        Self::with_config(headers, WebSocketConfig::default())
+    }
+
+    /// Like [`new`](Self::new), but with optional features enabled in `config`.
+    pub fn with_config(
+        headers: HeaderMap<HeaderValue>,
+        config: WebSocketConfig,
+    ) -> Result<(Self, Response<Body>), Error> {
         let protocols = headers
             .get(UPGRADE)
             .ok_or_else(|| format_err!("missing Upgrade header"))?
@@ -679,12 +880,15 @@ impl WebSocket {
             bail!("invalid websocket version");
         }
 
-        // we ignore extensions
+        let response_key = accept_key(key);
 
-        let mut sha1 = openssl::sha::Sha1::new();
-        let data = format!("{}{}", key, MAGIC_WEBSOCKET_GUID);
-        sha1.update(data.as_bytes());
-        let response_key = proxmox_base64::encode(sha1.finish());
+        // other extensions are ignored
+        let deflate = match headers.get(SEC_WEBSOCKET_EXTENSIONS) {
+            Some(extensions) if config.permessage_deflate => {
+                DeflateParams::negotiate(extensions.to_str()?)
+            }
+            _ => None,
+        };
 
         let mut response = Response::builder()
             .status(StatusCode::SWITCHING_PROTOCOLS)
@@ -700,9 +904,25 @@ impl WebSocket {
             response = response.header(SEC_WEBSOCKET_PROTOCOL, ws_proto)
         }
 
+        if let Some(deflate) = &deflate {
+            response = response.header(SEC_WEBSOCKET_EXTENSIONS, deflate.header_value());
+        }
+
         let response = response.body(Body::empty())?;
 
-        Ok((Self { mask: None }, response))
+        let websocket = Self {
+            mask: None,
+            config,
+            deflate,
+            client: false,
+        };
+
+        Ok((websocket, response))
+    }
+
+    /// The negotiated permessage-deflate parameters, if the extension is used.
+    pub fn deflate(&self) -> Option<DeflateParams> {
+        self.deflate
     }
 
     pub async fn handle_channel_message<W>(
@@ -739,11 +959,53 @@ impl WebSocket {
         }
     }
 
+    /// Sends pings and checks the idle timeout, returns the time to call this again.
+    async fn keepalive<W>(
+        &self,
+        writer: &mut WebSocketWriter<W>,
+        last_activity: &Mutex<Instant>,
+        last_ping: &mut Instant,
+    ) -> Result<Option<Instant>, Error>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let now = Instant::now();
+        let last_activity = *last_activity.lock().unwrap();
+        let mut next = None;
+
+        if let Some(idle_timeout) = self.config.idle_timeout {
+            let deadline = last_activity + idle_timeout;
+            if deadline <= now {
+                let err = WebSocketError::new(WebSocketErrorKind::Other, "idle timeout");
+                writer
+                    .send_control_frame(self.mask, OpCode::Close, &err.generate_frame_payload())
+                    .await?;
+                bail!("websocket connection timed out");
+            }
+            next = Some(deadline);
+        }
+
+        if let Some(ping_interval) = self.config.ping_interval {
+            let mut deadline = last_activity.max(*last_ping) + ping_interval;
+            if deadline <= now {
+                writer
+                    .send_control_frame(self.mask, OpCode::Ping, &[])
+                    .await?;
+                *last_ping = now;
+                deadline = now + ping_interval;
+            }
+            next = Some(next.map_or(deadline, |next: Instant| next.min(deadline)));
+        }
+
+        Ok(next)
+    }
+
     async fn copy_to_websocket<R, W>(
         &self,
         mut reader: &mut R,
         writer: &mut WebSocketWriter<W>,
         receiver: &mut mpsc::UnboundedReceiver<WebSocketReadResult>,
+        last_activity: &Mutex<Instant>,
     ) -> Result<bool, Error>
     where
         R: AsyncRead + Unpin + Send,
@@ -751,8 +1013,19 @@ impl WebSocket {
     {
         let mut buf = ByteBuffer::with_capacity(16 * 1024);
         let mut eof = false;
+        let mut last_ping = Instant::now();
+        let mut next_keepalive = self
+            .keepalive(writer, last_activity, &mut last_ping)
+            .await?;
         loop {
             if !buf.is_full() {
+                let keepalive_timer = async {
+                    match next_keepalive {
+                        Some(next) => tokio::time::sleep_until(next.into()).await,
+                        None => futures::future::pending().await,
+                    }
+                };
+
                 let bytes = select! {
                     res = buf.read_from_async(&mut reader).fuse() => res?,
                     res = receiver.recv().fuse() => {
@@ -762,6 +1035,10 @@ impl WebSocket {
                             _ => { continue; },
                         }
                     }
+                    _ = keepalive_timer.fuse() => {
+                        next_keepalive = self.keepalive(writer, last_activity, &mut last_ping).await?;
+                        continue;
+                    }
                 };
 
                 if bytes == 0 {
@@ -787,7 +1064,8 @@ impl WebSocket {
     /// and receives WebSocket frames, while 'downstream' only expects and sends raw data.
     ///
     /// This method takes care of copying the data between endpoints, and sending correct responses
-    /// for control frames (e.g. a Pont to a Ping).
+    /// for control frames (e.g. a Pont to a Ping). If configured, pings are sent to an idle peer
+    /// and the connection is closed after the idle timeout.
     pub async fn serve_connection<S, L>(&self, upstream: S, downstream: L) -> Result<(), Error>
     where
         S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
@@ -796,17 +1074,42 @@ impl WebSocket {
         let (usreader, uswriter) = tokio::io::split(upstream);
         let (mut dsreader, mut dswriter) = tokio::io::split(downstream);
 
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
+
         let (tx, mut rx) = mpsc::unbounded_channel();
-        let mut wsreader = WebSocketReader::new(usreader, tx);
+        let mut wsreader =
+            WebSocketReader::new(usreader, tx).track_activity(Arc::clone(&last_activity));
         let mut wswriter = WebSocketWriter::new(self.mask, uswriter);
 
+        if let Some(deflate) = &self.deflate {
+            wsreader = wsreader.enable_deflate();
+            if let Some(size) = self.config.max_message_size {
+                wsreader = wsreader.max_message_size(size);
+            }
+            let no_context_takeover = if self.client {
+                deflate.client_no_context_takeover
+            } else {
+                deflate.server_no_context_takeover
+            };
+            wswriter = wswriter.enable_deflate(no_context_takeover);
+        }
+
         let ws_future = tokio::io::copy(&mut wsreader, &mut dswriter);
-        let term_future = self.copy_to_websocket(&mut dsreader, &mut wswriter, &mut rx);
+        let term_future =
+            self.copy_to_websocket(&mut dsreader, &mut wswriter, &mut rx, &last_activity);
 
         select! {
             res = ws_future.fuse() => match res {
                 Ok(_) => Ok(()),
-                Err(err) => Err(Error::from(err)),
+                Err(err) => {
+                    // tell the peer why we are closing the connection on protocol errors
+                    if let Some(ws_err) = err.get_ref().and_then(|e| e.downcast_ref::<WebSocketError>()) {
+                        wswriter
+                            .send_control_frame(self.mask, OpCode::Close, &ws_err.generate_frame_payload())
+                            .await?;
+                    }
+                    Err(Error::from(err))
+                }
             },
             res = term_future.fuse() => match res {
                 Ok(sent_close) if !sent_close => {
@@ -822,3 +1125,134 @@ impl WebSocket {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deflate_roundtrip() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (tx, _rx) = mpsc::unbounded_channel();
+
+        let mut writer = WebSocketWriter::new(Some([1, 2, 3, 4]), client).enable_deflate(false);
+        let mut reader = WebSocketReader::new(server, tx).enable_deflate();
+
+        let messages: Vec<Vec<u8>> = vec![
+            b"hello world".to_vec(),
+            (0..20_000u32).flat_map(|i| (i % 7).to_le_bytes()).collect(),
+            b"hello world".to_vec(),
+        ];
+        let expected = messages.concat();
+
+        let write = async move {
+            for message in messages {
+                writer.write_all(&message).await.unwrap();
+            }
+            writer.shutdown().await.unwrap();
+        };
+        let read = async move {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).await.unwrap();
+            data
+        };
+        let ((), data) = tokio::join!(write, read);
+
+        assert_eq!(data, expected);
+    }
+
+    #[tokio::test]
+    async fn test_fresh_mask_per_frame() {
+        let mut writer = WebSocketWriter::new(Some([1, 2, 3, 4]), Vec::new());
+        writer.write_all(b"hello").await.unwrap();
+        writer.write_all(b"hello").await.unwrap();
+        let data = writer.writer;
+
+        // 2 header bytes, 4 mask bytes and 5 payload bytes per frame
+        let (first, second) = data.split_at(11);
+        assert_eq!(second.len(), 11);
+        assert_ne!(first[2..6], [1, 2, 3, 4]);
+        assert_ne!(first[2..], second[2..]);
+
+        for frame in [first, second] {
+            let mut payload = frame[6..].to_vec();
+            mask_bytes(Some(frame[2..6].try_into().unwrap()), &mut payload);
+            assert_eq!(payload, b"hello");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let (upstream, mut peer) = tokio::io::duplex(1024);
+        let (downstream, _downstream_peer) = tokio::io::duplex(1024);
+
+        let websocket = WebSocket {
+            mask: None,
+            config: WebSocketConfig {
+                ping_interval: Some(Duration::from_millis(20)),
+                idle_timeout: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+            deflate: None,
+            client: false,
+        };
+
+        let err = websocket
+            .serve_connection(upstream, downstream)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "websocket connection timed out");
+
+        // the peer got pinged and then a close frame
+        let mut data = Vec::new();
+        peer.read_to_end(&mut data).await.unwrap();
+        let mut frames = Vec::new();
+        let mut data = &data[..];
+        while let Some(header) = FrameHeader::try_from_bytes(data).unwrap() {
+            data = &data[header.header_len as usize + header.payload_len..];
+            frames.push(header.frametype);
+        }
+        assert_eq!(frames.first(), Some(&OpCode::Ping));
+        assert_eq!(frames.last(), Some(&OpCode::Close));
+    }
+
+    #[tokio::test]
+    async fn test_message_too_big() {
+        let (upstream, peer) = tokio::io::duplex(1024);
+        let (downstream, _downstream_peer) = tokio::io::duplex(1024);
+
+        let websocket = WebSocket {
+            mask: None,
+            config: WebSocketConfig {
+                max_message_size: Some(50_000),
+                ..Default::default()
+            },
+            deflate: Some(DeflateParams::default()),
+            client: false,
+        };
+
+        let (mut peer_reader, peer_writer) = tokio::io::split(peer);
+        let mut writer =
+            WebSocketWriter::new(Some([1, 2, 3, 4]), peer_writer).enable_deflate(false);
+        writer.write_all(&[0u8; 100_000]).await.unwrap();
+
+        let err = websocket
+            .serve_connection(upstream, downstream)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "decompressed message too big (Code: 1009)");
+
+        // the peer got a close frame with the reason
+        let mut data = vec![0u8; 128];
+        let len = peer_reader.read(&mut data).await.unwrap();
+        let header = FrameHeader::try_from_bytes(&data[..len]).unwrap().unwrap();
+        assert_eq!(header.frametype, OpCode::Close);
+        let payload = &data[header.header_len as usize..][..header.payload_len];
+        assert_eq!(
+            payload[..2],
+            WebSocketErrorKind::MessageTooBig.to_be_bytes()
+        );
+    }
+}
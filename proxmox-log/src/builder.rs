@@ -74,7 +74,9 @@ impl Logger {
 
     /// Print to journald.
     ///
-    /// If the journal cannot be opened, print to stderr instead.
+    /// Fields of events, their spans and the [`LogContext`] are passed as `PROXMOX_<NAME>` journal
+    /// fields, the target as `PROXMOX_TARGET`. If the journal cannot be opened, print to stderr
+    /// instead.
     pub fn journald(mut self) -> Logger {
         self.layer.push(
             journald_or_stderr_layer()
@@ -149,6 +151,22 @@ impl Logger {
         self
     }
 
+    /// Send logs to a remote receiver.
+    ///
+    /// Messages are sent by a background thread as RFC 5424 syslog messages or JSON lines, see
+    /// [`RemoteLogOptions`](crate::RemoteLogOptions), and are buffered while the receiver is
+    /// unavailable.
+    ///
+    /// Fails if the sink cannot be set up, e.g. due to an invalid TLS configuration. An
+    /// unreachable receiver is not an error.
+    #[cfg(feature = "remote")]
+    pub fn remote(mut self, options: crate::RemoteLogOptions) -> Result<Logger, anyhow::Error> {
+        let layer = crate::remote::RemoteLayer::new(options)?;
+        self.layer
+            .push(layer.with_filter(self.global_log_level).boxed());
+        Ok(self)
+    }
+
     /// Inits the tracing logger with the previously configured layers.
     ///
     /// Also configures the `LogTracer` which will convert all `log` events to tracing events.
@@ -0,0 +1,133 @@
+//! Collect the fields of events, their spans and the [`LogContext`] for structured outputs.
+
+use std::fmt::Write as _;
+use std::marker::PhantomData;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::LogContext;
+
+/// The message and the other fields of an event.
+#[derive(Debug, Default)]
+pub(crate) struct EventFields {
+    pub message: String,
+    /// Fields of the log context, the spans (outermost first) and the event itself.
+    pub fields: Vec<(String, String)>,
+}
+
+impl EventFields {
+    /// Collect the fields of 'event' and of all spans stored by the layer 'L'.
+    pub fn collect<L: 'static, S>(event: &Event<'_>, ctx: &Context<'_, S>) -> Self
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut result = EventFields::default();
+
+        if let Some(log_context) = LogContext::current() {
+            result.fields.extend_from_slice(log_context.fields());
+        }
+
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<SpanFields<L>>() {
+                    result.fields.extend_from_slice(&fields.fields);
+                }
+            }
+        }
+
+        event.record(&mut FieldVisitor {
+            message: Some(&mut result.message),
+            fields: &mut result.fields,
+        });
+
+        result
+    }
+}
+
+/// Span extension storing the fields of a span.
+///
+/// Every layer uses its own marker type 'L', as extensions of the same type can only be inserted
+/// once.
+pub(crate) struct SpanFields<L> {
+    fields: Vec<(String, String)>,
+    _layer: PhantomData<fn() -> L>,
+}
+
+impl<L: 'static> SpanFields<L> {
+    /// To be called from [`Layer::on_new_span`](tracing_subscriber::Layer::on_new_span).
+    pub fn on_new_span<S>(attrs: &Attributes<'_>, id: &Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = Vec::new();
+        attrs.record(&mut FieldVisitor {
+            message: None,
+            fields: &mut fields,
+        });
+        span.extensions_mut().replace(SpanFields::<L> {
+            fields,
+            _layer: PhantomData,
+        });
+    }
+
+    /// To be called from [`Layer::on_record`](tracing_subscriber::Layer::on_record).
+    pub fn on_record<S>(id: &Id, values: &Record<'_>, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(span_fields) = extensions.get_mut::<SpanFields<L>>() {
+            values.record(&mut FieldVisitor {
+                message: None,
+                fields: &mut span_fields.fields,
+            });
+        }
+    }
+}
+
+struct FieldVisitor<'a> {
+    message: Option<&'a mut String>,
+    fields: &'a mut Vec<(String, String)>,
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match self.message.as_mut() {
+            Some(message) if field.name() == "message" => message.push_str(value),
+            _ => self
+                .fields
+                .push((field.name().to_string(), value.to_string())),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match self.message.as_mut() {
+            Some(message) if field.name() == "message" => {
+                let _ = write!(message, "{value:?}");
+            }
+            _ => self
+                .fields
+                .push((field.name().to_string(), format!("{value:?}"))),
+        }
+    }
+}
+
+/// The name of the running program, used to identify our messages.
+pub(crate) fn program_name() -> String {
+    std::env::args_os()
+        .next()
+        .as_ref()
+        .and_then(|arg| std::path::Path::new(arg).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -1,9 +1,59 @@
 use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Error;
 use nix::fcntl::OFlag;
 
-use proxmox_sys::fs::{atomic_open_or_create_file, CreateOptions};
+use proxmox_sys::fs::{atomic_open_or_create_file, lock_file, CreateOptions};
+use proxmox_sys::logrotate::LogRotate;
+
+/// Time to wait before checking again after rotating failed
+const ROTATE_RETRY_INTERVAL: i64 = 60;
+
+/// Time between checks whether the file was rotated by another process or is due for rotation
+const ROTATE_CHECK_INTERVAL: i64 = 5;
+
+/// Options for the built-in rotation of a [FileLogger]
+///
+/// Rotated files are named like with [LogRotate]: `<file>.1`, `<file>.2.zst`, ...
+/// Multiple processes can append to and rotate the same file, they coordinate with a lock on
+/// `<file>.lck` and reopen the file when another process rotated it. Writing never waits for that
+/// lock, if it is busy the rotation is retried at the next check.
+///
+/// The limits are checked every few seconds, and additionally whenever this logger's own writes
+/// reach the size limit. Rotated files are compressed in a background thread, which keeps holding
+/// the lock until it is done.
+#[derive(Clone, Debug, Default)]
+pub struct LogRotateOptions {
+    /// Rotate when the file reaches this size in bytes
+    pub max_size: Option<u64>,
+    /// Rotate when the file is older than this, based on its creation time (needs support by
+    /// the file system)
+    pub max_age: Option<Duration>,
+    /// Maximum number of files to keep, including the current one
+    pub max_files: Option<usize>,
+    /// Compress rotated files with zstd, except for the newest one
+    pub compress: bool,
+}
+
+impl LogRotateOptions {
+    fn is_due(&self, metadata: &std::fs::Metadata) -> bool {
+        if metadata.len() == 0 {
+            return false;
+        }
+        if matches!(self.max_size, Some(max_size) if metadata.len() >= max_size) {
+            return true;
+        }
+        match (self.max_age, metadata.created()) {
+            (Some(max_age), Ok(created)) => SystemTime::now()
+                .duration_since(created)
+                .is_ok_and(|age| age >= max_age),
+            _ => false,
+        }
+    }
+}
 
 /// Options to control the behavior of a [FileLogger] instance
 #[derive(Default)]
@@ -24,6 +74,8 @@ pub struct FileLogOptions {
     pub prefix_time: bool,
     /// File owner/group and mode
     pub file_opts: CreateOptions,
+    /// Rotate the file automatically, see [LogRotateOptions]
+    pub rotate: Option<LogRotateOptions>,
 }
 
 /// Log messages with optional automatically added timestamps into files
@@ -47,6 +99,12 @@ pub struct FileLogger {
     file: std::fs::File,
     file_name: std::path::PathBuf,
     options: FileLogOptions,
+    next_rotate_check: i64,
+    /// Size of the file at the last rotation check
+    checked_size: u64,
+    /// Bytes written since the last rotation check
+    written: u64,
+    compression: Option<JoinHandle<()>>,
 }
 
 impl FileLogger {
@@ -56,7 +114,7 @@ impl FileLogger {
         file_name: P,
         options: FileLogOptions,
     ) -> Result<Self, Error> {
-        let file = Self::open(&file_name, &options)?;
+        let file = Self::open(&file_name, &options, options.exclusive)?;
 
         let file_name: std::path::PathBuf = file_name.as_ref().to_path_buf();
 
@@ -64,19 +122,139 @@ impl FileLogger {
             file,
             file_name,
             options,
+            next_rotate_check: 0,
+            checked_size: 0,
+            written: 0,
+            compression: None,
         })
     }
 
     /// Reopen logfile.
     pub fn reopen(&mut self) -> Result<&Self, Error> {
-        let file = Self::open(&self.file_name, &self.options)?;
+        let file = Self::open(&self.file_name, &self.options, self.options.exclusive)?;
         self.file = file;
         Ok(self)
     }
 
+    /// Change the options for rotating the file, `None` disables it.
+    pub fn set_rotate_options(&mut self, rotate: Option<LogRotateOptions>) {
+        self.options.rotate = rotate;
+        self.next_rotate_check = 0;
+    }
+
+    /// Rotate the file now, regardless of the size and age limits.
+    ///
+    /// Uses the options set in [FileLogOptions::rotate], or the defaults if unset. Only the
+    /// renaming happens right away, older files are compressed in a background thread.
+    ///
+    /// This waits for rotations and compressions of other loggers of the same file to finish.
+    pub fn rotate(&mut self) -> Result<(), Error> {
+        let mut lock = open_rotate_lock(&self.file_name, self.options.file_opts)?;
+        lock_file(&mut lock, true, None)?;
+        self.rotate_locked(lock)
+    }
+
+    fn rotate_locked(&mut self, lock: std::fs::File) -> Result<(), Error> {
+        let rotate = self.options.rotate.clone().unwrap_or_default();
+        let mut logrotate = LogRotate::new(
+            &self.file_name,
+            rotate.compress,
+            rotate.max_files,
+            Some(self.options.file_opts),
+        )?;
+
+        // another process may have rotated the file before we got the lock
+        let result = match is_replaced(&self.file, &self.file_name) {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                let result = logrotate.rotate_files();
+                if result.is_ok() && rotate.compress {
+                    self.compress_in_background(logrotate, lock);
+                }
+                result
+            }
+            Err(err) => Err(err),
+        };
+
+        self.file = Self::open(&self.file_name, &self.options, false)?;
+        self.checked_size = 0;
+        self.written = 0;
+        result
+    }
+
+    // the lock is passed on, so that no other logger renames files while they are compressed
+    fn compress_in_background(&mut self, mut logrotate: LogRotate, lock: std::fs::File) {
+        let file_name = self.file_name.clone();
+
+        let result = std::thread::Builder::new()
+            .name("logrotate".to_string())
+            .spawn(move || {
+                if let Err(err) = logrotate.compress_rotated() {
+                    eprintln!("unable to compress rotated log files of {file_name:?} - {err}");
+                }
+                drop(lock);
+            });
+
+        match result {
+            Ok(handle) => self.compression = Some(handle),
+            Err(err) => eprintln!("unable to start log file compression - {err}"),
+        }
+    }
+
+    fn rotate_if_due(&mut self) -> Result<(), Error> {
+        let Some(rotate) = &self.options.rotate else {
+            return Ok(());
+        };
+
+        if is_replaced(&self.file, &self.file_name)? {
+            self.file = Self::open(&self.file_name, &self.options, false)?;
+        }
+        let metadata = self.file.metadata()?;
+        self.checked_size = metadata.len();
+        self.written = 0;
+        if !rotate.is_due(&metadata) {
+            return Ok(());
+        }
+
+        let mut lock = open_rotate_lock(&self.file_name, self.options.file_opts)?;
+        match lock_file(&mut lock, true, Some(Duration::ZERO)) {
+            Ok(()) => self.rotate_locked(lock),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                // another logger is rotating or compressing, don't block writing and retry at
+                // the next check instead of on every write
+                self.checked_size = 0;
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn check_rotation(&mut self) {
+        let Some(rotate) = &self.options.rotate else {
+            return;
+        };
+        let now = proxmox_time::epoch_i64();
+        let size_reached = rotate
+            .max_size
+            .is_some_and(|max_size| self.checked_size + self.written >= max_size);
+        if now < self.next_rotate_check && !size_reached {
+            return;
+        }
+        self.next_rotate_check = now + ROTATE_CHECK_INTERVAL;
+        if let Err(err) = self.rotate_if_due() {
+            // logging the error would lead to recursion, and retrying on every write could
+            // flood stderr
+            eprintln!("unable to rotate log file {:?} - {err}", self.file_name);
+            self.next_rotate_check = now + ROTATE_RETRY_INTERVAL;
+            self.written = 0;
+        }
+    }
+
+    // a file created after rotating must not be 'exclusive', another process may have created it
     fn open<P: AsRef<std::path::Path>>(
         file_name: P,
         options: &FileLogOptions,
+        exclusive: bool,
     ) -> Result<std::fs::File, Error> {
         let mut flags = OFlag::O_CLOEXEC;
 
@@ -89,7 +267,7 @@ impl FileLogger {
         if options.append {
             flags |= OFlag::O_APPEND;
         }
-        if options.exclusive {
+        if exclusive {
             flags |= OFlag::O_EXCL;
         }
 
@@ -102,6 +280,7 @@ impl FileLogger {
     /// specified.
     pub fn log<S: AsRef<str>>(&mut self, msg: S) {
         let msg = msg.as_ref();
+        self.check_rotation();
 
         // TODO: remove whole to_stdout option, handled by tracing now
         //if self.options.to_stdout {
@@ -123,17 +302,48 @@ impl FileLogger {
         // Note: we ignore the potential error here because log methods
         // shouldn't panic. We also can't log an error, because that
         // would lead to recursion.
-        let _ = self.file.write_all(line.as_bytes());
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.written += line.len() as u64;
+        }
     }
 }
 
+// checks if the file was rotated by another process (or removed)
+fn is_replaced(file: &std::fs::File, file_name: &std::path::Path) -> Result<bool, Error> {
+    let current = file.metadata()?;
+    match std::fs::metadata(file_name) {
+        Ok(metadata) => Ok(metadata.dev() != current.dev() || metadata.ino() != current.ino()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// opens the lock file coordinating rotation and compression between all loggers of a file
+fn open_rotate_lock(
+    file_name: &std::path::Path,
+    options: CreateOptions,
+) -> Result<std::fs::File, Error> {
+    let mut lock_name = file_name.as_os_str().to_owned();
+    lock_name.push(".lck");
+    atomic_open_or_create_file(
+        lock_name,
+        OFlag::O_RDWR | OFlag::O_CLOEXEC,
+        &[],
+        options,
+        false,
+    )
+}
+
 impl std::io::Write for FileLogger {
     fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.check_rotation();
         // TODO: remove whole to_stdout option, handled by tracing now
         //if self.options.to_stdout {
         //    let _ = std::io::stdout().write(buf);
         //}
-        self.file.write(buf)
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
     }
 
     fn flush(&mut self) -> Result<(), std::io::Error> {
@@ -144,3 +354,120 @@ impl std::io::Write for FileLogger {
         self.file.flush()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file-logger-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn logger(path: &Path, rotate: LogRotateOptions) -> FileLogger {
+        let options = FileLogOptions {
+            append: true,
+            rotate: Some(rotate),
+            ..Default::default()
+        };
+        FileLogger::new(path, options).unwrap()
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = test_dir("size");
+        let path = dir.join("access.log");
+        let mut log = logger(
+            &path,
+            LogRotateOptions {
+                max_size: Some(10),
+                max_files: Some(3),
+                compress: true,
+                ..Default::default()
+            },
+        );
+
+        for line in ["first line", "second line", "third line", "fourth line"] {
+            log.log(line);
+            if let Some(compression) = log.compression.take() {
+                compression.join().unwrap();
+            }
+        }
+
+        let contents: Vec<String> = LogRotate::new(&path, true, None, None)
+            .unwrap()
+            .files()
+            .map(|mut file| std::io::read_to_string(&mut file).unwrap())
+            .collect();
+        assert_eq!(contents, ["fourth line\n", "third line\n", "second line\n"]);
+        assert!(dir.join("access.log.1").exists());
+        assert!(dir.join("access.log.2.zst").exists());
+        assert!(!dir.join("access.log.3.zst").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_multiple_writers() {
+        let dir = test_dir("writers");
+        let path = dir.join("access.log");
+        let options = LogRotateOptions {
+            max_size: Some(1024),
+            ..Default::default()
+        };
+        let mut first = logger(&path, options.clone());
+        let mut second = logger(&path, options);
+
+        first.log("before");
+        first.rotate().unwrap();
+        // the second logger notices the rotation and continues in the new file
+        second.log("after");
+        first.log("after again");
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("access.log.1")).unwrap(),
+            "before\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "after\nafter again\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_skipped_while_locked() {
+        let dir = test_dir("locked");
+        let path = dir.join("access.log");
+        let mut log = logger(
+            &path,
+            LogRotateOptions {
+                max_size: Some(10),
+                ..Default::default()
+            },
+        );
+
+        // like a compression of another logger in progress
+        let mut lock = open_rotate_lock(&path, CreateOptions::new()).unwrap();
+        lock_file(&mut lock, true, None).unwrap();
+
+        // writing does not wait for the lock, rotation is skipped for now
+        log.log("first line");
+        log.log("second line");
+        assert!(!dir.join("access.log.1").exists());
+
+        drop(lock);
+        log.rotate().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("access.log.1")).unwrap(),
+            "first line\nsecond line\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
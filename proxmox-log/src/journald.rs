@@ -0,0 +1,203 @@
+//! Structured logging to the systemd journal using its native protocol.
+
+use std::io::{self, IoSlice, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixDatagram;
+
+use nix::fcntl::{FcntlArg, SealFlag};
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags, UnixAddr};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::fields::{program_name, EventFields, SpanFields};
+
+const JOURNALD_PATH: &str = "/run/systemd/journal/socket";
+
+/// Prefix of the journal fields for the fields of events, spans and the log context.
+const FIELD_PREFIX: &str = "PROXMOX_";
+
+/// Sends events to the journal, mapping their fields to journal fields.
+///
+/// The message becomes `MESSAGE`, the target `PROXMOX_TARGET` and any other field of the event,
+/// its spans or the [`LogContext`](crate::LogContext) `PROXMOX_<NAME>`, e.g. an `upid` field can be
+/// filtered with `journalctl PROXMOX_UPID=...`.
+pub(crate) struct JournaldLayer {
+    socket: UnixDatagram,
+    syslog_identifier: String,
+}
+
+impl JournaldLayer {
+    /// Fails if the journal socket cannot be used.
+    pub fn new() -> io::Result<Self> {
+        let layer = Self {
+            socket: UnixDatagram::unbound()?,
+            syslog_identifier: program_name(),
+        };
+        // journald discards empty payloads, but this fails if nobody listens
+        layer.send(&[])?;
+        Ok(layer)
+    }
+
+    fn send(&self, payload: &[u8]) -> io::Result<()> {
+        match self.socket.send_to(payload, JOURNALD_PATH) {
+            Err(err) if err.raw_os_error() == Some(nix::libc::EMSGSIZE) => {
+                self.send_large_payload(payload)
+            }
+            result => result.map(drop),
+        }
+    }
+
+    // payloads exceeding the datagram size are passed as sealed memfd
+    fn send_large_payload(&self, payload: &[u8]) -> io::Result<()> {
+        let memfd = memfd_create(
+            c"proxmox-log",
+            MemFdCreateFlag::MFD_CLOEXEC | MemFdCreateFlag::MFD_ALLOW_SEALING,
+        )?;
+        let mut file = std::fs::File::from(memfd);
+        file.write_all(payload)?;
+        nix::fcntl::fcntl(
+            file.as_raw_fd(),
+            FcntlArg::F_ADD_SEALS(
+                SealFlag::F_SEAL_SHRINK
+                    | SealFlag::F_SEAL_GROW
+                    | SealFlag::F_SEAL_WRITE
+                    | SealFlag::F_SEAL_SEAL,
+            ),
+        )?;
+
+        let fds = [file.as_raw_fd()];
+        sendmsg(
+            self.socket.as_raw_fd(),
+            &[] as &[IoSlice],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            Some(&UnixAddr::new(JOURNALD_PATH)?),
+        )?;
+        Ok(())
+    }
+}
+
+impl<S> Layer<S> for JournaldLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        SpanFields::<Self>::on_new_span(attrs, id, &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        SpanFields::<Self>::on_record(id, values, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let fields = EventFields::collect::<Self, S>(event, &ctx);
+        let payload = encode_event(event.metadata(), &fields, &self.syslog_identifier);
+        // there is nobody to report the error to
+        let _ = self.send(&payload);
+    }
+}
+
+fn priority(level: &Level) -> &'static str {
+    match *level {
+        Level::ERROR => "3",
+        Level::WARN => "4",
+        Level::INFO => "6",
+        Level::DEBUG | Level::TRACE => "7",
+    }
+}
+
+fn encode_event(
+    metadata: &tracing::Metadata<'_>,
+    fields: &EventFields,
+    syslog_identifier: &str,
+) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(256);
+
+    put_field(&mut payload, "MESSAGE", fields.message.as_bytes());
+    put_field(
+        &mut payload,
+        "PRIORITY",
+        priority(metadata.level()).as_bytes(),
+    );
+    put_field(
+        &mut payload,
+        "SYSLOG_IDENTIFIER",
+        syslog_identifier.as_bytes(),
+    );
+    if let Some(file) = metadata.file() {
+        put_field(&mut payload, "CODE_FILE", file.as_bytes());
+    }
+    if let Some(line) = metadata.line() {
+        put_field(&mut payload, "CODE_LINE", line.to_string().as_bytes());
+    }
+    put_field(
+        &mut payload,
+        &format!("{FIELD_PREFIX}TARGET"),
+        metadata.target().as_bytes(),
+    );
+
+    for (name, value) in &fields.fields {
+        if let Some(name) = journal_field_name(name) {
+            put_field(&mut payload, &name, value.as_bytes());
+        }
+    }
+
+    payload
+}
+
+/// Maps a tracing field name to a valid journal field name.
+///
+/// Journal field names may only contain uppercase letters, digits and underscores and must be
+/// at most 64 characters long.
+fn journal_field_name(name: &str) -> Option<String> {
+    let mut result = String::from(FIELD_PREFIX);
+    result.extend(name.chars().map(|c| match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+        _ => '_',
+    }));
+    (result.len() > FIELD_PREFIX.len() && result.len() <= 64).then_some(result)
+}
+
+// uses the binary-safe format for values with newlines
+fn put_field(payload: &mut Vec<u8>, name: &str, value: &[u8]) {
+    payload.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        payload.push(b'=');
+    }
+    payload.extend_from_slice(value);
+    payload.push(b'\n');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_journal_field_name() {
+        assert_eq!(journal_field_name("upid").unwrap(), "PROXMOX_UPID");
+        assert_eq!(
+            journal_field_name("auth-id.name").unwrap(),
+            "PROXMOX_AUTH_ID_NAME"
+        );
+        assert!(journal_field_name("").is_none());
+        assert!(journal_field_name(&"x".repeat(57)).is_none());
+    }
+
+    #[test]
+    fn test_put_field() {
+        let mut payload = Vec::new();
+        put_field(&mut payload, "MESSAGE", b"hello");
+        put_field(&mut payload, "MESSAGE", b"two\nlines");
+        assert_eq!(
+            payload,
+            b"MESSAGE=hello\nMESSAGE\n\x09\0\0\0\0\0\0\0two\nlines\n"
+        );
+    }
+}
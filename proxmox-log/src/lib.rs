@@ -6,16 +6,22 @@ use std::future::Future;
 use std::sync::{Arc, Mutex};
 
 use tokio::task::futures::TaskLocalFuture;
-use tracing_journald::{Priority, PriorityMappings};
 use tracing_subscriber::prelude::*;
 
+mod fields;
 mod file_logger;
+mod journald;
 mod pve_task_formatter;
 mod tasklog_layer;
 
+#[cfg(feature = "remote")]
+mod remote;
+#[cfg(feature = "remote")]
+pub use remote::{RemoteLogOptions, RemoteTarget};
+
 pub mod builder;
 pub use builder::Logger;
-pub use file_logger::{FileLogOptions, FileLogger};
+pub use file_logger::{FileLogOptions, FileLogger, LogRotateOptions};
 
 pub use tracing::debug;
 pub use tracing::debug_span;
@@ -59,6 +65,7 @@ impl FileLogState {
 #[derive(Clone)]
 pub struct LogContext {
     logger: Arc<Mutex<FileLogState>>,
+    fields: Arc<Vec<(String, String)>>,
 }
 
 impl LogContext {
@@ -66,9 +73,24 @@ impl LogContext {
     pub fn new(logger: FileLogger) -> Self {
         Self {
             logger: Arc::new(Mutex::new(FileLogState::new(logger))),
+            fields: Arc::new(Vec::new()),
         }
     }
 
+    /// Add a field to all events logged in this context.
+    ///
+    /// The tasklog does not show the fields, but structured outputs like the journal (as
+    /// `PROXMOX_<NAME>`) or remote sinks include them, e.g. the `upid` of a worker task.
+    pub fn with_field<V: Into<String>>(mut self, name: &str, value: V) -> Self {
+        Arc::make_mut(&mut self.fields).push((name.to_string(), value.into()));
+        self
+    }
+
+    /// The fields added with [`with_field`](Self::with_field).
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
     /// Check to see if a log context exists without getting a strong reference to it.
     pub fn exists() -> bool {
         LOG_CONTEXT.try_with(|_| ()).is_ok()
@@ -115,16 +137,8 @@ where
     S: tracing::Subscriber,
     S: for<'a> tracing_subscriber::registry::LookupSpan<'a>,
 {
-    match tracing_journald::layer() {
-        Ok(layer) => layer
-            .with_priority_mappings(PriorityMappings {
-                error: Priority::Error,
-                warn: Priority::Warning,
-                info: Priority::Informational,
-                debug: Priority::Debug,
-                trace: Priority::Debug,
-            })
-            .boxed(),
+    match journald::JournaldLayer::new() {
+        Ok(layer) => layer.boxed(),
         Err(err) => {
             eprintln!("Unable to open syslog: {err:?}");
             plain_stderr_layer().boxed()
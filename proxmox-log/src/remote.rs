@@ -0,0 +1,630 @@
+//! Ship log messages to a remote receiver.
+//!
+//! Events are queued by the layer and sent by a background thread, which buffers them while the
+//! receiver is unavailable and reconnects with an increasing delay.
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use proxmox_http::client::Client;
+use proxmox_http::HttpOptions;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::fields::{program_name, EventFields, SpanFields};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+const MAX_BATCH_SIZE: usize = 512;
+
+/// The receiver of remote logs.
+#[derive(Clone, Debug)]
+pub struct RemoteTarget {
+    kind: TargetKind,
+}
+
+#[derive(Clone, Debug)]
+enum TargetKind {
+    Syslog { host: String, port: u16, tls: bool },
+    Http { url: String },
+}
+
+impl RemoteTarget {
+    /// RFC 5424 syslog messages over TCP, framed by octet counting (RFC 6587).
+    pub fn syslog(host: &str, port: u16) -> Self {
+        Self {
+            kind: TargetKind::Syslog {
+                host: host.to_string(),
+                port,
+                tls: false,
+            },
+        }
+    }
+
+    /// RFC 5424 syslog messages over TLS (RFC 5425).
+    pub fn syslog_tls(host: &str, port: u16) -> Self {
+        Self {
+            kind: TargetKind::Syslog {
+                host: host.to_string(),
+                port,
+                tls: true,
+            },
+        }
+    }
+
+    /// JSON lines sent with HTTP POST requests to an `http://` or `https://` URL.
+    pub fn http(url: &str) -> Result<Self, Error> {
+        let url = url::Url::parse(url)?;
+        match url.scheme() {
+            "http" | "https" => (),
+            scheme => bail!("unsupported url scheme '{scheme}'"),
+        }
+        if url.host_str().is_none() {
+            bail!("missing host in url");
+        }
+
+        Ok(Self {
+            kind: TargetKind::Http {
+                url: url.to_string(),
+            },
+        })
+    }
+}
+
+/// Options of a remote log sink, see [`Logger::remote`](crate::Logger::remote).
+#[derive(Clone, Debug)]
+pub struct RemoteLogOptions {
+    pub target: RemoteTarget,
+    /// Verify the certificate of TLS connections.
+    pub verify_tls: bool,
+    /// Maximum number of messages buffered while the receiver is unavailable, the oldest are
+    /// dropped if it is exceeded.
+    pub buffer_size: usize,
+    /// The syslog facility (0-23).
+    pub facility: u8,
+    /// The application name, defaults to the name of the program.
+    pub app_name: Option<String>,
+    /// The SD-ID of the structured data element containing the fields in syslog messages, which
+    /// needs to include a private enterprise number (e.g. `fields@<number>`). Fields are only
+    /// included in syslog messages if this is set.
+    pub structured_data_id: Option<String>,
+}
+
+impl RemoteLogOptions {
+    pub fn new(target: RemoteTarget) -> Self {
+        Self {
+            target,
+            verify_tls: true,
+            buffer_size: 10_000,
+            facility: 3, // daemon
+            app_name: None,
+            structured_data_id: None,
+        }
+    }
+}
+
+/// An event as it is queued for sending.
+#[derive(Clone, Debug)]
+struct LogRecord {
+    time: i64,
+    level: Level,
+    target: String,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    records: VecDeque<LogRecord>,
+    dropped: u64,
+}
+
+struct Queue {
+    state: Mutex<QueueState>,
+    wakeup: Condvar,
+    capacity: usize,
+}
+
+impl Queue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            wakeup: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn push(&self, record: LogRecord) {
+        let mut state = self.state.lock().unwrap();
+        if state.records.len() >= self.capacity {
+            state.records.pop_front();
+            state.dropped += 1;
+        }
+        state.records.push_back(record);
+        self.wakeup.notify_one();
+    }
+
+    /// Waits for records, returns them with the number of records dropped since the last call.
+    fn take_batch(&self, max: usize) -> (Vec<LogRecord>, u64) {
+        let mut state = self.state.lock().unwrap();
+        while state.records.is_empty() {
+            state = self.wakeup.wait(state).unwrap();
+        }
+        let count = state.records.len().min(max);
+        let batch = state.records.drain(..count).collect();
+        (batch, std::mem::take(&mut state.dropped))
+    }
+
+    /// Puts records which could not be sent back in front of the queue.
+    fn requeue(&self, batch: Vec<LogRecord>) {
+        let mut state = self.state.lock().unwrap();
+        for record in batch.into_iter().rev() {
+            if state.records.len() >= self.capacity {
+                // the remaining records are older than everything else in the queue
+                state.dropped += 1;
+            } else {
+                state.records.push_front(record);
+            }
+        }
+    }
+}
+
+struct Formatter {
+    hostname: String,
+    app_name: String,
+    pid: u32,
+    facility: u8,
+    structured_data_id: Option<String>,
+}
+
+impl Formatter {
+    fn new(options: &RemoteLogOptions) -> Self {
+        let app_name = options.app_name.clone().unwrap_or_else(program_name);
+        Self {
+            hostname: proxmox_sys::nodename().to_string(),
+            app_name,
+            pid: std::process::id(),
+            facility: options.facility.min(23),
+            structured_data_id: options.structured_data_id.clone(),
+        }
+    }
+
+    /// Formats an RFC 5424 message with octet counting framing.
+    fn syslog_frame(&self, record: &LogRecord) -> String {
+        let severity = match record.level {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            Level::DEBUG | Level::TRACE => 7,
+        };
+        let priority = self.facility as u32 * 8 + severity;
+        let timestamp =
+            proxmox_time::epoch_to_rfc3339_utc(record.time).unwrap_or_else(|_| "-".to_string());
+
+        let structured_data = match &self.structured_data_id {
+            Some(id) if !record.fields.is_empty() => {
+                let mut data = format!("[{}", syslog_header_value(id, 32));
+                for (name, value) in &record.fields {
+                    data.push_str(&format!(
+                        " {}=\"{}\"",
+                        syslog_header_value(name, 32).replace(['=', ']', '"'], "_"),
+                        value
+                            .replace('\\', "\\\\")
+                            .replace('"', "\\\"")
+                            .replace(']', "\\]"),
+                    ));
+                }
+                data.push(']');
+                data
+            }
+            _ => "-".to_string(),
+        };
+
+        let message = format!(
+            "<{priority}>1 {timestamp} {} {} {} - {structured_data} {}",
+            syslog_header_value(&self.hostname, 255),
+            syslog_header_value(&self.app_name, 48),
+            self.pid,
+            record.message,
+        );
+        format!("{} {message}", message.len())
+    }
+
+    fn json_line(&self, record: &LogRecord) -> String {
+        let fields: serde_json::Map<String, serde_json::Value> = record
+            .fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone().into()))
+            .collect();
+        let mut line = serde_json::json!({
+            "time": proxmox_time::epoch_to_rfc3339_utc(record.time).ok(),
+            "hostname": self.hostname,
+            "app": self.app_name,
+            "pid": self.pid,
+            "level": record.level.as_str(),
+            "target": record.target,
+            "message": record.message,
+            "fields": fields,
+        })
+        .to_string();
+        line.push('\n');
+        line
+    }
+}
+
+// header fields are restricted to printable ascii without spaces, '-' means empty
+fn syslog_header_value(value: &str, max_len: usize) -> String {
+    let value: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+trait Connection: Read + Write + Send {}
+impl<T: Read + Write + Send> Connection for T {}
+
+enum Transport {
+    Syslog {
+        host: String,
+        port: u16,
+        ssl_connector: Option<SslConnector>,
+        connection: Option<Box<dyn Connection>>,
+    },
+    Http {
+        url: String,
+        client: Client,
+        runtime: tokio::runtime::Runtime,
+    },
+}
+
+struct Sender {
+    transport: Transport,
+    formatter: Formatter,
+}
+
+impl Sender {
+    fn new(options: &RemoteLogOptions) -> Result<Self, Error> {
+        let ssl_connector = || -> Result<SslConnector, Error> {
+            let mut connector = SslConnector::builder(SslMethod::tls())?;
+            if !options.verify_tls {
+                connector.set_verify(SslVerifyMode::NONE);
+            }
+            Ok(connector.build())
+        };
+
+        let transport = match &options.target.kind {
+            TargetKind::Syslog { host, port, tls } => Transport::Syslog {
+                host: host.clone(),
+                port: *port,
+                ssl_connector: if *tls { Some(ssl_connector()?) } else { None },
+                connection: None,
+            },
+            TargetKind::Http { url } => {
+                let http_options = HttpOptions {
+                    connect_timeout: Some(CONNECT_TIMEOUT),
+                    request_timeout: Some(IO_TIMEOUT),
+                    ..Default::default()
+                };
+                Transport::Http {
+                    url: url.clone(),
+                    client: Client::with_ssl_connector(ssl_connector()?, http_options),
+                    runtime: tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?,
+                }
+            }
+        };
+
+        Ok(Self {
+            transport,
+            formatter: Formatter::new(options),
+        })
+    }
+
+    fn send(&mut self, batch: &[LogRecord]) -> Result<(), Error> {
+        match &mut self.transport {
+            Transport::Syslog {
+                host,
+                port,
+                ssl_connector,
+                connection,
+            } => {
+                let data: String = batch
+                    .iter()
+                    .map(|record| self.formatter.syslog_frame(record))
+                    .collect();
+                let mut stream = match connection.take() {
+                    Some(stream) => stream,
+                    None => syslog_connect(host, *port, ssl_connector.as_ref())?,
+                };
+                stream.write_all(data.as_bytes())?;
+                stream.flush()?;
+                *connection = Some(stream);
+            }
+            Transport::Http {
+                url,
+                client,
+                runtime,
+            } => {
+                let body: String = batch
+                    .iter()
+                    .map(|record| self.formatter.json_line(record))
+                    .collect();
+                let response = runtime.block_on(client.post(
+                    url,
+                    Some(body.into()),
+                    Some("application/x-ndjson"),
+                    None,
+                ))?;
+                let status = response.status();
+                if !status.is_success() {
+                    bail!("log receiver returned http status {status}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the syslog connection, so the next batch is sent with a new one.
+    fn disconnect(&mut self) {
+        if let Transport::Syslog { connection, .. } = &mut self.transport {
+            *connection = None;
+        }
+    }
+
+    fn run(mut self, queue: Arc<Queue>) {
+        let mut retry_delay = MIN_RETRY_DELAY;
+        loop {
+            let (mut batch, dropped) = queue.take_batch(MAX_BATCH_SIZE);
+            if dropped > 0 {
+                batch.insert(0, dropped_notice(dropped));
+            }
+
+            match self.send(&batch) {
+                Ok(()) => retry_delay = MIN_RETRY_DELAY,
+                Err(_) => {
+                    self.disconnect();
+                    queue.requeue(batch);
+                    std::thread::sleep(retry_delay);
+                    retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+    }
+}
+
+fn syslog_connect(
+    host: &str,
+    port: u16,
+    ssl_connector: Option<&SslConnector>,
+) -> Result<Box<dyn Connection>, Error> {
+    let mut last_err = None;
+    let mut stream = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(tcp) => {
+                stream = Some(tcp);
+                break;
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    let stream = match (stream, last_err) {
+        (Some(stream), _) => stream,
+        (None, Some(err)) => return Err(err.into()),
+        (None, None) => bail!("could not resolve '{host}'"),
+    };
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    match ssl_connector {
+        Some(connector) => Ok(Box::new(connector.connect(host, stream)?)),
+        None => Ok(Box::new(stream)),
+    }
+}
+
+fn dropped_notice(dropped: u64) -> LogRecord {
+    LogRecord {
+        time: proxmox_time::epoch_i64(),
+        level: Level::WARN,
+        target: module_path!().to_string(),
+        message: format!("dropped {dropped} log messages while the receiver was unavailable"),
+        fields: Vec::new(),
+    }
+}
+
+/// Queues events for sending them to a remote receiver.
+pub(crate) struct RemoteLayer {
+    queue: Arc<Queue>,
+}
+
+impl RemoteLayer {
+    /// Starts the background thread sending the queued events.
+    pub fn new(options: RemoteLogOptions) -> Result<Self, Error> {
+        let sender = Sender::new(&options)?;
+        let queue = Arc::new(Queue::new(options.buffer_size));
+
+        let thread_queue = Arc::clone(&queue);
+        std::thread::Builder::new()
+            .name("remote-log".to_string())
+            .spawn(move || sender.run(thread_queue))?;
+
+        Ok(Self { queue })
+    }
+}
+
+impl<S> Layer<S> for RemoteLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        SpanFields::<Self>::on_new_span(attrs, id, &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        SpanFields::<Self>::on_record(id, values, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let fields = EventFields::collect::<Self, S>(event, &ctx);
+        self.queue.push(LogRecord {
+            time: proxmox_time::epoch_i64(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message: fields.message,
+            fields: fields.fields,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn record(message: &str, fields: &[(&str, &str)]) -> LogRecord {
+        LogRecord {
+            time: 1_700_000_000,
+            level: Level::WARN,
+            target: "proxmox_log::test".to_string(),
+            message: message.to_string(),
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn formatter(structured_data_id: Option<&str>) -> Formatter {
+        Formatter {
+            hostname: "node1".to_string(),
+            app_name: "proxmox backup".to_string(),
+            pid: 42,
+            facility: 3,
+            structured_data_id: structured_data_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_syslog_format() {
+        let record = record("hello", &[("upid", "UPID:a\"b]"), ("a b", "c")]);
+
+        let message = "<28>1 2023-11-14T22:13:20Z node1 proxmoxbackup 42 - - hello";
+        assert_eq!(
+            formatter(None).syslog_frame(&record),
+            format!("{} {message}", message.len())
+        );
+
+        let message = "<28>1 2023-11-14T22:13:20Z node1 proxmoxbackup 42 - \
+                       [fields@32473 upid=\"UPID:a\\\"b\\]\" ab=\"c\"] hello";
+        assert_eq!(
+            formatter(Some("fields@32473")).syslog_frame(&record),
+            format!("{} {message}", message.len())
+        );
+    }
+
+    #[test]
+    fn test_json_format() {
+        let line = formatter(None).json_line(&record("hello", &[("upid", "UPID:x")]));
+        assert!(line.ends_with('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["time"], "2023-11-14T22:13:20Z");
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["message"], "hello");
+        assert_eq!(value["fields"]["upid"], "UPID:x");
+    }
+
+    #[test]
+    fn test_queue_overflow() {
+        let queue = Queue::new(2);
+        for message in ["1", "2", "3"] {
+            queue.push(record(message, &[]));
+        }
+        let (batch, dropped) = queue.take_batch(1);
+        assert_eq!((batch[0].message.as_str(), dropped), ("2", 1));
+
+        queue.push(record("4", &[]));
+        queue.requeue(batch);
+        let (batch, dropped) = queue.take_batch(10);
+        let messages: Vec<_> = batch.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!((messages, dropped), (vec!["3", "4"], 1));
+    }
+
+    #[test]
+    fn test_target_url() {
+        let target = RemoteTarget::http("https://[::1]/logs?source=pbs").unwrap();
+        assert!(
+            matches!(target.kind, TargetKind::Http { url } if url == "https://[::1]/logs?source=pbs")
+        );
+        assert!(RemoteTarget::http("ftp://localhost").is_err());
+        assert!(RemoteTarget::http("unix:/run/log.sock").is_err());
+    }
+
+    #[test]
+    fn test_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            // the syslog connection stays open, the http ones are closed after the response
+            for (end, response) in [
+                ("hello", None),
+                ("}\n", Some("HTTP/1.1 204 No Content\r\n\r\n")),
+                (
+                    "}\n",
+                    Some("HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\n\r\n"),
+                ),
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut data = String::new();
+                while !data.ends_with(end) {
+                    let mut buffer = [0u8; 4096];
+                    let len = stream.read(&mut buffer).unwrap();
+                    assert!(len > 0);
+                    data.push_str(std::str::from_utf8(&buffer[..len]).unwrap());
+                }
+                if let Some(response) = response {
+                    stream.write_all(response.as_bytes()).unwrap();
+                }
+                received.push(data);
+            }
+            received
+        });
+
+        let options = RemoteLogOptions::new(RemoteTarget::syslog("127.0.0.1", port));
+        let mut sender = Sender::new(&options).unwrap();
+        sender.formatter = formatter(None);
+        sender.send(&[record("hello", &[])]).unwrap();
+
+        let url = format!("http://127.0.0.1:{port}/logs");
+        let options = RemoteLogOptions::new(RemoteTarget::http(&url).unwrap());
+        let mut sender = Sender::new(&options).unwrap();
+        sender.formatter = formatter(None);
+        sender.send(&[record("hello", &[])]).unwrap();
+
+        let mut sender = Sender::new(&options).unwrap();
+        assert!(sender.send(&[record("hello", &[])]).is_err());
+
+        let received = server.join().unwrap();
+        assert!(received[0].ends_with(" 42 - - hello"));
+        assert!(received[1].starts_with("POST /logs HTTP/1.1\r\n"));
+        assert!(received[1].contains("\r\n\r\n{\"app\":\"proxmox backup\","));
+    }
+}
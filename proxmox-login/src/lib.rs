@@ -307,6 +307,17 @@ impl SecondFactorChallenge {
         }
     }
 
+    /// Create a HTTP request responding with a HOTP value.
+    ///
+    /// Errors with `TfaError::Unavailable` if HOTP is not available.
+    pub fn respond_hotp(&self, code: &str) -> Result<Request, TfaError> {
+        if !self.challenge.hotp {
+            Err(TfaError::Unavailable)
+        } else {
+            Ok(self.respond_raw(&format!("hotp:{code}")))
+        }
+    }
+
     /// Create a HTTP request responding with a recovery code.
     ///
     /// Errors with `TfaError::Unavailable` if no recovery codes are available.
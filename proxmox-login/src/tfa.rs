@@ -16,6 +16,10 @@ pub struct TfaChallenge {
     #[serde(skip_serializing_if = "bool_is_false", default)]
     pub totp: bool,
 
+    /// True if the user has HOTP tokens.
+    #[serde(skip_serializing_if = "bool_is_false", default)]
+    pub hotp: bool,
+
     /// Whether there are recovery keys available.
     #[serde(skip_serializing_if = "RecoveryState::is_unavailable", default)]
     pub recovery: RecoveryState,
@@ -74,6 +78,7 @@ pub struct RegisteredKey {
 /// A user's response to a TFA challenge.
 pub enum TfaResponse {
     Totp(String),
+    Hotp(String),
     U2f(serde_json::Value),
     Webauthn(serde_json::Value),
     Recovery(String),
@@ -109,6 +114,8 @@ impl std::str::FromStr for TfaResponse {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(if let Some(totp) = s.strip_prefix("totp:") {
             TfaResponse::Totp(totp.to_string())
+        } else if let Some(hotp) = s.strip_prefix("hotp:") {
+            TfaResponse::Hotp(hotp.to_string())
         } else if let Some(u2f) = s.strip_prefix("u2f:") {
             TfaResponse::U2f(serde_json::from_str(u2f)?)
         } else if let Some(webauthn) = s.strip_prefix("webauthn:") {
@@ -152,11 +152,20 @@ impl std::str::FromStr for Ticket {
         let s = &s[userid_start..];
 
         // get userid:
-        let userid_len = s.find(':').ok_or(TicketError)?;
-        if !s[..userid_len].contains('@') {
+        let data_len = s.find(':').ok_or(TicketError)?;
+        if !s[..data_len].contains('@') {
             return Err(TicketError);
         }
-        let s = &s[(userid_len + 1)..];
+        // session tickets are of the form `!session!<id>!<userid>`
+        let (userid_start, userid_len) = match s[..data_len].strip_prefix("!session!") {
+            Some(session) => {
+                let skip = session.find('!').ok_or(TicketError)? + 1;
+                let offset = "!session!".len() + skip;
+                (userid_start + offset, data_len - offset)
+            }
+            None => (userid_start, data_len),
+        };
+        let s = &s[(data_len + 1)..];
 
         // timestamp
         let timestamp_len = s.find(':').ok_or(TicketError)?;
@@ -313,3 +322,35 @@ fn epoch_i64() -> i64 {
         -i64::try_from(UNIX_EPOCH.duration_since(now).unwrap().as_secs()).unwrap_or(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Ticket;
+
+    #[test]
+    fn test_ticket_userid() {
+        let ticket: Ticket = "PBS:root@pam:5F3D1E2A::c2lnbmF0dXJl".parse().unwrap();
+        assert_eq!(ticket.product(), "PBS");
+        assert_eq!(ticket.userid(), "root@pam");
+        assert_eq!(ticket.timestamp(), 0x5F3D1E2A);
+
+        let ticket: Ticket = "PBS:!session!0123abcd!user@pve:5F3D1E2A::c2lnbmF0dXJl"
+            .parse()
+            .unwrap();
+        assert_eq!(ticket.userid(), "user@pve");
+        assert_eq!(ticket.timestamp(), 0x5F3D1E2A);
+
+        let ticket: Ticket = "PMGQUAR:!session!ff!quar@quarantine:5F3D1E2A::c2ln"
+            .parse()
+            .unwrap();
+        assert_eq!(ticket.product(), "PMG");
+        assert_eq!(ticket.userid(), "quar@quarantine");
+
+        assert!("PBS:!session!0123abcd:5F3D1E2A::c2ln"
+            .parse::<Ticket>()
+            .is_err());
+        assert!("PBS:!session!user@pve:5F3D1E2A::c2ln"
+            .parse::<Ticket>()
+            .is_err());
+    }
+}
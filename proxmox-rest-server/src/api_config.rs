@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
@@ -15,7 +16,8 @@ use tower_service::Service;
 
 use proxmox_daemon::command_socket::CommandSocket;
 use proxmox_http::Body;
-use proxmox_log::{FileLogOptions, FileLogger};
+use proxmox_log::{FileLogOptions, FileLogger, LogRotateOptions};
+use proxmox_network_types::ip_address::Cidr;
 use proxmox_router::{Router, RpcEnvironmentType, UserInformation};
 use proxmox_sys::fs::{create_path, CreateOptions};
 
@@ -29,10 +31,12 @@ pub struct ApiConfig {
     env_type: RpcEnvironmentType,
     request_log: Option<Arc<Mutex<FileLogger>>>,
     auth_log: Option<Arc<Mutex<FileLogger>>>,
+    log_rotation: Option<LogRotateOptions>,
     handlers: Vec<Handler>,
     auth_handler: Option<AuthHandler>,
     index_handler: Option<IndexHandler>,
     pub(crate) privileged_addr: Option<PrivilegedAddr>,
+    trusted_proxies: Vec<Cidr>,
 
     #[cfg(feature = "templates")]
     templates: templates::Templates,
@@ -58,10 +62,15 @@ impl ApiConfig {
             env_type,
             request_log: None,
             auth_log: None,
+            log_rotation: None,
             handlers: Vec::new(),
             auth_handler: None,
             index_handler: None,
             privileged_addr: None,
+            trusted_proxies: vec![
+                Cidr::new_v4(Ipv4Addr::LOCALHOST, 8).unwrap(),
+                Cidr::new_v6(Ipv6Addr::LOCALHOST, 128).unwrap(),
+            ],
 
             #[cfg(feature = "templates")]
             templates: templates::Templates::with_escape_fn(),
@@ -88,6 +97,26 @@ impl ApiConfig {
         self
     }
 
+    /// Set the networks of reverse proxies whose `Forwarded` header is trusted.
+    ///
+    /// The client address of requests from other peers is always the connection's peer address,
+    /// so that it cannot be spoofed. Defaults to the loopback addresses, which `protected` API
+    /// calls are proxied from. Connections via unix sockets are always trusted.
+    pub fn trusted_proxies<I>(mut self, proxies: I) -> Self
+    where
+        I: IntoIterator<Item = Cidr>,
+    {
+        self.trusted_proxies = proxies.into_iter().collect();
+        self
+    }
+
+    /// Whether the `Forwarded` header of requests from `peer` is trusted.
+    pub(crate) fn is_trusted_proxy(&self, peer: &SocketAddr) -> bool {
+        // unix socket connections use an unspecified address, see `PeerAddress`
+        let ip = peer.ip().to_canonical();
+        ip.is_unspecified() || self.trusted_proxies.iter().any(|cidr| cidr.contains_address(&ip))
+    }
+
     /// Set the index handler.
     pub fn index_handler(mut self, index_handler: IndexHandler) -> Self {
         self.index_handler = Some(index_handler);
@@ -117,9 +146,10 @@ impl ApiConfig {
         &self,
         headers: &HeaderMap,
         method: &Method,
+        client_ip: Option<IpAddr>,
     ) -> Result<(String, Box<dyn UserInformation + Sync + Send>), AuthError> {
         match self.auth_handler.as_ref() {
-            Some(handler) => (handler.func)(headers, method).await,
+            Some(handler) => (handler.func)(headers, method, client_ip).await,
             None => Err(AuthError::NoData),
         }
     }
@@ -199,6 +229,20 @@ impl ApiConfig {
         self.templates.render(name, data)
     }
 
+    /// Rotate the access and authentication logs automatically.
+    ///
+    /// Without this, the logs grow until they get rotated externally, followed by the
+    /// `api-access-log-reopen` and `api-auth-log-reopen` commands.
+    pub fn log_rotation(mut self, options: LogRotateOptions) -> Self {
+        for log in [&self.request_log, &self.auth_log].into_iter().flatten() {
+            log.lock()
+                .unwrap()
+                .set_rotate_options(Some(options.clone()));
+        }
+        self.log_rotation = Some(options);
+        self
+    }
+
     /// Enable the access log feature
     ///
     /// When enabled, all requests are logged to the specified file.
@@ -224,6 +268,7 @@ impl ApiConfig {
         let logger_options = FileLogOptions {
             append: true,
             file_opts: file_opts.unwrap_or_default(),
+            rotate: self.log_rotation.clone(),
             ..Default::default()
         };
         let request_log = Arc::new(Mutex::new(FileLogger::new(&path, logger_options)?));
@@ -264,6 +309,7 @@ impl ApiConfig {
             append: true,
             prefix_time: true,
             file_opts: file_opts.unwrap_or_default(),
+            rotate: self.log_rotation.clone(),
             ..Default::default()
         };
         let auth_log = Arc::new(Mutex::new(FileLogger::new(&path, logger_options)?));
@@ -461,13 +507,22 @@ pub type CheckAuthOutput = Result<(String, Box<dyn UserInformation + Send + Sync
 pub type CheckAuthFuture<'a> = Pin<Box<dyn Future<Output = CheckAuthOutput> + Send + 'a>>;
 pub type CheckAuthFunc =
     Box<dyn for<'a> Fn(&'a HeaderMap, &'a Method) -> CheckAuthFuture<'a> + Send + Sync>;
+pub type CheckAuthWithClientIpFunc = Box<
+    dyn for<'a> Fn(&'a HeaderMap, &'a Method, Option<IpAddr>) -> CheckAuthFuture<'a> + Send + Sync,
+>;
 
 pub struct AuthHandler {
-    func: CheckAuthFunc,
+    func: CheckAuthWithClientIpFunc,
 }
 
 impl From<CheckAuthFunc> for AuthHandler {
     fn from(func: CheckAuthFunc) -> Self {
+        Self::from_fn_with_client_ip(move |headers, method, _client_ip| func(headers, method))
+    }
+}
+
+impl From<CheckAuthWithClientIpFunc> for AuthHandler {
+    fn from(func: CheckAuthWithClientIpFunc) -> Self {
         Self { func }
     }
 }
@@ -479,6 +534,17 @@ impl AuthHandler {
     {
         Self::from(Box::new(func) as CheckAuthFunc)
     }
+
+    /// Create a handler which additionally gets the address of the client.
+    pub fn from_fn_with_client_ip<Func>(func: Func) -> Self
+    where
+        Func: for<'a> Fn(&'a HeaderMap, &'a Method, Option<IpAddr>) -> CheckAuthFuture<'a>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self::from(Box::new(func) as CheckAuthWithClientIpFunc)
+    }
 }
 
 /// Authentication Error
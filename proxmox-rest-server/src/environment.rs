@@ -13,6 +13,7 @@ pub struct RestEnvironment {
     result_attributes: Value,
     auth_id: Option<String>,
     client_ip: Option<SocketAddr>,
+    user_agent: Option<String>,
     api: Arc<ApiConfig>,
 }
 
@@ -22,6 +23,7 @@ impl RestEnvironment {
             result_attributes: json!({}),
             auth_id: None,
             client_ip: None,
+            user_agent: None,
             env_type,
             api,
         }
@@ -31,6 +33,15 @@ impl RestEnvironment {
         &self.api
     }
 
+    /// The `User-Agent` header of the request, if any.
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    pub(crate) fn set_user_agent(&mut self, user_agent: Option<String>) {
+        self.user_agent = user_agent;
+    }
+
     pub fn log_auth(&self, auth_id: &str) {
         let msg = format!("successful auth for user '{}'", auth_id);
         log::debug!("{}", msg); // avoid noisy syslog, admins can already check the auth log
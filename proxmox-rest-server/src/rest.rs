@@ -354,8 +354,8 @@ impl Service<Request<Incoming>> for ApiService {
 
         let config = Arc::clone(&self.api_config);
         let peer = match get_proxied_peer(req.headers()) {
-            Some(proxied_peer) => proxied_peer,
-            None => self.peer,
+            Some(proxied_peer) if config.is_trusted_proxy(&self.peer) => proxied_peer,
+            _ => self.peer,
         };
         async move {
             let response = match Arc::clone(&config).handle_request(req, &peer).await {
@@ -858,6 +858,13 @@ impl ApiConfig {
         let mut rpcenv = RestEnvironment::new(env_type, Arc::clone(&self));
 
         rpcenv.set_client_ip(Some(*peer));
+        rpcenv.set_user_agent(
+            parts
+                .headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+        );
 
         if let Some(handler) = self.find_handler(&components) {
             let relative_path_components = &components[handler.prefix.len()..];
@@ -879,7 +886,10 @@ impl ApiConfig {
         }
 
         if components.is_empty() {
-            match self.check_auth(&parts.headers, &method).await {
+            match self
+                .check_auth(&parts.headers, &method, Some(peer.ip()))
+                .await
+            {
                 Ok((auth_id, _user_info)) => {
                     rpcenv.set_auth_id(Some(auth_id));
                     return Ok(self.get_index(rpcenv, parts).await);
@@ -1004,7 +1014,10 @@ impl Formatted {
             Box::new(EmptyUserInformation {});
 
         if auth_required {
-            match config.check_auth(&parts.headers, &parts.method).await {
+            match config
+                .check_auth(&parts.headers, &parts.method, Some(peer.ip()))
+                .await
+            {
                 Ok((authid, info)) => {
                     rpcenv.set_auth_id(Some(authid));
                     user_info = info;
@@ -1111,7 +1124,10 @@ impl Unformatted {
         let user_info: Box<dyn UserInformation + Send + Sync>;
 
         if auth_required {
-            match config.check_auth(&parts.headers, &parts.method).await {
+            match config
+                .check_auth(&parts.headers, &parts.method, Some(peer.ip()))
+                .await
+            {
                 Ok((authid, info)) => {
                     rpcenv.set_auth_id(Some(authid));
                     user_info = info;
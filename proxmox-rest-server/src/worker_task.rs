@@ -944,7 +944,8 @@ impl WorkerTask {
         let upid_str = worker.upid.to_string();
         let f = f(worker.clone());
 
-        tokio::spawn(LogContext::new(logger).scope(async move {
+        let log_context = worker.log_context(logger);
+        tokio::spawn(log_context.scope(async move {
             let result = f.await;
             worker.log_result(&result);
         }));
@@ -966,10 +967,11 @@ impl WorkerTask {
         let (worker, logger) = WorkerTask::new(worker_type, worker_id, auth_id, to_stdout)?;
         let upid_str = worker.upid.to_string();
 
+        let log_context = worker.log_context(logger);
         let _child = std::thread::Builder::new()
             .name(upid_str.clone())
             .spawn(move || {
-                LogContext::new(logger).sync_scope(|| {
+                log_context.sync_scope(|| {
                     let worker1 = worker.clone();
 
                     let result = match std::panic::catch_unwind(move || f(worker1)) {
@@ -992,6 +994,13 @@ impl WorkerTask {
         Ok(upid_str)
     }
 
+    // the fields make the messages of the task filterable in structured logs
+    fn log_context(&self, logger: FileLogger) -> LogContext {
+        LogContext::new(logger)
+            .with_field("upid", self.upid.to_string())
+            .with_field("authid", self.upid.auth_id.clone())
+    }
+
     /// create state from self and a result
     pub fn create_state(&self, result: &Result<(), Error>) -> TaskState {
         let warn_count = match LogContext::current() {
@@ -0,0 +1,46 @@
+//! Generate a typed client for a small example API.
+//!
+//! Products call [`run_cli`] the same way with their API router, for instance:
+//!
+//! ```text
+//! cargo run --example client-gen --features client-gen -- --name ExampleClient --output client.rs
+//! ```
+
+use anyhow::Error;
+use serde_json::{json, Value};
+
+use proxmox_router::client_gen::run_cli;
+use proxmox_router::{ApiHandler, ApiMethod, Router, RpcEnvironment};
+use proxmox_schema::{ObjectSchema, ReturnType, StringSchema};
+
+fn get_version(
+    _param: Value,
+    _info: &ApiMethod,
+    _rpcenv: &mut dyn RpcEnvironment,
+) -> Result<Value, Error> {
+    Ok(json!({ "version": env!("CARGO_PKG_VERSION") }))
+}
+
+const API_METHOD_GET_VERSION: ApiMethod = ApiMethod::new(
+    &ApiHandler::Sync(&get_version),
+    &ObjectSchema::new("Get the API version.", &[]),
+)
+.returns(ReturnType::new(
+    false,
+    &ObjectSchema::new(
+        "Version information.",
+        &[(
+            "version",
+            false,
+            &StringSchema::new("The version.").schema(),
+        )],
+    )
+    .schema(),
+));
+
+const ROUTER: Router =
+    Router::new().subdirs(&[("version", &Router::new().get(&API_METHOD_GET_VERSION))]);
+
+fn main() -> Result<(), Error> {
+    run_cli(&ROUTER)
+}
@@ -12,7 +12,8 @@ use super::environment::CliEnvironment;
 use super::getopts;
 use super::{
     generate_nested_usage, generate_usage_str_do, print_help, print_nested_usage_error,
-    print_simple_usage_error_do, CliCommand, CliCommandMap, CommandLineInterface, GlobalOptions,
+    print_simple_usage_error_do, set_output_selection, CliCommand, CliCommandMap,
+    CommandLineInterface, DocGenerator, GlobalOptions, OutputSelection,
 };
 use crate::{ApiFuture, ApiHandler, ApiMethod, RpcEnvironment};
 
@@ -27,6 +28,12 @@ pub enum OutputFormat {
     Json,
     /// Prettified JSON output.
     JsonPretty,
+    /// YAML output.
+    Yaml,
+    /// Comma separated values, one line per entry.
+    Csv,
+    /// Tab separated values, one line per entry.
+    Tsv,
 }
 serde_plain::derive_display_from_serialize!(OutputFormat);
 serde_plain::derive_fromstr_from_deserialize!(OutputFormat);
@@ -40,12 +47,18 @@ impl ApiType for OutputFormat {
 /// - ``text``: command specific text format.
 /// - ``json``: JSON, single line.
 /// - ``json-pretty``: JSON, human readable.
+/// - ``yaml``: YAML, human readable.
+/// - ``csv``: comma separated values.
+/// - ``tsv``: tab separated values.
 ///
 pub const OUTPUT_FORMAT: Schema = StringSchema::new("Output format.")
     .format(&ApiStringFormat::Enum(&[
         EnumEntry::new("text", "plain text output"),
         EnumEntry::new("json", "single-line json formatted output"),
         EnumEntry::new("json-pretty", "pretty-printed json output"),
+        EnumEntry::new("yaml", "yaml formatted output"),
+        EnumEntry::new("csv", "comma separated values"),
+        EnumEntry::new("tsv", "tab separated values"),
     ]))
     .schema();
 
@@ -140,7 +153,43 @@ pub(crate) fn handle_simple_command<'cli>(
 ) -> Result<(), Error> {
     let params = parse_arguments(prefix, cli_cmd, args, global_options_iter)?;
 
-    let result = match cli_cmd.info.handler {
+    let selection = rpcenv
+        .global_option::<OutputSelection>()
+        .filter(|selection| !selection.is_empty())
+        .cloned();
+    set_output_selection(selection.clone());
+    let result = call_simple_command(cli_cmd, params, rpcenv, run);
+    set_output_selection(None);
+
+    let result = result.and_then(|mut value| {
+        if let Some(selection) = selection {
+            selection.apply(&mut value)?;
+        }
+        Ok(value)
+    });
+
+    match result {
+        Ok(value) => {
+            if value != Value::Null {
+                println!("Result: {}", serde_json::to_string_pretty(&value).unwrap());
+            }
+        }
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+fn call_simple_command(
+    cli_cmd: &CliCommand,
+    params: Value,
+    rpcenv: &mut CliEnvironment,
+    run: Option<fn(ApiFuture) -> Result<Value, Error>>,
+) -> Result<Value, Error> {
+    match cli_cmd.info.handler {
         ApiHandler::Sync(handler) => (handler)(params, cli_cmd.info, rpcenv),
         ApiHandler::SerializingSync(handler) => {
             (handler)(params, cli_cmd.info, rpcenv).and_then(|r| r.to_value().map_err(Error::from))
@@ -171,21 +220,7 @@ pub(crate) fn handle_simple_command<'cli>(
                 "CliHandler does not support ApiHandler::AsyncHttpBodyParameters - internal error"
             );
         }
-    };
-
-    match result {
-        Ok(value) => {
-            if value != Value::Null {
-                println!("Result: {}", serde_json::to_string_pretty(&value).unwrap());
-            }
-        }
-        Err(err) => {
-            eprintln!("Error: {err:?}");
-            return Err(err);
-        }
     }
-
-    Ok(())
 }
 
 fn parse_nested_command<'a>(
@@ -400,6 +435,17 @@ where
         }
 
         if args[0] == "printdoc" {
+            match args.get(1).map(String::as_str) {
+                Some("man") => {
+                    print!("{}", DocGenerator::new(def, &prefix).generate_man());
+                    std::process::exit(0);
+                }
+                Some("markdown") => {
+                    print!("{}", DocGenerator::new(def, &prefix).generate_markdown());
+                    std::process::exit(0);
+                }
+                _ => (),
+            }
             let usage = match def {
                 CommandLineInterface::Simple(cli_cmd) => generate_usage_str_do(
                     &prefix,
@@ -416,6 +462,19 @@ where
             println!("{}", usage);
             std::process::exit(0);
         }
+
+        if args[0] == "printcompletion" {
+            match args.get(1).map(String::as_str) {
+                Some("bash") => println!("complete -C '{prefix} bashcomplete' {prefix}"),
+                Some("zsh") => print!("{}", def.generate_zsh_completion(&prefix)),
+                Some("fish") => print!("{}", def.generate_fish_completion(&prefix)),
+                _ => {
+                    eprintln!("usage: {prefix} printcompletion <bash|zsh|fish>");
+                    std::process::exit(-1);
+                }
+            }
+            std::process::exit(0);
+        }
     }
 
     (prefix, args)
@@ -427,11 +486,12 @@ where
 /// argument is assumed to be the program name, and is passed as ``prefix`` to
 /// ``handle_command()``.
 ///
-/// This helper automatically add the help command, and two special
+/// This helper automatically add the help command, and three special
 /// sub-command:
 ///
 /// - ``bashcomplete``: Output bash completions instead of running the command.
-/// - ``printdoc``: Output ReST documentation.
+/// - ``printdoc [man|markdown]``: Output ReST documentation, a man page or Markdown.
+/// - ``printcompletion <bash|zsh|fish>``: Output a completion script for the shell.
 ///
 pub async fn run_async_cli_command<C: Into<CommandLineInterface>>(def: C, rpcenv: CliEnvironment) {
     run_async_cli_command_with_args(def, rpcenv, std::env::args()).await
@@ -455,11 +515,12 @@ pub fn run_cli_command<C: Into<CommandLineInterface>>(
 /// The first argument is assumed to be the program name, and is passed as ``prefix`` to
 /// ``handle_command()``.
 ///
-/// This helper automatically add the help command, and two special
+/// This helper automatically add the help command, and three special
 /// sub-command:
 ///
 /// - ``bashcomplete``: Output bash completions instead of running the command.
-/// - ``printdoc``: Output ReST documentation.
+/// - ``printdoc [man|markdown]``: Output ReST documentation, a man page or Markdown.
+/// - ``printcompletion <bash|zsh|fish>``: Output a completion script for the shell.
 ///
 pub async fn run_async_cli_command_with_args<A, C>(def: C, rpcenv: CliEnvironment, args: A)
 where
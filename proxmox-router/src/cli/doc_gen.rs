@@ -0,0 +1,681 @@
+//! Man page and Markdown reference generation for command line interfaces.
+
+use std::fmt::Write;
+
+use proxmox_schema::format::{get_schema_type_text, DocumentationFormat, ParameterDisplayStyle};
+use proxmox_schema::*;
+
+use super::format::generate_usage_str_do;
+use super::{CliCommand, CliCommandMap, CommandLineInterface, GlobalOptions};
+use crate::format::schema_description;
+
+/// Generates reference documentation for a [`CommandLineInterface`].
+///
+/// ```
+/// # use proxmox_router::cli::*;
+/// # fn code(cli: &CommandLineInterface) {
+/// let generator = DocGenerator::new(cli, "my-tool")
+///     .description("Manage my things.")
+///     .version("1.0");
+/// let man_page = generator.generate_man();
+/// let markdown = generator.generate_markdown();
+/// # }
+/// ```
+pub struct DocGenerator<'a> {
+    cli: &'a CommandLineInterface,
+    name: String,
+    section: String,
+    description: Option<String>,
+    version: Option<String>,
+}
+
+/// A documented parameter.
+struct ParamDoc {
+    /// `<name>` for positional arguments, `--name` for options.
+    usage: String,
+    type_text: String,
+    description: String,
+    default: Option<String>,
+}
+
+struct CommandDoc {
+    path: String,
+    synopsis: String,
+    description: &'static str,
+    args: Vec<ParamDoc>,
+    options: Vec<ParamDoc>,
+    inherited: Vec<String>,
+}
+
+/// Global options of a command group.
+struct GroupDoc {
+    path: String,
+    options: Vec<ParamDoc>,
+}
+
+#[derive(Default)]
+struct Docs {
+    commands: Vec<CommandDoc>,
+    groups: Vec<GroupDoc>,
+    aliases: Vec<(String, String)>,
+}
+
+fn schema_default(schema: &Schema) -> Option<String> {
+    match schema {
+        Schema::String(schema) => schema.default.map(str::to_string),
+        Schema::Boolean(schema) => schema.default.map(|v| v.to_string()),
+        Schema::Integer(schema) => schema.default.map(|v| v.to_string()),
+        Schema::Number(schema) => schema.default.map(|v| v.to_string()),
+        _ => None,
+    }
+}
+
+fn param_doc(usage: String, schema: &Schema) -> ParamDoc {
+    let mut description = schema_description(schema).trim().to_string();
+    if let Schema::Array(_) = schema {
+        description.push_str(" Can be specified more than once.");
+    }
+    ParamDoc {
+        usage,
+        type_text: get_schema_type_text(schema, ParameterDisplayStyle::Arg),
+        description,
+        default: schema_default(schema),
+    }
+}
+
+fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix} {name}")
+    }
+}
+
+fn command_doc(
+    path: String,
+    cli_cmd: &CliCommand,
+    skip_options: &[&str],
+    inherited: &[&GlobalOptions],
+) -> CommandDoc {
+    let schema = cli_cmd.info.parameters;
+    let synopsis = generate_usage_str_do(
+        &path,
+        cli_cmd,
+        DocumentationFormat::Short,
+        "",
+        skip_options,
+        [].into_iter(),
+    );
+
+    let mut args = Vec::new();
+    for name in cli_cmd.arg_param {
+        if let Some((_optional, param_schema)) = schema.lookup(name) {
+            args.push(param_doc(format!("<{name}>"), param_schema));
+        }
+    }
+
+    let mut options = Vec::new();
+    let mut properties: Vec<_> = schema.properties().collect();
+    properties.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, optional, param_schema) in properties {
+        if cli_cmd.arg_param.contains(name)
+            || cli_cmd.fixed_param.contains_key(name)
+            || skip_options.contains(name)
+        {
+            continue;
+        }
+        let doc = param_doc(format!("--{name}"), param_schema);
+        if *optional {
+            options.push(doc);
+        } else {
+            args.push(doc);
+        }
+    }
+
+    let mut inherited: Vec<String> = inherited
+        .iter()
+        .flat_map(|options| options.properties())
+        .map(|(name, _schema)| format!("--{name}"))
+        .collect();
+    inherited.sort();
+
+    CommandDoc {
+        path,
+        synopsis,
+        description: schema.description(),
+        args,
+        options,
+        inherited,
+    }
+}
+
+fn collect_docs<'cli>(
+    docs: &mut Docs,
+    cli: &'cli CommandLineInterface,
+    path: String,
+    skip_options: &[&str],
+    global_options: &mut Vec<&'cli GlobalOptions>,
+) {
+    match cli {
+        CommandLineInterface::Simple(cli_cmd) => {
+            docs.commands
+                .push(command_doc(path, cli_cmd, skip_options, global_options));
+        }
+        CommandLineInterface::Nested(map) => collect_map_docs(docs, map, path, global_options),
+    }
+}
+
+fn collect_map_docs<'cli>(
+    docs: &mut Docs,
+    map: &'cli CliCommandMap,
+    path: String,
+    global_options: &mut Vec<&'cli GlobalOptions>,
+) {
+    let mut group_options: Vec<_> = map
+        .global_options
+        .values()
+        .flat_map(|options| options.properties())
+        .map(|(name, schema)| (name, param_doc(format!("--{name}"), schema)))
+        .collect();
+    if !group_options.is_empty() {
+        group_options.sort_by(|a, b| a.0.cmp(b.0));
+        docs.groups.push(GroupDoc {
+            path: path.clone(),
+            options: group_options.into_iter().map(|(_, doc)| doc).collect(),
+        });
+    }
+
+    for (old, new) in &map.aliases {
+        docs.aliases.push((
+            join_path(&path, &old.join(" ")),
+            join_path(&path, &new.join(" ")),
+        ));
+    }
+
+    let depth = global_options.len();
+    global_options.extend(map.global_options.values());
+
+    let mut commands: Vec<_> = map.commands.iter().collect();
+    commands.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, sub_cli) in commands {
+        collect_docs(
+            docs,
+            sub_cli,
+            join_path(&path, name),
+            map.usage_skip_options,
+            global_options,
+        );
+    }
+
+    global_options.truncate(depth);
+}
+
+/// Escape text for roff, also protecting lines starting with a control character.
+fn roff_escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    let mut result = String::with_capacity(text.len());
+    for line in text.lines() {
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        if line.starts_with(['.', '\'']) {
+            result.push_str("\\&");
+        }
+        result.push_str(line);
+    }
+    result
+}
+
+/// Write description paragraphs, separated by blank lines in `text`.
+fn roff_paragraphs(out: &mut String, text: &str) {
+    let mut first = true;
+    for paragraph in text.split("\n\n") {
+        let paragraph = paragraph.trim();
+        if paragraph.is_empty() {
+            continue;
+        }
+        if !first {
+            out.push_str(".sp\n");
+        }
+        first = false;
+        out.push_str(&roff_escape(paragraph));
+        out.push('\n');
+    }
+}
+
+fn roff_param(out: &mut String, param: &ParamDoc) {
+    let _ = writeln!(
+        out,
+        ".TP\n\\fB{}\\fR \\fI{}\\fR",
+        roff_escape(&param.usage),
+        roff_escape(&param.type_text)
+    );
+    roff_paragraphs(out, &param.description);
+    if let Some(default) = &param.default {
+        let _ = writeln!(out, ".sp\nDefault: \\fB{}\\fR", roff_escape(default));
+    }
+}
+
+/// Escape characters with a meaning in Markdown.
+fn markdown_escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '<' | '>' | '[' | ']' | '#' | '|'
+        ) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Inline code span, using a longer fence if the text contains backticks.
+fn markdown_code(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {text} ``")
+    } else {
+        format!("`{text}`")
+    }
+}
+
+fn markdown_paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(|paragraph| markdown_escape(paragraph.trim()))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn markdown_param(out: &mut String, param: &ParamDoc) {
+    let _ = write!(
+        out,
+        "- {} {}",
+        markdown_code(&param.usage),
+        markdown_code(&param.type_text)
+    );
+    if let Some(default) = &param.default {
+        let _ = write!(out, " (default: {})", markdown_code(default));
+    }
+    let description = markdown_paragraphs(&param.description);
+    if !description.is_empty() {
+        let _ = write!(out, "\n\n  {}", description.replace("\n", "\n  "));
+    }
+    out.push_str("\n\n");
+}
+
+impl<'a> DocGenerator<'a> {
+    /// Create a generator for the program `name`.
+    pub fn new(cli: &'a CommandLineInterface, name: impl Into<String>) -> Self {
+        Self {
+            cli,
+            name: name.into(),
+            section: "1".to_string(),
+            description: None,
+            version: None,
+        }
+    }
+
+    /// Set the man page section, defaults to `1`.
+    pub fn section(mut self, section: impl Into<String>) -> Self {
+        self.section = section.into();
+        self
+    }
+
+    /// Set a description of the program as a whole.
+    ///
+    /// The first line is used as short description in the man page's `NAME` section.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the program version shown in the man page footer.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    fn collect(&self) -> Docs {
+        let mut docs = Docs::default();
+        collect_docs(&mut docs, self.cli, self.name.clone(), &[], &mut Vec::new());
+        docs
+    }
+
+    /// Generate a man page in roff format.
+    pub fn generate_man(&self) -> String {
+        let docs = self.collect();
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            ".TH \"{}\" \"{}\" \"\" \"{}\" \"User Commands\"",
+            roff_escape(&self.name.to_uppercase()),
+            roff_escape(&self.section),
+            roff_escape(&match &self.version {
+                Some(version) => format!("{} {version}", self.name),
+                None => self.name.clone(),
+            }),
+        );
+
+        out.push_str(".SH NAME\n");
+        match self.description.as_deref().and_then(|d| d.lines().next()) {
+            Some(summary) => {
+                let _ = writeln!(
+                    out,
+                    "{} \\- {}",
+                    roff_escape(&self.name),
+                    roff_escape(summary.trim())
+                );
+            }
+            None => out.push_str(&format!("{}\n", roff_escape(&self.name))),
+        }
+
+        out.push_str(".SH SYNOPSIS\n.nf\n");
+        for command in &docs.commands {
+            out.push_str(&roff_escape(&command.synopsis));
+            out.push('\n');
+        }
+        out.push_str(".fi\n");
+
+        if let Some(description) = &self.description {
+            out.push_str(".SH DESCRIPTION\n");
+            roff_paragraphs(&mut out, description);
+        }
+
+        let nested = matches!(self.cli, CommandLineInterface::Nested(_));
+        out.push_str(if nested {
+            ".SH COMMANDS\n"
+        } else {
+            ".SH OPTIONS\n"
+        });
+        for command in &docs.commands {
+            if nested {
+                let _ = writeln!(out, ".SS \"{}\"", roff_escape(&command.path));
+            }
+            roff_paragraphs(&mut out, command.description);
+            for param in command.args.iter().chain(&command.options) {
+                roff_param(&mut out, param);
+            }
+            if !command.inherited.is_empty() {
+                let _ = writeln!(
+                    out,
+                    ".PP\nInherited group options: {}",
+                    roff_escape(&command.inherited.join(", "))
+                );
+            }
+        }
+
+        if !docs.groups.is_empty() {
+            out.push_str(".SH GLOBAL OPTIONS\n");
+            for group in &docs.groups {
+                let _ = writeln!(
+                    out,
+                    ".SS \"Options available for command group {}\"",
+                    roff_escape(&group.path)
+                );
+                for param in &group.options {
+                    roff_param(&mut out, param);
+                }
+            }
+        }
+
+        if !docs.aliases.is_empty() {
+            out.push_str(".SH ALIASES\n");
+            for (alias, command) in &docs.aliases {
+                let _ = writeln!(
+                    out,
+                    ".TP\n\\fB{}\\fR\nAlias for \\fB{}\\fR.",
+                    roff_escape(alias),
+                    roff_escape(command)
+                );
+            }
+        }
+
+        out
+    }
+
+    /// Generate a Markdown reference.
+    pub fn generate_markdown(&self) -> String {
+        let docs = self.collect();
+        let mut out = format!("# {}\n\n", markdown_escape(&self.name));
+
+        if let Some(description) = &self.description {
+            let _ = write!(out, "{}\n\n", markdown_paragraphs(description));
+        }
+
+        out.push_str("## Synopsis\n\n```\n");
+        for command in &docs.commands {
+            let _ = writeln!(out, "{}", command.synopsis);
+        }
+        out.push_str("```\n\n");
+
+        out.push_str("## Commands\n\n");
+        for command in &docs.commands {
+            let _ = write!(out, "### {}\n\n", markdown_code(&command.path));
+            let description = markdown_paragraphs(command.description);
+            if !description.is_empty() {
+                let _ = write!(out, "{description}\n\n");
+            }
+            if !command.args.is_empty() {
+                out.push_str("Arguments:\n\n");
+                for param in &command.args {
+                    markdown_param(&mut out, param);
+                }
+            }
+            if !command.options.is_empty() {
+                out.push_str("Options:\n\n");
+                for param in &command.options {
+                    markdown_param(&mut out, param);
+                }
+            }
+            if !command.inherited.is_empty() {
+                let inherited: Vec<String> = command
+                    .inherited
+                    .iter()
+                    .map(|name| markdown_code(name))
+                    .collect();
+                let _ = write!(out, "Inherited group options: {}\n\n", inherited.join(", "));
+            }
+        }
+
+        if !docs.groups.is_empty() {
+            out.push_str("## Global options\n\n");
+            for group in &docs.groups {
+                let _ = write!(
+                    out,
+                    "### Options available for command group {}\n\n",
+                    markdown_code(&group.path)
+                );
+                for param in &group.options {
+                    markdown_param(&mut out, param);
+                }
+            }
+        }
+
+        if !docs.aliases.is_empty() {
+            out.push_str("## Aliases\n\n");
+            for (alias, command) in &docs.aliases {
+                let _ = writeln!(
+                    out,
+                    "- {} is an alias for {}",
+                    markdown_code(alias),
+                    markdown_code(command)
+                );
+            }
+            out.push('\n');
+        }
+
+        // a single trailing newline
+        let len = out.trim_end().len();
+        out.truncate(len);
+        out.push('\n');
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{ApiHandler, ApiMethod, RpcEnvironment};
+
+    fn dummy_method(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, anyhow::Error> {
+        Ok(Value::Null)
+    }
+
+    const API_METHOD_LIST: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "List the things.",
+            &[
+                (
+                    "limit",
+                    true,
+                    &IntegerSchema::new("Maximum number of entries.")
+                        .minimum(1)
+                        .default(50)
+                        .schema(),
+                ),
+                (
+                    "store",
+                    false,
+                    &StringSchema::new("Datastore name.").schema(),
+                ),
+            ],
+        ),
+    );
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Remote {
+        remote: Option<String>,
+    }
+
+    impl ApiType for Remote {
+        const API_SCHEMA: Schema = ObjectSchema::new(
+            "Remote options.",
+            &[(
+                "remote",
+                true,
+                &StringSchema::new("The remote to query.").schema(),
+            )],
+        )
+        .schema();
+    }
+
+    fn cli() -> CommandLineInterface {
+        let list = CliCommand::new(&API_METHOD_LIST).arg_param(&["store"]);
+        CliCommandMap::new()
+            .insert(
+                "item",
+                CliCommandMap::new()
+                    .global_option(GlobalOptions::of::<Remote>())
+                    .insert("list", list),
+            )
+            .alias(&["ls"], &["item", "list"])
+            .into()
+    }
+
+    #[test]
+    fn test_man_page() {
+        let man = DocGenerator::new(&cli(), "my-tool")
+            .description("Manage things.\n\nLonger text.")
+            .version("1.0")
+            .generate_man();
+
+        assert_eq!(
+            man,
+            r#".TH "MY\-TOOL" "1" "" "my\-tool 1.0" "User Commands"
+.SH NAME
+my\-tool \- Manage things.
+.SH SYNOPSIS
+.nf
+my\-tool item list <store> [OPTIONS]
+.fi
+.SH DESCRIPTION
+Manage things.
+.sp
+Longer text.
+.SH COMMANDS
+.SS "my\-tool item list"
+List the things.
+.TP
+\fB<store>\fR \fI<string>\fR
+Datastore name.
+.TP
+\fB\-\-limit\fR \fI<integer> (1 \- N)\fR
+Maximum number of entries.
+.sp
+Default: \fB50\fR
+.PP
+Inherited group options: \-\-remote
+.SH GLOBAL OPTIONS
+.SS "Options available for command group my\-tool item"
+.TP
+\fB\-\-remote\fR \fI<string>\fR
+The remote to query.
+.SH ALIASES
+.TP
+\fBmy\-tool ls\fR
+Alias for \fBmy\-tool item list\fR.
+"#
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let markdown = DocGenerator::new(&cli(), "my-tool").generate_markdown();
+
+        assert_eq!(
+            markdown,
+            r#"# my-tool
+
+## Synopsis
+
+```
+my-tool item list <store> [OPTIONS]
+```
+
+## Commands
+
+### `my-tool item list`
+
+List the things.
+
+Arguments:
+
+- `<store>` `<string>`
+
+  Datastore name.
+
+Options:
+
+- `--limit` `<integer> (1 - N)` (default: `50`)
+
+  Maximum number of entries.
+
+Inherited group options: `--remote`
+
+## Global options
+
+### Options available for command group `my-tool item`
+
+- `--remote` `<string>`
+
+  The remote to query.
+
+## Aliases
+
+- `my-tool ls` is an alias for `my-tool item list`
+"#
+        );
+    }
+}
@@ -11,29 +11,78 @@ use proxmox_schema::format::{
 };
 use proxmox_schema::*;
 
-use super::{value_to_text, TableFormatOptions};
+use super::{
+    current_output_selection, value_to_csv, value_to_text, value_to_tsv, value_to_yaml,
+    TableFormatOptions,
+};
 use super::{CliCommand, CliCommandMap, CommandLineInterface, GlobalOptions};
 
 /// Helper function to format and print result.
 ///
-/// This is implemented for machine generatable formats 'json' and
-/// 'json-pretty'. The 'text' format needs to be handled somewhere
-/// else.
+/// This is implemented for machine generatable formats 'json',
+/// 'json-pretty' and 'yaml'. The 'text' format needs to be handled
+/// somewhere else and falls back to 'json-pretty'. The 'csv' and 'tsv'
+/// formats require a return schema (see [`format_and_print_result_full`])
+/// and fall back to 'json' with a note on stderr.
+///
+/// A field selection set up via [`OutputSelection`](super::OutputSelection) is applied first.
 pub fn format_and_print_result<T: Serialize>(result: &T, output_format: &str) {
-    if output_format == "json-pretty" {
-        println!("{}", serde_json::to_string_pretty(&result).unwrap());
-    } else if output_format == "json" {
-        println!("{}", serde_json::to_string(&result).unwrap());
-    } else {
-        unimplemented!();
+    let output_format = match output_format {
+        "json" | "json-pretty" | "yaml" => output_format,
+        "csv" | "tsv" => {
+            eprintln!(
+                "output format '{output_format}' is not supported by this command, using 'json'"
+            );
+            "json"
+        }
+        _ => "json-pretty",
+    };
+
+    let selection = current_output_selection();
+
+    // without a selection, serialize directly to keep the field order
+    if selection.is_none() {
+        match output_format {
+            "json-pretty" => {
+                println!("{}", serde_json::to_string_pretty(&result).unwrap());
+                return;
+            }
+            "json" => {
+                println!("{}", serde_json::to_string(&result).unwrap());
+                return;
+            }
+            _ => (),
+        }
+    }
+
+    let mut result = serde_json::to_value(result).unwrap();
+    if let Some(selection) = selection {
+        if let Err(err) = selection.apply(&mut result) {
+            eprintln!("unable to format result: {err}");
+            return;
+        }
+    }
+
+    match output_format {
+        "json-pretty" => println!("{}", serde_json::to_string_pretty(&result).unwrap()),
+        "json" => println!("{}", serde_json::to_string(&result).unwrap()),
+        _ => {
+            if let Err(err) = value_to_yaml(std::io::stdout(), &result) {
+                eprintln!("unable to format result: {err}");
+            }
+        }
     }
 }
 
 /// Helper function to format and print result.
 ///
-/// This is implemented for machine generatable formats 'json' and
-/// 'json-pretty', and for the 'text' format which generates nicely
-/// formatted tables with borders.
+/// This is implemented for machine generatable formats 'json',
+/// 'json-pretty', 'yaml', 'csv' and 'tsv', and for the 'text' format
+/// which generates nicely formatted tables with borders. The table
+/// formats take their columns from the return schema.
+///
+/// A field selection set up via [`OutputSelection`](super::OutputSelection) is applied first,
+/// its top level fields become the table columns.
 pub fn format_and_print_result_full(
     result: &mut Value,
     return_type: &ReturnType,
@@ -44,16 +93,49 @@ pub fn format_and_print_result_full(
         return;
     }
 
-    if output_format == "json-pretty" {
-        println!("{}", serde_json::to_string_pretty(&result).unwrap());
-    } else if output_format == "json" {
-        println!("{}", serde_json::to_string(&result).unwrap());
-    } else if output_format == "text" {
-        if let Err(err) = value_to_text(std::io::stdout(), result, return_type.schema, options) {
-            eprintln!("unable to format result: {}", err);
+    let selected_options;
+    let options = match current_output_selection() {
+        Some(selection) => {
+            let columns = match selection.apply(result).and_then(|()| selection.columns()) {
+                Ok(columns) => columns,
+                Err(err) => {
+                    eprintln!("unable to format result: {}", err);
+                    return;
+                }
+            };
+            match columns {
+                Some(columns) => {
+                    selected_options = options.select_columns(&columns);
+                    &selected_options
+                }
+                None => options,
+            }
         }
-    } else {
-        eprintln!("undefined output format '{}'", output_format);
+        None => options,
+    };
+
+    let stdout = std::io::stdout();
+    let formatted = match output_format {
+        "json-pretty" => {
+            println!("{}", serde_json::to_string_pretty(&result).unwrap());
+            Ok(())
+        }
+        "json" => {
+            println!("{}", serde_json::to_string(&result).unwrap());
+            Ok(())
+        }
+        "yaml" => value_to_yaml(stdout, result),
+        "csv" => value_to_csv(stdout, result, return_type.schema, options),
+        "tsv" => value_to_tsv(stdout, result, return_type.schema, options),
+        "text" => value_to_text(stdout, result, return_type.schema, options),
+        _ => {
+            eprintln!("undefined output format '{}'", output_format);
+            Ok(())
+        }
+    };
+
+    if let Err(err) = formatted {
+        eprintln!("unable to format result: {}", err);
     }
 }
 
@@ -8,7 +8,7 @@
 //! - Use declarative API schema to define the CLI
 //! - Automatic parameter verification
 //! - Automatically generate documentation and manual pages
-//! - Automatically generate bash completion helpers
+//! - Automatically generate bash completion helpers and zsh/fish completion scripts
 //! - Ability to create interactive commands (using ``rustyline``)
 //! - Supports complex/nested commands
 
@@ -37,8 +37,19 @@ pub use format::*;
 mod text_table;
 pub use text_table::*;
 
+mod selection;
+pub use selection::*;
+
+mod yaml;
+pub use yaml::*;
+
 mod completion;
 
+mod shell_completion;
+
+mod doc_gen;
+pub use doc_gen::*;
+
 mod completion_helpers;
 pub use completion_helpers::*;
 
@@ -0,0 +1,333 @@
+use std::cmp::Ordering;
+use std::sync::RwLock;
+
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use proxmox_schema::*;
+
+/// Generic field selection and filtering for command output.
+///
+/// Register it with [`GlobalOptions::of`](super::GlobalOptions::of) to provide the
+/// ``--output-fields`` and ``--filter`` options for all commands of a command line interface.
+/// The selection is applied to results printed with
+/// [`format_and_print_result`](super::format_and_print_result),
+/// [`format_and_print_result_full`](super::format_and_print_result_full) and to results returned
+/// by commands without their own output handling.
+///
+/// Paths separate nested fields with dots, arrays are traversed transparently, so
+/// ``--output-fields id,status.cpu`` selects the ``id`` and the ``cpu`` member of ``status`` of
+/// every listed entry.
+///
+/// Filters apply to the entries of lists and have the form ``<path><op><value>``, where ``op`` is
+/// one of ``=``, ``!=``, ``<``, ``<=``, ``>``, ``>=`` or ``~`` (contains). Values compare
+/// numerically if both sides are numbers. An entry is only kept if it matches all filters.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutputSelection {
+    /// Comma separated list of field paths to output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_fields: Option<String>,
+    /// Filter expressions for list entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Vec<String>>,
+}
+
+impl ApiType for OutputSelection {
+    const API_SCHEMA: Schema = ObjectSchema::new(
+        "Output field selection and filtering.",
+        &[
+            (
+                "filter",
+                true,
+                &ArraySchema::new("List of filters.", &OUTPUT_FILTER).schema(),
+            ),
+            ("output-fields", true, &OUTPUT_FIELDS),
+        ],
+    )
+    .schema();
+}
+
+/// Schema definition for ``--output-fields`` parameter.
+pub const OUTPUT_FIELDS: Schema =
+    StringSchema::new("Comma separated list of (dot separated) field paths to output.").schema();
+
+/// Schema definition for a single ``--filter`` expression.
+pub const OUTPUT_FILTER: Schema = StringSchema::new(
+    "Only output list entries matching '<path><op><value>', \
+     where op is one of '=', '!=', '<', '<=', '>', '>=' or '~' (contains).",
+)
+.schema();
+
+static CURRENT_SELECTION: RwLock<Option<OutputSelection>> = RwLock::new(None);
+
+pub(crate) fn set_output_selection(selection: Option<OutputSelection>) {
+    *CURRENT_SELECTION.write().unwrap() = selection;
+}
+
+pub(crate) fn current_output_selection() -> Option<OutputSelection> {
+    CURRENT_SELECTION.read().unwrap().clone()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+struct Filter<'a> {
+    path: Vec<&'a str>,
+    op: FilterOp,
+    value: &'a str,
+}
+
+impl<'a> Filter<'a> {
+    fn parse(expr: &'a str) -> Result<Self, Error> {
+        // two character operators first, so that '<=' is not taken for '<'
+        const OPS: &[(&str, FilterOp)] = &[
+            ("!=", FilterOp::Ne),
+            ("<=", FilterOp::Le),
+            (">=", FilterOp::Ge),
+            ("=", FilterOp::Eq),
+            ("<", FilterOp::Lt),
+            (">", FilterOp::Gt),
+            ("~", FilterOp::Contains),
+        ];
+
+        let Some((pos, len, op)) = OPS
+            .iter()
+            .filter_map(|(token, op)| expr.find(token).map(|pos| (pos, token.len(), *op)))
+            .min_by_key(|(pos, len, _)| (*pos, usize::MAX - len))
+        else {
+            bail!("invalid filter '{expr}' - missing operator");
+        };
+
+        let path = parse_path(expr[..pos].trim())?;
+        Ok(Self {
+            path,
+            op,
+            value: expr[pos + len..].trim(),
+        })
+    }
+
+    fn matches(&self, entry: &Value) -> bool {
+        let Some(data) = lookup(entry, &self.path) else {
+            return self.op == FilterOp::Ne;
+        };
+
+        let ordering = match data {
+            Value::Null => return self.op == FilterOp::Ne,
+            Value::Number(number) => match (number.as_f64(), self.value.parse::<f64>()) {
+                (Some(a), Ok(b)) => a.partial_cmp(&b),
+                _ => None,
+            },
+            Value::Bool(value) => match self.value {
+                "1" | "true" => Some(value.cmp(&true)),
+                "0" | "false" => Some(value.cmp(&false)),
+                _ => None,
+            },
+            Value::String(text) => {
+                if self.op == FilterOp::Contains {
+                    return text.contains(self.value);
+                }
+                Some(text.as_str().cmp(self.value))
+            }
+            Value::Array(_) | Value::Object(_) => None,
+        };
+
+        match (self.op, ordering) {
+            (FilterOp::Contains, _) => data.to_string().contains(self.value),
+            (FilterOp::Ne, None) => true,
+            (_, None) => false,
+            (FilterOp::Eq, Some(ord)) => ord == Ordering::Equal,
+            (FilterOp::Ne, Some(ord)) => ord != Ordering::Equal,
+            (FilterOp::Lt, Some(ord)) => ord == Ordering::Less,
+            (FilterOp::Le, Some(ord)) => ord != Ordering::Greater,
+            (FilterOp::Gt, Some(ord)) => ord == Ordering::Greater,
+            (FilterOp::Ge, Some(ord)) => ord != Ordering::Less,
+        }
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<&str>, Error> {
+    let path: Vec<&str> = path.split('.').map(str::trim).collect();
+    if path.iter().any(|part| part.is_empty()) {
+        bail!("invalid field path '{}'", path.join("."));
+    }
+    Ok(path)
+}
+
+fn lookup<'v>(mut value: &'v Value, path: &[&str]) -> Option<&'v Value> {
+    for part in path {
+        value = value.as_object()?.get(*part)?;
+    }
+    Some(value)
+}
+
+/// Keep only the members of `value` selected by `paths`. Lists are handled entry by entry.
+fn select_fields(value: &Value, paths: &[&[&str]]) -> Value {
+    match value {
+        Value::Array(list) => Value::Array(
+            list.iter()
+                .map(|entry| select_fields(entry, paths))
+                .collect(),
+        ),
+        Value::Object(map) => {
+            let mut result = Map::new();
+            for (i, path) in paths.iter().enumerate() {
+                let name = path[0];
+                if result.contains_key(name) {
+                    continue;
+                }
+                let Some(member) = map.get(name) else {
+                    continue;
+                };
+
+                // gather all paths below this member
+                let subpaths: Vec<&[&str]> = paths[i..]
+                    .iter()
+                    .filter(|other| other[0] == name)
+                    .map(|other| &other[1..])
+                    .collect();
+
+                if subpaths.iter().any(|subpath| subpath.is_empty()) {
+                    result.insert(name.to_string(), member.clone());
+                } else if member.is_object() || member.is_array() {
+                    result.insert(name.to_string(), select_fields(member, &subpaths));
+                }
+            }
+            Value::Object(result)
+        }
+        other => other.clone(),
+    }
+}
+
+impl OutputSelection {
+    /// Returns `true` if neither fields nor filters are set.
+    pub fn is_empty(&self) -> bool {
+        self.output_fields.is_none() && self.filter.as_ref().is_none_or(|list| list.is_empty())
+    }
+
+    /// The top level fields in the order they were selected, used as table columns.
+    pub fn columns(&self) -> Result<Option<Vec<String>>, Error> {
+        let Some(fields) = &self.output_fields else {
+            return Ok(None);
+        };
+
+        let mut columns = Vec::new();
+        for field in fields.split(',') {
+            let name = parse_path(field)?[0].to_string();
+            if !columns.contains(&name) {
+                columns.push(name);
+            }
+        }
+        Ok(Some(columns))
+    }
+
+    /// Filter list entries and reduce `data` to the selected fields.
+    pub fn apply(&self, data: &mut Value) -> Result<(), Error> {
+        if let Some(filters) = &self.filter {
+            let filters = filters
+                .iter()
+                .map(|expr| Filter::parse(expr))
+                .collect::<Result<Vec<_>, Error>>()?;
+            if let Value::Array(list) = data {
+                list.retain(|entry| filters.iter().all(|filter| filter.matches(entry)));
+            }
+        }
+
+        if let Some(fields) = &self.output_fields {
+            let paths = fields
+                .split(',')
+                .map(parse_path)
+                .collect::<Result<Vec<_>, Error>>()?;
+            let paths: Vec<&[&str]> = paths.iter().map(|path| path.as_slice()).collect();
+            *data = select_fields(data, &paths);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn selection(fields: Option<&str>, filter: &[&str]) -> OutputSelection {
+        OutputSelection {
+            output_fields: fields.map(str::to_string),
+            filter: Some(filter.iter().map(|f| f.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_output_fields() {
+        let mut data = json!([
+            { "id": "a", "status": { "cpu": 0.5, "mem": 10 }, "tags": ["x"] },
+            { "id": "b", "status": { "cpu": 0.1, "mem": 20 } },
+        ]);
+
+        let fields = selection(Some("status.cpu,id"), &[]);
+        assert_eq!(
+            fields.columns().unwrap(),
+            Some(vec!["status".to_string(), "id".to_string()])
+        );
+        fields.apply(&mut data).unwrap();
+        assert_eq!(
+            data,
+            json!([
+                { "id": "a", "status": { "cpu": 0.5 } },
+                { "id": "b", "status": { "cpu": 0.1 } },
+            ])
+        );
+
+        let mut data = json!({ "id": "a", "status": { "cpu": 0.5 } });
+        OutputSelection {
+            output_fields: Some("status,status.cpu,id.foo".to_string()),
+            filter: None,
+        }
+        .apply(&mut data)
+        .unwrap();
+        assert_eq!(data, json!({ "status": { "cpu": 0.5 } }));
+
+        assert!(selection(Some("id,,x"), &[]).apply(&mut data).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let data = json!([
+            { "id": "vm-100", "cpu": 2, "running": true, "status": { "mem": 10 } },
+            { "id": "vm-101", "cpu": 8, "running": false, "status": { "mem": 20 } },
+            { "id": "ct-102", "cpu": 10, "running": true },
+        ]);
+
+        let ids = |filter: &[&str]| {
+            let mut data = data.clone();
+            selection(None, filter).apply(&mut data).unwrap();
+            data.as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry["id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(&["id=vm-100"]), ["vm-100"]);
+        assert_eq!(ids(&["id != vm-100"]), ["vm-101", "ct-102"]);
+        assert_eq!(ids(&["cpu>=8"]), ["vm-101", "ct-102"]);
+        assert_eq!(ids(&["cpu<8"]), ["vm-100"]);
+        assert_eq!(ids(&["id~vm", "running=1"]), ["vm-100"]);
+        assert_eq!(ids(&["status.mem>10"]), ["vm-101"]);
+        assert_eq!(ids(&["status.mem!=10"]), ["vm-101", "ct-102"]);
+
+        let mut data = data.clone();
+        assert!(selection(None, &["cpu"]).apply(&mut data).is_err());
+    }
+}
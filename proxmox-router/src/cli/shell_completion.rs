@@ -0,0 +1,617 @@
+//! Completion script generators for zsh and fish.
+//!
+//! The scripts are derived from the command tree and parameter schemas. Values of parameters with
+//! a completion function are queried from the binary itself via the ``bashcomplete`` protocol.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use proxmox_schema::*;
+
+use super::{CliCommand, CliCommandMap, CommandLineInterface, CompletionFunction};
+use crate::format::schema_description;
+
+/// How values of a parameter get completed.
+enum ValueKind {
+    /// A boolean flag, the value is optional.
+    Flag,
+    /// One of the listed values.
+    Enum(&'static [EnumEntry]),
+    /// Ask the binary via its completion functions.
+    Dynamic,
+    /// No completion available.
+    Plain,
+}
+
+struct ParamInfo {
+    name: &'static str,
+    description: String,
+    optional: bool,
+    multiple: bool,
+    kind: ValueKind,
+}
+
+/// The first line of a description, without a trailing dot.
+fn short_description(description: &str) -> String {
+    let line = description.lines().next().unwrap_or_default().trim();
+    line.strip_suffix('.').unwrap_or(line).to_string()
+}
+
+fn param_info(name: &'static str, optional: bool, schema: &Schema, dynamic: bool) -> ParamInfo {
+    let (item_schema, multiple) = match schema {
+        Schema::Array(array) => (array.items, true),
+        schema => (schema, false),
+    };
+
+    let kind = match item_schema {
+        _ if dynamic => ValueKind::Dynamic,
+        Schema::Boolean(_) if !multiple => ValueKind::Flag,
+        Schema::String(StringSchema {
+            format: Some(ApiStringFormat::Enum(entries)),
+            ..
+        }) => ValueKind::Enum(entries),
+        _ => ValueKind::Plain,
+    };
+
+    ParamInfo {
+        name,
+        description: short_description(schema_description(schema)),
+        optional,
+        multiple,
+        kind,
+    }
+}
+
+/// Options of the global option types of a command map.
+fn global_options(map: &CliCommandMap) -> Vec<ParamInfo> {
+    let mut options = Vec::new();
+    for entry in map.global_options.values() {
+        for (name, schema) in entry.properties() {
+            let dynamic = entry.completion_functions.contains_key(name);
+            options.push(param_info(name, true, schema, dynamic));
+        }
+    }
+    options.sort_by_key(|option| option.name);
+    options
+}
+
+/// The `--option` and positional parameters of a command.
+fn command_params(
+    cli_cmd: &CliCommand,
+    global_completions: &HashMap<&'static str, CompletionFunction>,
+    dynamic_args: bool,
+) -> (Vec<ParamInfo>, Vec<ParamInfo>) {
+    let is_dynamic = |name: &str| {
+        cli_cmd.completion_functions.contains_key(name) || global_completions.contains_key(name)
+    };
+
+    let mut options = Vec::new();
+    for (name, optional, schema) in cli_cmd.info.parameters.properties() {
+        if cli_cmd.arg_param.contains(name) || cli_cmd.fixed_param.contains_key(name) {
+            continue;
+        }
+        options.push(param_info(name, *optional, schema, is_dynamic(name)));
+    }
+
+    let mut args = Vec::new();
+    for name in cli_cmd.arg_param {
+        if let Some((optional, schema)) = cli_cmd.info.parameters.lookup(name) {
+            let mut info = param_info(name, optional, schema, dynamic_args || is_dynamic(name));
+            if let ValueKind::Flag = info.kind {
+                info.kind = ValueKind::Plain;
+            }
+            args.push(info);
+        }
+    }
+
+    (options, args)
+}
+
+fn sorted_commands(map: &CliCommandMap) -> Vec<(&String, &CommandLineInterface)> {
+    let mut commands: Vec<_> = map.commands.iter().collect();
+    commands.sort_by_key(|(name, _)| *name);
+    commands
+}
+
+fn command_description(cli: &CommandLineInterface) -> String {
+    match cli {
+        CommandLineInterface::Simple(cli_cmd) => {
+            short_description(cli_cmd.info.parameters.description())
+        }
+        CommandLineInterface::Nested(_) => String::new(),
+    }
+}
+
+/// Completion functions of global options defined on the way to a command.
+fn collect_global_completions(
+    map: &CliCommandMap,
+    completions: &mut HashMap<&'static str, CompletionFunction>,
+) {
+    for entry in map.global_options.values() {
+        for (name, _schema) in entry.properties() {
+            if let Some(cb) = entry.completion_functions.get(name) {
+                completions.insert(name, *cb);
+            }
+        }
+    }
+}
+
+fn function_name(prefix: &str, prog: &str, path: &[&str]) -> String {
+    let mut name = format!("{prefix}{prog}");
+    for part in path {
+        name.push('_');
+        name.push_str(part);
+    }
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Quote a string for the shell, `'` is written as `'\''`.
+fn zsh_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+fn zsh_value_action(kind: &ValueKind, dynamic_fn: &str) -> String {
+    match kind {
+        ValueKind::Flag => "(0 1)".to_string(),
+        ValueKind::Dynamic => dynamic_fn.to_string(),
+        ValueKind::Plain => String::new(),
+        ValueKind::Enum(entries) => {
+            let values: Vec<String> = entries
+                .iter()
+                .map(|entry| {
+                    let description = short_description(entry.description);
+                    let description =
+                        escape_chars(&description, &['\\', '"', '$', '`']).replace(':', r"\:");
+                    format!("{}\\:\"{description}\"", entry.value.replace(':', r"\:"))
+                })
+                .collect();
+            format!("(({}))", values.join(" "))
+        }
+    }
+}
+
+/// Prefix each of `chars` in `text` with a backslash.
+fn escape_chars(text: &str, chars: &[char]) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if chars.contains(&c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn zsh_option_spec(option: &ParamInfo, dynamic_fn: &str) -> String {
+    let description = escape_chars(&option.description, &['\\', '[', ']']);
+    let repeat = if option.multiple { "*" } else { "" };
+    let action = zsh_value_action(&option.kind, dynamic_fn);
+    let separator = match option.kind {
+        ValueKind::Flag => "::",
+        _ => ":",
+    };
+    format!(
+        "{repeat}--{}[{description}]{separator}{}:{action}",
+        option.name, option.name
+    )
+}
+
+fn zsh_arg_spec(position: usize, arg: &ParamInfo, dynamic_fn: &str) -> String {
+    let action = zsh_value_action(&arg.kind, dynamic_fn);
+    if arg.multiple {
+        format!("*:{}:{action}", arg.name)
+    } else if arg.optional {
+        format!("{position}::{}:{action}", arg.name)
+    } else {
+        format!("{position}:{}:{action}", arg.name)
+    }
+}
+
+fn write_zsh_arguments(out: &mut String, specs: &[String], extra: &[&str]) {
+    if specs.is_empty() && extra.is_empty() {
+        out.push_str("    return 1\n");
+        return;
+    }
+
+    // with states, `-C` updates the (local) `curcontext`
+    out.push_str(if extra.is_empty() {
+        "    _arguments"
+    } else {
+        "    _arguments -C"
+    });
+    for spec in specs {
+        let _ = write!(out, " \\\n        {}", zsh_quote(spec));
+    }
+    for spec in extra {
+        let _ = write!(out, " \\\n        {spec}");
+    }
+    out.push('\n');
+}
+
+struct ZshGenerator<'a> {
+    prog: &'a str,
+    dynamic_fn: String,
+    functions: Vec<String>,
+}
+
+impl ZshGenerator<'_> {
+    fn generate<'c>(
+        &mut self,
+        cli: &'c CommandLineInterface,
+        path: &mut Vec<&'c str>,
+        globals: &[String],
+        global_completions: &HashMap<&'static str, CompletionFunction>,
+    ) {
+        let name = function_name("_", self.prog, path);
+        let mut out = format!("{name}() {{\n");
+
+        match cli {
+            CommandLineInterface::Simple(cli_cmd) => {
+                let dynamic_args = path.last() == Some(&"help");
+                let (options, args) = command_params(cli_cmd, global_completions, dynamic_args);
+
+                let mut specs = globals.to_vec();
+                specs.extend(
+                    options
+                        .iter()
+                        .map(|option| zsh_option_spec(option, &self.dynamic_fn)),
+                );
+                for (i, arg) in args.iter().enumerate() {
+                    specs.push(zsh_arg_spec(i + 1, arg, &self.dynamic_fn));
+                }
+                write_zsh_arguments(&mut out, &specs, &[]);
+            }
+            CommandLineInterface::Nested(map) => {
+                let mut global_completions = global_completions.clone();
+                collect_global_completions(map, &mut global_completions);
+
+                let mut globals = globals.to_vec();
+                globals.extend(
+                    global_options(map)
+                        .iter()
+                        .map(|option| zsh_option_spec(option, &self.dynamic_fn)),
+                );
+
+                out.push_str("    local curcontext=\"$curcontext\" state line\n");
+                write_zsh_arguments(
+                    &mut out,
+                    &globals,
+                    &["'1: :->command'", "'*:: :->argument'"],
+                );
+                out.push_str("\n    case $state in\n");
+                out.push_str("        command)\n");
+                out.push_str("            local -a commands\n");
+                out.push_str("            commands=(\n");
+                let commands = sorted_commands(map);
+                for (command, sub_cli) in &commands {
+                    let description = command_description(sub_cli);
+                    let entry = if description.is_empty() {
+                        command.replace(':', r"\:")
+                    } else {
+                        format!("{}:{description}", command.replace(':', r"\:"))
+                    };
+                    let _ = writeln!(out, "                {}", zsh_quote(&entry));
+                }
+                out.push_str("            )\n");
+                out.push_str("            _describe -t commands 'command' commands\n");
+                out.push_str("            ;;\n");
+                out.push_str("        argument)\n");
+                out.push_str("            case $line[1] in\n");
+                for (command, sub_cli) in commands {
+                    path.push(command);
+                    let _ = writeln!(
+                        out,
+                        "                ({}) {} ;;",
+                        zsh_quote(command),
+                        function_name("_", self.prog, path)
+                    );
+                    self.generate(sub_cli, path, &globals, &global_completions);
+                    path.pop();
+                }
+                out.push_str("            esac\n");
+                out.push_str("            ;;\n");
+                out.push_str("    esac\n");
+            }
+        }
+
+        out.push_str("}\n");
+        self.functions.push(out);
+    }
+}
+
+/// Quote a string for fish, only `\` and `'` need escaping in single quotes.
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", escape_chars(text, &['\\', '\'']))
+}
+
+fn fish_values(kind: &ValueKind, dynamic_fn: &str) -> Option<String> {
+    match kind {
+        ValueKind::Flag | ValueKind::Plain => None,
+        ValueKind::Dynamic => Some(format!("({dynamic_fn})")),
+        ValueKind::Enum(entries) => {
+            let mut printf = String::from(r#"(printf "%s\t%s\n""#);
+            for entry in entries.iter() {
+                let description = short_description(entry.description);
+                let _ = write!(
+                    printf,
+                    r#" "{}" "{}""#,
+                    escape_chars(entry.value, &['\\', '"', '$']),
+                    escape_chars(&description, &['\\', '"', '$'])
+                );
+            }
+            printf.push(')');
+            Some(printf)
+        }
+    }
+}
+
+struct FishGenerator<'a> {
+    prog: &'a str,
+    dynamic_fn: String,
+    using_fn: String,
+    lines: Vec<String>,
+}
+
+impl FishGenerator<'_> {
+    fn condition(&self, path: &[&str], exclude: &[&String]) -> Option<String> {
+        let mut conditions = Vec::new();
+        if !path.is_empty() {
+            conditions.push(format!("{} {}", self.using_fn, path.join(" ")));
+        }
+        if !exclude.is_empty() {
+            let names: Vec<&str> = exclude.iter().map(|name| name.as_str()).collect();
+            conditions.push(format!(
+                "not __fish_seen_subcommand_from {}",
+                names.join(" ")
+            ));
+        }
+        (!conditions.is_empty()).then(|| conditions.join("; and "))
+    }
+
+    fn complete_line(&self, condition: &Option<String>) -> String {
+        let mut line = format!("complete -c {}", self.prog);
+        if let Some(condition) = condition {
+            let _ = write!(line, " -n {}", fish_quote(condition));
+        }
+        line
+    }
+
+    fn add_option(&mut self, condition: &Option<String>, option: &ParamInfo) {
+        let mut line = self.complete_line(condition);
+        let _ = write!(line, " -l {}", option.name);
+        if !option.description.is_empty() {
+            let _ = write!(line, " -d {}", fish_quote(&option.description));
+        }
+        if !matches!(option.kind, ValueKind::Flag) {
+            line.push_str(" -x");
+            if let Some(values) = fish_values(&option.kind, &self.dynamic_fn) {
+                let _ = write!(line, " -a {}", fish_quote(&values));
+            }
+        }
+        self.lines.push(line);
+    }
+
+    fn generate<'c>(
+        &mut self,
+        cli: &'c CommandLineInterface,
+        path: &mut Vec<&'c str>,
+        global_completions: &HashMap<&'static str, CompletionFunction>,
+    ) {
+        match cli {
+            CommandLineInterface::Simple(cli_cmd) => {
+                let dynamic_args = path.last() == Some(&"help");
+                let (options, args) = command_params(cli_cmd, global_completions, dynamic_args);
+                let condition = self.condition(path, &[]);
+                for option in &options {
+                    self.add_option(&condition, option);
+                }
+                for arg in &args {
+                    if let Some(values) = fish_values(&arg.kind, &self.dynamic_fn) {
+                        let line = self.complete_line(&condition);
+                        self.lines
+                            .push(format!("{line} -a {}", fish_quote(&values)));
+                    }
+                }
+            }
+            CommandLineInterface::Nested(map) => {
+                let mut global_completions = global_completions.clone();
+                collect_global_completions(map, &mut global_completions);
+
+                let condition = self.condition(path, &[]);
+                for option in global_options(map) {
+                    self.add_option(&condition, &option);
+                }
+
+                let commands = sorted_commands(map);
+                let names: Vec<&String> = commands.iter().map(|(name, _)| *name).collect();
+                let condition = self.condition(path, &names);
+                for (command, sub_cli) in &commands {
+                    let mut line = self.complete_line(&condition);
+                    let _ = write!(line, " -a {}", fish_quote(command));
+                    let description = command_description(sub_cli);
+                    if !description.is_empty() {
+                        let _ = write!(line, " -d {}", fish_quote(&description));
+                    }
+                    self.lines.push(line);
+                }
+
+                for (command, sub_cli) in commands {
+                    path.push(command);
+                    self.generate(sub_cli, path, &global_completions);
+                    path.pop();
+                }
+            }
+        }
+    }
+}
+
+impl CommandLineInterface {
+    /// Generate a zsh completion script for the program `prog`.
+    ///
+    /// Parameters with a completion function are completed by calling ``prog bashcomplete``.
+    pub fn generate_zsh_completion(&self, prog: &str) -> String {
+        let mut generator = ZshGenerator {
+            prog,
+            dynamic_fn: function_name("__", prog, &["dynamic"]),
+            functions: Vec::new(),
+        };
+        generator.generate(self, &mut Vec::new(), &[], &HashMap::new());
+
+        let mut out = format!("#compdef {prog}\n\n");
+        let _ = writeln!(
+            out,
+            "{}() {{\n    local -a values\n    \
+             values=(${{(f)\"$(COMP_LINE=\"$LBUFFER\" COMP_POINT=${{#LBUFFER}} {} bashcomplete 2>/dev/null)\"}})\n    \
+             compadd -a values\n}}\n",
+            generator.dynamic_fn,
+            zsh_quote(prog),
+        );
+        // the main function comes last, print sub command functions first
+        for function in generator.functions {
+            out.push_str(&function);
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{} \"$@\"", function_name("_", prog, &[]));
+        out
+    }
+
+    /// Generate a fish completion script for the program `prog`.
+    ///
+    /// Parameters with a completion function are completed by calling ``prog bashcomplete``.
+    pub fn generate_fish_completion(&self, prog: &str) -> String {
+        let mut generator = FishGenerator {
+            prog,
+            dynamic_fn: function_name("__fish_", prog, &["dynamic"]),
+            using_fn: function_name("__fish_", prog, &["using"]),
+            lines: Vec::new(),
+        };
+        generator.generate(self, &mut Vec::new(), &HashMap::new());
+
+        let mut out = format!("# fish completion for {prog}\n\n");
+        let _ = writeln!(
+            out,
+            "function {}\n    for cmd in $argv\n        \
+             __fish_seen_subcommand_from $cmd; or return 1\n    end\nend\n",
+            generator.using_fn,
+        );
+        let _ = writeln!(
+            out,
+            "function {}\n    set -lx COMP_LINE (commandline -cp)\n    \
+             set -lx COMP_POINT (string length -- \"$COMP_LINE\")\n    \
+             {} bashcomplete 2>/dev/null\nend\n",
+            generator.dynamic_fn,
+            fish_quote(prog),
+        );
+        let _ = writeln!(out, "complete -c {prog} -f");
+        for line in generator.lines {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{ApiHandler, ApiMethod, RpcEnvironment};
+
+    fn dummy_method(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, anyhow::Error> {
+        Ok(Value::Null)
+    }
+
+    const API_METHOD_LIST: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "List the things.",
+            &[
+                (
+                    "format",
+                    true,
+                    &StringSchema::new("Output format.")
+                        .format(&ApiStringFormat::Enum(&[
+                            EnumEntry::new("json", "JSON: \"machine\" output"),
+                            EnumEntry::new("text", "plain text"),
+                        ]))
+                        .schema(),
+                ),
+                (
+                    "store",
+                    false,
+                    &StringSchema::new("Datastore name.").schema(),
+                ),
+                (
+                    "verbose",
+                    true,
+                    &BooleanSchema::new("Verbose [debug] output.").schema(),
+                ),
+            ],
+        ),
+    );
+
+    fn complete_store(_arg: &str, _param: &HashMap<String, String>) -> Vec<String> {
+        vec!["store1".to_string()]
+    }
+
+    fn cli() -> CommandLineInterface {
+        let list = CliCommand::new(&API_METHOD_LIST)
+            .arg_param(&["store"])
+            .completion_cb("store", complete_store);
+        CliCommandMap::new()
+            .insert("item", CliCommandMap::new().insert("list", list))
+            .insert_help()
+            .into()
+    }
+
+    #[test]
+    fn test_zsh_completion() {
+        let script = cli().generate_zsh_completion("my-tool");
+        assert!(script.starts_with("#compdef my-tool\n"));
+        assert!(script.contains("__my_tool_dynamic() {"));
+        assert!(script.contains(
+            "'--format[Output format]:format:((json\\:\"JSON\\: \\\"machine\\\" output\" \
+             text\\:\"plain text\"))'"
+        ));
+        assert!(script.contains("'--verbose[Verbose \\[debug\\] output]::verbose:(0 1)'"));
+        assert!(script.contains("'1:store:__my_tool_dynamic'"));
+        assert!(script.contains("                ('item') _my_tool_item ;;\n"));
+        assert!(script.contains("                'list:List the things'\n"));
+        assert!(script.contains("_my_tool_item_list() {"));
+        assert!(script.ends_with("_my_tool \"$@\"\n"));
+    }
+
+    #[test]
+    fn test_fish_completion() {
+        let script = cli().generate_fish_completion("my-tool");
+        assert!(script.contains(
+            "complete -c my-tool -n 'not __fish_seen_subcommand_from help item' -a 'item'\n"
+        ));
+        assert!(script.contains(
+            "complete -c my-tool -n '__fish_my_tool_using item; and not \
+             __fish_seen_subcommand_from list' -a 'list' -d 'List the things'\n"
+        ));
+        assert!(script.contains(
+            "complete -c my-tool -n '__fish_my_tool_using item list' -l format \
+             -d 'Output format' -x -a '(printf \"%s\\\\t%s\\\\n\" \"json\" \
+             \"JSON: \\\\\"machine\\\\\" output\" \"text\" \"plain text\")'\n"
+        ));
+        assert!(script.contains(
+            "complete -c my-tool -n '__fish_my_tool_using item list' -l verbose \
+             -d 'Verbose [debug] output'\n"
+        ));
+        assert!(script.contains(
+            "complete -c my-tool -n '__fish_my_tool_using item list' -a '(__fish_my_tool_dynamic)'\n"
+        ));
+        assert!(script.contains(
+            "complete -c my-tool -n '__fish_my_tool_using help' -a '(__fish_my_tool_dynamic)'\n"
+        ));
+    }
+}
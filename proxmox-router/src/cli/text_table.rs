@@ -317,6 +317,46 @@ impl TableFormatOptions {
         self
     }
 
+    /// Copy of these options printing only the columns in `names`, in that order.
+    ///
+    /// Column configurations for these columns are kept, sort keys referring to other columns
+    /// are dropped.
+    pub(crate) fn select_columns(&self, names: &[String]) -> Self {
+        let column_config = names
+            .iter()
+            .map(
+                |name| match self.column_config.iter().find(|c| c.name == *name) {
+                    Some(config) => ColumnConfig {
+                        name: name.clone(),
+                        header: config.header.clone(),
+                        right_align: config.right_align,
+                        renderer: config.renderer,
+                    },
+                    None => ColumnConfig::new(name),
+                },
+            )
+            .collect();
+
+        let sortkeys = self.sortkeys.as_ref().and_then(|keys| {
+            let keys: Vec<_> = keys
+                .iter()
+                .filter(|(key, _)| names.contains(key))
+                .cloned()
+                .collect();
+            // fall back to sorting by the leftmost column if all sort keys were removed
+            (!keys.is_empty() || self.sortkeys == Some(Vec::new())).then_some(keys)
+        });
+
+        Self {
+            sortkeys,
+            noborder: self.noborder,
+            noheader: self.noheader,
+            columns: self.columns,
+            ascii_delimiters: self.ascii_delimiters,
+            column_config,
+        }
+    }
+
     fn lookup_column_info(
         &self,
         column_name: &str,
@@ -352,27 +392,13 @@ struct TableColumn {
     right_align: bool,
 }
 
-fn format_table<W: Write>(
-    output: W,
+/// Sort table rows by `options.sortkeys`, or by the first column if not set.
+fn sort_table(
     list: &mut [Value],
     schema: &dyn ObjectSchemaType,
     options: &TableFormatOptions,
+    properties_to_print: &[String],
 ) -> Result<(), Error> {
-    let properties_to_print = if options.column_config.is_empty() {
-        extract_properties_to_print(schema.properties())
-    } else {
-        options
-            .column_config
-            .iter()
-            .map(|v| v.name.clone())
-            .collect()
-    };
-
-    let column_count = properties_to_print.len();
-    if column_count == 0 {
-        return Ok(());
-    };
-
     let sortkeys = if let Some(ref sortkeys) = options.sortkeys {
         sortkeys.clone()
     } else {
@@ -435,6 +461,32 @@ fn format_table<W: Write>(
         Ordering::Equal
     });
 
+    Ok(())
+}
+
+fn format_table<W: Write>(
+    output: W,
+    list: &mut [Value],
+    schema: &dyn ObjectSchemaType,
+    options: &TableFormatOptions,
+) -> Result<(), Error> {
+    let properties_to_print = if options.column_config.is_empty() {
+        extract_properties_to_print(schema.properties())
+    } else {
+        options
+            .column_config
+            .iter()
+            .map(|v| v.name.clone())
+            .collect()
+    };
+
+    let column_count = properties_to_print.len();
+    if column_count == 0 {
+        return Ok(());
+    };
+
+    sort_table(list, schema, options, &properties_to_print)?;
+
     let mut tabledata: Vec<TableColumn> = Vec::new();
 
     let mut column_names = Vec::new();
@@ -847,3 +899,204 @@ pub fn value_to_text<W: Write>(
     }
     Ok(())
 }
+
+#[derive(Clone, Copy)]
+enum Delimiter {
+    Comma,
+    Tab,
+}
+
+impl Delimiter {
+    fn write_field<W: Write>(self, output: &mut W, text: &str) -> Result<(), Error> {
+        match self {
+            Delimiter::Comma => {
+                if text.contains([',', '"', '\n', '\r']) {
+                    write!(output, "\"{}\"", text.replace('"', "\"\""))?;
+                } else {
+                    output.write_all(text.as_bytes())?;
+                }
+            }
+            Delimiter::Tab => {
+                for c in text.chars() {
+                    match c {
+                        '\\' => output.write_all(b"\\\\")?,
+                        '\t' => output.write_all(b"\\t")?,
+                        '\n' => output.write_all(b"\\n")?,
+                        '\r' => output.write_all(b"\\r")?,
+                        c => write!(output, "{c}")?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_record<W, I, S>(self, output: &mut W, fields: I) -> Result<(), Error>
+    where
+        W: Write,
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                output.write_all(match self {
+                    Delimiter::Comma => b",",
+                    Delimiter::Tab => b"\t",
+                })?;
+            }
+            self.write_field(output, field.as_ref())?;
+        }
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+fn format_separated_rows<W: Write>(
+    output: &mut W,
+    rows: &[Value],
+    schema: &dyn ObjectSchemaType,
+    options: &TableFormatOptions,
+    properties_to_print: &[String],
+    delimiter: Delimiter,
+) -> Result<(), Error> {
+    if properties_to_print.is_empty() {
+        return Ok(());
+    }
+
+    let mut prop_schemas = Vec::new();
+    for name in properties_to_print {
+        match schema.lookup(name) {
+            Some((_optional, prop_schema)) => prop_schemas.push(prop_schema),
+            None => bail!("property {} does not exist in schema.", name),
+        }
+    }
+
+    if !options.noheader {
+        delimiter.write_record(output, properties_to_print)?;
+    }
+
+    for entry in rows {
+        let mut fields = Vec::with_capacity(properties_to_print.len());
+        for (name, prop_schema) in properties_to_print.iter().zip(&prop_schemas) {
+            match data_to_text(&entry[name], prop_schema) {
+                Ok(text) => fields.push(text),
+                Err(err) => bail!("unable to format property {} - {}", name, err),
+            }
+        }
+        delimiter.write_record(output, fields)?;
+    }
+
+    Ok(())
+}
+
+fn value_to_separated<W: Write>(
+    mut output: W,
+    data: &mut Value,
+    schema: &Schema,
+    options: &TableFormatOptions,
+    delimiter: Delimiter,
+) -> Result<(), Error> {
+    let configured_columns = || -> Option<Vec<String>> {
+        (!options.column_config.is_empty()).then(|| {
+            options
+                .column_config
+                .iter()
+                .map(|v| v.name.clone())
+                .collect()
+        })
+    };
+
+    match schema {
+        Schema::Null => {
+            if *data != Value::Null {
+                bail!("got unexpected data (expected null).");
+            }
+        }
+        Schema::Boolean(_) | Schema::Integer(_) | Schema::Number(_) | Schema::String(_) => {
+            delimiter.write_record(&mut output, [data_to_text(data, schema)?])?;
+        }
+        Schema::Object(schema) => {
+            let properties = configured_columns()
+                .unwrap_or_else(|| extract_properties_to_print(schema.properties()));
+            let rows = std::slice::from_ref(data);
+            format_separated_rows(&mut output, rows, schema, options, &properties, delimiter)?;
+        }
+        Schema::AllOf(schema) => {
+            let properties = configured_columns()
+                .unwrap_or_else(|| extract_properties_to_print(schema.properties()));
+            let rows = std::slice::from_ref(data);
+            format_separated_rows(&mut output, rows, schema, options, &properties, delimiter)?;
+        }
+        Schema::OneOf(schema) => {
+            let properties = match configured_columns() {
+                Some(properties) => properties,
+                None => extract_one_of_variant_properties(data, schema)?,
+            };
+            let rows = std::slice::from_ref(data);
+            format_separated_rows(&mut output, rows, schema, options, &properties, delimiter)?;
+        }
+        Schema::Array(array_schema) => {
+            let list = match data.as_array_mut() {
+                Some(list) => list,
+                None => bail!("got unexpected data (expected array)."),
+            };
+            if list.is_empty() {
+                return Ok(());
+            }
+
+            let item_schema: &dyn ObjectSchemaType = match array_schema.items {
+                Schema::Object(schema) => schema,
+                Schema::AllOf(schema) => schema,
+                Schema::OneOf(schema) => schema,
+                items => {
+                    for item in list.iter() {
+                        delimiter.write_record(&mut output, [data_to_text(item, items)?])?;
+                    }
+                    return Ok(());
+                }
+            };
+
+            let properties = configured_columns()
+                .unwrap_or_else(|| extract_properties_to_print(item_schema.properties()));
+            if properties.is_empty() {
+                return Ok(());
+            }
+            sort_table(list, item_schema, options, &properties)?;
+            format_separated_rows(
+                &mut output,
+                list,
+                item_schema,
+                options,
+                &properties,
+                delimiter,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Format data as comma separated values (RFC 4180)
+///
+/// Columns and sorting follow the same rules as [`value_to_text`], renderers are not used so the
+/// output stays machine readable. `noheader` suppresses the header line.
+pub fn value_to_csv<W: Write>(
+    output: W,
+    data: &mut Value,
+    schema: &Schema,
+    options: &TableFormatOptions,
+) -> Result<(), Error> {
+    value_to_separated(output, data, schema, options, Delimiter::Comma)
+}
+
+/// Format data as tab separated values
+///
+/// Like [`value_to_csv`], but tabs, newlines and backslashes in values are escaped as `\t`,
+/// `\n` and `\\`.
+pub fn value_to_tsv<W: Write>(
+    output: W,
+    data: &mut Value,
+    schema: &Schema,
+    options: &TableFormatOptions,
+) -> Result<(), Error> {
+    value_to_separated(output, data, schema, options, Delimiter::Tab)
+}
@@ -0,0 +1,158 @@
+use std::io::Write;
+
+use anyhow::Error;
+use serde_json::Value;
+
+/// Format data as YAML document
+///
+/// Strings which could be read as something else (numbers, booleans, dates, ...) or contain
+/// special characters are written as double quoted scalars.
+pub fn value_to_yaml<W: Write>(mut output: W, data: &Value) -> Result<(), Error> {
+    let mut text = String::new();
+    match data {
+        Value::Array(list) if !list.is_empty() => yaml_block(&mut text, data, 0),
+        Value::Object(map) if !map.is_empty() => yaml_block(&mut text, data, 0),
+        scalar => {
+            text.push_str(&yaml_scalar(scalar));
+            text.push('\n');
+        }
+    }
+    output.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+fn is_block(value: &Value) -> bool {
+    match value {
+        Value::Array(list) => !list.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+        _ => false,
+    }
+}
+
+/// Write a non-empty array or object, every line indented by `indent`.
+fn yaml_block(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) => {
+            for (key, member) in map {
+                out.extend(std::iter::repeat_n(' ', indent));
+                out.push_str(&yaml_string(key));
+                out.push(':');
+                yaml_member(out, member, indent + 2);
+            }
+        }
+        Value::Array(list) => {
+            for item in list {
+                out.extend(std::iter::repeat_n(' ', indent));
+                out.push('-');
+                if let Value::Object(map) = item {
+                    if !map.is_empty() {
+                        // the first member goes on the same line as the dash
+                        let mut nested = String::new();
+                        yaml_block(&mut nested, item, indent + 2);
+                        out.push(' ');
+                        out.push_str(&nested[indent + 2..]);
+                        continue;
+                    }
+                }
+                yaml_member(out, item, indent + 2);
+            }
+        }
+        scalar => {
+            out.extend(std::iter::repeat_n(' ', indent));
+            out.push_str(&yaml_scalar(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+/// Write the value following a key or dash.
+fn yaml_member(out: &mut String, value: &Value, indent: usize) {
+    if is_block(value) {
+        out.push('\n');
+        yaml_block(out, value, indent);
+    } else {
+        out.push(' ');
+        out.push_str(&yaml_scalar(value));
+        out.push('\n');
+    }
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => yaml_string(text),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+    }
+}
+
+fn yaml_string(text: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "null", "~", "true", "false", "yes", "no", "on", "off", "y", "n",
+    ];
+
+    let plain = text
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '/' || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.' | '/' | '@' | '+'))
+        && !text.ends_with(' ')
+        && !RESERVED.iter().any(|word| text.eq_ignore_ascii_case(word));
+
+    if plain {
+        text.to_string()
+    } else {
+        // JSON strings are valid double quoted YAML scalars
+        Value::from(text).to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn to_yaml(data: &Value) -> String {
+        let mut output = Vec::new();
+        value_to_yaml(&mut output, data).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_yaml() {
+        assert_eq!(to_yaml(&json!("text")), "text\n");
+        assert_eq!(to_yaml(&json!([])), "[]\n");
+        assert_eq!(
+            to_yaml(&json!({
+                "name": "vm 100",
+                "tags": ["a", "yes", "10:00"],
+                "config": { "memory": 512, "cores": null, "desc": "line\n\"2\"" },
+                "disks": [{ "id": "scsi0", "size": 1.5 }, {}, [1]],
+                "empty": {},
+            })),
+            "\
+config:
+  cores: null
+  desc: \"line\\n\\\"2\\\"\"
+  memory: 512
+disks:
+  - id: scsi0
+    size: 1.5
+  - {}
+  -
+    - 1
+empty: {}
+name: vm 100
+tags:
+  - a
+  - \"yes\"
+  - \"10:00\"
+"
+        );
+    }
+}
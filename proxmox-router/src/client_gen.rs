@@ -0,0 +1,954 @@
+//! Generate a typed Rust API client from a [`Router`].
+//!
+//! The generated code provides a client struct wrapping a `proxmox_client::HttpApiClient`, with an
+//! async method for every API endpoint. Required parameters are passed as method arguments,
+//! optional parameters via a builder style `...Params` struct per endpoint. Object return types
+//! are turned into structs. Endpoints reading a raw request body, like uploads, take the body as
+//! a stream and require the client to implement `proxmox_client::HttpApiUploadClient`, their
+//! parameters are passed in the query string.
+//!
+//! The generated code depends on the `proxmox-client` (with the `upload` feature), `http`, `serde`
+//! and `serde_json` crates and is not formatted, so it is best passed through `rustfmt`.
+//!
+//! This can be used from a build script via [`ClientGenerator`], or from a small binary of the
+//! product via [`run_cli`], see the `client-gen` example.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::{bail, format_err, Error};
+
+use proxmox_schema::{ObjectSchemaType, Schema};
+
+use crate::format::schema_description;
+use crate::{ApiHandler, ApiMethod, Router, SubRoute};
+
+/// Generator for typed API clients.
+pub struct ClientGenerator<'a> {
+    router: &'a Router,
+    name: String,
+    base_path: String,
+}
+
+#[derive(Clone, Copy)]
+enum Segment {
+    Static(&'static str),
+    Param(&'static str),
+}
+
+struct Endpoint {
+    http_method: &'static str,
+    path: Vec<Segment>,
+    method: &'static ApiMethod,
+}
+
+/// How an endpoint's request and response are handled.
+enum RequestKind {
+    Json,
+    Stream,
+    /// The endpoint reads the raw request body, which is sent via `HttpApiUploadClient`.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    Upload,
+}
+
+/// Where an endpoint's parameters are sent.
+#[derive(Clone, Copy, PartialEq)]
+enum ParamLocation {
+    Query,
+    Body,
+}
+
+struct Param {
+    name: &'static str,
+    ident: String,
+    ty: String,
+    description: &'static str,
+    schema: &'static Schema,
+}
+
+#[derive(Default)]
+struct Output {
+    types: String,
+    methods: String,
+    type_names: HashSet<String>,
+    method_names: HashSet<String>,
+}
+
+impl<'a> ClientGenerator<'a> {
+    /// Create a generator for the API provided by `router`.
+    pub fn new(router: &'a Router) -> Self {
+        Self {
+            router,
+            name: "ApiClient".to_string(),
+            base_path: "/api2/extjs".to_string(),
+        }
+    }
+
+    /// Set the name of the generated client struct. Defaults to `ApiClient`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Set the path the router is mounted at. Defaults to `/api2/extjs`.
+    pub fn base_path(mut self, base_path: impl Into<String>) -> Self {
+        self.base_path = base_path.into();
+        self
+    }
+
+    /// Generate the client code.
+    pub fn generate(&self) -> Result<String, Error> {
+        let mut endpoints = Vec::new();
+        collect_endpoints(self.router, &mut Vec::new(), &mut endpoints);
+
+        let mut output = Output::default();
+        for endpoint in &endpoints {
+            output.method(endpoint)?;
+        }
+
+        let mut code = String::new();
+        writeln!(code, "// Generated from the API schema, do not edit.\n")?;
+        writeln!(code, "/// Typed client for the API.")?;
+        writeln!(code, "pub struct {}<C> {{\n    client: C,\n}}\n", self.name)?;
+        writeln!(code, "impl<C> {}<C>", self.name)?;
+        writeln!(code, "where\n    C: ::proxmox_client::HttpApiClient,\n{{")?;
+        writeln!(
+            code,
+            "    const BASE_PATH: &'static str = {:?};\n",
+            self.base_path
+        )?;
+        writeln!(code, "    pub fn new(client: C) -> Self {{")?;
+        writeln!(code, "        Self {{ client }}\n    }}\n")?;
+        writeln!(code, "    /// Access the underlying client.")?;
+        writeln!(code, "    pub fn client(&self) -> &C {{")?;
+        writeln!(code, "        &self.client\n    }}\n")?;
+        code.push_str(ENCODE_PATH_PARAM);
+        code.push_str(output.methods.trim_end());
+        code.push_str("\n}\n");
+        code.push_str(&output.types);
+
+        Ok(code)
+    }
+
+    /// Generate the client code and write it to a file.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        std::fs::write(path, self.generate()?)
+            .map_err(|err| format_err!("failed to write {path:?} - {err}"))
+    }
+}
+
+const ENCODE_PATH_PARAM: &str = r#"    fn encode_path_param(value: impl ::std::fmt::Display) -> String {
+        let mut out = String::new();
+        for b in value.to_string().bytes() {
+            if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+                out.push(b as char);
+            } else {
+                out.push_str(&format!("%{b:02X}"));
+            }
+        }
+        out
+    }
+
+"#;
+
+/// Run the client generator as a command line tool.
+///
+/// Supported arguments are `--name <struct name>`, `--base-path <path>` and `--output <file>`.
+/// Without an output file, the code is printed to stdout.
+pub fn run_cli(router: &Router) -> Result<(), Error> {
+    let mut generator = ClientGenerator::new(router);
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format_err!("missing value for argument '{arg}'"))
+        };
+        match arg.as_str() {
+            "--name" => generator = generator.name(value()?),
+            "--base-path" => generator = generator.base_path(value()?),
+            "--output" => output = Some(value()?),
+            _ => bail!("unknown argument '{arg}'"),
+        }
+    }
+
+    match output {
+        Some(path) => generator.write_file(path),
+        None => {
+            print!("{}", generator.generate()?);
+            Ok(())
+        }
+    }
+}
+
+/// Collect all endpoints of `router`, which is mounted at `path`.
+fn collect_endpoints(router: &Router, path: &mut Vec<Segment>, endpoints: &mut Vec<Endpoint>) {
+    for (http_method, method) in [
+        ("GET", router.get),
+        ("POST", router.post),
+        ("PUT", router.put),
+        ("DELETE", router.delete),
+    ] {
+        if let Some(method) = method {
+            endpoints.push(Endpoint {
+                http_method,
+                path: path.clone(),
+                method,
+            });
+        }
+    }
+
+    match &router.subroute {
+        None => (),
+        Some(SubRoute::MatchAll { router, param_name }) => {
+            path.push(Segment::Param(param_name));
+            collect_endpoints(router, path, endpoints);
+            path.pop();
+        }
+        Some(SubRoute::Map(dirmap)) => {
+            for (name, sub_router) in dirmap.iter() {
+                path.push(Segment::Static(name));
+                collect_endpoints(sub_router, path, endpoints);
+                path.pop();
+            }
+        }
+    }
+}
+
+impl Output {
+    fn method(&mut self, endpoint: &Endpoint) -> Result<(), Error> {
+        let api_method = endpoint.method;
+        let http_method = endpoint.http_method;
+
+        let path_display: Vec<String> = endpoint
+            .path
+            .iter()
+            .map(|segment| match segment {
+                Segment::Static(name) => name.to_string(),
+                Segment::Param(name) => format!("{{{name}}}"),
+            })
+            .collect();
+        let path_display = format!("/{}", path_display.join("/"));
+
+        let request_kind = match api_method.handler {
+            ApiHandler::StreamSync(_) | ApiHandler::StreamAsync(_) => RequestKind::Stream,
+            #[cfg(feature = "server")]
+            ApiHandler::AsyncHttp(_) if http_method == "GET" => RequestKind::Stream,
+            #[cfg(feature = "server")]
+            ApiHandler::AsyncHttp(_) => RequestKind::Upload,
+            #[cfg(feature = "server")]
+            ApiHandler::AsyncHttpBodyParameters(_) => RequestKind::Stream,
+            _ => RequestKind::Json,
+        };
+
+        let name = self.method_name(endpoint, api_method);
+        let type_prefix = camel_case(&name);
+
+        let location = match (http_method, &request_kind) {
+            // the body is taken by the upload itself
+            (_, RequestKind::Upload) => ParamLocation::Query,
+            ("GET" | "DELETE", _) => ParamLocation::Query,
+            _ => ParamLocation::Body,
+        };
+
+        // path parameters are part of the parameter schema as well
+        let path_params: Vec<&str> = endpoint
+            .path
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Param(name) => Some(*name),
+                Segment::Static(_) => None,
+            })
+            .collect();
+
+        let mut args = String::new();
+        for param in &path_params {
+            let ty = match api_method.parameters.lookup(param) {
+                Some((_, schema)) => self.param_type(schema),
+                None => "String".to_string(),
+            };
+            write!(args, ", {}: {}", ident(param), arg_type(&ty))?;
+        }
+
+        let mut required = Vec::new();
+        let mut optional = Vec::new();
+        for (name, is_optional, schema) in api_method.parameters.properties() {
+            if path_params.contains(name) {
+                continue;
+            }
+            let param = Param {
+                name,
+                ident: ident(name),
+                ty: self.param_type(schema),
+                description: schema_description(schema),
+                schema,
+            };
+            if *is_optional {
+                optional.push(param);
+            } else {
+                required.push(param);
+            }
+        }
+
+        for param in &required {
+            write!(args, ", {}: {}", param.ident, arg_type(&param.ty))?;
+        }
+
+        let params_type = if optional.is_empty() {
+            None
+        } else {
+            let params_type = self.unique_type_name(&format!("{type_prefix}Params"));
+            self.params_struct(&params_type, &name, &optional)?;
+            write!(args, ", params: {params_type}")?;
+            Some(params_type)
+        };
+
+        let return_type = match request_kind {
+            RequestKind::Stream => "::proxmox_client::HttpApiResponseStream<C::Body>".to_string(),
+            _ => {
+                let ty =
+                    self.return_type(api_method.returns.schema, &format!("{type_prefix}Response"))?;
+                if api_method.returns.optional && ty != "()" {
+                    format!("Option<{ty}>")
+                } else {
+                    ty
+                }
+            }
+        };
+
+        self.method_doc(api_method, http_method, &path_display)?;
+        if let RequestKind::Upload = request_kind {
+            writeln!(
+                self.methods,
+                "    ///\n    /// The request `body` is streamed to the server with the given `content_type`."
+            )?;
+            writeln!(
+                self.methods,
+                "    pub async fn {name}<S>(&self{args}, content_type: &str, body: S) -> Result<{return_type}, ::proxmox_client::Error>"
+            )?;
+            writeln!(
+                self.methods,
+                "    where\n        C: ::proxmox_client::HttpApiUploadClient,\n        S: ::proxmox_client::UploadStream,\n    {{"
+            )?;
+        } else {
+            writeln!(
+                self.methods,
+                "    pub async fn {name}(&self{args}) -> Result<{return_type}, ::proxmox_client::Error> {{"
+            )?;
+        }
+
+        // path
+        let mut format_str = String::from("{}");
+        let mut format_args = String::from("Self::BASE_PATH");
+        for segment in &endpoint.path {
+            match segment {
+                Segment::Static(name) => write!(format_str, "/{name}")?,
+                Segment::Param(name) => {
+                    format_str.push_str("/{}");
+                    write!(format_args, ", Self::encode_path_param({})", ident(name))?;
+                }
+            }
+        }
+        writeln!(
+            self.methods,
+            "        let path = format!(\"{format_str}\", {format_args});"
+        )?;
+
+        // parameters
+        let has_params = !required.is_empty() || params_type.is_some();
+        match location {
+            ParamLocation::Query if has_params => {
+                writeln!(
+                    self.methods,
+                    "        let mut query = ::proxmox_client::ApiPathBuilder::new(path);"
+                )?;
+                for param in &required {
+                    self.query_arg(param, &param.ident, false)?;
+                }
+                for param in &optional {
+                    self.query_arg(param, &format!("params.{}", param.ident), true)?;
+                }
+                writeln!(self.methods, "        let path = query.build();")?;
+            }
+            ParamLocation::Body if has_params => {
+                writeln!(self.methods, "        #[derive(::serde::Serialize)]")?;
+                writeln!(self.methods, "        struct Body<'a> {{")?;
+                for param in &required {
+                    writeln!(
+                        self.methods,
+                        "            #[serde(rename = {:?})]",
+                        param.name
+                    )?;
+                    writeln!(
+                        self.methods,
+                        "            {}: &'a {},",
+                        param.ident,
+                        arg_type(&param.ty).trim_start_matches('&')
+                    )?;
+                }
+                if let Some(params_type) = &params_type {
+                    writeln!(self.methods, "            #[serde(flatten)]")?;
+                    writeln!(self.methods, "            params: &'a {params_type},")?;
+                }
+                writeln!(self.methods, "        }}")?;
+                let mut fields: Vec<String> = required
+                    .iter()
+                    .map(|param| match arg_type(&param.ty).starts_with('&') {
+                        true => param.ident.clone(),
+                        false => format!("{0}: &{0}", param.ident),
+                    })
+                    .collect();
+                if params_type.is_some() {
+                    fields.push("params: &params".to_string());
+                }
+                writeln!(
+                    self.methods,
+                    "        let body = Body {{ {} }};",
+                    fields.join(", ")
+                )?;
+            }
+            _ => (),
+        }
+
+        let body_arg = match (location, has_params) {
+            (ParamLocation::Body, true) => "Some(&body)",
+            _ => "None::<()>",
+        };
+
+        match request_kind {
+            RequestKind::Stream => {
+                writeln!(
+                    self.methods,
+                    "        self.client\n            .streaming_request(::http::Method::{http_method}, &path, {body_arg})\n            .await"
+                )?;
+            }
+            RequestKind::Upload => {
+                writeln!(
+                    self.methods,
+                    "        let response = self\n            .client\n            .upload_request(::http::Method::{http_method}, &path, content_type, body)\n            .await?;"
+                )?;
+                if return_type == "()" {
+                    writeln!(self.methods, "        response.nodata()")?;
+                } else {
+                    writeln!(self.methods, "        Ok(response.expect_json()?.data)")?;
+                }
+            }
+            RequestKind::Json => {
+                writeln!(
+                    self.methods,
+                    "        let response = self\n            .client\n            .request(::http::Method::{http_method}, &path, {body_arg})\n            .await?;"
+                )?;
+                if return_type == "()" {
+                    writeln!(self.methods, "        response.nodata()")?;
+                } else {
+                    writeln!(self.methods, "        Ok(response.expect_json()?.data)")?;
+                }
+            }
+        }
+        writeln!(self.methods, "    }}\n")?;
+
+        Ok(())
+    }
+
+    fn method_doc(
+        &mut self,
+        api_method: &ApiMethod,
+        http_method: &str,
+        path_display: &str,
+    ) -> Result<(), Error> {
+        for line in api_method.parameters.description().lines() {
+            writeln!(self.methods, "    /// {line}")?;
+        }
+        writeln!(
+            self.methods,
+            "    ///\n    /// `{http_method} {path_display}`"
+        )?;
+        Ok(())
+    }
+
+    fn method_name(&mut self, endpoint: &Endpoint, api_method: &ApiMethod) -> String {
+        let verb = match endpoint.http_method {
+            "GET" if matches!(api_method.returns.schema, Schema::Array(_)) => "list",
+            "GET" => "get",
+            "POST" => "create",
+            "PUT" => "update",
+            _ => "delete",
+        };
+
+        let mut name = verb.to_string();
+        for segment in &endpoint.path {
+            if let Segment::Static(segment) = segment {
+                name.push('_');
+                name.push_str(&snake_case(segment));
+            }
+        }
+
+        if self.method_names.contains(&name) {
+            for segment in &endpoint.path {
+                if let Segment::Param(param) = segment {
+                    name.push_str("_by_");
+                    name.push_str(&snake_case(param));
+                }
+            }
+        }
+
+        let name = unique_name(&self.method_names, name);
+        self.method_names.insert(name.clone());
+        name
+    }
+
+    fn unique_type_name(&mut self, name: &str) -> String {
+        let name = unique_name(&self.type_names, name.to_string());
+        self.type_names.insert(name.clone());
+        name
+    }
+
+    /// The type used for a parameter. Objects are passed as JSON values.
+    fn param_type(&mut self, schema: &Schema) -> String {
+        match schema {
+            Schema::Array(array) => format!("Vec<{}>", self.param_type(array.items)),
+            Schema::Object(_) | Schema::AllOf(_) | Schema::OneOf(_) => {
+                "::serde_json::Value".to_string()
+            }
+            other => scalar_type(other).to_string(),
+        }
+    }
+
+    /// The type used for a returned value. Objects are turned into structs called `name`.
+    fn return_type(&mut self, schema: &Schema, name: &str) -> Result<String, Error> {
+        Ok(match schema {
+            Schema::Array(array) => {
+                let item_name = format!("{}Item", name.trim_end_matches("Response"));
+                format!("Vec<{}>", self.return_type(array.items, &item_name)?)
+            }
+            Schema::Object(_) | Schema::AllOf(_) => {
+                let object = schema
+                    .any_object()
+                    .ok_or_else(|| format_err!("'{name}' is not an object schema"))?;
+                if object.properties().next().is_none() {
+                    return Ok("::serde_json::Value".to_string());
+                }
+                let name = self.unique_type_name(name);
+                self.return_struct(&name, object)?;
+                name
+            }
+            Schema::OneOf(_) => "::serde_json::Value".to_string(),
+            other => scalar_type(other).to_string(),
+        })
+    }
+
+    fn return_struct(&mut self, name: &str, object: &dyn ObjectSchemaType) -> Result<(), Error> {
+        let mut fields = String::new();
+        for (prop, optional, schema) in object.properties() {
+            let field_type_name =
+                format!("{}{}", name.trim_end_matches("Response"), camel_case(prop));
+            let ty = self.return_type(schema, &field_type_name)?;
+            write_field(&mut fields, prop, schema, &ty, *optional)?;
+        }
+
+        for line in object.description().lines() {
+            writeln!(self.types, "\n/// {line}")?;
+        }
+        writeln!(
+            self.types,
+            "#[derive(Clone, Debug, ::serde::Deserialize, ::serde::Serialize)]"
+        )?;
+        writeln!(self.types, "pub struct {name} {{{fields}}}")?;
+        Ok(())
+    }
+
+    fn params_struct(&mut self, name: &str, method: &str, params: &[Param]) -> Result<(), Error> {
+        let mut fields = String::new();
+        let mut setters = String::new();
+        for param in params {
+            write_field(&mut fields, param.name, param.schema, &param.ty, true)?;
+            writeln!(setters, "\n    /// {}", first_line(param.description))?;
+            writeln!(
+                setters,
+                "    pub fn {}(mut self, value: impl Into<{}>) -> Self {{",
+                param.ident, param.ty
+            )?;
+            writeln!(
+                setters,
+                "        self.{} = Some(value.into());\n        self\n    }}",
+                param.ident
+            )?;
+        }
+
+        writeln!(self.types, "\n/// Optional parameters for `{method}`.")?;
+        writeln!(
+            self.types,
+            "#[derive(Clone, Debug, Default, ::serde::Serialize)]"
+        )?;
+        writeln!(self.types, "pub struct {name} {{{fields}}}\n")?;
+        writeln!(self.types, "impl {name} {{{setters}}}")?;
+        Ok(())
+    }
+
+    fn query_arg(&mut self, param: &Param, value: &str, optional: bool) -> Result<(), Error> {
+        let name = param.name;
+        let is_array = param.ty.starts_with("Vec<");
+        let methods = &mut self.methods;
+        match (is_array, optional) {
+            (false, false) => writeln!(
+                methods,
+                "        query = query.arg({name:?}, {value});"
+            )?,
+            (false, true) => writeln!(
+                methods,
+                "        query = query.maybe_arg({name:?}, &{value});"
+            )?,
+            (true, false) => {
+                writeln!(methods, "        for item in &{value} {{")?;
+                writeln!(methods, "            query = query.arg({name:?}, item);")?;
+                writeln!(methods, "        }}")?;
+            }
+            (true, true) => {
+                writeln!(methods, "        for item in {value}.iter().flatten() {{")?;
+                writeln!(methods, "            query = query.arg({name:?}, item);")?;
+                writeln!(methods, "        }}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_field(
+    out: &mut String,
+    name: &str,
+    schema: &Schema,
+    ty: &str,
+    optional: bool,
+) -> Result<(), Error> {
+    let field = ident(name);
+
+    writeln!(out)?;
+    for line in schema_description(schema).lines() {
+        writeln!(out, "    /// {line}")?;
+    }
+    if field.trim_start_matches("r#") != name {
+        writeln!(out, "    #[serde(rename = {name:?})]")?;
+    }
+    if optional {
+        writeln!(
+            out,
+            "    #[serde(default, skip_serializing_if = \"Option::is_none\")]"
+        )?;
+        writeln!(out, "    pub {field}: Option<{ty}>,")?;
+    } else {
+        writeln!(out, "    pub {field}: {ty},")?;
+    }
+    Ok(())
+}
+
+fn scalar_type(schema: &Schema) -> &'static str {
+    match schema {
+        Schema::Null => "()",
+        Schema::Boolean(_) => "bool",
+        Schema::Integer(_) => "i64",
+        Schema::Number(_) => "f64",
+        Schema::String(_) => "String",
+        _ => "::serde_json::Value",
+    }
+}
+
+/// Strings are passed by reference to generated methods.
+fn arg_type(ty: &str) -> &str {
+    match ty {
+        "String" => "&str",
+        other => other,
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+fn unique_name(existing: &HashSet<String>, name: String) -> String {
+    if !existing.contains(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{name}{n}"))
+        .find(|name| !existing.contains(name))
+        .unwrap()
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+/// Turn a property name into a valid field or argument name.
+fn ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod",
+        "move", "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+        "unsafe", "use", "where", "while", "yield",
+    ];
+
+    let name = snake_case(name);
+    match name.as_str() {
+        "self" | "super" | "crate" | "params" | "query" | "path" | "body" | "content_type"
+        | "response" => {
+            format!("{name}_")
+        }
+        other if KEYWORDS.contains(&other) => format!("r#{name}"),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use serde_json::Value;
+
+    use proxmox_schema::{
+        ArraySchema, BooleanSchema, IntegerSchema, ObjectSchema, Schema, StringSchema,
+    };
+
+    use super::ClientGenerator;
+    use crate::{ApiHandler, ApiMethod, Router, RpcEnvironment, SubdirMap};
+
+    fn dummy_method(
+        _param: Value,
+        _info: &ApiMethod,
+        _rpcenv: &mut dyn RpcEnvironment,
+    ) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    const ITEM_SCHEMA: Schema = ObjectSchema::new(
+        "A thing.",
+        &[
+            ("name", false, &StringSchema::new("The name.").schema()),
+            ("size", true, &IntegerSchema::new("The size.").schema()),
+            ("type", false, &StringSchema::new("The type.").schema()),
+        ],
+    )
+    .schema();
+
+    const API_METHOD_LIST_THINGS: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "List things.",
+            &[
+                ("node", false, &StringSchema::new("The node.").schema()),
+                (
+                    "running",
+                    true,
+                    &BooleanSchema::new("Only running.").schema(),
+                ),
+            ],
+        ),
+    )
+    .returns(proxmox_schema::ReturnType::new(
+        false,
+        &ArraySchema::new("Things.", &ITEM_SCHEMA).schema(),
+    ));
+
+    const API_METHOD_CREATE_THING: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "Create a thing.",
+            &[
+                ("name", false, &StringSchema::new("The name.").schema()),
+                ("node", false, &StringSchema::new("The node.").schema()),
+                ("size", true, &IntegerSchema::new("The size.").schema()),
+            ],
+        ),
+    );
+
+    const API_METHOD_DELETE_THINGS: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "Delete things.",
+            &[
+                (
+                    "ids",
+                    true,
+                    &ArraySchema::new("Thing IDs.", &IntegerSchema::new("An ID.").schema())
+                        .schema(),
+                ),
+                ("node", false, &StringSchema::new("The node.").schema()),
+                ("type", false, &StringSchema::new("The type.").schema()),
+            ],
+        ),
+    );
+
+    const API_METHOD_GET_STATUS: ApiMethod = ApiMethod::new(
+        &ApiHandler::Sync(&dummy_method),
+        &ObjectSchema::new(
+            "Node status.",
+            &[("node", false, &StringSchema::new("The node.").schema())],
+        ),
+    )
+    .returns(proxmox_schema::ReturnType::new(
+        true,
+        &ObjectSchema::new(
+            "The status.",
+            &[
+                (
+                    "memory",
+                    false,
+                    &ObjectSchema::new(
+                        "Memory usage.",
+                        &[("used-bytes", false, &IntegerSchema::new("Used.").schema())],
+                    )
+                    .schema(),
+                ),
+                ("uptime", false, &IntegerSchema::new("Uptime.").schema()),
+            ],
+        )
+        .schema(),
+    ));
+
+    const THINGS_ROUTER: Router = Router::new()
+        .get(&API_METHOD_LIST_THINGS)
+        .post(&API_METHOD_CREATE_THING)
+        .delete(&API_METHOD_DELETE_THINGS);
+
+    const STATUS_ROUTER: Router = Router::new().get(&API_METHOD_GET_STATUS);
+
+    #[cfg(feature = "server")]
+    fn dummy_upload(
+        _parts: http::request::Parts,
+        _body: hyper::body::Incoming,
+        _param: Value,
+        _info: &'static ApiMethod,
+        _rpcenv: Box<dyn RpcEnvironment>,
+    ) -> crate::ApiResponseFuture {
+        Box::pin(async { anyhow::bail!("uploads are not handled in tests") })
+    }
+
+    #[cfg(feature = "server")]
+    const API_METHOD_UPLOAD_THING: ApiMethod = ApiMethod::new(
+        &ApiHandler::AsyncHttp(&dummy_upload),
+        &ObjectSchema::new(
+            "Upload a thing.",
+            &[
+                (
+                    "compress",
+                    false,
+                    &BooleanSchema::new("Compress the thing.").schema(),
+                ),
+                ("node", false, &StringSchema::new("The node.").schema()),
+            ],
+        ),
+    );
+
+    #[cfg(feature = "server")]
+    const UPLOAD_ROUTER: Router = Router::new().post(&API_METHOD_UPLOAD_THING);
+    #[cfg(not(feature = "server"))]
+    const UPLOAD_ROUTER: Router = Router::new();
+
+    const NODE_SUBDIRS: SubdirMap = &[
+        ("status", &STATUS_ROUTER),
+        ("things", &THINGS_ROUTER),
+        ("upload", &UPLOAD_ROUTER),
+    ];
+
+    const NODE_ROUTER: Router = Router::new().subdirs(NODE_SUBDIRS);
+
+    const NODES_ROUTER: Router = Router::new().match_all("node", &NODE_ROUTER);
+
+    const ROUTER: Router = Router::new().subdirs(&[("nodes", &NODES_ROUTER)]);
+
+    #[test]
+    fn test_generate_client() {
+        let code = ClientGenerator::new(&ROUTER)
+            .name("TestClient")
+            .generate()
+            .unwrap();
+
+        assert!(code.contains("pub struct TestClient<C> {"));
+        assert!(code.contains(
+            "pub async fn list_nodes_things(&self, node: &str, params: ListNodesThingsParams) \
+             -> Result<Vec<ListNodesThingsItem>, ::proxmox_client::Error>"
+        ));
+        assert!(code.contains("Self::encode_path_param(node)"));
+        assert!(code.contains("query = query.maybe_arg(\"running\", &params.running);"));
+        assert!(code.contains("pub struct ListNodesThingsItem {"));
+        assert!(code.contains("pub r#type: String,"));
+        assert!(code.contains("pub size: Option<i64>,"));
+
+        assert!(code.contains(
+            "pub async fn create_nodes_things(&self, node: &str, name: &str, \
+             params: CreateNodesThingsParams) -> Result<(), ::proxmox_client::Error>"
+        ));
+        assert!(code.contains("let body = Body { name, params: &params };"));
+
+        assert!(code.contains(
+            "pub async fn delete_nodes_things(&self, node: &str, r#type: &str, \
+             params: DeleteNodesThingsParams)"
+        ));
+        assert!(code.contains("for item in params.ids.iter().flatten() {"));
+
+        assert!(code.contains(
+            "pub async fn get_nodes_status(&self, node: &str) \
+             -> Result<Option<GetNodesStatusResponse>, ::proxmox_client::Error>"
+        ));
+        assert!(code.contains("pub memory: GetNodesStatusMemory,"));
+        assert!(code.contains("#[serde(rename = \"used-bytes\")]\n    pub used_bytes: i64,"));
+
+        #[cfg(feature = "server")]
+        {
+            assert!(code.contains(
+                "pub async fn create_nodes_upload<S>(&self, node: &str, compress: bool, \
+                 content_type: &str, body: S) -> Result<(), ::proxmox_client::Error>"
+            ));
+            assert!(
+                code.contains(".upload_request(::http::Method::POST, &path, content_type, body)")
+            );
+        }
+    }
+
+    /// The generated code is compiled by the `client_gen` integration test via this fixture.
+    ///
+    /// Set `UPDATE_CLIENT_GEN_FIXTURE` to update it after changing the generator.
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_generated_fixture() {
+        const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/client_gen/generated.rs");
+
+        let code = ClientGenerator::new(&ROUTER)
+            .name("TestClient")
+            .generate()
+            .unwrap();
+
+        if std::env::var_os("UPDATE_CLIENT_GEN_FIXTURE").is_some() {
+            std::fs::write(FIXTURE, &code).unwrap();
+        }
+        assert!(
+            std::fs::read_to_string(FIXTURE).unwrap() == code,
+            "{FIXTURE} is outdated, set UPDATE_CLIENT_GEN_FIXTURE to update it"
+        );
+    }
+}
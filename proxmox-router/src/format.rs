@@ -6,11 +6,29 @@ use anyhow::Error;
 
 use proxmox_schema::format::*;
 use proxmox_schema::ObjectSchemaType;
+#[cfg(any(feature = "cli", feature = "client-gen"))]
+use proxmox_schema::Schema;
 
 #[cfg(feature = "server")]
 use crate::ApiHandler;
 use crate::ApiMethod;
 
+/// The description of any kind of schema.
+#[cfg(any(feature = "cli", feature = "client-gen"))]
+pub(crate) fn schema_description(schema: &Schema) -> &'static str {
+    match schema {
+        Schema::Null => "",
+        Schema::Boolean(schema) => schema.description,
+        Schema::Integer(schema) => schema.description,
+        Schema::Number(schema) => schema.description,
+        Schema::String(schema) => schema.description,
+        Schema::Object(schema) => schema.description,
+        Schema::Array(schema) => schema.description,
+        Schema::AllOf(schema) => schema.description,
+        Schema::OneOf(schema) => schema.description,
+    }
+}
+
 fn dump_method_definition(method: &str, path: &str, def: Option<&ApiMethod>) -> Option<String> {
     let style = ParameterDisplayStyle::Config;
     match def {
@@ -2,6 +2,8 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+#[cfg(feature = "client-gen")]
+pub mod client_gen;
 pub mod format;
 
 #[cfg(feature = "cli")]
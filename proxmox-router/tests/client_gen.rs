@@ -0,0 +1,64 @@
+//! Compiles the client generated from the test router in `proxmox_router::client_gen`.
+
+#[allow(dead_code)]
+mod generated {
+    include!("client_gen/generated.rs");
+}
+
+use generated::{CreateNodesThingsParams, ListNodesThingsParams, TestClient};
+use proxmox_client::mock::{MockClient, MockRequest, MockResponse};
+
+// only needs to compile, the generated methods are checked against the `HttpApiClient` trait
+#[allow(dead_code)]
+async fn call_client<C: proxmox_client::HttpApiClient>(
+    client: &TestClient<C>,
+) -> Result<(), proxmox_client::Error> {
+    let things = client
+        .list_nodes_things("node1", ListNodesThingsParams::default().running(true))
+        .await?;
+    for thing in things {
+        let params = CreateNodesThingsParams::default().size(thing.size.unwrap_or(0));
+        client
+            .create_nodes_things("node2", &thing.name, params)
+            .await?;
+    }
+
+    let status = client.get_nodes_status("node1").await?;
+    if let Some(status) = status {
+        let _: i64 = status.memory.used_bytes + status.uptime;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_generated_params() {
+    let params = ListNodesThingsParams::default().running(true);
+    assert_eq!(
+        serde_json::to_value(&params).unwrap(),
+        serde_json::json!({ "running": true })
+    );
+}
+
+#[test]
+fn test_generated_upload() {
+    let client = TestClient::new(MockClient::new());
+    client.client().expect(
+        MockRequest::new(
+            http::Method::POST,
+            "/api2/extjs/nodes/node1/upload?compress=true",
+        )
+        .upload("hello world"),
+        MockResponse::nodata(),
+    );
+
+    let body = futures::stream::iter(["hello", " ", "world"].map(|s| Ok(bytes::Bytes::from(s))));
+    futures::executor::block_on(client.create_nodes_upload(
+        "node1",
+        true,
+        "application/octet-stream",
+        body,
+    ))
+    .unwrap();
+    client.client().assert_done();
+}
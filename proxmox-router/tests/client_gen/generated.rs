@@ -0,0 +1,200 @@
+// Generated from the API schema, do not edit.
+
+/// Typed client for the API.
+pub struct TestClient<C> {
+    client: C,
+}
+
+impl<C> TestClient<C>
+where
+    C: ::proxmox_client::HttpApiClient,
+{
+    const BASE_PATH: &'static str = "/api2/extjs";
+
+    pub fn new(client: C) -> Self {
+        Self { client }
+    }
+
+    /// Access the underlying client.
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    fn encode_path_param(value: impl ::std::fmt::Display) -> String {
+        let mut out = String::new();
+        for b in value.to_string().bytes() {
+            if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+                out.push(b as char);
+            } else {
+                out.push_str(&format!("%{b:02X}"));
+            }
+        }
+        out
+    }
+
+    /// Node status.
+    ///
+    /// `GET /nodes/{node}/status`
+    pub async fn get_nodes_status(&self, node: &str) -> Result<Option<GetNodesStatusResponse>, ::proxmox_client::Error> {
+        let path = format!("{}/nodes/{}/status", Self::BASE_PATH, Self::encode_path_param(node));
+        let response = self
+            .client
+            .request(::http::Method::GET, &path, None::<()>)
+            .await?;
+        Ok(response.expect_json()?.data)
+    }
+
+    /// List things.
+    ///
+    /// `GET /nodes/{node}/things`
+    pub async fn list_nodes_things(&self, node: &str, params: ListNodesThingsParams) -> Result<Vec<ListNodesThingsItem>, ::proxmox_client::Error> {
+        let path = format!("{}/nodes/{}/things", Self::BASE_PATH, Self::encode_path_param(node));
+        let mut query = ::proxmox_client::ApiPathBuilder::new(path);
+        query = query.maybe_arg("running", &params.running);
+        let path = query.build();
+        let response = self
+            .client
+            .request(::http::Method::GET, &path, None::<()>)
+            .await?;
+        Ok(response.expect_json()?.data)
+    }
+
+    /// Create a thing.
+    ///
+    /// `POST /nodes/{node}/things`
+    pub async fn create_nodes_things(&self, node: &str, name: &str, params: CreateNodesThingsParams) -> Result<(), ::proxmox_client::Error> {
+        let path = format!("{}/nodes/{}/things", Self::BASE_PATH, Self::encode_path_param(node));
+        #[derive(::serde::Serialize)]
+        struct Body<'a> {
+            #[serde(rename = "name")]
+            name: &'a str,
+            #[serde(flatten)]
+            params: &'a CreateNodesThingsParams,
+        }
+        let body = Body { name, params: &params };
+        let response = self
+            .client
+            .request(::http::Method::POST, &path, Some(&body))
+            .await?;
+        response.nodata()
+    }
+
+    /// Delete things.
+    ///
+    /// `DELETE /nodes/{node}/things`
+    pub async fn delete_nodes_things(&self, node: &str, r#type: &str, params: DeleteNodesThingsParams) -> Result<(), ::proxmox_client::Error> {
+        let path = format!("{}/nodes/{}/things", Self::BASE_PATH, Self::encode_path_param(node));
+        let mut query = ::proxmox_client::ApiPathBuilder::new(path);
+        query = query.arg("type", r#type);
+        for item in params.ids.iter().flatten() {
+            query = query.arg("ids", item);
+        }
+        let path = query.build();
+        let response = self
+            .client
+            .request(::http::Method::DELETE, &path, None::<()>)
+            .await?;
+        response.nodata()
+    }
+
+    /// Upload a thing.
+    ///
+    /// `POST /nodes/{node}/upload`
+    ///
+    /// The request `body` is streamed to the server with the given `content_type`.
+    pub async fn create_nodes_upload<S>(&self, node: &str, compress: bool, content_type: &str, body: S) -> Result<(), ::proxmox_client::Error>
+    where
+        C: ::proxmox_client::HttpApiUploadClient,
+        S: ::proxmox_client::UploadStream,
+    {
+        let path = format!("{}/nodes/{}/upload", Self::BASE_PATH, Self::encode_path_param(node));
+        let mut query = ::proxmox_client::ApiPathBuilder::new(path);
+        query = query.arg("compress", compress);
+        let path = query.build();
+        let response = self
+            .client
+            .upload_request(::http::Method::POST, &path, content_type, body)
+            .await?;
+        response.nodata()
+    }
+}
+
+/// Memory usage.
+#[derive(Clone, Debug, ::serde::Deserialize, ::serde::Serialize)]
+pub struct GetNodesStatusMemory {
+    /// Used.
+    #[serde(rename = "used-bytes")]
+    pub used_bytes: i64,
+}
+
+/// The status.
+#[derive(Clone, Debug, ::serde::Deserialize, ::serde::Serialize)]
+pub struct GetNodesStatusResponse {
+    /// Memory usage.
+    pub memory: GetNodesStatusMemory,
+
+    /// Uptime.
+    pub uptime: i64,
+}
+
+/// Optional parameters for `list_nodes_things`.
+#[derive(Clone, Debug, Default, ::serde::Serialize)]
+pub struct ListNodesThingsParams {
+    /// Only running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub running: Option<bool>,
+}
+
+impl ListNodesThingsParams {
+    /// Only running.
+    pub fn running(mut self, value: impl Into<bool>) -> Self {
+        self.running = Some(value.into());
+        self
+    }
+}
+
+/// A thing.
+#[derive(Clone, Debug, ::serde::Deserialize, ::serde::Serialize)]
+pub struct ListNodesThingsItem {
+    /// The name.
+    pub name: String,
+
+    /// The size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+
+    /// The type.
+    pub r#type: String,
+}
+
+/// Optional parameters for `create_nodes_things`.
+#[derive(Clone, Debug, Default, ::serde::Serialize)]
+pub struct CreateNodesThingsParams {
+    /// The size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+}
+
+impl CreateNodesThingsParams {
+    /// The size.
+    pub fn size(mut self, value: impl Into<i64>) -> Self {
+        self.size = Some(value.into());
+        self
+    }
+}
+
+/// Optional parameters for `delete_nodes_things`.
+#[derive(Clone, Debug, Default, ::serde::Serialize)]
+pub struct DeleteNodesThingsParams {
+    /// Thing IDs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<i64>>,
+}
+
+impl DeleteNodesThingsParams {
+    /// Thing IDs.
+    pub fn ids(mut self, value: impl Into<Vec<i64>>) -> Self {
+        self.ids = Some(value.into());
+        self
+    }
+}
@@ -7,7 +7,7 @@ use std::time::Duration;
 
 use anyhow::{bail, format_err, Context as _, Error};
 use nix::errno::Errno;
-use nix::fcntl::OFlag;
+use nix::fcntl::{FcntlArg, OFlag};
 use nix::sys::stat;
 use nix::unistd;
 use nix::NixPath;
@@ -303,7 +303,16 @@ pub fn atomic_open_or_create_file<P: AsRef<Path>>(
     });
 
     match rename_result {
-        Ok(Ok(Ok(_))) => Ok(file),
+        Ok(Ok(Ok(_))) => {
+            // the temporary file was not opened with 'oflag', but appending needs to work the
+            // same as for existing files
+            if oflag.contains(OFlag::O_APPEND) {
+                nix::fcntl::fcntl(file.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_APPEND)).map_err(
+                    |err| format_err!("setting O_APPEND on {:?} failed - {}", path, err),
+                )?;
+            }
+            Ok(file)
+        }
         Ok(Ok(Err(err))) => {
             // if another process has already raced ahead and created
             // the file, let's just open theirs instead:
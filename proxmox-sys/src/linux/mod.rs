@@ -5,6 +5,7 @@ use anyhow::{bail, Error};
 use proxmox_io::vec;
 
 pub mod magic;
+pub mod netlink;
 pub mod pid;
 pub mod procfs;
 pub mod socket;
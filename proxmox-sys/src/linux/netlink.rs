@@ -0,0 +1,1075 @@
+//! Network interface, address, route and neighbour state via rtnetlink.
+//!
+//! Unlike the `/proc/net` readers in [`procfs`](super::procfs) this exposes the link state, MTU,
+//! hardware address, bond and bridge membership and the addresses of both IPv4 and IPv6.
+//!
+//! See "man 7 rtnetlink" for details.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::AsRawFd;
+
+use anyhow::{bail, format_err, Error};
+use nix::sys::socket::{
+    bind, recv, send, socket, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol,
+    SockType,
+};
+use serde::Serialize;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 0x01;
+const NLM_F_DUMP: u16 = 0x300;
+const NLM_F_DUMP_INTR: u16 = 0x10;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+/// How often a dump is attempted if it keeps being interrupted by concurrent changes.
+const DUMP_ATTEMPTS: usize = 3;
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_GETLINK: u16 = 18;
+const RTM_NEWADDR: u16 = 20;
+const RTM_GETADDR: u16 = 22;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_GETROUTE: u16 = 26;
+const RTM_NEWNEIGH: u16 = 28;
+const RTM_GETNEIGH: u16 = 30;
+
+const IFINFOMSG_LEN: usize = 16;
+const IFLA_ADDRESS: u16 = 1;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_MTU: u16 = 4;
+const IFLA_LINK: u16 = 5;
+const IFLA_MASTER: u16 = 10;
+const IFLA_OPERSTATE: u16 = 16;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_STATS64: u16 = 23;
+const IFLA_CARRIER: u16 = 33;
+const IFLA_INFO_KIND: u16 = 1;
+const IFLA_INFO_SLAVE_KIND: u16 = 4;
+
+const IFADDRMSG_LEN: usize = 8;
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_LABEL: u16 = 3;
+const IFA_FLAGS: u16 = 8;
+
+const RTMSG_LEN: usize = 12;
+const RTA_DST: u16 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PRIORITY: u16 = 6;
+const RTA_PREFSRC: u16 = 7;
+const RTA_MULTIPATH: u16 = 9;
+const RTA_TABLE: u16 = 15;
+const RTM_F_CLONED: u32 = 0x200;
+
+const NDMSG_LEN: usize = 12;
+const NDA_DST: u16 = 1;
+const NDA_LLADDR: u16 = 2;
+const NTF_ROUTER: u8 = 0x80;
+
+/// `IFF_UP`, the interface is administratively up.
+pub const IFF_UP: u32 = libc::IFF_UP as u32;
+/// `IFF_LOOPBACK`, the interface is a loopback device.
+pub const IFF_LOOPBACK: u32 = libc::IFF_LOOPBACK as u32;
+/// `IFF_LOWER_UP`, the driver signals layer 1 up (e.g. carrier).
+pub const IFF_LOWER_UP: u32 = libc::IFF_LOWER_UP as u32;
+
+/// `IFA_F_SECONDARY`, a secondary (alias) IPv4 address or temporary IPv6 address.
+pub const IFA_F_SECONDARY: u32 = 0x01;
+/// `IFA_F_DADFAILED`, duplicate address detection failed.
+pub const IFA_F_DADFAILED: u32 = 0x08;
+/// `IFA_F_DEPRECATED`, the preferred lifetime of the address expired.
+pub const IFA_F_DEPRECATED: u32 = 0x20;
+/// `IFA_F_TENTATIVE`, duplicate address detection is still in progress.
+pub const IFA_F_TENTATIVE: u32 = 0x40;
+/// `IFA_F_PERMANENT`, the address was configured statically rather than autoconfigured.
+pub const IFA_F_PERMANENT: u32 = 0x80;
+
+/// The address family of an address or route.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    Inet,
+    Inet6,
+}
+
+impl Family {
+    fn from_raw(family: u8) -> Option<Self> {
+        match family as i32 {
+            libc::AF_INET => Some(Family::Inet),
+            libc::AF_INET6 => Some(Family::Inet6),
+            _ => None,
+        }
+    }
+}
+
+/// The scope of an address or route (`RT_SCOPE_*`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Global,
+    Site,
+    Link,
+    Host,
+    Nowhere,
+    /// A user defined scope.
+    Other(u8),
+}
+
+impl From<u8> for Scope {
+    fn from(scope: u8) -> Self {
+        match scope {
+            libc::RT_SCOPE_UNIVERSE => Scope::Global,
+            libc::RT_SCOPE_SITE => Scope::Site,
+            libc::RT_SCOPE_LINK => Scope::Link,
+            libc::RT_SCOPE_HOST => Scope::Host,
+            libc::RT_SCOPE_NOWHERE => Scope::Nowhere,
+            other => Scope::Other(other),
+        }
+    }
+}
+
+/// The RFC 2863 operational state of a link (`IF_OPER_*`).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OperState {
+    #[default]
+    Unknown,
+    NotPresent,
+    Down,
+    LowerLayerDown,
+    Testing,
+    Dormant,
+    Up,
+}
+
+impl From<u8> for OperState {
+    fn from(state: u8) -> Self {
+        match state {
+            1 => OperState::NotPresent,
+            2 => OperState::Down,
+            3 => OperState::LowerLayerDown,
+            4 => OperState::Testing,
+            5 => OperState::Dormant,
+            6 => OperState::Up,
+            _ => OperState::Unknown,
+        }
+    }
+}
+
+/// The duplex mode of an ethernet link.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Duplex {
+    Half,
+    Full,
+}
+
+/// Link counters from `IFLA_STATS64`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LinkStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+    pub multicast: u64,
+    pub collisions: u64,
+}
+
+impl LinkStats {
+    fn parse(data: &[u8]) -> Self {
+        let value = |index: usize| read_u64(data, index * 8).unwrap_or(0);
+        Self {
+            rx_packets: value(0),
+            tx_packets: value(1),
+            rx_bytes: value(2),
+            tx_bytes: value(3),
+            rx_errors: value(4),
+            tx_errors: value(5),
+            rx_dropped: value(6),
+            tx_dropped: value(7),
+            multicast: value(8),
+            collisions: value(9),
+        }
+    }
+}
+
+/// A network interface.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Link {
+    /// The interface index.
+    pub index: u32,
+    /// The interface name.
+    pub name: String,
+    /// The hardware type (`ARPHRD_*`).
+    pub link_type: u16,
+    /// The interface flags (`IFF_*`).
+    pub flags: u32,
+    /// The operational state.
+    pub operstate: OperState,
+    /// Whether the link has a carrier, not reported by very old kernels.
+    pub carrier: Option<bool>,
+    pub mtu: Option<u32>,
+    /// The hardware address, formatted as colon separated hex bytes.
+    pub hw_address: Option<String>,
+    /// The link type for virtual devices, e.g. `bridge`, `bond`, `vlan` or `veth`.
+    pub kind: Option<String>,
+    /// The type of the master for bond or bridge ports, e.g. `bridge` or `bond`.
+    pub slave_kind: Option<String>,
+    /// The index of the bond or bridge this interface is part of.
+    pub master: Option<u32>,
+    /// The index of the underlying link, e.g. of a vlan device.
+    pub parent: Option<u32>,
+    pub stats: Option<LinkStats>,
+    /// The link speed in Mbit/s, only known for physical links with a carrier.
+    pub speed: Option<u32>,
+    /// The duplex mode, only known for physical links with a carrier.
+    pub duplex: Option<Duplex>,
+}
+
+impl Link {
+    /// Whether the interface is administratively up.
+    pub fn is_up(&self) -> bool {
+        self.flags & IFF_UP != 0
+    }
+
+    /// Whether the lower layer of the interface is up, i.e. it has a carrier.
+    pub fn is_lower_up(&self) -> bool {
+        self.flags & IFF_LOWER_UP != 0
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.flags & IFF_LOOPBACK != 0
+    }
+
+    pub fn is_bridge(&self) -> bool {
+        self.kind.as_deref() == Some("bridge")
+    }
+
+    pub fn is_bond(&self) -> bool {
+        self.kind.as_deref() == Some("bond")
+    }
+
+    /// The links in 'links' which are ports of this bond or bridge.
+    pub fn members<'a>(&self, links: &'a [Link]) -> impl Iterator<Item = &'a Link> {
+        let index = self.index;
+        links.iter().filter(move |link| link.master == Some(index))
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < IFINFOMSG_LEN {
+            bail!("short ifinfomsg");
+        }
+
+        let mut link = Link {
+            link_type: read_u16(data, 2).unwrap_or(0),
+            index: read_u32(data, 4).unwrap_or(0),
+            flags: read_u32(data, 8).unwrap_or(0),
+            ..Default::default()
+        };
+
+        for (kind, payload) in Attributes::new(&data[IFINFOMSG_LEN..]) {
+            match kind {
+                IFLA_IFNAME => link.name = parse_string(payload),
+                IFLA_MTU => link.mtu = read_u32(payload, 0),
+                IFLA_LINK => link.parent = read_u32(payload, 0),
+                IFLA_MASTER => link.master = read_u32(payload, 0),
+                IFLA_OPERSTATE => link.operstate = payload.first().copied().unwrap_or(0).into(),
+                IFLA_CARRIER => link.carrier = payload.first().map(|carrier| *carrier != 0),
+                IFLA_ADDRESS => link.hw_address = Some(format_hw_address(payload)),
+                IFLA_STATS64 => link.stats = Some(LinkStats::parse(payload)),
+                IFLA_LINKINFO => {
+                    for (kind, payload) in Attributes::new(payload) {
+                        match kind {
+                            IFLA_INFO_KIND => link.kind = Some(parse_string(payload)),
+                            IFLA_INFO_SLAVE_KIND => link.slave_kind = Some(parse_string(payload)),
+                            _ => (),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if link.name.is_empty() {
+            bail!("link {} without name", link.index);
+        }
+
+        Ok(link)
+    }
+
+    // The speed and duplex mode are not part of rtnetlink but reported via ethtool, which sysfs
+    // exposes. Reading them fails for links without a carrier.
+    fn read_speed_and_duplex(&mut self) {
+        let path = format!("/sys/class/net/{}", self.name);
+
+        self.speed = std::fs::read_to_string(format!("{}/speed", path))
+            .ok()
+            .and_then(|speed| speed.trim().parse::<i64>().ok())
+            .filter(|speed| *speed > 0 && *speed < u32::MAX as i64)
+            .map(|speed| speed as u32);
+
+        self.duplex = match std::fs::read_to_string(format!("{}/duplex", path)) {
+            Ok(duplex) => match duplex.trim() {
+                "full" => Some(Duplex::Full),
+                "half" => Some(Duplex::Half),
+                _ => None,
+            },
+            Err(_) => None,
+        };
+    }
+}
+
+/// An IPv4 or IPv6 address of an interface.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Address {
+    /// The index of the interface the address is configured on.
+    pub index: u32,
+    pub address: IpAddr,
+    pub prefix_len: u8,
+    /// The peer address of point-to-point interfaces.
+    pub peer: Option<IpAddr>,
+    /// The IPv4 address label, e.g. `eth0:1`.
+    pub label: Option<String>,
+    pub scope: Scope,
+    /// The address flags (`IFA_F_*`).
+    pub flags: u32,
+}
+
+impl Address {
+    pub fn family(&self) -> Family {
+        match self.address {
+            IpAddr::V4(_) => Family::Inet,
+            IpAddr::V6(_) => Family::Inet6,
+        }
+    }
+
+    /// Whether duplicate address detection is still running or has failed for this address.
+    pub fn is_tentative(&self) -> bool {
+        self.flags & (IFA_F_TENTATIVE | IFA_F_DADFAILED) != 0
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.flags & IFA_F_DEPRECATED != 0
+    }
+
+    fn parse(data: &[u8]) -> Result<Option<Self>, Error> {
+        if data.len() < IFADDRMSG_LEN {
+            bail!("short ifaddrmsg");
+        }
+
+        let Some(family) = Family::from_raw(data[0]) else {
+            return Ok(None);
+        };
+        let prefix_len = data[1];
+        let mut flags = data[2] as u32;
+        let scope = data[3].into();
+        let index = read_u32(data, 4).unwrap_or(0);
+
+        let mut address = None;
+        let mut local = None;
+        let mut label = None;
+
+        for (kind, payload) in Attributes::new(&data[IFADDRMSG_LEN..]) {
+            match kind {
+                IFA_ADDRESS => address = parse_ip(family, payload),
+                IFA_LOCAL => local = parse_ip(family, payload),
+                IFA_LABEL => label = Some(parse_string(payload)),
+                IFA_FLAGS => flags = read_u32(payload, 0).unwrap_or(flags),
+                _ => (),
+            }
+        }
+
+        // IFA_LOCAL is the local address where set, IFA_ADDRESS is then the peer's
+        let (address, peer) = match (local, address) {
+            (Some(local), Some(address)) if local != address => (local, Some(address)),
+            (Some(local), _) => (local, None),
+            (None, Some(address)) => (address, None),
+            (None, None) => bail!("address on interface {} without IFA_ADDRESS", index),
+        };
+
+        Ok(Some(Self {
+            index,
+            address,
+            prefix_len,
+            peer,
+            label,
+            scope,
+            flags,
+        }))
+    }
+}
+
+/// A next hop of a multipath route.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NextHop {
+    pub gateway: Option<IpAddr>,
+    /// The index of the output interface.
+    pub output_index: u32,
+    /// The weight of the next hop, starting at 1.
+    pub weight: u16,
+}
+
+/// An IPv4 or IPv6 route.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Route {
+    pub family: Family,
+    /// The destination network, `None` for the default route.
+    pub destination: Option<IpAddr>,
+    pub prefix_len: u8,
+    pub gateway: Option<IpAddr>,
+    /// The index of the output interface.
+    pub output_index: Option<u32>,
+    /// The preferred source address.
+    pub source: Option<IpAddr>,
+    pub metric: Option<u32>,
+    /// The routing table, `libc::RT_TABLE_MAIN` (254) for the main table.
+    pub table: u32,
+    /// The origin of the route (`RTPROT_*`), e.g. 2 for the kernel or 4 for static routes.
+    pub protocol: u8,
+    pub scope: Scope,
+    /// The route type (`RTN_*`), e.g. 1 for unicast or 2 for local routes.
+    pub route_type: u8,
+    /// The next hops of a multipath route.
+    pub next_hops: Vec<NextHop>,
+}
+
+impl Route {
+    pub fn is_default(&self) -> bool {
+        self.prefix_len == 0
+    }
+
+    fn parse(data: &[u8]) -> Result<Option<Self>, Error> {
+        if data.len() < RTMSG_LEN {
+            bail!("short rtmsg");
+        }
+
+        let Some(family) = Family::from_raw(data[0]) else {
+            return Ok(None);
+        };
+        if read_u32(data, 8).unwrap_or(0) & RTM_F_CLONED != 0 {
+            return Ok(None);
+        }
+
+        let mut route = Route {
+            family,
+            destination: None,
+            prefix_len: data[1],
+            gateway: None,
+            output_index: None,
+            source: None,
+            metric: None,
+            table: data[4] as u32,
+            protocol: data[5],
+            scope: data[6].into(),
+            route_type: data[7],
+            next_hops: Vec::new(),
+        };
+
+        for (kind, payload) in Attributes::new(&data[RTMSG_LEN..]) {
+            match kind {
+                RTA_DST => route.destination = parse_ip(family, payload),
+                RTA_GATEWAY => route.gateway = parse_ip(family, payload),
+                RTA_OIF => route.output_index = read_u32(payload, 0),
+                RTA_PREFSRC => route.source = parse_ip(family, payload),
+                RTA_PRIORITY => route.metric = read_u32(payload, 0),
+                RTA_TABLE => route.table = read_u32(payload, 0).unwrap_or(route.table),
+                RTA_MULTIPATH => route.next_hops = parse_next_hops(family, payload),
+                _ => (),
+            }
+        }
+
+        Ok(Some(route))
+    }
+}
+
+// parses a list of `struct rtnexthop`, each followed by its attributes
+fn parse_next_hops(family: Family, mut data: &[u8]) -> Vec<NextHop> {
+    let mut next_hops = Vec::new();
+
+    while let Some(len) = read_u16(data, 0) {
+        let len = len as usize;
+        if len < 8 || len > data.len() {
+            break;
+        }
+
+        let mut next_hop = NextHop {
+            gateway: None,
+            output_index: read_u32(data, 4).unwrap_or(0),
+            weight: data[3] as u16 + 1,
+        };
+        for (kind, payload) in Attributes::new(&data[8..len]) {
+            if kind == RTA_GATEWAY {
+                next_hop.gateway = parse_ip(family, payload);
+            }
+        }
+        next_hops.push(next_hop);
+
+        data = data.get(align(len)..).unwrap_or_default();
+    }
+
+    next_hops
+}
+
+/// The state of a neighbour table entry (`NUD_*`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NeighbourState {
+    None,
+    Incomplete,
+    Reachable,
+    Stale,
+    Delay,
+    Probe,
+    Failed,
+    NoArp,
+    Permanent,
+    /// A combination of states.
+    Other(u16),
+}
+
+impl From<u16> for NeighbourState {
+    fn from(state: u16) -> Self {
+        match state {
+            libc::NUD_NONE => NeighbourState::None,
+            libc::NUD_INCOMPLETE => NeighbourState::Incomplete,
+            libc::NUD_REACHABLE => NeighbourState::Reachable,
+            libc::NUD_STALE => NeighbourState::Stale,
+            libc::NUD_DELAY => NeighbourState::Delay,
+            libc::NUD_PROBE => NeighbourState::Probe,
+            libc::NUD_FAILED => NeighbourState::Failed,
+            libc::NUD_NOARP => NeighbourState::NoArp,
+            libc::NUD_PERMANENT => NeighbourState::Permanent,
+            other => NeighbourState::Other(other),
+        }
+    }
+}
+
+/// An entry of the ARP or IPv6 neighbour table.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Neighbour {
+    /// The index of the interface the neighbour is reachable on.
+    pub index: u32,
+    pub address: IpAddr,
+    /// The hardware address, not known for incomplete or failed entries.
+    pub hw_address: Option<String>,
+    pub state: NeighbourState,
+    /// Whether the neighbour is an IPv6 router.
+    pub router: bool,
+}
+
+impl Neighbour {
+    fn parse(data: &[u8]) -> Result<Option<Self>, Error> {
+        if data.len() < NDMSG_LEN {
+            bail!("short ndmsg");
+        }
+
+        let Some(family) = Family::from_raw(data[0]) else {
+            return Ok(None);
+        };
+        let index = read_u32(data, 4).unwrap_or(0);
+
+        let mut address = None;
+        let mut hw_address = None;
+        for (kind, payload) in Attributes::new(&data[NDMSG_LEN..]) {
+            match kind {
+                NDA_DST => address = parse_ip(family, payload),
+                NDA_LLADDR => hw_address = Some(format_hw_address(payload)),
+                _ => (),
+            }
+        }
+
+        let Some(address) = address else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            index,
+            address,
+            hw_address,
+            state: read_u16(data, 8).unwrap_or(0).into(),
+            router: data[10] & NTF_ROUTER != 0,
+        }))
+    }
+}
+
+/// Read all network interfaces, including their speed and duplex mode.
+pub fn read_links() -> Result<Vec<Link>, Error> {
+    let mut links = dump(RTM_GETLINK, RTM_NEWLINK, &[0u8; IFINFOMSG_LEN], |data| {
+        Link::parse(data).map(Some)
+    })?;
+
+    for link in links.iter_mut() {
+        link.read_speed_and_duplex();
+    }
+
+    Ok(links)
+}
+
+/// Read the IPv4 and IPv6 addresses of all interfaces.
+pub fn read_addresses() -> Result<Vec<Address>, Error> {
+    dump(
+        RTM_GETADDR,
+        RTM_NEWADDR,
+        &[0u8; IFADDRMSG_LEN],
+        Address::parse,
+    )
+}
+
+/// Read the IPv4 and IPv6 routes of all routing tables.
+///
+/// Use [`Route::table`] to filter for e.g. the main table.
+pub fn read_routes() -> Result<Vec<Route>, Error> {
+    dump(RTM_GETROUTE, RTM_NEWROUTE, &[0u8; RTMSG_LEN], Route::parse)
+}
+
+/// Read the ARP and IPv6 neighbour tables.
+pub fn read_neighbours() -> Result<Vec<Neighbour>, Error> {
+    dump(
+        RTM_GETNEIGH,
+        RTM_NEWNEIGH,
+        &[0u8; NDMSG_LEN],
+        Neighbour::parse,
+    )
+}
+
+// Send a dump request with the (all zero, i.e. AF_UNSPEC) family header and collect the replies.
+//
+// Dumps interrupted by a concurrent change may be inconsistent, so they are retried.
+fn dump<T>(
+    request: u16,
+    reply: u16,
+    header: &[u8],
+    parse: impl Fn(&[u8]) -> Result<Option<T>, Error>,
+) -> Result<Vec<T>, Error> {
+    for _ in 0..DUMP_ATTEMPTS {
+        if let Some(result) = dump_once(request, reply, header, &parse)? {
+            return Ok(result);
+        }
+    }
+    bail!("netlink dump was interrupted by concurrent changes {DUMP_ATTEMPTS} times");
+}
+
+// Returns `None` if the dump was interrupted.
+fn dump_once<T>(
+    request: u16,
+    reply: u16,
+    header: &[u8],
+    parse: &impl Fn(&[u8]) -> Result<Option<T>, Error>,
+) -> Result<Option<Vec<T>>, Error> {
+    const SEQ: u32 = 1;
+
+    let socket = socket(
+        AddressFamily::Netlink,
+        SockType::Raw,
+        SockFlag::SOCK_CLOEXEC,
+        SockProtocol::NetlinkRoute,
+    )
+    .map_err(|err| format_err!("unable to open netlink socket - {}", err))?;
+    let fd = socket.as_raw_fd();
+    bind(fd, &NetlinkAddr::new(0, 0))?;
+
+    let mut message = Vec::with_capacity(NLMSG_HDRLEN + header.len());
+    message.extend_from_slice(&((NLMSG_HDRLEN + header.len()) as u32).to_ne_bytes());
+    message.extend_from_slice(&request.to_ne_bytes());
+    message.extend_from_slice(&(NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+    message.extend_from_slice(&SEQ.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(header);
+    send(fd, &message, MsgFlags::empty())?;
+
+    let mut result = Vec::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        // with MSG_TRUNC, the full length is returned even if the datagram did not fit
+        let len = recv(fd, &mut buffer, MsgFlags::MSG_TRUNC)?;
+        if len == 0 {
+            bail!("netlink socket closed unexpectedly");
+        }
+        if len > buffer.len() {
+            bail!(
+                "netlink reply of {len} bytes exceeds the receive buffer of {} bytes",
+                buffer.len()
+            );
+        }
+
+        for message in Messages::new(&buffer[..len]) {
+            let message = message?;
+            if message.seq != SEQ {
+                continue;
+            }
+            if message.flags & NLM_F_DUMP_INTR != 0 {
+                return Ok(None);
+            }
+            match message.kind {
+                NLMSG_DONE => return Ok(Some(result)),
+                NLMSG_ERROR => check_error(message.payload)?,
+                kind if kind == reply => {
+                    if let Some(item) = parse(message.payload)? {
+                        result.push(item);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn check_error(payload: &[u8]) -> Result<(), Error> {
+    let errno = read_u32(payload, 0).ok_or_else(|| format_err!("short netlink error message"))?;
+    match errno as i32 {
+        0 => Ok(()),
+        errno => bail!(
+            "netlink request failed - {}",
+            nix::errno::Errno::from_raw(-errno)
+        ),
+    }
+}
+
+struct Message<'a> {
+    kind: u16,
+    flags: u16,
+    seq: u32,
+    payload: &'a [u8],
+}
+
+// iterates the netlink messages (`struct nlmsghdr` and payload) in a buffer
+struct Messages<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Messages<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Messages<'a> {
+    type Item = Result<Message<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let len = read_u32(self.data, 0).unwrap_or(0) as usize;
+        if len < NLMSG_HDRLEN || len > self.data.len() {
+            self.data = &[];
+            return Some(Err(format_err!("truncated netlink message")));
+        }
+
+        let message = Message {
+            kind: read_u16(self.data, 4).unwrap_or(0),
+            flags: read_u16(self.data, 6).unwrap_or(0),
+            seq: read_u32(self.data, 8).unwrap_or(0),
+            payload: &self.data[NLMSG_HDRLEN..len],
+        };
+        self.data = self.data.get(align(len)..).unwrap_or_default();
+
+        Some(Ok(message))
+    }
+}
+
+// iterates the attributes (`struct rtattr` and payload) in a buffer, stops at malformed ones
+struct Attributes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Attributes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = read_u16(self.data, 0)? as usize;
+        let kind = read_u16(self.data, 2)? & NLA_TYPE_MASK;
+        if len < 4 || len > self.data.len() {
+            self.data = &[];
+            return None;
+        }
+
+        let payload = &self.data[4..len];
+        self.data = self.data.get(align(len)..).unwrap_or_default();
+
+        Some((kind, payload))
+    }
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_ne_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_ne_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn parse_string(data: &[u8]) -> String {
+    let data = data.split(|b| *b == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(data).into_owned()
+}
+
+fn parse_ip(family: Family, data: &[u8]) -> Option<IpAddr> {
+    match family {
+        Family::Inet => Some(Ipv4Addr::from(<[u8; 4]>::try_from(data).ok()?).into()),
+        Family::Inet6 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?).into()),
+    }
+}
+
+fn format_hw_address(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attr(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&((4 + payload.len()) as u16).to_ne_bytes());
+        data.extend_from_slice(&kind.to_ne_bytes());
+        data.extend_from_slice(payload);
+        data.resize(align(data.len()), 0);
+        data
+    }
+
+    fn message(kind: u16, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        data.extend_from_slice(&kind.to_ne_bytes());
+        data.extend_from_slice(&0u16.to_ne_bytes());
+        data.extend_from_slice(&seq.to_ne_bytes());
+        data.extend_from_slice(&0u32.to_ne_bytes());
+        data.extend_from_slice(payload);
+        data.resize(align(data.len()), 0);
+        data
+    }
+
+    #[test]
+    fn test_parse_link() {
+        let mut data = vec![0u8; IFINFOMSG_LEN];
+        data[2..4].copy_from_slice(&1u16.to_ne_bytes());
+        data[4..8].copy_from_slice(&3u32.to_ne_bytes());
+        data[8..12].copy_from_slice(&(IFF_UP | IFF_LOWER_UP).to_ne_bytes());
+        data.extend(attr(IFLA_IFNAME, b"eno1\0"));
+        data.extend(attr(IFLA_MTU, &9000u32.to_ne_bytes()));
+        data.extend(attr(IFLA_MASTER, &7u32.to_ne_bytes()));
+        data.extend(attr(IFLA_OPERSTATE, &[6]));
+        data.extend(attr(IFLA_CARRIER, &[1]));
+        data.extend(attr(IFLA_ADDRESS, &[0x52, 0x54, 0x00, 0x12, 0xab, 0xcd]));
+        let linkinfo = attr(IFLA_INFO_SLAVE_KIND, b"bond\0");
+        data.extend(attr(IFLA_LINKINFO, &linkinfo));
+        let stats: Vec<u8> = (1..=24u64).flat_map(|v| v.to_ne_bytes()).collect();
+        data.extend(attr(IFLA_STATS64, &stats));
+
+        let link = Link::parse(&data).expect("failed to parse link");
+        assert_eq!(link.index, 3);
+        assert_eq!(link.name, "eno1");
+        assert_eq!(link.link_type, 1);
+        assert!(link.is_up() && link.is_lower_up() && !link.is_loopback());
+        assert_eq!(link.mtu, Some(9000));
+        assert_eq!(link.master, Some(7));
+        assert_eq!(link.operstate, OperState::Up);
+        assert_eq!(link.carrier, Some(true));
+        assert_eq!(link.hw_address.as_deref(), Some("52:54:00:12:ab:cd"));
+        assert_eq!(link.kind, None);
+        assert_eq!(link.slave_kind.as_deref(), Some("bond"));
+
+        let stats = link.stats.as_ref().unwrap();
+        assert_eq!(stats.rx_packets, 1);
+        assert_eq!(stats.tx_bytes, 4);
+        assert_eq!(stats.collisions, 10);
+
+        let bond = Link {
+            index: 7,
+            name: "bond0".to_string(),
+            kind: Some("bond".to_string()),
+            ..Default::default()
+        };
+        assert!(bond.is_bond());
+        let links = [bond.clone(), link];
+        let members: Vec<_> = bond.members(&links).map(|l| l.name.as_str()).collect();
+        assert_eq!(members, ["eno1"]);
+
+        assert!(Link::parse(&[0u8; IFINFOMSG_LEN]).is_err());
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let mut data = vec![libc::AF_INET as u8, 24, 0x80, 0, 2, 0, 0, 0];
+        data.extend(attr(IFA_ADDRESS, &[192, 168, 1, 10]));
+        data.extend(attr(IFA_LOCAL, &[192, 168, 1, 10]));
+        data.extend(attr(IFA_LABEL, b"vmbr0\0"));
+
+        let address = Address::parse(&data).unwrap().unwrap();
+        assert_eq!(address.index, 2);
+        assert_eq!(address.family(), Family::Inet);
+        assert_eq!(address.address, "192.168.1.10".parse::<IpAddr>().unwrap());
+        assert_eq!(address.prefix_len, 24);
+        assert_eq!(address.peer, None);
+        assert_eq!(address.label.as_deref(), Some("vmbr0"));
+        assert_eq!(address.scope, Scope::Global);
+        assert_eq!(address.flags, IFA_F_PERMANENT);
+
+        let mut data = vec![libc::AF_INET6 as u8, 64, 0, libc::RT_SCOPE_LINK, 2, 0, 0, 0];
+        let ip: Ipv6Addr = "fe80::5054:ff:fe12:abcd".parse().unwrap();
+        data.extend(attr(IFA_ADDRESS, &ip.octets()));
+        data.extend(attr(
+            IFA_FLAGS,
+            &(IFA_F_PERMANENT | IFA_F_TENTATIVE).to_ne_bytes(),
+        ));
+
+        let address = Address::parse(&data).unwrap().unwrap();
+        assert_eq!(address.address, IpAddr::V6(ip));
+        assert_eq!(address.scope, Scope::Link);
+        assert!(address.is_tentative());
+        assert!(!address.is_deprecated());
+
+        // other families are skipped
+        assert!(
+            Address::parse(&[libc::AF_PACKET as u8, 0, 0, 0, 2, 0, 0, 0])
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_parse_route() {
+        let mut data = vec![libc::AF_INET6 as u8, 0, 0, 0, 254, 4, 0, 1, 0, 0, 0, 0];
+        let gateway: Ipv6Addr = "fe80::1".parse().unwrap();
+        data.extend(attr(RTA_GATEWAY, &gateway.octets()));
+        data.extend(attr(RTA_OIF, &2u32.to_ne_bytes()));
+        data.extend(attr(RTA_PRIORITY, &1024u32.to_ne_bytes()));
+        data.extend(attr(RTA_TABLE, &254u32.to_ne_bytes()));
+
+        let route = Route::parse(&data).unwrap().unwrap();
+        assert!(route.is_default());
+        assert_eq!(route.family, Family::Inet6);
+        assert_eq!(route.destination, None);
+        assert_eq!(route.gateway, Some(IpAddr::V6(gateway)));
+        assert_eq!(route.output_index, Some(2));
+        assert_eq!(route.metric, Some(1024));
+        assert_eq!(route.table, 254);
+        assert_eq!(route.protocol, 4);
+        assert_eq!(route.scope, Scope::Global);
+
+        let mut data = vec![libc::AF_INET as u8, 16, 0, 0, 254, 4, 0, 1, 0, 0, 0, 0];
+        data.extend(attr(RTA_DST, &[10, 10, 0, 0]));
+        let mut next_hops = Vec::new();
+        for (index, gateway, hops) in [(2u32, [192, 168, 1, 1], 0u8), (3, [192, 168, 2, 1], 1)] {
+            let gateway = attr(RTA_GATEWAY, &gateway);
+            next_hops.extend_from_slice(&((8 + gateway.len()) as u16).to_ne_bytes());
+            next_hops.extend_from_slice(&[0, hops]);
+            next_hops.extend_from_slice(&index.to_ne_bytes());
+            next_hops.extend(gateway);
+        }
+        data.extend(attr(RTA_MULTIPATH, &next_hops));
+
+        let route = Route::parse(&data).unwrap().unwrap();
+        assert!(!route.is_default());
+        assert_eq!(route.destination, Some("10.10.0.0".parse().unwrap()));
+        assert_eq!(route.prefix_len, 16);
+        assert_eq!(route.gateway, None);
+        assert_eq!(
+            route.next_hops,
+            [
+                NextHop {
+                    gateway: Some("192.168.1.1".parse().unwrap()),
+                    output_index: 2,
+                    weight: 1,
+                },
+                NextHop {
+                    gateway: Some("192.168.2.1".parse().unwrap()),
+                    output_index: 3,
+                    weight: 2,
+                },
+            ]
+        );
+
+        // cloned (cached) routes are skipped
+        let mut data = vec![libc::AF_INET6 as u8, 128, 0, 0, 254, 2, 0, 1];
+        data.extend_from_slice(&RTM_F_CLONED.to_ne_bytes());
+        assert!(Route::parse(&data).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_neighbour() {
+        let mut data = vec![libc::AF_INET as u8, 0, 0, 0];
+        data.extend_from_slice(&2u32.to_ne_bytes());
+        data.extend_from_slice(&libc::NUD_REACHABLE.to_ne_bytes());
+        data.extend_from_slice(&[0, 1]);
+        data.extend(attr(NDA_DST, &[192, 168, 1, 1]));
+        data.extend(attr(NDA_LLADDR, &[0xaa, 0xbb, 0xcc, 0x00, 0x11, 0x22]));
+
+        let neighbour = Neighbour::parse(&data).unwrap().unwrap();
+        assert_eq!(neighbour.index, 2);
+        assert_eq!(neighbour.address, "192.168.1.1".parse::<IpAddr>().unwrap());
+        assert_eq!(neighbour.hw_address.as_deref(), Some("aa:bb:cc:00:11:22"));
+        assert_eq!(neighbour.state, NeighbourState::Reachable);
+        assert!(!neighbour.router);
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut data = message(RTM_NEWLINK, 1, &[1, 2, 3]);
+        data.extend(message(NLMSG_ERROR, 1, &(-libc::EPERM).to_ne_bytes()));
+        data.extend(message(NLMSG_DONE, 1, &0u32.to_ne_bytes()));
+
+        let messages: Vec<_> = Messages::new(&data).collect::<Result<_, _>>().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].kind, RTM_NEWLINK);
+        assert_eq!(messages[0].payload, [1, 2, 3]);
+        assert!(check_error(messages[1].payload).is_err());
+        assert!(check_error(&0u32.to_ne_bytes()).is_ok());
+        assert_eq!(messages[2].kind, NLMSG_DONE);
+
+        let mut data = message(RTM_NEWLINK, 1, &[]);
+        data.truncate(NLMSG_HDRLEN - 1);
+        assert!(Messages::new(&data).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_read_loopback() {
+        let links = read_links().expect("failed to read links");
+        let lo = links
+            .iter()
+            .find(|link| link.is_loopback())
+            .expect("no loopback link");
+
+        let addresses = read_addresses().expect("failed to read addresses");
+        if lo.is_up() {
+            assert!(addresses
+                .iter()
+                .any(|address| address.index == lo.index && address.address.is_loopback()));
+        }
+
+        read_routes().expect("failed to read routes");
+        read_neighbours().expect("failed to read neighbours");
+    }
+}
@@ -0,0 +1,465 @@
+//! Statistics of cgroup v2 control groups from `/sys/fs/cgroup`.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use nix::unistd::Pid;
+use serde::Serialize;
+
+use super::pressure::{Pressure, PressureResource};
+
+/// Mount point of the unified cgroup hierarchy.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// A control group of the unified (v2) hierarchy.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CGroup {
+    path: PathBuf,
+}
+
+impl CGroup {
+    /// The root control group.
+    pub fn root() -> Self {
+        Self::from_path(CGROUP_ROOT)
+    }
+
+    /// A control group by its name relative to the root, e.g. `qemu.slice/100.scope`.
+    pub fn new<N: AsRef<Path>>(name: N) -> Self {
+        let name = name.as_ref();
+        Self::from_path(Path::new(CGROUP_ROOT).join(name.strip_prefix("/").unwrap_or(name)))
+    }
+
+    /// A control group by the path of its directory.
+    pub fn from_path<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The control group of a process, from `/proc/PID/cgroup`.
+    pub fn of_pid(pid: Pid) -> Result<Self, Error> {
+        let path = format!("/proc/{}/cgroup", pid);
+        let data = std::fs::read_to_string(&path)
+            .map_err(|err| format_err!("unable to read {} - {}", path, err))?;
+        Self::parse_proc_cgroup(&data).ok_or_else(|| format_err!("no cgroup v2 entry in {}", path))
+    }
+
+    fn parse_proc_cgroup(data: &str) -> Option<Self> {
+        data.lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .map(Self::new)
+    }
+
+    /// The directory of the control group.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The direct child groups.
+    pub fn children(&self) -> Result<Vec<CGroup>, Error> {
+        let mut children = Vec::new();
+        let entries = std::fs::read_dir(&self.path)
+            .map_err(|err| format_err!("unable to read {:?} - {}", self.path, err))?;
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                children.push(Self::from_path(entry.path()));
+            }
+        }
+        Ok(children)
+    }
+
+    fn read_file(&self, name: &str) -> Result<String, Error> {
+        let path = self.path.join(name);
+        std::fs::read_to_string(&path)
+            .map_err(|err| format_err!("unable to read {:?} - {}", path, err))
+    }
+
+    /// Read and parse `cpu.stat`.
+    pub fn cpu_stat(&self) -> Result<CGroupCpuStat, Error> {
+        CGroupCpuStat::parse(&self.read_file("cpu.stat")?)
+    }
+
+    /// Read `memory.current`, the memory usage in bytes including the page cache.
+    pub fn memory_current(&self) -> Result<u64, Error> {
+        let data = self.read_file("memory.current")?;
+        data.trim()
+            .parse()
+            .map_err(|err| format_err!("unable to parse memory.current - {}", err))
+    }
+
+    /// Read and parse `memory.stat`.
+    pub fn memory_stat(&self) -> Result<CGroupMemoryStat, Error> {
+        CGroupMemoryStat::parse(&self.read_file("memory.stat")?)
+    }
+
+    /// Read and parse `io.stat`, which contains one entry per device.
+    pub fn io_stat(&self) -> Result<Vec<CGroupIoStat>, Error> {
+        CGroupIoStat::parse(&self.read_file("io.stat")?)
+    }
+
+    /// Read and parse the `cpu.pressure`, `memory.pressure` or `io.pressure` file.
+    pub fn pressure(&self, resource: PressureResource) -> Result<Pressure, Error> {
+        Pressure::read(self.path.join(format!("{}.pressure", resource.as_str())))
+    }
+}
+
+/// Iterate over the `key value` lines of flat keyed cgroup files.
+fn parse_flat_keyed(data: &str) -> impl Iterator<Item = Result<(&str, u64), Error>> {
+    data.lines().filter(|line| !line.is_empty()).map(|line| {
+        let (key, value) = line
+            .split_once(' ')
+            .ok_or_else(|| format_err!("invalid line '{}'", line))?;
+        let value = value
+            .trim()
+            .parse()
+            .map_err(|err| format_err!("invalid value for '{}' - {}", key, err))?;
+        Ok((key, value))
+    })
+}
+
+/// The `cpu.stat` values of a control group.
+///
+/// The throttling values are only available if the `cpu` controller is enabled for the group.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CGroupCpuStat {
+    /// Total CPU time in microseconds.
+    pub usage_usec: u64,
+    /// CPU time spent in user mode in microseconds.
+    pub user_usec: u64,
+    /// CPU time spent in system mode in microseconds.
+    pub system_usec: u64,
+    /// Number of enforcement periods which elapsed.
+    pub nr_periods: u64,
+    /// Number of periods in which the group was throttled.
+    pub nr_throttled: u64,
+    /// Total time throttled in microseconds.
+    pub throttled_usec: u64,
+}
+
+impl CGroupCpuStat {
+    /// Parse the contents of a `cpu.stat` file.
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        let mut stat = Self::default();
+        let mut has_usage = false;
+        for item in parse_flat_keyed(data) {
+            let (key, value) = item?;
+            match key {
+                "usage_usec" => {
+                    stat.usage_usec = value;
+                    has_usage = true;
+                }
+                "user_usec" => stat.user_usec = value,
+                "system_usec" => stat.system_usec = value,
+                "nr_periods" => stat.nr_periods = value,
+                "nr_throttled" => stat.nr_throttled = value,
+                "throttled_usec" => stat.throttled_usec = value,
+                _ => (),
+            }
+        }
+        if !has_usage {
+            bail!("missing 'usage_usec' in cpu.stat");
+        }
+        Ok(stat)
+    }
+
+    /// The difference of the counters to the 'prev' sample.
+    pub fn delta(&self, prev: &Self) -> Self {
+        Self {
+            usage_usec: self.usage_usec.saturating_sub(prev.usage_usec),
+            user_usec: self.user_usec.saturating_sub(prev.user_usec),
+            system_usec: self.system_usec.saturating_sub(prev.system_usec),
+            nr_periods: self.nr_periods.saturating_sub(prev.nr_periods),
+            nr_throttled: self.nr_throttled.saturating_sub(prev.nr_throttled),
+            throttled_usec: self.throttled_usec.saturating_sub(prev.throttled_usec),
+        }
+    }
+
+    /// The average number of CPUs used between the 'prev' sample and this one, taken 'elapsed'
+    /// apart, e.g. `1.5` if one and a half CPUs were busy.
+    pub fn cpu_usage(&self, prev: &Self, elapsed: Duration) -> f64 {
+        let elapsed = elapsed.as_micros() as f64;
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        self.usage_usec.saturating_sub(prev.usage_usec) as f64 / elapsed
+    }
+
+    /// The fraction (0 - 1.0) of enforcement periods in which the group was throttled since the
+    /// 'prev' sample.
+    pub fn throttled_ratio(&self, prev: &Self) -> f64 {
+        let delta = self.delta(prev);
+        if delta.nr_periods == 0 {
+            return 0.0;
+        }
+        delta.nr_throttled as f64 / delta.nr_periods as f64
+    }
+}
+
+/// The commonly used `memory.stat` values of a control group, in bytes or events.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CGroupMemoryStat {
+    /// Anonymous memory, e.g. heap and stack.
+    pub anon: u64,
+    /// Page cache, including tmpfs and shared memory.
+    pub file: u64,
+    /// Kernel memory, not available on kernels older than 5.18.
+    pub kernel: u64,
+    /// Memory allocated to kernel stacks.
+    pub kernel_stack: u64,
+    /// Memory allocated for page tables.
+    pub pagetables: u64,
+    /// Memory used in network transmission buffers.
+    pub sock: u64,
+    /// Swap backed memory, like tmpfs and shared memory.
+    pub shmem: u64,
+    /// Page cache mapped into memory.
+    pub file_mapped: u64,
+    /// Page cache modified but not yet written back.
+    pub file_dirty: u64,
+    /// Page cache being written back.
+    pub file_writeback: u64,
+    /// Memory used for in-kernel data structures.
+    pub slab: u64,
+    /// Reclaimable part of `slab`.
+    pub slab_reclaimable: u64,
+    /// Anonymous memory on the active LRU list.
+    pub active_anon: u64,
+    /// Anonymous memory on the inactive LRU list.
+    pub inactive_anon: u64,
+    /// Page cache on the active LRU list.
+    pub active_file: u64,
+    /// Page cache on the inactive LRU list.
+    pub inactive_file: u64,
+    /// Memory which cannot be reclaimed, e.g. locked memory.
+    pub unevictable: u64,
+    /// Number of page faults.
+    pub pgfault: u64,
+    /// Number of major page faults.
+    pub pgmajfault: u64,
+}
+
+impl CGroupMemoryStat {
+    /// Parse the contents of a `memory.stat` file, unknown keys are ignored.
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        let mut stat = Self::default();
+        for item in parse_flat_keyed(data) {
+            let (key, value) = item?;
+            let field = match key {
+                "anon" => &mut stat.anon,
+                "file" => &mut stat.file,
+                "kernel" => &mut stat.kernel,
+                "kernel_stack" => &mut stat.kernel_stack,
+                "pagetables" => &mut stat.pagetables,
+                "sock" => &mut stat.sock,
+                "shmem" => &mut stat.shmem,
+                "file_mapped" => &mut stat.file_mapped,
+                "file_dirty" => &mut stat.file_dirty,
+                "file_writeback" => &mut stat.file_writeback,
+                "slab" => &mut stat.slab,
+                "slab_reclaimable" => &mut stat.slab_reclaimable,
+                "active_anon" => &mut stat.active_anon,
+                "inactive_anon" => &mut stat.inactive_anon,
+                "active_file" => &mut stat.active_file,
+                "inactive_file" => &mut stat.inactive_file,
+                "unevictable" => &mut stat.unevictable,
+                "pgfault" => &mut stat.pgfault,
+                "pgmajfault" => &mut stat.pgmajfault,
+                _ => continue,
+            };
+            *field = value;
+        }
+        Ok(stat)
+    }
+}
+
+/// The `io.stat` values of a control group for a single device.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CGroupIoStat {
+    /// Major number of the device.
+    pub major: u32,
+    /// Minor number of the device.
+    pub minor: u32,
+    /// Bytes read.
+    pub rbytes: u64,
+    /// Bytes written.
+    pub wbytes: u64,
+    /// Number of read operations.
+    pub rios: u64,
+    /// Number of write operations.
+    pub wios: u64,
+    /// Bytes discarded.
+    pub dbytes: u64,
+    /// Number of discard operations.
+    pub dios: u64,
+}
+
+impl CGroupIoStat {
+    /// Parse the contents of an `io.stat` file.
+    pub fn parse(data: &str) -> Result<Vec<Self>, Error> {
+        let mut result = Vec::new();
+        for line in data.lines() {
+            let mut parts = line.split_ascii_whitespace();
+            let Some(device) = parts.next() else {
+                continue;
+            };
+            let (major, minor) = device
+                .split_once(':')
+                .ok_or_else(|| format_err!("invalid device '{}' in io.stat", device))?;
+            let mut stat = Self {
+                major: major.parse()?,
+                minor: minor.parse()?,
+                ..Default::default()
+            };
+            for part in parts {
+                let (key, value) = part
+                    .split_once('=')
+                    .ok_or_else(|| format_err!("invalid field '{}' in io.stat", part))?;
+                let field = match key {
+                    "rbytes" => &mut stat.rbytes,
+                    "wbytes" => &mut stat.wbytes,
+                    "rios" => &mut stat.rios,
+                    "wios" => &mut stat.wios,
+                    "dbytes" => &mut stat.dbytes,
+                    "dios" => &mut stat.dios,
+                    _ => continue,
+                };
+                *field = value.parse()?;
+            }
+            result.push(stat);
+        }
+        Ok(result)
+    }
+
+    /// The difference of the counters to the 'prev' sample of the same device.
+    pub fn delta(&self, prev: &Self) -> Self {
+        Self {
+            major: self.major,
+            minor: self.minor,
+            rbytes: self.rbytes.saturating_sub(prev.rbytes),
+            wbytes: self.wbytes.saturating_sub(prev.wbytes),
+            rios: self.rios.saturating_sub(prev.rios),
+            wios: self.wios.saturating_sub(prev.wios),
+            dbytes: self.dbytes.saturating_sub(prev.dbytes),
+            dios: self.dios.saturating_sub(prev.dios),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cgroup_paths() {
+        assert_eq!(CGroup::root().path(), Path::new("/sys/fs/cgroup"));
+        assert_eq!(
+            CGroup::new("/qemu.slice/100.scope").path(),
+            Path::new("/sys/fs/cgroup/qemu.slice/100.scope")
+        );
+        assert_eq!(
+            CGroup::parse_proc_cgroup("0::/system.slice/pveproxy.service\n"),
+            Some(CGroup::new("system.slice/pveproxy.service"))
+        );
+        assert_eq!(CGroup::parse_proc_cgroup("0::/\n"), Some(CGroup::root()));
+        assert_eq!(
+            CGroup::parse_proc_cgroup("1:name=systemd:/init.scope\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_cpu_stat() {
+        let prev = CGroupCpuStat::parse(
+            "usage_usec 1000000\n\
+             user_usec 600000\n\
+             system_usec 400000\n\
+             core_sched.force_idle_usec 0\n\
+             nr_periods 100\n\
+             nr_throttled 10\n\
+             throttled_usec 50000\n\
+             nr_bursts 0\n\
+             burst_usec 0\n",
+        )
+        .expect("failed to parse cpu.stat");
+        assert_eq!(prev.usage_usec, 1000000);
+        assert_eq!(prev.user_usec, 600000);
+        assert_eq!(prev.system_usec, 400000);
+        assert_eq!(prev.nr_periods, 100);
+        assert_eq!(prev.nr_throttled, 10);
+        assert_eq!(prev.throttled_usec, 50000);
+
+        let current = CGroupCpuStat::parse(
+            "usage_usec 4000000\nuser_usec 2000000\nsystem_usec 2000000\n\
+             nr_periods 200\nnr_throttled 60\nthrottled_usec 90000\n",
+        )
+        .unwrap();
+        assert_eq!(current.cpu_usage(&prev, Duration::from_secs(2)), 1.5);
+        assert_eq!(current.throttled_ratio(&prev), 0.5);
+        assert_eq!(current.delta(&prev).throttled_usec, 40000);
+
+        // without the cpu controller
+        let stat = CGroupCpuStat::parse("usage_usec 10\nuser_usec 5\nsystem_usec 5\n").unwrap();
+        assert_eq!(stat.throttled_ratio(&CGroupCpuStat::default()), 0.0);
+
+        assert!(CGroupCpuStat::parse("user_usec 5\n").is_err());
+        assert!(CGroupCpuStat::parse("usage_usec x\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_memory_stat() {
+        let stat = CGroupMemoryStat::parse(
+            "anon 104857600\n\
+             file 52428800\n\
+             kernel 8388608\n\
+             kernel_stack 294912\n\
+             pagetables 1048576\n\
+             sec_pagetables 0\n\
+             percpu 0\n\
+             sock 4096\n\
+             shmem 8192\n\
+             file_mapped 20971520\n\
+             file_dirty 12288\n\
+             file_writeback 0\n\
+             slab_reclaimable 2097152\n\
+             slab_unreclaimable 1048576\n\
+             slab 3145728\n\
+             pgfault 123456\n\
+             pgmajfault 42\n",
+        )
+        .expect("failed to parse memory.stat");
+        assert_eq!(stat.anon, 104857600);
+        assert_eq!(stat.file, 52428800);
+        assert_eq!(stat.kernel, 8388608);
+        assert_eq!(stat.sock, 4096);
+        assert_eq!(stat.file_dirty, 12288);
+        assert_eq!(stat.slab, 3145728);
+        assert_eq!(stat.slab_reclaimable, 2097152);
+        assert_eq!(stat.pgmajfault, 42);
+        assert_eq!(stat.unevictable, 0);
+    }
+
+    #[test]
+    fn test_parse_io_stat() {
+        let stats = CGroupIoStat::parse(
+            "8:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0\n\
+             253:1 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=8192 dios=2 cost.usage=10\n",
+        )
+        .expect("failed to parse io.stat");
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].major, stats[0].minor), (8, 0));
+        assert_eq!(stats[0].rbytes, 1459200);
+        assert_eq!(stats[0].wbytes, 314773504);
+        assert_eq!(stats[0].wios, 353);
+        assert_eq!((stats[1].major, stats[1].minor), (253, 1));
+        assert_eq!(stats[1].dios, 2);
+
+        let delta = stats[0].delta(&CGroupIoStat {
+            rbytes: 1459000,
+            ..Default::default()
+        });
+        assert_eq!(delta.rbytes, 200);
+        assert_eq!((delta.major, delta.minor), (8, 0));
+
+        assert!(CGroupIoStat::parse("8-0 rbytes=1\n").is_err());
+    }
+}
@@ -0,0 +1,506 @@
+//! Block device I/O statistics from `/proc/diskstats` and `/sys/class/block/*/stat`.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use serde::Serialize;
+
+use super::mountinfo::{Device, MountInfo};
+use super::unescape_octal;
+
+/// The unit of the sector counters, independent of the sector size of the device.
+pub const SECTOR_SIZE: u64 = 512;
+
+/// I/O statistics of a block device or partition.
+///
+/// See the kernel's `Documentation/admin-guide/iostats.rst`. Fields not provided by older kernels
+/// are zero.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DiskStat {
+    /// Major number of the device.
+    pub major: u32,
+    /// Minor number of the device.
+    pub minor: u32,
+    /// Kernel name of the device, e.g. `sda1`, `dm-0` or `zd16`.
+    pub name: String,
+    /// Reads completed.
+    pub read_ios: u64,
+    /// Adjacent reads merged.
+    pub read_merges: u64,
+    /// Sectors read.
+    pub read_sectors: u64,
+    /// Time spent reading in milliseconds.
+    pub read_ticks: u64,
+    /// Writes completed.
+    pub write_ios: u64,
+    /// Adjacent writes merged.
+    pub write_merges: u64,
+    /// Sectors written.
+    pub write_sectors: u64,
+    /// Time spent writing in milliseconds.
+    pub write_ticks: u64,
+    /// I/Os currently in progress.
+    pub in_flight: u64,
+    /// Time the device was busy in milliseconds.
+    pub io_ticks: u64,
+    /// Weighted time spent doing I/Os in milliseconds.
+    pub time_in_queue: u64,
+    /// Discards completed.
+    pub discard_ios: u64,
+    /// Adjacent discards merged.
+    pub discard_merges: u64,
+    /// Sectors discarded.
+    pub discard_sectors: u64,
+    /// Time spent discarding in milliseconds.
+    pub discard_ticks: u64,
+    /// Flush requests completed.
+    pub flush_ios: u64,
+    /// Time spent flushing in milliseconds.
+    pub flush_ticks: u64,
+}
+
+impl DiskStat {
+    /// The device number.
+    pub fn device(&self) -> Device {
+        Device::new(self.major, self.minor)
+    }
+
+    /// Parse the counters of a line from `/proc/diskstats` or a sysfs `stat` file.
+    fn parse_counters<'a>(&mut self, parts: impl Iterator<Item = &'a str>) -> Result<(), Error> {
+        let fields = [
+            &mut self.read_ios,
+            &mut self.read_merges,
+            &mut self.read_sectors,
+            &mut self.read_ticks,
+            &mut self.write_ios,
+            &mut self.write_merges,
+            &mut self.write_sectors,
+            &mut self.write_ticks,
+            &mut self.in_flight,
+            &mut self.io_ticks,
+            &mut self.time_in_queue,
+            &mut self.discard_ios,
+            &mut self.discard_merges,
+            &mut self.discard_sectors,
+            &mut self.discard_ticks,
+            &mut self.flush_ios,
+            &mut self.flush_ticks,
+        ];
+
+        let mut count = 0;
+        for (field, value) in fields.into_iter().zip(parts) {
+            *field = value
+                .parse()
+                .map_err(|err| format_err!("invalid counter '{}' - {}", value, err))?;
+            count += 1;
+        }
+
+        // the discard and flush counters were added in 4.18 and 5.5
+        if count < 11 {
+            bail!("missing counters in disk statistics");
+        }
+
+        Ok(())
+    }
+
+    /// Parse a line from `/proc/diskstats`.
+    pub fn parse_line(line: &str) -> Result<Self, Error> {
+        let mut parts = line.split_ascii_whitespace();
+        let mut next = |what| {
+            parts
+                .next()
+                .ok_or_else(|| format_err!("missing {} in diskstats line", what))
+        };
+
+        let mut stat = DiskStat {
+            major: next("major")?.parse()?,
+            minor: next("minor")?.parse()?,
+            name: next("name")?.to_string(),
+            ..Default::default()
+        };
+        stat.parse_counters(parts)?;
+
+        Ok(stat)
+    }
+
+    /// Compute the rates between the 'prev' sample of the same device and this one, taken
+    /// 'elapsed' apart.
+    pub fn rate(&self, prev: &DiskStat, elapsed: Duration) -> DiskRate {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return DiskRate::default();
+        }
+        let per_sec = |current: u64, prev: u64| current.saturating_sub(prev) as f64 / secs;
+
+        let read_ios = self.read_ios.saturating_sub(prev.read_ios);
+        let write_ios = self.write_ios.saturating_sub(prev.write_ios);
+        let ios = read_ios + write_ios;
+        let ticks = self.read_ticks.saturating_sub(prev.read_ticks)
+            + self.write_ticks.saturating_sub(prev.write_ticks);
+
+        DiskRate {
+            read_bytes: per_sec(self.read_sectors, prev.read_sectors) * SECTOR_SIZE as f64,
+            write_bytes: per_sec(self.write_sectors, prev.write_sectors) * SECTOR_SIZE as f64,
+            discard_bytes: per_sec(self.discard_sectors, prev.discard_sectors) * SECTOR_SIZE as f64,
+            read_ios: read_ios as f64 / secs,
+            write_ios: write_ios as f64 / secs,
+            utilization: (per_sec(self.io_ticks, prev.io_ticks) / 1000.0).min(1.0),
+            avg_latency_ms: if ios > 0 {
+                ticks as f64 / ios as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Rates of a block device between two samples, see [`DiskStat::rate`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DiskRate {
+    /// Bytes read per second.
+    pub read_bytes: f64,
+    /// Bytes written per second.
+    pub write_bytes: f64,
+    /// Bytes discarded per second.
+    pub discard_bytes: f64,
+    /// Reads per second.
+    pub read_ios: f64,
+    /// Writes per second.
+    pub write_ios: f64,
+    /// The fraction of time (0 - 1.0) the device was busy.
+    pub utilization: f64,
+    /// The average time a read or write took in milliseconds.
+    pub avg_latency_ms: f64,
+}
+
+/// Read the statistics of all block devices and partitions from `/proc/diskstats`.
+pub fn read_diskstats() -> Result<Vec<DiskStat>, Error> {
+    let data = std::fs::read_to_string("/proc/diskstats")?;
+    parse_diskstats(&data)
+}
+
+fn parse_diskstats(data: &str) -> Result<Vec<DiskStat>, Error> {
+    data.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(DiskStat::parse_line)
+        .collect()
+}
+
+/// Read the statistics of a single block device or partition from sysfs.
+///
+/// 'name' is the kernel name of the device, e.g. `sda`, `nvme0n1p2` or `dm-1`.
+pub fn read_block_device_stat(name: &str) -> Result<DiskStat, Error> {
+    let dir = sysfs_block_path(name)?;
+
+    let dev = std::fs::read_to_string(dir.join("dev"))
+        .map_err(|err| format_err!("unable to read device number of '{}' - {}", name, err))?;
+    let device: Device = dev.trim().parse()?;
+
+    let data = std::fs::read_to_string(dir.join("stat"))
+        .map_err(|err| format_err!("unable to read statistics of '{}' - {}", name, err))?;
+
+    let mut stat = DiskStat {
+        major: device.major(),
+        minor: device.minor(),
+        name: name.to_string(),
+        ..Default::default()
+    };
+    stat.parse_counters(data.split_ascii_whitespace())?;
+
+    Ok(stat)
+}
+
+fn sysfs_block_path(name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        bail!("invalid block device name '{}'", name);
+    }
+    Ok(Path::new("/sys/class/block").join(name))
+}
+
+/// Check whether the block device with the kernel name 'name' is a partition.
+pub fn is_partition(name: &str) -> bool {
+    sysfs_block_path(name)
+        .map(|path| path.join("partition").exists())
+        .unwrap_or(false)
+}
+
+/// The device-mapper name of a `dm-N` device, e.g. `pve-root`.
+pub fn device_mapper_name(name: &str) -> Result<Option<String>, Error> {
+    let path = sysfs_block_path(name)?.join("dm/name");
+    match std::fs::read_to_string(path) {
+        Ok(dm_name) => Ok(Some(dm_name.trim().to_string())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format_err!(
+            "unable to read dm name of '{}' - {}",
+            name,
+            err
+        )),
+    }
+}
+
+/// Map the kernel names of ZFS volumes (`zdN`) to their dataset names.
+///
+/// Partitions of volumes are included with their `-partN` suffix, e.g. `zd0p1` maps to
+/// `rpool/data/vm-100-disk-0-part1`.
+pub fn read_zvol_names() -> Result<HashMap<String, String>, Error> {
+    let mut names = HashMap::new();
+    let base = Path::new("/dev/zvol");
+    if base.exists() {
+        collect_zvol_names(base, base, &mut names)?;
+    }
+    Ok(names)
+}
+
+fn collect_zvol_names(
+    base: &Path,
+    dir: &Path,
+    names: &mut HashMap<String, String>,
+) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_zvol_names(base, &path, names)?;
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            let (Some(kernel_name), Ok(dataset)) = (target.file_name(), path.strip_prefix(base))
+            else {
+                continue;
+            };
+            names.insert(
+                kernel_name.to_string_lossy().into_owned(),
+                dataset.to_string_lossy().into_owned(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a human readable name for a block device.
+///
+/// Returns the device-mapper name for `dm-N` devices, the dataset name for ZFS volumes (looked
+/// up in 'zvol_names', see [`read_zvol_names`]) and the kernel name otherwise.
+pub fn resolve_device_name(name: &str, zvol_names: &HashMap<String, String>) -> String {
+    if let Some(dataset) = zvol_names.get(name) {
+        return dataset.clone();
+    }
+    if name.starts_with("dm-") {
+        if let Ok(Some(dm_name)) = device_mapper_name(name) {
+            return dm_name;
+        }
+    }
+    name.to_string()
+}
+
+/// Find the block device backing the file system 'path' is on.
+///
+/// Returns `None` for file systems without a single backing block device, e.g. ZFS datasets,
+/// network or virtual file systems.
+pub fn mount_block_device(mounts: &MountInfo, path: &Path) -> Result<Option<Device>, Error> {
+    let path = std::fs::canonicalize(path)
+        .map_err(|err| format_err!("unable to resolve {:?} - {}", path, err))?;
+
+    let mut candidates = Vec::new();
+    let mut found_len = 0;
+    for entry in mounts.values() {
+        let mount_point = PathBuf::from(OsStr::from_bytes(&unescape_octal(
+            entry.mount_point.as_os_str().as_bytes(),
+        )));
+        let len = mount_point.as_os_str().len();
+        if !path.starts_with(&mount_point) || len < found_len {
+            continue;
+        }
+        if len > found_len {
+            candidates.clear();
+            found_len = len;
+        }
+        candidates.push(entry);
+    }
+
+    let Some(mut entry) = candidates.first().copied() else {
+        return Ok(None);
+    };
+
+    // a mount on top of another one on the same mount point has the hidden one as its parent,
+    // mount IDs get reused and say nothing about the order
+    for _ in 1..candidates.len() {
+        match candidates
+            .iter()
+            .find(|other| other.parent == entry.id && other.id != entry.id)
+        {
+            Some(upper) => entry = upper,
+            None => break,
+        }
+    }
+
+    if entry.device.major() != 0 {
+        return Ok(Some(entry.device));
+    }
+
+    // file systems like btrfs report an anonymous device, use their source if it is a device
+    match &entry.mount_source {
+        Some(source) if source.as_bytes().starts_with(b"/dev/") => {
+            let source = PathBuf::from(OsStr::from_bytes(&unescape_octal(source.as_bytes())));
+            match std::fs::metadata(&source) {
+                Ok(metadata) if metadata.file_type().is_block_device() => {
+                    use std::os::unix::fs::MetadataExt;
+                    Ok(Some(Device::from_dev_t(metadata.rdev())))
+                }
+                _ => Ok(None),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Read the statistics of the block device backing the file system 'path' is on.
+///
+/// See [`mount_block_device`].
+pub fn read_mount_diskstat(path: &Path) -> Result<Option<DiskStat>, Error> {
+    let mounts = MountInfo::read()?;
+    let Some(device) = mount_block_device(&mounts, path)? else {
+        return Ok(None);
+    };
+    Ok(read_diskstats()?
+        .into_iter()
+        .find(|stat| stat.device() == device))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISKSTATS: &str = "\
+   8       0 sda 93851 21870 6710562 48123 288402 213958 19340586 375013 0 264488 459270 0 0 0 0 27384 36133
+   8       1 sda1 124 0 6824 27 0 0 0 0 0 52 27 0 0 0 0 0 0
+ 253       0 dm-0 3014 0 92882 1316 10840 0 292384 11916 0 10356 13232 0 0 0 0 0 0
+ 230       0 zd0 512 12 40960 120 64 0 2048 30 2 300 150
+";
+
+    #[test]
+    fn test_parse_diskstats() {
+        let stats = parse_diskstats(DISKSTATS).expect("failed to parse diskstats");
+        assert_eq!(stats.len(), 4);
+
+        let sda = &stats[0];
+        assert_eq!((sda.major, sda.minor), (8, 0));
+        assert_eq!(sda.name, "sda");
+        assert_eq!(sda.read_ios, 93851);
+        assert_eq!(sda.read_sectors, 6710562);
+        assert_eq!(sda.write_sectors, 19340586);
+        assert_eq!(sda.io_ticks, 264488);
+        assert_eq!(sda.flush_ios, 27384);
+        assert_eq!(sda.flush_ticks, 36133);
+        assert_eq!(sda.device(), Device::new(8, 0));
+
+        // kernels older than 4.18 have no discard and flush counters
+        let zd0 = &stats[3];
+        assert_eq!(zd0.name, "zd0");
+        assert_eq!(zd0.in_flight, 2);
+        assert_eq!(zd0.time_in_queue, 150);
+        assert_eq!(zd0.discard_ios, 0);
+
+        assert!(DiskStat::parse_line("8 0 sda 1 2 3").is_err());
+        assert!(DiskStat::parse_line("8 0 sda 1 2 3 4 5 6 7 8 9 10 x").is_err());
+    }
+
+    #[test]
+    fn test_disk_rate() {
+        let prev = DiskStat {
+            read_ios: 100,
+            read_sectors: 1000,
+            read_ticks: 50,
+            write_ios: 200,
+            write_sectors: 4000,
+            write_ticks: 150,
+            io_ticks: 1000,
+            ..Default::default()
+        };
+        let current = DiskStat {
+            read_ios: 300,
+            read_sectors: 5000,
+            read_ticks: 250,
+            write_ios: 400,
+            write_sectors: 8000,
+            write_ticks: 550,
+            io_ticks: 2000,
+            ..Default::default()
+        };
+
+        let rate = current.rate(&prev, Duration::from_secs(2));
+        assert_eq!(rate.read_bytes, 2000.0 * 512.0);
+        assert_eq!(rate.write_bytes, 2000.0 * 512.0);
+        assert_eq!(rate.read_ios, 100.0);
+        assert_eq!(rate.write_ios, 100.0);
+        assert_eq!(rate.utilization, 0.5);
+        assert_eq!(rate.avg_latency_ms, 1.5);
+
+        assert_eq!(current.rate(&prev, Duration::ZERO), DiskRate::default());
+        // counter resets
+        assert_eq!(prev.rate(&current, Duration::from_secs(1)).read_bytes, 0.0);
+    }
+
+    #[test]
+    fn test_zvol_names() {
+        let dir =
+            std::env::temp_dir().join(format!("proxmox-sys-zvol-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("rpool/data")).unwrap();
+        std::os::unix::fs::symlink("../../../zd0", dir.join("rpool/data/vm-100-disk-0")).unwrap();
+        std::os::unix::fs::symlink("../../../zd0p1", dir.join("rpool/data/vm-100-disk-0-part1"))
+            .unwrap();
+        std::os::unix::fs::symlink("../../zd16", dir.join("rpool/swap")).unwrap();
+
+        let mut names = HashMap::new();
+        let result = collect_zvol_names(&dir, &dir, &mut names);
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+
+        assert_eq!(names.len(), 3);
+        assert_eq!(names["zd0"], "rpool/data/vm-100-disk-0");
+        assert_eq!(names["zd0p1"], "rpool/data/vm-100-disk-0-part1");
+        assert_eq!(resolve_device_name("zd16", &names), "rpool/swap");
+        assert_eq!(resolve_device_name("sda", &names), "sda");
+    }
+
+    #[test]
+    fn test_mount_block_device() {
+        let mounts = MountInfo::parse(
+            b"22 1 0:21 / / rw,relatime - zfs rpool/ROOT/pve-1 rw,xattr,noacl\n\
+              23 22 8:1 / /boot/efi rw,relatime - vfat /dev/sda1 rw\n\
+              24 22 0:22 / /proc rw,relatime - proc proc rw\n",
+        )
+        .unwrap();
+
+        assert_eq!(mount_block_device(&mounts, Path::new("/")).unwrap(), None);
+        assert_eq!(
+            mount_block_device(&mounts, Path::new("/proc/self")).unwrap(),
+            None
+        );
+        assert!(mount_block_device(&mounts, Path::new("/nonexistent/path")).is_err());
+
+        // the overmounting file system has the lower mount ID here
+        let mounts = MountInfo::parse(
+            b"30 1 8:2 / / rw,relatime - ext4 /dev/sda2 rw\n\
+              25 30 0:21 / /proc rw,relatime - proc proc rw\n\
+              24 25 8:3 / /proc rw,relatime - ext4 /dev/sda3 rw\n",
+        )
+        .unwrap();
+        assert_eq!(
+            mount_block_device(&mounts, Path::new("/proc")).unwrap(),
+            Some(Device::new(8, 3))
+        );
+
+        let mounts = MountInfo::parse(b"22 1 8:2 / / rw,relatime - ext4 /dev/sda2 rw\n").unwrap();
+        assert_eq!(
+            mount_block_device(&mounts, Path::new("/proc")).unwrap(),
+            Some(Device::new(8, 2))
+        );
+    }
+}
@@ -14,10 +14,30 @@ use serde::Serialize;
 
 use crate::fs::{file_read_firstline, read_firstline};
 
+pub mod cgroup;
+#[doc(inline)]
+pub use cgroup::CGroup;
+
+pub mod diskstats;
+#[doc(inline)]
+pub use diskstats::{read_diskstats, DiskStat};
+
 pub mod mountinfo;
 #[doc(inline)]
 pub use mountinfo::MountInfo;
 
+pub mod mountstats;
+#[doc(inline)]
+pub use mountstats::MountStats;
+
+pub mod pressure;
+#[doc(inline)]
+pub use pressure::{read_pressure, Pressure, PressureResource};
+
+pub mod process;
+#[doc(inline)]
+pub use process::{PidIo, PidStatm, PidStatus, ProcessTree};
+
 /// POSIX sysconf call
 pub fn sysconf(name: i32) -> i64 {
     unsafe extern "C" {
@@ -661,6 +681,26 @@ pub fn read_proc_net_dev() -> Result<Vec<ProcFsNetDev>, Error> {
     Ok(result)
 }
 
+// Unescape the octal escapes (e.g. `\\040` for a space) used for paths in files like
+// `/proc/self/mountinfo`.
+pub(crate) fn unescape_octal(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        match data.get(i..i + 4) {
+            Some([b'\\', a @ b'0'..=b'3', b @ b'0'..=b'7', c @ b'0'..=b'7']) => {
+                result.push(((a - b'0') << 6) | ((b - b'0') << 3) | (c - b'0'));
+                i += 4;
+            }
+            _ => {
+                result.push(data[i]);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
 // Parse a hexadecimal digit into a byte.
 #[inline]
 fn hex_nibble(c: u8) -> Result<u8, Error> {
@@ -825,6 +865,13 @@ pub fn read_proc_net_ipv6_route() -> Result<Vec<ProcFsNetIPv6Route>, Error> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_unescape_octal() {
+        assert_eq!(unescape_octal(b"/mnt/a\\040b\\134c"), b"/mnt/a b\\c");
+        assert_eq!(unescape_octal(b"trailing\\04"), b"trailing\\04");
+        assert_eq!(unescape_octal(b"\\999"), b"\\999");
+    }
+
     #[test]
     fn test_read_proc_net_route() {
         read_proc_net_route().unwrap();
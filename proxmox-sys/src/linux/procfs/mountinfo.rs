@@ -31,6 +31,18 @@ pub struct Device {
 }
 
 impl Device {
+    pub fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
     pub fn from_dev_t(dev: stat::dev_t) -> Self {
         Self {
             major: stat::major(dev) as u32,
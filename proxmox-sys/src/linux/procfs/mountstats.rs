@@ -0,0 +1,316 @@
+//! `/proc/PID/mountstats` handling, mainly the NFS client statistics.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{format_err, Error};
+use nix::unistd::Pid;
+use serde::Serialize;
+
+use super::unescape_octal;
+
+/// Byte counters of an NFS mount, from the `bytes:` line.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NfsByteStats {
+    /// Bytes read by applications using `read(2)`.
+    pub normal_read: u64,
+    /// Bytes written by applications using `write(2)`.
+    pub normal_write: u64,
+    /// Bytes read from files opened with `O_DIRECT`.
+    pub direct_read: u64,
+    /// Bytes written to files opened with `O_DIRECT`.
+    pub direct_write: u64,
+    /// Bytes read from the server.
+    pub server_read: u64,
+    /// Bytes written to the server.
+    pub server_write: u64,
+    /// Pages read via `readpage(s)`.
+    pub read_pages: u64,
+    /// Pages written via `writepage(s)`.
+    pub write_pages: u64,
+}
+
+impl NfsByteStats {
+    fn parse(values: &[u64]) -> Self {
+        let value = |index: usize| values.get(index).copied().unwrap_or(0);
+        Self {
+            normal_read: value(0),
+            normal_write: value(1),
+            direct_read: value(2),
+            direct_write: value(3),
+            server_read: value(4),
+            server_write: value(5),
+            read_pages: value(6),
+            write_pages: value(7),
+        }
+    }
+
+    /// Bytes read from and written to the server per second between the 'prev' sample and this
+    /// one, taken 'elapsed' apart.
+    pub fn server_rate(&self, prev: &NfsByteStats, elapsed: Duration) -> (f64, f64) {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return (0.0, 0.0);
+        }
+        (
+            self.server_read.saturating_sub(prev.server_read) as f64 / secs,
+            self.server_write.saturating_sub(prev.server_write) as f64 / secs,
+        )
+    }
+}
+
+/// Statistics of a single NFS operation, from the per-op statistics section.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NfsOpStats {
+    /// Number of requests.
+    pub ops: u64,
+    /// Number of transmissions, more than `ops` if requests were retransmitted.
+    pub transmissions: u64,
+    /// Number of major timeouts.
+    pub major_timeouts: u64,
+    /// Bytes sent including headers.
+    pub bytes_sent: u64,
+    /// Bytes received including headers.
+    pub bytes_received: u64,
+    /// Total time requests were queued for transmission in milliseconds.
+    pub queue_ms: u64,
+    /// Total round trip time in milliseconds.
+    pub rtt_ms: u64,
+    /// Total time from queueing to completion in milliseconds.
+    pub execute_ms: u64,
+    /// Number of requests completed with an error, not reported by kernels older than 5.3.
+    pub errors: u64,
+}
+
+impl NfsOpStats {
+    fn parse(values: &[u64]) -> Self {
+        let value = |index: usize| values.get(index).copied().unwrap_or(0);
+        Self {
+            ops: value(0),
+            transmissions: value(1),
+            major_timeouts: value(2),
+            bytes_sent: value(3),
+            bytes_received: value(4),
+            queue_ms: value(5),
+            rtt_ms: value(6),
+            execute_ms: value(7),
+            errors: value(8),
+        }
+    }
+
+    /// Operations per second and their average execution time in milliseconds between the 'prev'
+    /// sample and this one, taken 'elapsed' apart.
+    pub fn rate(&self, prev: &NfsOpStats, elapsed: Duration) -> (f64, f64) {
+        let secs = elapsed.as_secs_f64();
+        let ops = self.ops.saturating_sub(prev.ops);
+        if secs == 0.0 || ops == 0 {
+            return (0.0, 0.0);
+        }
+        let execute_ms = self.execute_ms.saturating_sub(prev.execute_ms);
+        (ops as f64 / secs, execute_ms as f64 / ops as f64)
+    }
+}
+
+/// An entry of `/proc/PID/mountstats`.
+///
+/// Only NFS mounts report statistics, for other file systems just the mount is listed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MountStats {
+    /// The mount source, e.g. `server:/export`.
+    pub device: String,
+    /// The mount point.
+    pub mount_point: PathBuf,
+    /// The file system type.
+    pub fs_type: String,
+    /// Version of the statistics format, if statistics are reported.
+    pub statvers: Option<String>,
+    /// Seconds since the file system was mounted.
+    pub age: Option<u64>,
+    /// The byte counters.
+    pub bytes: Option<NfsByteStats>,
+    /// The statistics of each operation, e.g. `READ` or `GETATTR`.
+    pub ops: BTreeMap<String, NfsOpStats>,
+}
+
+impl MountStats {
+    /// Read `/proc/self/mountstats`.
+    pub fn read() -> Result<Vec<Self>, Error> {
+        Self::parse(&std::fs::read("/proc/self/mountstats")?)
+    }
+
+    /// Read `/proc/PID/mountstats`.
+    pub fn read_for_pid(pid: Pid) -> Result<Vec<Self>, Error> {
+        Self::parse(&std::fs::read(format!("/proc/{}/mountstats", pid))?)
+    }
+
+    /// Parse the contents of a `mountstats` file.
+    pub fn parse(data: &[u8]) -> Result<Vec<Self>, Error> {
+        let data = String::from_utf8_lossy(data);
+        let mut result: Vec<MountStats> = Vec::new();
+        let mut in_ops = false;
+
+        for line in data.lines() {
+            if let Some(rest) = line.strip_prefix("device ") {
+                result.push(Self::parse_device_line(rest)?);
+                in_ops = false;
+                continue;
+            }
+
+            let Some(current) = result.last_mut() else {
+                continue;
+            };
+
+            let line = line.trim();
+            if line == "per-op statistics" {
+                in_ops = true;
+                continue;
+            }
+
+            let Some((key, values)) = line.split_once(':') else {
+                continue;
+            };
+
+            if in_ops {
+                current
+                    .ops
+                    .insert(key.to_string(), NfsOpStats::parse(&parse_values(values)?));
+                continue;
+            }
+
+            match key {
+                "age" => current.age = Some(values.trim().parse()?),
+                "bytes" => current.bytes = Some(NfsByteStats::parse(&parse_values(values)?)),
+                _ => (),
+            }
+        }
+
+        Ok(result)
+    }
+
+    // parses "SOURCE mounted on MOUNTPOINT with fstype TYPE [statvers=VERSION]"
+    fn parse_device_line(line: &str) -> Result<Self, Error> {
+        let invalid = || format_err!("invalid mountstats line 'device {}'", line);
+
+        let (device, rest) = line.split_once(" mounted on ").ok_or_else(invalid)?;
+        let (mount_point, rest) = rest.split_once(" with fstype ").ok_or_else(invalid)?;
+        let mut parts = rest.split_ascii_whitespace();
+        let fs_type = parts.next().ok_or_else(invalid)?;
+        let statvers = parts
+            .next()
+            .and_then(|part| part.strip_prefix("statvers="))
+            .map(str::to_string);
+
+        Ok(Self {
+            device: String::from_utf8_lossy(&unescape_octal(device.as_bytes())).into_owned(),
+            mount_point: OsString::from_vec(unescape_octal(mount_point.as_bytes())).into(),
+            fs_type: fs_type.to_string(),
+            statvers,
+            ..Default::default()
+        })
+    }
+}
+
+fn parse_values(values: &str) -> Result<Vec<u64>, Error> {
+    values
+        .split_ascii_whitespace()
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|err| format_err!("invalid value '{}' - {}", value, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTSTATS: &[u8] = b"\
+device proc mounted on /proc with fstype proc
+device /dev/sda2 mounted on / with fstype ext4
+device 10.0.0.1:/export/backup mounted on /mnt/pve/nfs\\040store with fstype nfs4 statvers=1.1
+\topts:\trw,vers=4.2,rsize=1048576,wsize=1048576,namlen=255,acregmin=3
+\tage:\t86400
+\tcaps:\tcaps=0x3fffdf,wtmult=512,dtsize=32768,bsize=0,namlen=255
+\tsec:\tflavor=1,pseudoflavor=1
+\tevents:\t1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27
+\tbytes:\t1048576 2097152 0 4096 1052672 2101248 256 512
+\tRPC iostats version: 1.1  p/v: 100003/4 (nfs)
+\txprt:\ttcp 0 0 1 0 10 5000 5000 0 12000 0 2 100 200
+\tper-op statistics
+\t        NULL: 1 1 0 44 24 0 0 0 0
+\t        READ: 256 256 0 36864 1081344 10 2560 2600 0
+\t       WRITE: 512 513 1 2170880 73728 20 5120 5300 2
+\t     GETATTR: 1000 1000 0 180000 240000 5 900 950
+";
+
+    #[test]
+    fn test_parse_mountstats() {
+        let stats = MountStats::parse(MOUNTSTATS).expect("failed to parse mountstats");
+        assert_eq!(stats.len(), 3);
+
+        assert_eq!(stats[0].fs_type, "proc");
+        assert_eq!(stats[0].statvers, None);
+        assert_eq!(stats[0].bytes, None);
+        assert!(stats[0].ops.is_empty());
+        assert_eq!(stats[1].mount_point, PathBuf::from("/"));
+
+        let nfs = &stats[2];
+        assert_eq!(nfs.device, "10.0.0.1:/export/backup");
+        assert_eq!(nfs.mount_point, PathBuf::from("/mnt/pve/nfs store"));
+        assert_eq!(nfs.fs_type, "nfs4");
+        assert_eq!(nfs.statvers.as_deref(), Some("1.1"));
+        assert_eq!(nfs.age, Some(86400));
+
+        let bytes = nfs.bytes.as_ref().unwrap();
+        assert_eq!(bytes.normal_read, 1048576);
+        assert_eq!(bytes.direct_write, 4096);
+        assert_eq!(bytes.server_read, 1052672);
+        assert_eq!(bytes.write_pages, 512);
+
+        assert_eq!(nfs.ops.len(), 4);
+        let write = &nfs.ops["WRITE"];
+        assert_eq!(write.ops, 512);
+        assert_eq!(write.transmissions, 513);
+        assert_eq!(write.major_timeouts, 1);
+        assert_eq!(write.execute_ms, 5300);
+        assert_eq!(write.errors, 2);
+        // older kernels do not report errors
+        assert_eq!(nfs.ops["GETATTR"].errors, 0);
+        assert_eq!(nfs.ops["GETATTR"].execute_ms, 950);
+    }
+
+    #[test]
+    fn test_nfs_rates() {
+        let prev = NfsOpStats {
+            ops: 100,
+            execute_ms: 1000,
+            ..Default::default()
+        };
+        let current = NfsOpStats {
+            ops: 300,
+            execute_ms: 2000,
+            ..Default::default()
+        };
+        assert_eq!(current.rate(&prev, Duration::from_secs(2)), (100.0, 5.0));
+        assert_eq!(prev.rate(&current, Duration::from_secs(2)), (0.0, 0.0));
+
+        let prev = NfsByteStats {
+            server_read: 1000,
+            server_write: 0,
+            ..Default::default()
+        };
+        let current = NfsByteStats {
+            server_read: 5000,
+            server_write: 2000,
+            ..Default::default()
+        };
+        assert_eq!(
+            current.server_rate(&prev, Duration::from_secs(4)),
+            (1000.0, 500.0)
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Pressure stall information (PSI) from `/proc/pressure` and cgroup `*.pressure` files.
+//!
+//! See the kernel documentation in `Documentation/accounting/psi.rst`.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use serde::Serialize;
+
+/// The resources for which pressure stall information is available.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureResource {
+    Cpu,
+    Memory,
+    Io,
+}
+
+impl PressureResource {
+    /// The name used in the file names, e.g. `cpu` for `/proc/pressure/cpu`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PressureResource::Cpu => "cpu",
+            PressureResource::Memory => "memory",
+            PressureResource::Io => "io",
+        }
+    }
+}
+
+/// A single `some` or `full` line of a pressure file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct PressureRecord {
+    /// Percentage of time stalled in the last 10 seconds.
+    pub avg10: f64,
+    /// Percentage of time stalled in the last 60 seconds.
+    pub avg60: f64,
+    /// Percentage of time stalled in the last 300 seconds.
+    pub avg300: f64,
+    /// Total time stalled in microseconds.
+    pub total: u64,
+}
+
+impl PressureRecord {
+    fn parse<'a>(parts: impl Iterator<Item = &'a str>) -> Result<Self, Error> {
+        let mut record = PressureRecord::default();
+        let mut seen = 0;
+        for part in parts {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format_err!("invalid pressure field '{}'", part))?;
+            match key {
+                "avg10" => record.avg10 = value.parse()?,
+                "avg60" => record.avg60 = value.parse()?,
+                "avg300" => record.avg300 = value.parse()?,
+                "total" => record.total = value.parse()?,
+                _ => continue,
+            }
+            seen += 1;
+        }
+        if seen != 4 {
+            bail!("missing fields in pressure line");
+        }
+        Ok(record)
+    }
+
+    /// The fraction of time (0 - 1.0) stalled between the 'prev' sample and this one, taken
+    /// 'elapsed' apart.
+    ///
+    /// Unlike the kernel's averages this covers exactly the sampling interval.
+    pub fn stall_ratio(&self, prev: &PressureRecord, elapsed: Duration) -> f64 {
+        let elapsed = elapsed.as_micros() as f64;
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        let stalled = self.total.saturating_sub(prev.total) as f64;
+        (stalled / elapsed).min(1.0)
+    }
+}
+
+/// Pressure stall information of a resource.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Pressure {
+    /// Time in which at least some tasks were stalled on the resource.
+    pub some: PressureRecord,
+    /// Time in which all non-idle tasks were stalled on the resource at the same time.
+    ///
+    /// Not available for the system wide CPU pressure on kernels older than 5.13.
+    pub full: Option<PressureRecord>,
+}
+
+impl Pressure {
+    /// Read and parse a pressure file, e.g. `/proc/pressure/io` or a cgroup's `io.pressure`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .map_err(|err| format_err!("unable to read {:?} - {}", path, err))?;
+        Self::parse(&data).map_err(|err| format_err!("unable to parse {:?} - {}", path, err))
+    }
+
+    /// Parse the contents of a pressure file.
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        let mut some = None;
+        let mut full = None;
+
+        for line in data.lines() {
+            let mut parts = line.split_ascii_whitespace();
+            match parts.next() {
+                Some("some") => some = Some(PressureRecord::parse(parts)?),
+                Some("full") => full = Some(PressureRecord::parse(parts)?),
+                _ => continue,
+            }
+        }
+
+        Ok(Self {
+            some: some.ok_or_else(|| format_err!("missing 'some' line"))?,
+            full,
+        })
+    }
+
+    /// The fractions of time (0 - 1.0) stalled between the 'prev' sample and this one, taken
+    /// 'elapsed' apart.
+    pub fn stall_ratio(&self, prev: &Pressure, elapsed: Duration) -> PressureRatio {
+        PressureRatio {
+            some: self.some.stall_ratio(&prev.some, elapsed),
+            full: match (&self.full, &prev.full) {
+                (Some(full), Some(prev)) => Some(full.stall_ratio(prev, elapsed)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// The fractions of time stalled in a sampling interval, see [`Pressure::stall_ratio`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct PressureRatio {
+    pub some: f64,
+    pub full: Option<f64>,
+}
+
+/// Read the system wide pressure of a resource from `/proc/pressure`.
+pub fn read_pressure(resource: PressureResource) -> Result<Pressure, Error> {
+    Pressure::read(format!("/proc/pressure/{}", resource.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pressure() {
+        let pressure = Pressure::parse(
+            "some avg10=1.53 avg60=0.87 avg300=0.21 total=35873951\n\
+             full avg10=0.50 avg60=0.20 avg300=0.05 total=12054234\n",
+        )
+        .expect("failed to parse pressure");
+
+        assert_eq!(pressure.some.avg10, 1.53);
+        assert_eq!(pressure.some.avg60, 0.87);
+        assert_eq!(pressure.some.avg300, 0.21);
+        assert_eq!(pressure.some.total, 35873951);
+        assert_eq!(pressure.full.unwrap().total, 12054234);
+
+        // system wide cpu pressure on older kernels
+        let pressure = Pressure::parse("some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n")
+            .expect("failed to parse pressure");
+        assert!(pressure.full.is_none());
+
+        assert!(Pressure::parse("full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").is_err());
+        assert!(Pressure::parse("some avg10=0.00 avg60=0.00\n").is_err());
+    }
+
+    #[test]
+    fn test_pressure_stall_ratio() {
+        let prev = Pressure::parse(
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total=1000000\n\
+             full avg10=0.00 avg60=0.00 avg300=0.00 total=500000\n",
+        )
+        .unwrap();
+        let current = Pressure::parse(
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total=1500000\n\
+             full avg10=0.00 avg60=0.00 avg300=0.00 total=600000\n",
+        )
+        .unwrap();
+
+        let ratio = current.stall_ratio(&prev, Duration::from_secs(2));
+        assert_eq!(ratio.some, 0.25);
+        assert_eq!(ratio.full, Some(0.05));
+
+        // counter resets must not lead to bogus values
+        let ratio = prev.stall_ratio(&current, Duration::from_secs(2));
+        assert_eq!(ratio.some, 0.0);
+        assert_eq!(
+            current.stall_ratio(&prev, Duration::ZERO),
+            PressureRatio {
+                some: 0.0,
+                full: Some(0.0)
+            }
+        );
+    }
+}
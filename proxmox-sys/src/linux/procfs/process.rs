@@ -0,0 +1,614 @@
+//! Per-process resource information from `/proc/PID` and process trees.
+//!
+//! The control group of a process is available via [`CGroup::of_pid`](super::CGroup::of_pid).
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Error};
+use nix::unistd::{Gid, Pid, Uid};
+use serde::Serialize;
+
+use super::{read_proc_uptime_ticks, sysconf, PidStat, CLOCK_TICKS};
+
+fn read_pid_file(pid: Pid, name: &str) -> Result<Vec<u8>, Error> {
+    let path = format!("/proc/{}/{}", pid, name);
+    std::fs::read(&path).map_err(|err| format_err!("unable to read {} - {}", path, err))
+}
+
+/// Selected contents of the `/proc/PID/status` file, memory values are in bytes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PidStatus {
+    /// The command name, truncated to 15 characters by the kernel.
+    pub name: String,
+    /// The process state, e.g. `R` for running or `S` for sleeping.
+    pub state: char,
+    pub tgid: i32,
+    pub ppid: i32,
+    /// Real, effective, saved and file system user ID.
+    pub uid: [u32; 4],
+    /// Real, effective, saved and file system group ID.
+    pub gid: [u32; 4],
+    /// Peak virtual memory size.
+    pub vm_peak: u64,
+    /// Virtual memory size.
+    pub vm_size: u64,
+    /// Peak resident set size.
+    pub vm_hwm: u64,
+    /// Resident set size, the sum of `rss_anon`, `rss_file` and `rss_shmem`.
+    pub vm_rss: u64,
+    /// Resident anonymous memory.
+    pub rss_anon: u64,
+    /// Resident file mappings.
+    pub rss_file: u64,
+    /// Resident shared memory.
+    pub rss_shmem: u64,
+    /// Swapped out anonymous memory.
+    pub vm_swap: u64,
+    /// Number of threads.
+    pub threads: u64,
+    pub voluntary_ctxt_switches: u64,
+    pub nonvoluntary_ctxt_switches: u64,
+}
+
+impl PidStatus {
+    /// Retrieve the `status` file contents of a process.
+    pub fn read_from_pid(pid: Pid) -> Result<Self, Error> {
+        Self::parse(&String::from_utf8_lossy(&read_pid_file(pid, "status")?))
+    }
+
+    /// The real user ID.
+    pub fn uid(&self) -> Uid {
+        Uid::from_raw(self.uid[0])
+    }
+
+    /// The real group ID.
+    pub fn gid(&self) -> Gid {
+        Gid::from_raw(self.gid[0])
+    }
+
+    /// Parse the contents of a `/proc/PID/status` file.
+    ///
+    /// Memory values are missing for kernel threads and thus zero.
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        let mut status = PidStatus::default();
+        let mut has_name = false;
+
+        for line in data.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            has_name |= key == "Name";
+            status
+                .parse_field(key, value.trim())
+                .map_err(|err| format_err!("invalid '{}' in /proc/PID/status - {}", key, err))?;
+        }
+
+        if !has_name {
+            bail!("missing 'Name' in /proc/PID/status");
+        }
+
+        Ok(status)
+    }
+
+    fn parse_field(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        fn kilobytes(value: &str) -> Result<u64, Error> {
+            let value = value.strip_suffix(" kB").unwrap_or(value).trim();
+            Ok(value.parse::<u64>()? * 1024)
+        }
+
+        fn ids(value: &str) -> Result<[u32; 4], Error> {
+            let mut result = [0; 4];
+            let mut parts = value.split_ascii_whitespace();
+            for id in result.iter_mut() {
+                *id = parts
+                    .next()
+                    .ok_or_else(|| format_err!("missing id"))?
+                    .parse()?;
+            }
+            Ok(result)
+        }
+
+        match key {
+            "Name" => self.name = value.to_string(),
+            "State" => self.state = value.chars().next().ok_or_else(|| format_err!("empty"))?,
+            "Tgid" => self.tgid = value.parse()?,
+            "PPid" => self.ppid = value.parse()?,
+            "Uid" => self.uid = ids(value)?,
+            "Gid" => self.gid = ids(value)?,
+            "VmPeak" => self.vm_peak = kilobytes(value)?,
+            "VmSize" => self.vm_size = kilobytes(value)?,
+            "VmHWM" => self.vm_hwm = kilobytes(value)?,
+            "VmRSS" => self.vm_rss = kilobytes(value)?,
+            "RssAnon" => self.rss_anon = kilobytes(value)?,
+            "RssFile" => self.rss_file = kilobytes(value)?,
+            "RssShmem" => self.rss_shmem = kilobytes(value)?,
+            "VmSwap" => self.vm_swap = kilobytes(value)?,
+            "Threads" => self.threads = value.parse()?,
+            "voluntary_ctxt_switches" => self.voluntary_ctxt_switches = value.parse()?,
+            "nonvoluntary_ctxt_switches" => self.nonvoluntary_ctxt_switches = value.parse()?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// The contents of `/proc/PID/statm`, converted from pages to bytes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PidStatm {
+    /// Virtual memory size.
+    pub size: u64,
+    /// Resident set size.
+    pub resident: u64,
+    /// Resident shared pages, i.e. file mappings and shared memory.
+    pub shared: u64,
+    /// Program code.
+    pub text: u64,
+    /// Data and stack.
+    pub data: u64,
+}
+
+impl PidStatm {
+    /// Retrieve the `statm` file contents of a process.
+    pub fn read_from_pid(pid: Pid) -> Result<Self, Error> {
+        let page_size = sysconf(libc::_SC_PAGESIZE) as u64;
+        Self::parse(
+            &String::from_utf8_lossy(&read_pid_file(pid, "statm")?),
+            page_size,
+        )
+    }
+
+    /// Parse the contents of a `/proc/PID/statm` file.
+    pub fn parse(data: &str, page_size: u64) -> Result<Self, Error> {
+        let mut values = data.split_ascii_whitespace().map(|value| {
+            value
+                .parse::<u64>()
+                .map(|pages| pages * page_size)
+                .map_err(|err| format_err!("invalid value in /proc/PID/statm - {}", err))
+        });
+        let mut next = || {
+            values
+                .next()
+                .ok_or_else(|| format_err!("missing value in /proc/PID/statm"))?
+        };
+
+        let size = next()?;
+        let resident = next()?;
+        let shared = next()?;
+        let text = next()?;
+        let _lib = next()?;
+        let data = next()?;
+
+        Ok(Self {
+            size,
+            resident,
+            shared,
+            text,
+            data,
+        })
+    }
+}
+
+/// The I/O counters from `/proc/PID/io`.
+///
+/// Reading them requires the same permissions as tracing the process.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PidIo {
+    /// Bytes read via syscalls, including the page cache and pipes.
+    pub rchar: u64,
+    /// Bytes written via syscalls, including the page cache and pipes.
+    pub wchar: u64,
+    /// Number of read syscalls.
+    pub syscr: u64,
+    /// Number of write syscalls.
+    pub syscw: u64,
+    /// Bytes fetched from the storage layer.
+    pub read_bytes: u64,
+    /// Bytes sent to the storage layer.
+    pub write_bytes: u64,
+    /// Bytes which were not written because of truncated page cache.
+    pub cancelled_write_bytes: u64,
+}
+
+impl PidIo {
+    /// Retrieve the `io` file contents of a process.
+    pub fn read_from_pid(pid: Pid) -> Result<Self, Error> {
+        Self::parse(&String::from_utf8_lossy(&read_pid_file(pid, "io")?))
+    }
+
+    /// Parse the contents of a `/proc/PID/io` file.
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        let mut io = Self::default();
+        for line in data.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let field = match key {
+                "rchar" => &mut io.rchar,
+                "wchar" => &mut io.wchar,
+                "syscr" => &mut io.syscr,
+                "syscw" => &mut io.syscw,
+                "read_bytes" => &mut io.read_bytes,
+                "write_bytes" => &mut io.write_bytes,
+                "cancelled_write_bytes" => &mut io.cancelled_write_bytes,
+                _ => continue,
+            };
+            *field = value
+                .trim()
+                .parse()
+                .map_err(|err| format_err!("invalid '{}' in /proc/PID/io - {}", key, err))?;
+        }
+        Ok(io)
+    }
+
+    /// The difference of the counters to the 'prev' sample.
+    pub fn delta(&self, prev: &Self) -> Self {
+        Self {
+            rchar: self.rchar.saturating_sub(prev.rchar),
+            wchar: self.wchar.saturating_sub(prev.wchar),
+            syscr: self.syscr.saturating_sub(prev.syscr),
+            syscw: self.syscw.saturating_sub(prev.syscw),
+            read_bytes: self.read_bytes.saturating_sub(prev.read_bytes),
+            write_bytes: self.write_bytes.saturating_sub(prev.write_bytes),
+            cancelled_write_bytes: self
+                .cancelled_write_bytes
+                .saturating_sub(prev.cancelled_write_bytes),
+        }
+    }
+}
+
+/// Read the command line arguments of a process, empty for kernel threads.
+pub fn read_pid_cmdline(pid: Pid) -> Result<Vec<OsString>, Error> {
+    Ok(split_nul(read_pid_file(pid, "cmdline")?))
+}
+
+/// Read the initial environment of a process.
+pub fn read_pid_environ(pid: Pid) -> Result<Vec<(OsString, OsString)>, Error> {
+    Ok(split_nul(read_pid_file(pid, "environ")?)
+        .into_iter()
+        .map(|entry| {
+            let entry = entry.into_vec();
+            match entry.iter().position(|b| *b == b'=') {
+                Some(pos) => (
+                    OsString::from_vec(entry[..pos].to_vec()),
+                    OsString::from_vec(entry[(pos + 1)..].to_vec()),
+                ),
+                None => (OsString::from_vec(entry), OsString::new()),
+            }
+        })
+        .collect())
+}
+
+fn split_nul(data: Vec<u8>) -> Vec<OsString> {
+    data.split(|b| *b == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| OsString::from_vec(part.to_vec()))
+        .collect()
+}
+
+/// Count the open file descriptors of a process.
+pub fn read_pid_fd_count(pid: Pid) -> Result<usize, Error> {
+    let path = format!("/proc/{}/fd", pid);
+    let entries =
+        std::fs::read_dir(&path).map_err(|err| format_err!("unable to read {} - {}", path, err))?;
+    Ok(entries.count())
+}
+
+impl PidStat {
+    /// The total CPU time of the process in clock ticks.
+    pub fn cpu_ticks(&self) -> u64 {
+        self.utime + self.stime
+    }
+
+    /// The average number of CPUs used between the 'prev' sample and this one, taken 'elapsed'
+    /// apart, e.g. `0.5` for half of a CPU.
+    ///
+    /// Returns `0.0` if 'prev' belongs to a different process with the same PID.
+    pub fn cpu_usage(&self, prev: &PidStat, elapsed: Duration) -> f64 {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0.0 || prev.pid != self.pid || prev.starttime != self.starttime {
+            return 0.0;
+        }
+        self.cpu_ticks().saturating_sub(prev.cpu_ticks()) as f64 / *CLOCK_TICKS / secs
+    }
+}
+
+/// A snapshot of all processes and their parent-child relations.
+pub struct ProcessTree {
+    processes: HashMap<Pid, PidStat>,
+    children: HashMap<Pid, Vec<Pid>>,
+    /// System uptime in clock ticks when the snapshot was taken.
+    uptime_ticks: u64,
+}
+
+impl ProcessTree {
+    /// Read the `stat` file of all processes.
+    ///
+    /// Processes exiting while reading are skipped.
+    pub fn read() -> Result<Self, Error> {
+        let (uptime_ticks, _) = read_proc_uptime_ticks()?;
+        let mut stats = Vec::new();
+        for entry in std::fs::read_dir("/proc")? {
+            let entry = entry?;
+            let Some(pid) = std::str::from_utf8(entry.file_name().as_bytes())
+                .ok()
+                .and_then(|name| name.parse::<i32>().ok())
+            else {
+                continue;
+            };
+            if let Ok(stat) = PidStat::read_from_pid(Pid::from_raw(pid)) {
+                stats.push(stat);
+            }
+        }
+        Ok(Self::from_stats(stats, uptime_ticks))
+    }
+
+    fn from_stats(stats: Vec<PidStat>, uptime_ticks: u64) -> Self {
+        let mut processes = HashMap::new();
+        let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+        for stat in stats {
+            children.entry(stat.ppid).or_default().push(stat.pid);
+            processes.insert(stat.pid, stat);
+        }
+        for list in children.values_mut() {
+            list.sort();
+        }
+        Self {
+            processes,
+            children,
+            uptime_ticks,
+        }
+    }
+
+    /// The `stat` of a process.
+    pub fn get(&self, pid: Pid) -> Option<&PidStat> {
+        self.processes.get(&pid)
+    }
+
+    /// The PIDs of all processes.
+    pub fn pids(&self) -> impl Iterator<Item = Pid> + '_ {
+        self.processes.keys().copied()
+    }
+
+    /// The direct children of a process.
+    pub fn children(&self, pid: Pid) -> &[Pid] {
+        self.children
+            .get(&pid)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// All descendants of a process, parents before their children.
+    ///
+    /// Each process is only listed once, even if the snapshot contains a cycle, which can happen
+    /// when PIDs are reused while reading.
+    pub fn descendants(&self, pid: Pid) -> Vec<Pid> {
+        let mut visited = HashSet::from([pid]);
+        let mut result = Vec::new();
+        let mut parent = pid;
+        let mut index = 0;
+        loop {
+            for &child in self.children(parent) {
+                if visited.insert(child) {
+                    result.push(child);
+                }
+            }
+            let Some(&next) = result.get(index) else {
+                break;
+            };
+            parent = next;
+            index += 1;
+        }
+        result
+    }
+
+    /// The process and all its descendants.
+    pub fn subtree(&self, pid: Pid) -> impl Iterator<Item = &PidStat> + '_ {
+        std::iter::once(pid)
+            .chain(self.descendants(pid))
+            .filter_map(|pid| self.processes.get(&pid))
+    }
+
+    /// The average number of CPUs used by a process and its descendants between the 'prev'
+    /// snapshot and this one, taken 'elapsed' apart.
+    ///
+    /// Processes started after the 'prev' snapshot are counted with their whole CPU time, the
+    /// time of processes which exited in between is lost.
+    pub fn subtree_cpu_usage(&self, prev: &ProcessTree, pid: Pid, elapsed: Duration) -> f64 {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        let ticks: u64 = self
+            .subtree(pid)
+            .map(|stat| match prev.get(stat.pid) {
+                Some(old) if old.starttime == stat.starttime => {
+                    stat.cpu_ticks().saturating_sub(old.cpu_ticks())
+                }
+                _ if stat.starttime >= prev.uptime_ticks => stat.cpu_ticks(),
+                _ => 0,
+            })
+            .sum();
+        ticks as f64 / *CLOCK_TICKS / secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pid_status() {
+        let status = PidStatus::parse(
+            "Name:\tproxmox-backup\n\
+             Umask:\t0022\n\
+             State:\tS (sleeping)\n\
+             Tgid:\t1234\n\
+             Ngid:\t0\n\
+             Pid:\t1234\n\
+             PPid:\t1\n\
+             Uid:\t34\t34\t34\t34\n\
+             Gid:\t34\t34\t34\t34\n\
+             VmPeak:\t  204800 kB\n\
+             VmSize:\t  200000 kB\n\
+             VmHWM:\t   51200 kB\n\
+             VmRSS:\t   40960 kB\n\
+             RssAnon:\t   30720 kB\n\
+             RssFile:\t   10240 kB\n\
+             RssShmem:\t       0 kB\n\
+             VmSwap:\t     512 kB\n\
+             Threads:\t8\n\
+             voluntary_ctxt_switches:\t150\n\
+             nonvoluntary_ctxt_switches:\t7\n",
+        )
+        .expect("failed to parse /proc/PID/status");
+
+        assert_eq!(status.name, "proxmox-backup");
+        assert_eq!(status.state, 'S');
+        assert_eq!(status.tgid, 1234);
+        assert_eq!(status.ppid, 1);
+        assert_eq!(status.uid(), Uid::from_raw(34));
+        assert_eq!(status.gid, [34; 4]);
+        assert_eq!(status.vm_peak, 204800 * 1024);
+        assert_eq!(status.vm_rss, 40960 * 1024);
+        assert_eq!(status.rss_anon, 30720 * 1024);
+        assert_eq!(status.vm_swap, 512 * 1024);
+        assert_eq!(status.threads, 8);
+        assert_eq!(status.nonvoluntary_ctxt_switches, 7);
+
+        // kernel threads have no memory values
+        let status =
+            PidStatus::parse("Name:\tkworker/0:1\nState:\tI (idle)\nThreads:\t1\n").unwrap();
+        assert_eq!(status.vm_rss, 0);
+
+        assert!(PidStatus::parse("State:\tR (running)\n").is_err());
+        assert!(PidStatus::parse("Name:\tx\nVmRSS:\tlots kB\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_pid_statm_and_io() {
+        let statm = PidStatm::parse("660 353 327 5 0 123 0\n", 4096).unwrap();
+        assert_eq!(statm.size, 660 * 4096);
+        assert_eq!(statm.resident, 353 * 4096);
+        assert_eq!(statm.shared, 327 * 4096);
+        assert_eq!(statm.text, 5 * 4096);
+        assert_eq!(statm.data, 123 * 4096);
+        assert!(PidStatm::parse("660 353", 4096).is_err());
+
+        let prev = PidIo::parse(
+            "rchar: 3980\nwchar: 100\nsyscr: 9\nsyscw: 1\nread_bytes: 4096\n\
+             write_bytes: 0\ncancelled_write_bytes: 0\n",
+        )
+        .unwrap();
+        assert_eq!(prev.rchar, 3980);
+        assert_eq!(prev.read_bytes, 4096);
+
+        let current = PidIo {
+            rchar: 4980,
+            write_bytes: 8192,
+            ..prev.clone()
+        };
+        let delta = current.delta(&prev);
+        assert_eq!(delta.rchar, 1000);
+        assert_eq!(delta.write_bytes, 8192);
+        assert_eq!(delta.syscr, 0);
+    }
+
+    #[test]
+    fn test_split_nul() {
+        assert_eq!(
+            split_nul(b"proxmox-backup-client\0backup\0root.pxar:/\0".to_vec()),
+            ["proxmox-backup-client", "backup", "root.pxar:/"]
+        );
+        assert!(split_nul(Vec::new()).is_empty());
+    }
+
+    fn stat(pid: i32, ppid: i32, starttime: u64, utime: u64) -> PidStat {
+        PidStat {
+            pid: Pid::from_raw(pid),
+            ppid: Pid::from_raw(ppid),
+            status: b'S',
+            utime,
+            stime: 0,
+            num_threads: 1,
+            starttime,
+            vsize: 0,
+            rss: 0,
+        }
+    }
+
+    #[test]
+    fn test_process_tree() {
+        let ticks = *CLOCK_TICKS as u64;
+        let prev = ProcessTree::from_stats(
+            vec![
+                stat(1, 0, 0, 0),
+                stat(100, 1, 10, 0),
+                stat(101, 100, 20, ticks),
+                stat(102, 100, 30, ticks),
+                stat(200, 1, 40, 5 * ticks),
+            ],
+            1000,
+        );
+        let current = ProcessTree::from_stats(
+            vec![
+                stat(1, 0, 0, 0),
+                stat(100, 1, 10, 0),
+                stat(101, 100, 20, 2 * ticks),
+                // pid reused by a process started before the previous snapshot
+                stat(102, 100, 900, 4 * ticks),
+                stat(103, 101, 1500, ticks),
+                stat(200, 1, 40, 9 * ticks),
+            ],
+            2000,
+        );
+
+        let pid = Pid::from_raw;
+        assert_eq!(current.children(pid(100)), [pid(101), pid(102)]);
+        assert!(current.children(pid(103)).is_empty());
+        assert_eq!(
+            current.descendants(pid(100)),
+            [pid(101), pid(102), pid(103)]
+        );
+        assert_eq!(current.subtree(pid(101)).count(), 2);
+
+        // a cycle of parent PIDs must not loop forever
+        let cycle = ProcessTree::from_stats(
+            vec![
+                stat(300, 302, 0, 0),
+                stat(301, 300, 0, 0),
+                stat(302, 301, 0, 0),
+            ],
+            1000,
+        );
+        assert_eq!(cycle.descendants(pid(300)), [pid(301), pid(302)]);
+
+        // 101 used one second, 103 is new and used one second
+        assert_eq!(
+            current.subtree_cpu_usage(&prev, pid(100), Duration::from_secs(2)),
+            1.0
+        );
+
+        let (old, new) = (prev.get(pid(200)).unwrap(), current.get(pid(200)).unwrap());
+        assert_eq!(new.cpu_usage(old, Duration::from_secs(8)), 0.5);
+        let (old, new) = (prev.get(pid(102)).unwrap(), current.get(pid(102)).unwrap());
+        assert_eq!(new.cpu_usage(old, Duration::from_secs(1)), 0.0);
+    }
+
+    #[test]
+    fn test_read_self() {
+        let pid = nix::unistd::getpid();
+        let status = PidStatus::read_from_pid(pid).unwrap();
+        assert_eq!(status.tgid, pid.as_raw());
+        assert!(PidStatm::read_from_pid(pid).unwrap().resident > 0);
+        assert!(!read_pid_cmdline(pid).unwrap().is_empty());
+        assert!(read_pid_fd_count(pid).unwrap() >= 3);
+
+        let tree = ProcessTree::read().unwrap();
+        assert!(tree.get(pid).is_some());
+        assert!(tree.children(nix::unistd::getppid()).contains(&pid));
+    }
+}
@@ -104,33 +104,47 @@ impl LogRotate {
     /// foo.1     => foo.2.zst
     /// foo       => foo.1
     pub fn do_rotate(&mut self) -> Result<(), Error> {
-        let mut filenames: Vec<PathBuf> = self.file_names().collect();
+        self.rotate_files()?;
+        self.compress_rotated()
+    }
+
+    /// Rotates the files like [`do_rotate`](Self::do_rotate()), but without compressing them
+    ///
+    /// This only renames files, so it is quick enough to be done while writers are blocked. The
+    /// compression can be done afterwards with [`compress_rotated`](Self::compress_rotated()).
+    ///
+    /// e.g. rotates
+    /// foo.2.zst => foo.3.zst
+    /// foo.1     => foo.2
+    /// foo       => foo.1
+    pub fn rotate_files(&mut self) -> Result<(), Error> {
+        let filenames: Vec<PathBuf> = self.file_names().collect();
         if filenames.is_empty() {
             return Ok(()); // no file means nothing to rotate
         }
-        let count = filenames.len() + 1;
 
-        let mut next_filename = self.base_path.clone().canonicalize()?.into_os_string();
-        next_filename.push(format!(".{}", filenames.len()));
-        if self.compress && count > 2 {
-            next_filename.push(".zst");
-        }
+        // the newest rotated file is named after the canonical path, so that a symlinked base
+        // path rotates the file it points to
+        let canonical_path = self.base_path.canonicalize()?;
 
-        filenames.push(PathBuf::from(next_filename));
-
-        for i in (0..count - 1).rev() {
-            if self.compress
-                && filenames[i].extension() != Some(std::ffi::OsStr::new("zst"))
-                && filenames[i + 1].extension() == Some(std::ffi::OsStr::new("zst"))
-            {
-                Self::compress(&filenames[i], &filenames[i + 1], &self.options)?;
+        let mut rotated = Vec::with_capacity(filenames.len());
+        for (i, filename) in filenames.iter().enumerate().rev() {
+            let base_path = if i + 1 == filenames.len() {
+                &canonical_path
             } else {
-                rename(&filenames[i], &filenames[i + 1])?;
+                &self.base_path
+            };
+            let mut next_filename = base_path.clone().into_os_string();
+            next_filename.push(format!(".{}", i + 1));
+            if is_compressed(filename) {
+                next_filename.push(".zst");
             }
+            rename(filename, &next_filename)?;
+            rotated.push((i + 1, PathBuf::from(next_filename)));
         }
 
         if let Some(max_files) = self.max_files {
-            for file in filenames.iter().skip(max_files) {
+            for (_, file) in rotated.iter().filter(|(index, _)| *index >= max_files) {
                 if let Err(err) = unistd::unlink(file) {
                     eprintln!("could not remove {:?}: {}", &file, err);
                 }
@@ -140,6 +154,26 @@ impl LogRotate {
         Ok(())
     }
 
+    /// Compresses all rotated files except the newest one, if the 'compress' option was given
+    ///
+    /// e.g. compresses
+    /// foo.2     => foo.2.zst
+    pub fn compress_rotated(&mut self) -> Result<(), Error> {
+        if !self.compress {
+            return Ok(());
+        }
+
+        for filename in self.file_names().skip(2) {
+            if !is_compressed(&filename) {
+                let mut target = filename.clone().into_os_string();
+                target.push(".zst");
+                Self::compress(&filename, Path::new(&target), &self.options)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Conditional rotate if file bigger than 'max_size'
     pub fn rotate(&mut self, max_size: u64) -> Result<bool, Error> {
         let metadata = match self.base_path.metadata() {
@@ -157,6 +191,10 @@ impl LogRotate {
     }
 }
 
+fn is_compressed(path: &Path) -> bool {
+    path.extension() == Some(std::ffi::OsStr::new("zst"))
+}
+
 /// Iterator over logrotated file names
 pub struct LogRotateFileNames {
     base_path: PathBuf,
@@ -207,7 +245,7 @@ impl Iterator for LogRotateFiles {
         let filename = self.file_names.next()?;
         let file = File::open(&filename).ok()?;
 
-        if filename.extension() == Some(std::ffi::OsStr::new("zst")) {
+        if is_compressed(&filename) {
             let encoder = zstd::stream::read::Decoder::new(file).ok()?;
             return Some(Box::new(encoder));
         }
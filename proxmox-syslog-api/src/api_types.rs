@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use proxmox_schema::api;
@@ -98,3 +100,172 @@ pub struct JournalFilter {
     pub startcursor: Option<String>,
     pub endcursor: Option<String>,
 }
+
+#[api]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Syslog priority of a journal entry, from the most to the least severe.
+pub enum JournalPriority {
+    /// System is unusable (0).
+    Emerg,
+    /// Action must be taken immediately (1).
+    Alert,
+    /// Critical conditions (2).
+    Crit,
+    /// Error conditions (3).
+    Err,
+    /// Warning conditions (4).
+    Warning,
+    /// Normal but significant condition (5).
+    Notice,
+    /// Informational (6).
+    Info,
+    /// Debug-level messages (7).
+    Debug,
+}
+
+impl JournalPriority {
+    /// All priorities, ordered by their numeric value.
+    pub const ALL: [JournalPriority; 8] = [
+        JournalPriority::Emerg,
+        JournalPriority::Alert,
+        JournalPriority::Crit,
+        JournalPriority::Err,
+        JournalPriority::Warning,
+        JournalPriority::Notice,
+        JournalPriority::Info,
+        JournalPriority::Debug,
+    ];
+
+    /// The numeric value as used in the `PRIORITY` journal field.
+    pub fn as_number(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the priority from its numeric value.
+    pub fn from_number(value: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(value)).copied()
+    }
+}
+
+#[api(
+    properties: {
+        "min-priority": {
+            type: JournalPriority,
+            optional: true,
+        },
+        "max-priority": {
+            type: JournalPriority,
+            optional: true,
+        },
+        unit: {
+            type: String,
+            optional: true,
+            description: "Only include entries of this systemd unit.",
+            max_length: 256,
+        },
+        identifier: {
+            type: String,
+            optional: true,
+            description: "Only include entries with this syslog identifier.",
+            max_length: 256,
+        },
+        boot: {
+            type: String,
+            optional: true,
+            description: "Only include entries of this boot ID, 'current' for the running system.",
+            max_length: 36,
+        },
+        since: {
+            type: Integer,
+            optional: true,
+            description: "Only include entries since this UNIX epoch.",
+            minimum: 0,
+        },
+        until: {
+            type: Integer,
+            optional: true,
+            description: "Only include entries until this UNIX epoch.",
+            minimum: 0,
+        },
+        "after-cursor": {
+            type: String,
+            optional: true,
+            description: "Return the entries after the given cursor. Conflicts with 'before-cursor'.",
+        },
+        "before-cursor": {
+            type: String,
+            optional: true,
+            description: "Return the entries before the given cursor. Conflicts with 'after-cursor'.",
+        },
+        limit: {
+            type: Integer,
+            optional: true,
+            description: "Max. number of entries, defaults to 50.",
+            minimum: 1,
+            maximum: 10000,
+        },
+    },
+)]
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Structured journal query options.
+///
+/// Without 'after-cursor' or 'since' the newest entries (before 'before-cursor' or 'until') are
+/// returned. Entries are always ordered from oldest to newest.
+pub struct JournalQuery {
+    /// Only include entries with this or a numerically higher, i.e. less severe, priority.
+    pub min_priority: Option<JournalPriority>,
+    /// Only include entries with this or a numerically lower, i.e. more severe, priority.
+    pub max_priority: Option<JournalPriority>,
+    pub unit: Option<String>,
+    pub identifier: Option<String>,
+    pub boot: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub after_cursor: Option<String>,
+    pub before_cursor: Option<String>,
+    pub limit: Option<u64>,
+}
+
+#[api(
+    properties: {
+        priority: {
+            type: JournalPriority,
+            optional: true,
+        },
+        fields: {
+            type: Object,
+            description: "All fields of the entry.",
+            additional_properties: true,
+            properties: {},
+        },
+    },
+)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// A structured journal entry.
+pub struct JournalEntry {
+    /// Cursor of the entry, used for pagination.
+    pub cursor: String,
+    /// Time of the entry in microseconds since the UNIX epoch.
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<JournalPriority>,
+    /// The systemd unit which logged the entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// The syslog identifier.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    /// The process ID which logged the entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// The boot ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot_id: Option<String>,
+    /// The message.
+    pub message: String,
+    /// All fields of the entry, binary values are converted lossily.
+    pub fields: BTreeMap<String, String>,
+}
@@ -1,9 +1,20 @@
+use std::collections::BTreeMap;
 use std::io::{BufRead, BufReader};
 use std::process::{Command, Stdio};
+use std::time::Duration;
 
-use anyhow::Error;
+use anyhow::{bail, format_err, Error};
+use tokio::sync::mpsc;
 
-use super::{JournalFilter, SyslogFilter, SyslogLine};
+use proxmox_router::Record;
+use proxmox_systemd::journal::{Journal, JournalEvent};
+
+use super::{JournalEntry, JournalFilter, JournalPriority, JournalQuery, SyslogFilter, SyslogLine};
+
+const DEFAULT_QUERY_LIMIT: u64 = 50;
+
+/// How often a followed journal checks whether the receiver is still there.
+const FOLLOW_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Syslog API implementation
 ///
@@ -141,3 +152,351 @@ pub fn dump_journal(filter: JournalFilter) -> Result<Vec<String>, Error> {
 
     Ok(lines)
 }
+
+/// Structured journal API implementation
+///
+/// Reads the local journal directly and returns up to 'limit' entries matching the query,
+/// ordered from oldest to newest. The cursors of the first and last entry can be passed as
+/// 'before-cursor' and 'after-cursor' to get the previous or next page.
+pub fn query_journal(query: &JournalQuery) -> Result<Vec<JournalEntry>, Error> {
+    let mut journal = open_journal(query)?;
+    let range = TimeRange::new(query);
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT) as usize;
+
+    let forward =
+        query.after_cursor.is_some() || (query.since.is_some() && query.before_cursor.is_none());
+
+    let skip_cursor = if forward {
+        match &query.after_cursor {
+            Some(cursor) => seek_cursor(&mut journal, cursor)?,
+            None => journal.seek_realtime(range.since.unwrap_or(0))?,
+        }
+        query.after_cursor.as_deref()
+    } else {
+        match (&query.before_cursor, range.until) {
+            (Some(cursor), _) => seek_cursor(&mut journal, cursor)?,
+            (None, Some(until)) => journal.seek_realtime(until.saturating_add(1))?,
+            (None, None) => journal.seek_tail()?,
+        }
+        query.before_cursor.as_deref()
+    };
+
+    let mut entries = Vec::new();
+    let mut first = true;
+
+    while entries.len() < limit {
+        let found = if forward {
+            journal.next_entry()?
+        } else {
+            journal.previous_entry()?
+        };
+        if !found {
+            break;
+        }
+
+        // seeking to a cursor positions on its entry, which is not part of the result
+        if std::mem::take(&mut first) {
+            if let Some(cursor) = skip_cursor {
+                if journal.test_cursor(cursor)? {
+                    continue;
+                }
+            }
+        }
+
+        let timestamp = journal.realtime()?;
+        if range.is_past(timestamp, forward) {
+            break;
+        } else if range.is_before(timestamp, forward) {
+            continue;
+        }
+
+        entries.push(read_entry(&mut journal, timestamp)?);
+    }
+
+    if !forward {
+        entries.reverse();
+    }
+
+    Ok(entries)
+}
+
+/// Follow the journal, streaming new entries as they are appended
+///
+/// Starts after 'after-cursor', at 'since' or with the next new entry. The returned stream
+/// can be used as result of a [`StreamAsync`](proxmox_router::ApiHandler::StreamAsync) API
+/// handler to send the entries as `json-seq`. 'limit' is ignored, 'until' and 'before-cursor'
+/// are not supported.
+///
+/// The journal is read by a separate thread, which exits once the stream is dropped.
+pub fn follow_journal(query: &JournalQuery) -> Result<proxmox_router::Stream, Error> {
+    if query.until.is_some() || query.before_cursor.is_some() {
+        bail!("'until' and 'before-cursor' cannot be used when following the journal");
+    }
+
+    let mut journal = open_journal(query)?;
+    let since = TimeRange::new(query).since;
+
+    match (&query.after_cursor, since) {
+        (Some(cursor), _) => seek_cursor(&mut journal, cursor)?,
+        (None, Some(since)) => journal.seek_realtime(since)?,
+        (None, None) => {
+            journal.seek_tail()?;
+            // position on the last entry, so only new ones are returned
+            journal.previous_entry()?;
+        }
+    }
+
+    let skip_cursor = query.after_cursor.clone();
+    let (sender, mut receiver) = mpsc::channel(64);
+
+    std::thread::Builder::new()
+        .name("journal-follow".to_string())
+        .spawn(move || {
+            if let Err(err) = follow_entries(&mut journal, skip_cursor, since, &sender) {
+                let _ = sender.blocking_send(Err(err));
+            }
+        })?;
+
+    Ok(futures::stream::poll_fn(move |cx| receiver.poll_recv(cx)).into())
+}
+
+fn follow_entries(
+    journal: &mut Journal,
+    mut skip_cursor: Option<String>,
+    since: Option<u64>,
+    sender: &mpsc::Sender<Result<Record, Error>>,
+) -> Result<(), Error> {
+    loop {
+        while journal.next_entry()? {
+            if let Some(cursor) = skip_cursor.take() {
+                if journal.test_cursor(&cursor)? {
+                    continue;
+                }
+            }
+
+            let timestamp = journal.realtime()?;
+            if since.is_some_and(|since| timestamp < since) {
+                continue;
+            }
+
+            let entry = read_entry(journal, timestamp)?;
+            if sender.blocking_send(Ok(Record::new(&entry))).is_err() {
+                return Ok(());
+            }
+        }
+
+        while journal.wait(Some(FOLLOW_CHECK_INTERVAL))? == JournalEvent::Nop {
+            if sender.is_closed() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Open the local journal with the matches of the query.
+fn open_journal(query: &JournalQuery) -> Result<Journal, Error> {
+    if query.after_cursor.is_some() && query.before_cursor.is_some() {
+        bail!("'after-cursor' and 'before-cursor' cannot be used together");
+    }
+
+    let min_priority = query.min_priority.unwrap_or(JournalPriority::Emerg);
+    let max_priority = query.max_priority.unwrap_or(JournalPriority::Debug);
+    if min_priority > max_priority {
+        bail!("'min-priority' must not be less severe than 'max-priority'");
+    }
+
+    let mut journal =
+        Journal::open_local().map_err(|err| format_err!("unable to open journal - {err}"))?;
+
+    if let Some(unit) = &query.unit {
+        journal.add_match("_SYSTEMD_UNIT", unit.as_bytes())?;
+        // also include the messages of systemd about the unit, like `journalctl -u`
+        journal.add_disjunction()?;
+        journal.add_match("_PID", b"1")?;
+        journal.add_match("UNIT", unit.as_bytes())?;
+        journal.add_conjunction()?;
+    }
+
+    if (min_priority, max_priority) != (JournalPriority::Emerg, JournalPriority::Debug) {
+        for priority in JournalPriority::ALL {
+            if (min_priority..=max_priority).contains(&priority) {
+                journal.add_match("PRIORITY", priority.as_number().to_string().as_bytes())?;
+            }
+        }
+    }
+
+    if let Some(identifier) = &query.identifier {
+        journal.add_match("SYSLOG_IDENTIFIER", identifier.as_bytes())?;
+    }
+
+    if let Some(boot) = &query.boot {
+        journal.add_match("_BOOT_ID", parse_boot_id(boot)?.as_bytes())?;
+    }
+
+    Ok(journal)
+}
+
+fn seek_cursor(journal: &mut Journal, cursor: &str) -> Result<(), Error> {
+    journal
+        .seek_cursor(cursor)
+        .map_err(|err| format_err!("invalid cursor '{cursor}' - {err}"))
+}
+
+/// The time range of a query in microseconds, 'until' includes the whole second.
+struct TimeRange {
+    since: Option<u64>,
+    until: Option<u64>,
+}
+
+impl TimeRange {
+    fn new(query: &JournalQuery) -> Self {
+        Self {
+            since: query.since.map(|since| since.saturating_mul(1_000_000)),
+            until: query
+                .until
+                .map(|until| until.saturating_add(1).saturating_mul(1_000_000) - 1),
+        }
+    }
+
+    /// The timestamp lies beyond the range in reading direction.
+    fn is_past(&self, timestamp: u64, forward: bool) -> bool {
+        if forward {
+            self.until.is_some_and(|until| timestamp > until)
+        } else {
+            self.since.is_some_and(|since| timestamp < since)
+        }
+    }
+
+    /// The timestamp lies before the range in reading direction.
+    fn is_before(&self, timestamp: u64, forward: bool) -> bool {
+        self.is_past(timestamp, !forward)
+    }
+}
+
+/// Normalize a boot ID to the format of the `_BOOT_ID` field.
+fn parse_boot_id(boot: &str) -> Result<String, Error> {
+    let id = if boot == "current" {
+        std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+            .map_err(|err| format_err!("unable to read current boot ID - {err}"))?
+    } else {
+        boot.to_string()
+    };
+
+    let id: String = id
+        .trim()
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    if id.len() != 32 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("invalid boot ID '{boot}'");
+    }
+
+    Ok(id)
+}
+
+fn read_entry(journal: &mut Journal, timestamp: u64) -> Result<JournalEntry, Error> {
+    Ok(build_entry(journal.cursor()?, timestamp, journal.fields()?))
+}
+
+/// Build an entry from the raw fields, only the first value of repeated fields is used.
+fn build_entry(cursor: String, timestamp: u64, raw_fields: Vec<(String, Vec<u8>)>) -> JournalEntry {
+    let mut fields = BTreeMap::new();
+    for (name, value) in raw_fields {
+        fields
+            .entry(name)
+            .or_insert_with(|| String::from_utf8_lossy(&value).into_owned());
+    }
+
+    let field = |name: &str| fields.get(name).cloned();
+
+    JournalEntry {
+        cursor,
+        timestamp,
+        priority: fields
+            .get("PRIORITY")
+            .and_then(|priority| priority.parse().ok())
+            .and_then(JournalPriority::from_number),
+        unit: field("_SYSTEMD_UNIT"),
+        identifier: field("SYSLOG_IDENTIFIER"),
+        pid: fields.get("_PID").and_then(|pid| pid.parse().ok()),
+        boot_id: field("_BOOT_ID"),
+        message: field("MESSAGE").unwrap_or_default(),
+        fields,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_entry() {
+        let raw = vec![
+            ("MESSAGE".to_string(), b"hello\nworld".to_vec()),
+            ("PRIORITY".to_string(), b"4".to_vec()),
+            ("_PID".to_string(), b"1234".to_vec()),
+            (
+                "_SYSTEMD_UNIT".to_string(),
+                b"proxmox-backup.service".to_vec(),
+            ),
+            (
+                "SYSLOG_IDENTIFIER".to_string(),
+                b"proxmox-backup-api".to_vec(),
+            ),
+            ("CUSTOM".to_string(), b"first".to_vec()),
+            ("CUSTOM".to_string(), b"second".to_vec()),
+            ("BINARY".to_string(), vec![0xff, b'a']),
+        ];
+        let entry = build_entry("s=1".to_string(), 1_700_000_000_000_000, raw);
+
+        assert_eq!(entry.message, "hello\nworld");
+        assert_eq!(entry.priority, Some(JournalPriority::Warning));
+        assert_eq!(entry.pid, Some(1234));
+        assert_eq!(entry.unit.as_deref(), Some("proxmox-backup.service"));
+        assert_eq!(entry.identifier.as_deref(), Some("proxmox-backup-api"));
+        assert_eq!(entry.boot_id, None);
+        assert_eq!(entry.fields["CUSTOM"], "first");
+        assert_eq!(entry.fields["BINARY"], "\u{fffd}a");
+        assert_eq!(entry.fields.len(), 7);
+    }
+
+    #[test]
+    fn test_parse_boot_id() {
+        assert_eq!(
+            parse_boot_id("0F1E2D3C-4B5A-6978-8796-A5B4C3D2E1F0").unwrap(),
+            "0f1e2d3c4b5a69788796a5b4c3d2e1f0"
+        );
+        assert!(parse_boot_id("0f1e2d3c").is_err());
+        assert!(parse_boot_id("xf1e2d3c4b5a69788796a5b4c3d2e1f0").is_err());
+    }
+
+    #[test]
+    fn test_time_range() {
+        let query = JournalQuery {
+            since: Some(10),
+            until: Some(20),
+            ..Default::default()
+        };
+        let range = TimeRange::new(&query);
+
+        assert!(!range.is_past(20_999_999, true));
+        assert!(range.is_past(21_000_000, true));
+        assert!(range.is_before(9_999_999, true));
+        assert!(range.is_past(9_999_999, false));
+        assert!(range.is_before(21_000_000, false));
+        assert!(!range.is_before(10_000_000, false));
+    }
+
+    #[test]
+    fn test_priority() {
+        assert_eq!(JournalPriority::Err.as_number(), 3);
+        assert_eq!(
+            JournalPriority::from_number(7),
+            Some(JournalPriority::Debug)
+        );
+        assert_eq!(JournalPriority::from_number(8), None);
+        assert!(JournalPriority::Emerg < JournalPriority::Debug);
+    }
+}
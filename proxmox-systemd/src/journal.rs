@@ -1,7 +1,9 @@
-use std::ffi::{c_int, CString, OsStr};
+use std::ffi::{c_int, CStr, CString, OsStr};
 use std::io;
 use std::os::fd::{FromRawFd, OwnedFd};
 use std::os::unix::ffi::OsStrExt;
+use std::ptr::{self, NonNull};
+use std::time::Duration;
 
 use crate::sys;
 
@@ -25,3 +27,208 @@ pub fn stream_fd<I: AsRef<OsStr>>(
         Ok(unsafe { OwnedFd::from_raw_fd(fd) })
     }
 }
+
+/// Event reported by [`Journal::wait`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JournalEvent {
+    /// The timeout passed without changes.
+    Nop,
+    /// New entries were appended.
+    Append,
+    /// Journal files were added or removed, e.g. due to rotation.
+    Invalidate,
+}
+
+/// A reader for the local system journal.
+///
+/// The reader is positioned on an entry with [`next_entry`](Journal::next_entry) or
+/// [`previous_entry`](Journal::previous_entry) after seeking, the accessors then refer to that
+/// entry. Entries can be filtered with matches of the form `FIELD=value`: matches of the same
+/// field are combined with OR, matches of different fields with AND.
+/// [`add_disjunction`](Journal::add_disjunction) and
+/// [`add_conjunction`](Journal::add_conjunction) combine the previous terms with the following
+/// ones.
+pub struct Journal {
+    journal: NonNull<sys::sd_journal>,
+}
+
+// The handle may be moved between threads, but must not be used concurrently.
+unsafe impl Send for Journal {}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        unsafe { sys::sd_journal_close(self.journal.as_ptr()) };
+    }
+}
+
+impl Journal {
+    /// Open the journal files of the local machine.
+    pub fn open_local() -> Result<Self, io::Error> {
+        let mut journal = ptr::null_mut();
+        sys::check_call(unsafe { sys::sd_journal_open(&mut journal, sys::SD_JOURNAL_LOCAL_ONLY) })?;
+        let journal =
+            NonNull::new(journal).ok_or_else(|| io::Error::other("failed to open journal"))?;
+        Ok(Self { journal })
+    }
+
+    fn as_ptr(&mut self) -> *mut sys::sd_journal {
+        self.journal.as_ptr()
+    }
+
+    /// Only return entries having `field` set to `value`.
+    pub fn add_match(&mut self, field: &str, value: &[u8]) -> Result<(), io::Error> {
+        let mut data = Vec::with_capacity(field.len() + 1 + value.len());
+        data.extend_from_slice(field.as_bytes());
+        data.push(b'=');
+        data.extend_from_slice(value);
+        sys::check_call(unsafe {
+            sys::sd_journal_add_match(self.as_ptr(), data.as_ptr().cast(), data.len())
+        })?;
+        Ok(())
+    }
+
+    /// Combine the previous matches with the following ones using OR.
+    pub fn add_disjunction(&mut self) -> Result<(), io::Error> {
+        sys::check_call(unsafe { sys::sd_journal_add_disjunction(self.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Combine the previous matches with the following ones using AND.
+    pub fn add_conjunction(&mut self) -> Result<(), io::Error> {
+        sys::check_call(unsafe { sys::sd_journal_add_conjunction(self.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Remove all matches.
+    pub fn flush_matches(&mut self) {
+        unsafe { sys::sd_journal_flush_matches(self.as_ptr()) };
+    }
+
+    /// Seek to before the oldest entry.
+    pub fn seek_head(&mut self) -> Result<(), io::Error> {
+        sys::check_call(unsafe { sys::sd_journal_seek_head(self.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Seek to after the newest entry.
+    pub fn seek_tail(&mut self) -> Result<(), io::Error> {
+        sys::check_call(unsafe { sys::sd_journal_seek_tail(self.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Seek to the given wall clock time in microseconds since the epoch.
+    pub fn seek_realtime(&mut self, usec: u64) -> Result<(), io::Error> {
+        sys::check_call(unsafe { sys::sd_journal_seek_realtime_usec(self.as_ptr(), usec) })?;
+        Ok(())
+    }
+
+    /// Seek to the entry with the given cursor.
+    ///
+    /// The next [`next_entry`](Journal::next_entry) or
+    /// [`previous_entry`](Journal::previous_entry) call moves to that entry, or the closest one
+    /// if it does not exist anymore; use [`test_cursor`](Journal::test_cursor) to check that.
+    pub fn seek_cursor(&mut self, cursor: &str) -> Result<(), io::Error> {
+        let cursor = cursor_cstring(cursor)?;
+        sys::check_call(unsafe { sys::sd_journal_seek_cursor(self.as_ptr(), cursor.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Check whether the current entry matches the cursor.
+    pub fn test_cursor(&mut self, cursor: &str) -> Result<bool, io::Error> {
+        let cursor = cursor_cstring(cursor)?;
+        let res = sys::check_call(unsafe {
+            sys::sd_journal_test_cursor(self.as_ptr(), cursor.as_ptr())
+        })?;
+        Ok(res > 0)
+    }
+
+    /// Move to the next entry, returns `false` at the end of the journal.
+    pub fn next_entry(&mut self) -> Result<bool, io::Error> {
+        let res = sys::check_call(unsafe { sys::sd_journal_next(self.as_ptr()) })?;
+        Ok(res > 0)
+    }
+
+    /// Move to the previous entry, returns `false` at the start of the journal.
+    pub fn previous_entry(&mut self) -> Result<bool, io::Error> {
+        let res = sys::check_call(unsafe { sys::sd_journal_previous(self.as_ptr()) })?;
+        Ok(res > 0)
+    }
+
+    /// The wall clock time of the current entry in microseconds since the epoch.
+    pub fn realtime(&mut self) -> Result<u64, io::Error> {
+        let mut usec = 0;
+        sys::check_call(unsafe { sys::sd_journal_get_realtime_usec(self.as_ptr(), &mut usec) })?;
+        Ok(usec)
+    }
+
+    /// The cursor of the current entry.
+    pub fn cursor(&mut self) -> Result<String, io::Error> {
+        let mut cursor = ptr::null_mut();
+        sys::check_call(unsafe { sys::sd_journal_get_cursor(self.as_ptr(), &mut cursor) })?;
+        let result = unsafe { CStr::from_ptr(cursor) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { libc::free(cursor.cast()) };
+        Ok(result)
+    }
+
+    /// The value of a field of the current entry, `None` if the entry does not have it.
+    pub fn field(&mut self, name: &str) -> Result<Option<Vec<u8>>, io::Error> {
+        let c_name = CString::new(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid field name"))?;
+        let mut data = ptr::null();
+        let mut len = 0;
+        let res = unsafe {
+            sys::sd_journal_get_data(self.as_ptr(), c_name.as_ptr(), &mut data, &mut len)
+        };
+        if res == -libc::ENOENT {
+            return Ok(None);
+        }
+        sys::check_call(res)?;
+        // the data is returned as `FIELD=value`
+        let data = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), len) };
+        Ok(Some(data[(name.len() + 1).min(len)..].to_vec()))
+    }
+
+    /// All fields of the current entry.
+    ///
+    /// Fields can occur multiple times.
+    pub fn fields(&mut self) -> Result<Vec<(String, Vec<u8>)>, io::Error> {
+        let mut fields = Vec::new();
+        unsafe { sys::sd_journal_restart_data(self.as_ptr()) };
+        loop {
+            let mut data = ptr::null();
+            let mut len = 0;
+            let res = sys::check_call(unsafe {
+                sys::sd_journal_enumerate_data(self.as_ptr(), &mut data, &mut len)
+            })?;
+            if res == 0 {
+                break;
+            }
+            let data = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), len) };
+            if let Some(pos) = data.iter().position(|&b| b == b'=') {
+                let name = String::from_utf8_lossy(&data[..pos]).into_owned();
+                fields.push((name, data[(pos + 1)..].to_vec()));
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Wait for changes of the journal, `None` waits without timeout.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<JournalEvent, io::Error> {
+        let timeout = match timeout {
+            Some(timeout) => u64::try_from(timeout.as_micros()).unwrap_or(u64::MAX - 1),
+            None => u64::MAX,
+        };
+        let res = sys::check_call(unsafe { sys::sd_journal_wait(self.as_ptr(), timeout) })?;
+        Ok(match res {
+            sys::SD_JOURNAL_APPEND => JournalEvent::Append,
+            sys::SD_JOURNAL_INVALIDATE => JournalEvent::Invalidate,
+            _ => JournalEvent::Nop,
+        })
+    }
+}
+
+fn cursor_cstring(cursor: &str) -> Result<CString, io::Error> {
+    CString::new(cursor).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid cursor"))
+}
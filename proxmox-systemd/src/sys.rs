@@ -1,9 +1,19 @@
-use std::ffi::{c_char, c_int, c_uchar, c_uint};
+use std::ffi::{c_char, c_int, c_uchar, c_uint, c_void};
 use std::io;
 use std::os::fd::RawFd;
 
 pub const LISTEN_FDS_START: RawFd = 3;
 
+pub const SD_JOURNAL_LOCAL_ONLY: c_int = 1 << 0;
+
+pub const SD_JOURNAL_APPEND: c_int = 1;
+pub const SD_JOURNAL_INVALIDATE: c_int = 2;
+
+#[repr(C)]
+pub struct sd_journal {
+    _private: [u8; 0],
+}
+
 #[link(name = "systemd")]
 unsafe extern "C" {
     pub fn sd_journal_stream_fd(
@@ -24,6 +34,35 @@ unsafe extern "C" {
         unset_environment: c_int,
         names: *mut *mut *mut c_char,
     ) -> c_int;
+
+    pub fn sd_journal_open(ret: *mut *mut sd_journal, flags: c_int) -> c_int;
+    pub fn sd_journal_close(j: *mut sd_journal);
+    pub fn sd_journal_add_match(j: *mut sd_journal, data: *const c_void, size: usize) -> c_int;
+    pub fn sd_journal_add_disjunction(j: *mut sd_journal) -> c_int;
+    pub fn sd_journal_add_conjunction(j: *mut sd_journal) -> c_int;
+    pub fn sd_journal_flush_matches(j: *mut sd_journal);
+    pub fn sd_journal_seek_head(j: *mut sd_journal) -> c_int;
+    pub fn sd_journal_seek_tail(j: *mut sd_journal) -> c_int;
+    pub fn sd_journal_seek_realtime_usec(j: *mut sd_journal, usec: u64) -> c_int;
+    pub fn sd_journal_seek_cursor(j: *mut sd_journal, cursor: *const c_char) -> c_int;
+    pub fn sd_journal_test_cursor(j: *mut sd_journal, cursor: *const c_char) -> c_int;
+    pub fn sd_journal_next(j: *mut sd_journal) -> c_int;
+    pub fn sd_journal_previous(j: *mut sd_journal) -> c_int;
+    pub fn sd_journal_get_realtime_usec(j: *mut sd_journal, ret: *mut u64) -> c_int;
+    pub fn sd_journal_get_cursor(j: *mut sd_journal, cursor: *mut *mut c_char) -> c_int;
+    pub fn sd_journal_get_data(
+        j: *mut sd_journal,
+        field: *const c_char,
+        data: *mut *const c_void,
+        length: *mut usize,
+    ) -> c_int;
+    pub fn sd_journal_restart_data(j: *mut sd_journal);
+    pub fn sd_journal_enumerate_data(
+        j: *mut sd_journal,
+        data: *mut *const c_void,
+        length: *mut usize,
+    ) -> c_int;
+    pub fn sd_journal_wait(j: *mut sd_journal, timeout_usec: u64) -> c_int;
 }
 
 pub fn check_call(ret: c_int) -> Result<c_int, io::Error> {
@@ -6,6 +6,7 @@
 use anyhow::{bail, format_err, Error};
 
 use super::{OpenUserChallengeData, TfaConfig, TfaInfo, TfaUserData};
+use crate::hotp::{self, Hotp};
 use crate::totp::Totp;
 
 pub use crate::types::{TfaType, TfaUpdateInfo, TfaUser, TypedTfaInfo};
@@ -13,6 +14,7 @@ pub use crate::types::{TfaType, TfaUpdateInfo, TfaUser, TypedTfaInfo};
 fn to_data(data: &TfaUserData) -> Vec<TypedTfaInfo> {
     let mut out = Vec::with_capacity(
         data.totp.len()
+            + data.hotp.len()
             + data.u2f.len()
             + data.webauthn.len()
             + data.yubico.len()
@@ -30,6 +32,12 @@ fn to_data(data: &TfaUserData) -> Vec<TypedTfaInfo> {
             info: entry.info.clone(),
         });
     }
+    for entry in &data.hotp {
+        out.push(TypedTfaInfo {
+            ty: TfaType::Hotp,
+            info: entry.info.clone(),
+        });
+    }
     for entry in &data.webauthn {
         out.push(TypedTfaInfo {
             ty: TfaType::Webauthn,
@@ -57,6 +65,12 @@ fn tfa_id_iter(data: &TfaUserData) -> impl Iterator<Item = (TfaType, usize, &str
         .iter()
         .enumerate()
         .map(|(i, entry)| (TfaType::Totp, i, entry.info.id.as_str()))
+        .chain(
+            data.hotp
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| (TfaType::Hotp, i, entry.info.id.as_str())),
+        )
         .chain(
             data.webauthn
                 .iter()
@@ -119,6 +133,10 @@ pub fn get_tfa_entry(config: &TfaConfig, userid: &str, id: &str) -> Option<Typed
                 ty: TfaType::Totp,
                 info: user_data.totp.get(index).unwrap().info.clone(),
             },
+            Some((TfaType::Hotp, index)) => TypedTfaInfo {
+                ty: TfaType::Hotp,
+                info: user_data.hotp.get(index).unwrap().info.clone(),
+            },
             Some((TfaType::Webauthn, index)) => TypedTfaInfo {
                 ty: TfaType::Webauthn,
                 info: user_data.webauthn.get(index).unwrap().info.clone(),
@@ -162,6 +180,7 @@ pub fn delete_tfa(config: &mut TfaConfig, userid: &str, id: &str) -> Result<bool
     match res {
         Some((TfaType::Recovery, _)) => user_data.recovery = None,
         Some((TfaType::Totp, index)) => drop(user_data.totp.remove(index)),
+        Some((TfaType::Hotp, index)) => drop(user_data.hotp.remove(index)),
         Some((TfaType::Webauthn, index)) => drop(user_data.webauthn.remove(index)),
         Some((TfaType::U2f, index)) => drop(user_data.u2f.remove(index)),
         Some((TfaType::Yubico, index)) => drop(user_data.yubico.remove(index)),
@@ -276,6 +295,7 @@ pub fn add_tfa_entry<A: OpenUserChallengeData>(
 
             add_totp(config, userid, need_description(description)?, totp, value)
         }
+        TfaType::Hotp => bail!("'hotp' entries must be added via 'add_hotp_entry'"),
         TfaType::Webauthn => {
             if totp.is_some() {
                 bail!("'totp' parameter is invalid for 'webauthn' entries");
@@ -347,6 +367,81 @@ fn add_totp(
     )))
 }
 
+/// API call implementation for `POST /access/tfa/{userid}` with `type` set to `hotp`.
+///
+/// `hotp` is the token's `otpauth://hotp/` URI and `value` the token's next value.
+///
+/// Permissions for accessing `userid` must have been verified by the caller.
+///
+/// The caller must have already verified the user's password!
+pub fn add_hotp_entry(
+    config: &mut TfaConfig,
+    userid: &str,
+    description: Option<String>,
+    hotp: &str,
+    value: &str,
+) -> Result<TfaUpdateInfo, Error> {
+    let hotp: Hotp = hotp.parse()?;
+    add_hotp(config, userid, need_description(description)?, hotp, value)
+}
+
+/// API call implementation for importing a HOTP token from a PSKC (RFC 6030) key container.
+///
+/// `serial` selects the token by its serial number or key id if the container holds more than
+/// one. As with [`add_hotp_entry`], `value` must be the token's next value, so only a user in
+/// possession of the token can register it.
+///
+/// Permissions for accessing `userid` must have been verified by the caller.
+///
+/// The caller must have already verified the user's password!
+#[cfg(feature = "pskc")]
+pub fn import_pskc_hotp_entry(
+    config: &mut TfaConfig,
+    userid: &str,
+    description: Option<String>,
+    pskc: &str,
+    serial: Option<&str>,
+    value: &str,
+) -> Result<TfaUpdateInfo, Error> {
+    let mut tokens = hotp::parse_pskc(pskc)?;
+    if let Some(serial) = serial {
+        tokens.retain(|token| token.serial.as_deref() == Some(serial) || token.id == serial);
+    }
+
+    let token = match tokens.len() {
+        0 => bail!("no matching HOTP token found in PSKC document"),
+        1 => tokens.pop().unwrap(),
+        _ => bail!("PSKC document contains multiple HOTP tokens, a serial number is required"),
+    };
+
+    add_hotp(
+        config,
+        userid,
+        need_description(description)?,
+        token.hotp,
+        value,
+    )
+}
+
+fn add_hotp(
+    config: &mut TfaConfig,
+    userid: &str,
+    description: String,
+    mut hotp: Hotp,
+    value: &str,
+) -> Result<TfaUpdateInfo, Error> {
+    // Use the same look-ahead as for logins, so enrollment is no easier to guess than a login.
+    match hotp.verify(value, hotp::DEFAULT_WINDOW)? {
+        Some(counter) => hotp.advance(counter),
+        None => bail!("failed to verify HOTP value"),
+    }
+    Ok(TfaUpdateInfo::with_id(config.add_hotp(
+        userid,
+        description,
+        hotp,
+    )))
+}
+
 fn add_yubico(
     config: &mut TfaConfig,
     userid: &str,
@@ -414,6 +509,27 @@ fn add_webauthn<A: ?Sized + OpenUserChallengeData>(
     }
 }
 
+/// API call implementation for resynchronizing a HOTP token, `POST
+/// /access/tfa/{userid}/{id}/resync`.
+///
+/// Permissions for accessing `userid` must have been verified by the caller.
+///
+/// The TFA config must be WRITE locked.
+///
+/// Failed attempts count towards the user's TFA failure limits.
+///
+/// The caller must *save* the config afterwards, even on failure!
+pub fn resync_hotp_entry<A: ?Sized + OpenUserChallengeData>(
+    config: &mut TfaConfig,
+    access: &A,
+    userid: &str,
+    id: &str,
+    first: &str,
+    second: &str,
+) -> Result<(), Error> {
+    config.resync_hotp(access, userid, id, first, second)
+}
+
 /// API call implementation for `PUT /access/tfa/{userid}/{id}`.
 ///
 /// The caller must have already verified the user's password.
@@ -442,3 +558,22 @@ pub fn update_tfa_entry(
 
     Ok(())
 }
+
+#[test]
+fn test_add_hotp_entry_window() {
+    // RFC 4226 test secret
+    const URI: &str = "otpauth://hotp/test?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=0";
+
+    let hotp: Hotp = URI.parse().unwrap();
+    let far_ahead = hotp.value(hotp::DEFAULT_WINDOW + 1).unwrap().to_string();
+    let near = hotp.value(3).unwrap().to_string();
+
+    let mut config = TfaConfig::default();
+    let desc = || Some("token".to_string());
+    assert!(add_hotp_entry(&mut config, "user", desc(), URI, &far_ahead).is_err());
+    assert!(config.users.is_empty());
+
+    let info = add_hotp_entry(&mut config, "user", desc(), URI, &near).unwrap();
+    assert!(info.id.is_some());
+    assert_eq!(config.users["user"].hotp[0].entry.counter(), 4);
+}
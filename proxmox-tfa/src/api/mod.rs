@@ -14,6 +14,7 @@ use url::Url;
 
 use webauthn_rs::{Webauthn, WebauthnBuilder};
 
+use crate::hotp::{self, Hotp};
 use crate::totp::Totp;
 use crate::types::bool_is_false;
 use proxmox_uuid::Uuid;
@@ -68,6 +69,12 @@ pub trait OpenUserChallengeData {
         3600
     }
 
+    /// This allows overriding the number of counter values to look ahead when verifying HOTP
+    /// values.
+    fn hotp_window(&self) -> u64 {
+        hotp::DEFAULT_WINDOW
+    }
+
     /// Since PVE needs cluster-wide package upgrades for new entries in [`TfaUserData`], TOTP code
     /// reuse checks can be configured here.
     fn enable_lockout(&self) -> bool {
@@ -319,6 +326,83 @@ impl TfaConfig {
             .add_totp(description, value)
     }
 
+    /// Add a HOTP token for a user.
+    ///
+    /// The token's counter should already be synchronized, see [`Hotp::verify`].
+    pub fn add_hotp(&mut self, userid: &str, description: String, value: Hotp) -> String {
+        self.users
+            .entry(userid.to_owned())
+            .or_default()
+            .add_hotp(description, value)
+    }
+
+    /// Resynchronize the counter of a user's HOTP token using two consecutive values generated by
+    /// the token.
+    ///
+    /// Failed attempts count towards the user's TOTP and TFA failure limits, just like failed
+    /// logins, and may lock the user out.
+    ///
+    /// The caller must *save* the config afterwards, even if this fails!
+    pub fn resync_hotp<A: ?Sized + OpenUserChallengeData>(
+        &mut self,
+        access: &A,
+        userid: &str,
+        id: &str,
+        first: &str,
+        second: &str,
+    ) -> Result<(), Error> {
+        let user = self
+            .users
+            .get_mut(userid)
+            .ok_or_else(|| format_err!("no such hotp entry"))?;
+
+        if user.tfa_is_locked() || user.totp_locked {
+            bail!("2nd factor of user '{userid}' is locked");
+        }
+
+        let entry = user
+            .hotp
+            .iter_mut()
+            .find(|entry| entry.info.id == id)
+            .ok_or_else(|| format_err!("no such hotp entry"))?;
+
+        let result = entry
+            .entry
+            .resync(first, second, hotp::DEFAULT_RESYNC_WINDOW);
+
+        let mut data = access.open(userid)?;
+        let data_mut = data.get_mut();
+
+        if let Ok(true) = result {
+            if data_mut.totp_failures != 0 || data_mut.tfa_failures != 0 {
+                data_mut.totp_failures = 0;
+                data_mut.tfa_failures = 0;
+                data.save()?;
+            }
+            return Ok(());
+        }
+
+        data_mut.totp_failures += 1;
+        data_mut.tfa_failures += 1;
+
+        let tfa_limit_reached = data_mut.tfa_failures >= access.tfa_failure_limit();
+        let totp_limit_reached = data_mut.totp_failures >= access.totp_failure_limit();
+        data.save()?;
+        drop(data);
+
+        if totp_limit_reached {
+            user.totp_locked = access.enable_lockout();
+        }
+
+        if tfa_limit_reached && access.enable_lockout() {
+            user.tfa_locked_until =
+                Some(proxmox_time::epoch_i64() + access.tfa_failure_lock_time());
+        }
+
+        result?;
+        bail!("failed to resynchronize HOTP token");
+    }
+
     /// Add a Yubico key to a user.
     ///
     /// Unlike U2F/WA, this does not require a challenge/response. The user can choose their secret
@@ -391,6 +475,17 @@ impl TfaConfig {
                 user.verify_totp(access, userid, &value)
                     .map(|needs_saving| TfaResult::Success { needs_saving })
             }
+            TfaResponse::Hotp(value) => {
+                // HOTP tokens share the failure counter and lockout with TOTP
+                was_totp = true;
+                if user.totp_locked {
+                    log::error!("HOTP of user '{userid}' is locked");
+                    return TfaResult::Locked;
+                }
+                // the token counter is stored in the user data, so we always need to save
+                user.verify_hotp(access, userid, &value)
+                    .map(|()| TfaResult::Success { needs_saving: true })
+            }
             TfaResponse::U2f(value) => match &challenge.u2f {
                 Some(challenge) => user
                     .verify_u2f(access, userid, &self.u2f, &challenge.challenge, value)
@@ -558,6 +653,10 @@ pub struct TfaUserData {
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub totp: Vec<TfaEntry<TotpEntry>>,
 
+    /// Hotp tokens for a user.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub hotp: Vec<TfaEntry<Hotp>>,
+
     /// Registered u2f tokens for a user.
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub u2f: Vec<TfaEntry<u2f::Registration>>,
@@ -601,6 +700,7 @@ impl TfaUserData {
     /// `true` if no second factors exist
     pub fn is_empty(&self) -> bool {
         self.totp.is_empty()
+            && self.hotp.is_empty()
             && self.u2f.is_empty()
             && self.webauthn.is_empty()
             && self.yubico.is_empty()
@@ -616,6 +716,12 @@ impl TfaUserData {
             }
         }
 
+        for entry in &mut self.hotp {
+            if entry.info.id == id {
+                return Some(&mut entry.info);
+            }
+        }
+
         for entry in &mut self.webauthn {
             if entry.info.id == id {
                 return Some(&mut entry.info);
@@ -757,6 +863,13 @@ impl TfaUserData {
         id
     }
 
+    fn add_hotp(&mut self, description: String, hotp: Hotp) -> String {
+        let entry = TfaEntry::new(description, hotp);
+        let id = entry.info.id.clone();
+        self.hotp.push(entry);
+        id
+    }
+
     fn add_yubico(&mut self, description: String, key: String) -> String {
         let entry = TfaEntry::new(description, key);
         let id = entry.info.id.clone();
@@ -850,6 +963,36 @@ impl TfaUserData {
         bail!("totp verification failed");
     }
 
+    /// Verify a hotp value. The `value` should be the hotp digits as plain text.
+    ///
+    /// On success the token's counter is advanced, so the config needs to be saved afterwards.
+    fn verify_hotp<A: ?Sized + OpenUserChallengeData>(
+        &mut self,
+        access: &A,
+        userid: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        let window = access.hotp_window();
+        for entry in self.hotp.iter_mut().filter(|e| e.info.enable) {
+            if let Some(counter) = entry.entry.verify(value, window)? {
+                entry.entry.advance(counter);
+
+                let mut data = access.open(userid)?;
+                let data_access = data.get_mut();
+                data_access.totp_failures = 0;
+                data.save()?;
+                return Ok(());
+            }
+        }
+
+        let mut data = access.open(userid)?;
+        let data_access = data.get_mut();
+        data_access.totp_failures += 1;
+        data.save()?;
+
+        bail!("hotp verification failed");
+    }
+
     /// Generate a generic TFA challenge. See the [`TfaChallenge`] description for details.
     fn challenge<A: ?Sized + OpenUserChallengeData>(
         &mut self,
@@ -869,6 +1012,7 @@ impl TfaUserData {
 
         let challenge = TfaChallenge {
             totp: self.totp.iter().any(|e| e.info.enable),
+            hotp: self.hotp.iter().any(|e| e.info.enable),
             recovery: self.recovery_state(),
             webauthn: match webauthn {
                 Some(webauthn) => match self.webauthn_challenge(access, userid, webauthn) {
@@ -1254,6 +1398,10 @@ pub struct TfaChallenge {
     #[serde(skip_serializing_if = "bool_is_false", default)]
     pub totp: bool,
 
+    /// True if the user has HOTP tokens.
+    #[serde(skip_serializing_if = "bool_is_false", default)]
+    pub hotp: bool,
+
     /// Whether there are recovery keys available.
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub recovery: Option<RecoveryState>,
@@ -1275,6 +1423,7 @@ pub struct TfaChallenge {
 impl TfaChallenge {
     pub fn is_empty(&self) -> bool {
         !self.totp
+            && !self.hotp
             && self.recovery.is_none()
             && self.u2f.is_none()
             && self.webauthn.is_none()
@@ -1285,6 +1434,7 @@ impl TfaChallenge {
 /// A user's response to a TFA challenge.
 pub enum TfaResponse {
     Totp(String),
+    Hotp(String),
     U2f(Value),
     Webauthn(Value),
     Recovery(String),
@@ -1297,6 +1447,8 @@ impl std::str::FromStr for TfaResponse {
     fn from_str(s: &str) -> Result<Self, Error> {
         Ok(if let Some(totp) = s.strip_prefix("totp:") {
             TfaResponse::Totp(totp.to_string())
+        } else if let Some(hotp) = s.strip_prefix("hotp:") {
+            TfaResponse::Hotp(hotp.to_string())
         } else if let Some(u2f) = s.strip_prefix("u2f:") {
             TfaResponse::U2f(serde_json::from_str(u2f)?)
         } else if let Some(webauthn) = s.strip_prefix("webauthn:") {
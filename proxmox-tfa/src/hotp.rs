@@ -0,0 +1,600 @@
+//! HOTP (RFC 4226) implementation for event based OATH tokens.
+
+use std::error::Error as StdError;
+
+use percent_encoding::{percent_decode, percent_encode};
+use serde::{Serialize, Serializer};
+
+use crate::totp::{hotp_value, Algorithm, Error, TotpValue};
+
+/// Default number of counter values to look ahead when verifying a value.
+pub const DEFAULT_WINDOW: u64 = 10;
+
+/// Default number of counter values to search when resynchronizing a token.
+pub const DEFAULT_RESYNC_WINDOW: u64 = 100;
+
+/// HOTP secret builder.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct HotpBuilder {
+    inner: Hotp,
+}
+
+impl From<Hotp> for HotpBuilder {
+    #[inline]
+    fn from(inner: Hotp) -> Self {
+        Self { inner }
+    }
+}
+
+impl HotpBuilder {
+    pub fn secret(mut self, secret: Vec<u8>) -> Self {
+        self.inner.secret = secret;
+        self
+    }
+
+    /// Set the requested number of decimal digits.
+    pub fn digits(mut self, digits: u8) -> Self {
+        self.inner.digits = digits;
+        self
+    }
+
+    /// Set the algorithm.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.inner.algorithm = algorithm;
+        self
+    }
+
+    /// Set the counter value expected to be used next.
+    pub fn counter(mut self, counter: u64) -> Self {
+        self.inner.counter = counter;
+        self
+    }
+
+    /// Set the issuer.
+    pub fn issuer(mut self, issuer: String) -> Self {
+        self.inner.issuer = Some(issuer);
+        self
+    }
+
+    /// Set the account name. This is required to create an URI.
+    pub fn account_name(mut self, account_name: String) -> Self {
+        self.inner.account_name = Some(account_name);
+        self
+    }
+
+    /// Finalize the HOTP instance.
+    pub fn build(self) -> Hotp {
+        self.inner
+    }
+}
+
+/// HOTP secret key along with the counter value expected to be used next.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hotp {
+    /// The secret shared with the token.
+    secret: Vec<u8>,
+
+    /// The requested number decimal digits.
+    digits: u8,
+
+    /// The algorithm, defaults to sha1.
+    algorithm: Algorithm,
+
+    /// The next counter value expected from the token.
+    counter: u64,
+
+    /// An optional issuer. To help users identify their HOTP settings.
+    issuer: Option<String>,
+
+    /// An optional account name, possibly chosen by the user, to identify their HOTP settings.
+    account_name: Option<String>,
+}
+
+impl Hotp {
+    /// Allow modifying parameters by turning this into a builder.
+    pub fn into_builder(self) -> HotpBuilder {
+        self.into()
+    }
+
+    /// Create a new empty HOTP instance with default values.
+    pub fn empty() -> Self {
+        Self {
+            secret: Vec::new(),
+            digits: 6,
+            algorithm: Algorithm::Sha1,
+            counter: 0,
+            issuer: None,
+            account_name: None,
+        }
+    }
+
+    /// Create a HOTP builder prefilled with default values.
+    pub fn builder() -> HotpBuilder {
+        HotpBuilder {
+            inner: Self::empty(),
+        }
+    }
+
+    /// Create a new HOTP secret key builder using a secret specified in hexadecimal bytes.
+    pub fn builder_from_hex(secret: &str) -> Result<HotpBuilder, Error> {
+        Ok(Self::builder().secret(
+            hex::decode(secret)
+                .map_err(|err| Error::decode("not a valid hexadecimal string", err))?,
+        ))
+    }
+
+    /// Get the secret key in binary form.
+    pub fn secret(&self) -> &[u8] {
+        &self.secret
+    }
+
+    /// Get the requested number of decimal digits.
+    pub fn digits(&self) -> u8 {
+        self.digits
+    }
+
+    /// Get the used algorithm.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Get the counter value expected to be used next.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// Get the issuer, if any.
+    pub fn issuer(&self) -> Option<&str> {
+        self.issuer.as_deref()
+    }
+
+    /// Get the account name, if any.
+    pub fn account_name(&self) -> Option<&str> {
+        self.account_name.as_deref()
+    }
+
+    /// Create the HOTP value for a counter.
+    pub fn value(&self, counter: u64) -> Result<TotpValue, Error> {
+        hotp_value(&self.secret, self.algorithm, self.digits, counter)
+    }
+
+    /// Verify a value against the next `window` counter values, starting at the current counter.
+    ///
+    /// On success the matching counter value is returned, the caller must then advance the
+    /// counter past it via [`advance`](Hotp::advance()) to prevent reuse.
+    pub fn verify(&self, digits: &str, window: u64) -> Result<Option<u64>, Error> {
+        for counter in self.counter..=self.counter.saturating_add(window) {
+            if self.value(counter)? == digits {
+                return Ok(Some(counter));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Mark all counter values up to and including `counter` as used.
+    pub fn advance(&mut self, counter: u64) {
+        self.counter = self.counter.max(counter.saturating_add(1));
+    }
+
+    /// Resynchronize a token which ran ahead of the look-ahead window, for example due to its
+    /// button being pressed repeatedly.
+    ///
+    /// This searches the next `window` counter values for two consecutively generated values.
+    /// On success the counter is advanced past the second value and `true` is returned.
+    pub fn resync(&mut self, first: &str, second: &str, window: u64) -> Result<bool, Error> {
+        let end = self.counter.saturating_add(window);
+        for counter in self.counter..end {
+            if self.value(counter)? == first && self.value(counter + 1)? == second {
+                self.advance(counter + 1);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Create an otpauth URI for this configuration.
+    pub fn to_uri(&self) -> Result<String, Error> {
+        let account_name = self
+            .account_name
+            .as_deref()
+            .ok_or_else(|| Error::msg("cannot create otpauth uri without an account name"))?;
+
+        let mut out = String::from("otpauth://hotp/");
+
+        let issuer = self.issuer.as_ref().map(|issuer| {
+            percent_encode(issuer.as_bytes(), percent_encoding::NON_ALPHANUMERIC).to_string()
+        });
+        if let Some(issuer) = &issuer {
+            out.push_str(issuer);
+            out.push(':');
+        }
+
+        out.push_str(&format!(
+            "{}?secret={}&digits={}&algorithm={}&counter={}",
+            percent_encode(account_name.as_bytes(), percent_encoding::NON_ALPHANUMERIC),
+            base32::encode(base32::Alphabet::RFC4648 { padding: false }, &self.secret),
+            self.digits,
+            self.algorithm,
+            self.counter,
+        ));
+
+        if let Some(issuer) = issuer {
+            out.push_str(&format!("&issuer={issuer}"));
+        }
+
+        Ok(out)
+    }
+}
+
+impl std::str::FromStr for Hotp {
+    type Err = Error;
+
+    fn from_str(uri: &str) -> Result<Self, Error> {
+        let uri = uri
+            .strip_prefix("otpauth://hotp/")
+            .ok_or_else(|| Error::msg("not an otpauth hotp uri"))?;
+
+        let (account, query) = uri
+            .split_once('?')
+            .ok_or_else(|| Error::msg("missing '?' in otp uri"))?;
+
+        let mut hotp = Hotp::empty();
+
+        let decode = |s: &str| {
+            percent_decode(s.as_bytes())
+                .decode_utf8_lossy()
+                .into_owned()
+        };
+        match account.split_once(':') {
+            Some((issuer, account_name)) => {
+                hotp.issuer = Some(decode(issuer));
+                hotp.account_name = Some(decode(account_name));
+            }
+            None => hotp.account_name = Some(decode(account)),
+        }
+
+        fn parse<T>(value: &str, n: &'static str) -> Result<T, Error>
+        where
+            T: std::str::FromStr,
+            T::Err: StdError + Send + Sync + 'static,
+        {
+            value.parse().map_err(|err| {
+                Error::BadParameter(format!("failed to parse value '{n}'"), Box::new(err))
+            })
+        }
+
+        let mut have_counter = false;
+        for part in query.split('&') {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| Error::msg("bad value in otpauth uri"))?;
+            let value = decode(value);
+
+            match decode(key).as_str() {
+                "secret" => {
+                    hotp.secret =
+                        base32::decode(base32::Alphabet::RFC4648 { padding: false }, &value)
+                            .ok_or_else(|| {
+                                Error::msg("failed to decode otp secret in otpauth url")
+                            })?
+                }
+                "digits" => hotp.digits = parse(&value, "digits")?,
+                "algorithm" => hotp.algorithm = value.parse()?,
+                "counter" => {
+                    hotp.counter = parse(&value, "counter")?;
+                    have_counter = true;
+                }
+                "issuer" => hotp.issuer = Some(value),
+                other => return Err(Error::UnknownParameter(other.to_string())),
+            }
+        }
+
+        if hotp.secret.is_empty() {
+            return Err(Error::msg("missing secret in otpauth url"));
+        }
+
+        if !have_counter {
+            return Err(Error::msg("missing counter in otpauth hotp url"));
+        }
+
+        Ok(hotp)
+    }
+}
+
+serde_plain::derive_deserialize_from_fromstr!(Hotp, "valid HOTP url");
+
+impl Serialize for Hotp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        use serde::ser::Error;
+
+        serializer.serialize_str(
+            &self
+                .to_uri()
+                .map_err(|err| Error::custom(err.to_string()))?,
+        )
+    }
+}
+
+#[cfg(feature = "pskc")]
+pub use pskc::{parse_pskc, PskcToken};
+
+#[cfg(feature = "pskc")]
+mod pskc {
+    //! Import of HOTP token seeds from PSKC (RFC 6030) key containers.
+    //!
+    //! Only unencrypted secrets (`PlainValue`) are supported.
+
+    use serde::Deserialize;
+
+    use super::{Error, Hotp};
+    use crate::totp::Algorithm;
+
+    const HOTP_ALGORITHM: &str = "urn:ietf:params:xml:ns:keyprov:pskc:hotp";
+
+    /// A HOTP token found in a PSKC key container.
+    #[derive(Clone, Debug)]
+    pub struct PskcToken {
+        /// The key's `Id` attribute.
+        pub id: String,
+
+        /// The serial number of the device the key belongs to, if available.
+        pub serial: Option<String>,
+
+        /// The token's secret and counter.
+        pub hotp: Hotp,
+    }
+
+    #[derive(Deserialize)]
+    struct KeyContainer {
+        #[serde(rename = "KeyPackage", default)]
+        key_packages: Vec<KeyPackage>,
+    }
+
+    #[derive(Deserialize)]
+    struct KeyPackage {
+        #[serde(rename = "DeviceInfo")]
+        device_info: Option<DeviceInfo>,
+        #[serde(rename = "Key")]
+        key: Option<Key>,
+    }
+
+    #[derive(Deserialize)]
+    struct DeviceInfo {
+        #[serde(rename = "SerialNo")]
+        serial_no: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Key {
+        #[serde(rename = "Id")]
+        id: String,
+        #[serde(rename = "Algorithm")]
+        algorithm: Option<String>,
+        #[serde(rename = "Issuer")]
+        issuer: Option<String>,
+        #[serde(rename = "AlgorithmParameters")]
+        algorithm_parameters: Option<AlgorithmParameters>,
+        #[serde(rename = "Data")]
+        data: Option<KeyData>,
+    }
+
+    #[derive(Deserialize)]
+    struct AlgorithmParameters {
+        #[serde(rename = "Suite")]
+        suite: Option<String>,
+        #[serde(rename = "ResponseFormat")]
+        response_format: Option<ResponseFormat>,
+    }
+
+    #[derive(Deserialize)]
+    struct ResponseFormat {
+        #[serde(rename = "Length")]
+        length: u8,
+        #[serde(rename = "Encoding")]
+        encoding: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct KeyData {
+        #[serde(rename = "Secret")]
+        secret: Option<DataValue>,
+        #[serde(rename = "Counter")]
+        counter: Option<DataValue>,
+    }
+
+    #[derive(Deserialize)]
+    struct DataValue {
+        #[serde(rename = "PlainValue")]
+        plain_value: Option<String>,
+        #[serde(rename = "EncryptedValue")]
+        encrypted_value: Option<serde::de::IgnoredAny>,
+    }
+
+    impl DataValue {
+        fn plain(&self, what: &str) -> Result<&str, Error> {
+            match &self.plain_value {
+                Some(value) => Ok(value.trim()),
+                None if self.encrypted_value.is_some() => Err(Error::msg(format!(
+                    "encrypted {what} values are not supported"
+                ))),
+                None => Err(Error::msg(format!("missing {what} value"))),
+            }
+        }
+    }
+
+    /// Parse the HOTP keys from a PSKC key container. Keys using other algorithms are skipped.
+    pub fn parse_pskc(xml: &str) -> Result<Vec<PskcToken>, Error> {
+        let container: KeyContainer = serde_xml_rs::from_str(xml)
+            .map_err(|err| Error::decode("failed to parse PSKC document", err))?;
+
+        let mut tokens = Vec::new();
+        for package in container.key_packages {
+            let Some(key) = package.key else {
+                continue;
+            };
+            if key.algorithm.as_deref() != Some(HOTP_ALGORITHM) {
+                continue;
+            }
+
+            let data = key
+                .data
+                .as_ref()
+                .ok_or_else(|| Error::msg(format!("missing data of key '{}'", key.id)))?;
+
+            let secret = data
+                .secret
+                .as_ref()
+                .ok_or_else(|| Error::msg(format!("missing secret of key '{}'", key.id)))?
+                .plain("secret")?;
+            let secret: String = secret.split_whitespace().collect();
+            let secret = proxmox_base64::decode(secret)
+                .map_err(|err| Error::decode("failed to decode PSKC secret", err))?;
+
+            let counter = match &data.counter {
+                Some(counter) => counter.plain("counter")?.parse().map_err(|err| {
+                    Error::BadParameter(
+                        format!("invalid counter of key '{}'", key.id),
+                        Box::new(err),
+                    )
+                })?,
+                None => 0,
+            };
+
+            let mut hotp = Hotp::builder().secret(secret).counter(counter);
+
+            if let Some(params) = &key.algorithm_parameters {
+                if let Some(format) = &params.response_format {
+                    if format
+                        .encoding
+                        .as_deref()
+                        .is_some_and(|encoding| encoding != "DECIMAL")
+                    {
+                        return Err(Error::msg(format!(
+                            "unsupported response encoding of key '{}'",
+                            key.id
+                        )));
+                    }
+                    hotp = hotp.digits(format.length);
+                }
+                if let Some(suite) = &params.suite {
+                    let suite = suite.trim_start_matches("HMAC-").replace('-', "");
+                    hotp = hotp.algorithm(suite.parse::<Algorithm>()?);
+                }
+            }
+
+            if let Some(issuer) = key.issuer {
+                hotp = hotp.issuer(issuer);
+            }
+
+            tokens.push(PskcToken {
+                id: key.id,
+                serial: package.device_info.and_then(|info| info.serial_no),
+                hotp: hotp.build(),
+            });
+        }
+
+        Ok(tokens)
+    }
+}
+
+#[test]
+fn test_hotp() {
+    // RFC 4226 Appendix D test values.
+    const SECRET: &str = "3132333435363738393031323334353637383930";
+    const EXPECTED: [&str; 10] = [
+        "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871",
+        "520489",
+    ];
+
+    let mut hotp = Hotp::builder_from_hex(SECRET)
+        .expect("failed to create Hotp key")
+        .build();
+    for (counter, expected) in EXPECTED.iter().enumerate() {
+        assert_eq!(
+            hotp.value(counter as u64)
+                .expect("failed to create hotp value"),
+            *expected
+        );
+    }
+
+    assert_eq!(hotp.verify(EXPECTED[0], 2).unwrap(), Some(0));
+    assert_eq!(hotp.verify(EXPECTED[2], 2).unwrap(), Some(2));
+    assert_eq!(hotp.verify(EXPECTED[3], 2).unwrap(), None);
+
+    hotp.advance(2);
+    assert_eq!(hotp.counter(), 3);
+    // used values are rejected
+    assert_eq!(hotp.verify(EXPECTED[2], 2).unwrap(), None);
+    // advancing never moves backwards
+    hotp.advance(0);
+    assert_eq!(hotp.counter(), 3);
+
+    // values beyond the window require resynchronization
+    assert_eq!(hotp.verify(EXPECTED[8], 2).unwrap(), None);
+    assert!(!hotp.resync(EXPECTED[8], EXPECTED[7], 10).unwrap());
+    assert!(hotp.resync(EXPECTED[7], EXPECTED[8], 10).unwrap());
+    assert_eq!(hotp.counter(), 9);
+
+    let hotp = hotp
+        .into_builder()
+        .account_name("My Token".to_string())
+        .issuer("An Issuer".to_string())
+        .build();
+    let uri = hotp.to_uri().expect("failed to create otpauth uri");
+    let parsed: Hotp = uri.parse().expect("failed to parse otp uri");
+    assert_eq!(parsed, hotp);
+    assert_eq!(parsed.counter(), 9);
+    assert_eq!(parsed.issuer(), Some("An Issuer"));
+
+    assert!("otpauth://hotp/foo?secret=GEZDGNBV"
+        .parse::<Hotp>()
+        .is_err());
+}
+
+#[cfg(feature = "pskc")]
+#[test]
+fn test_pskc_import() {
+    // Based on RFC 6030 Figure 2.
+    const PSKC: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<KeyContainer Version="1.0" xmlns="urn:ietf:params:xml:ns:keyprov:pskc">
+  <KeyPackage>
+    <DeviceInfo>
+      <Manufacturer>Manufacturer</Manufacturer>
+      <SerialNo>987654321</SerialNo>
+    </DeviceInfo>
+    <Key Id="12345678" Algorithm="urn:ietf:params:xml:ns:keyprov:pskc:hotp">
+      <Issuer>Issuer-A</Issuer>
+      <AlgorithmParameters>
+        <ResponseFormat Length="8" Encoding="DECIMAL"/>
+      </AlgorithmParameters>
+      <Data>
+        <Secret>
+          <PlainValue>MTIzNDU2Nzg5MDEyMzQ1Njc4OTA=</PlainValue>
+        </Secret>
+        <Counter>
+          <PlainValue>5</PlainValue>
+        </Counter>
+      </Data>
+    </Key>
+  </KeyPackage>
+  <KeyPackage>
+    <Key Id="other" Algorithm="urn:ietf:params:xml:ns:keyprov:pskc:pin">
+    </Key>
+  </KeyPackage>
+</KeyContainer>"#;
+
+    let tokens = parse_pskc(PSKC).expect("failed to parse PSKC document");
+    assert_eq!(tokens.len(), 1);
+
+    let token = &tokens[0];
+    assert_eq!(token.id, "12345678");
+    assert_eq!(token.serial.as_deref(), Some("987654321"));
+    assert_eq!(token.hotp.secret(), b"12345678901234567890");
+    assert_eq!(token.hotp.digits(), 8);
+    assert_eq!(token.hotp.counter(), 5);
+    assert_eq!(token.hotp.issuer(), Some("Issuer-A"));
+}
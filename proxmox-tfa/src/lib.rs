@@ -6,6 +6,9 @@ pub mod u2f;
 #[cfg(feature = "totp")]
 pub mod totp;
 
+#[cfg(feature = "totp")]
+pub mod hotp;
+
 #[cfg(feature = "api")]
 pub mod api;
 
@@ -47,14 +47,14 @@ impl fmt::Display for Error {
 }
 
 impl Error {
-    fn decode<E>(msg: &'static str, err: E) -> Self
+    pub(crate) fn decode<E>(msg: &'static str, err: E) -> Self
     where
         E: StdError + Send + Sync + 'static,
     {
         Error::Decode(msg, Box::new(err))
     }
 
-    fn msg<T: fmt::Display>(err: T) -> Self {
+    pub(crate) fn msg<T: fmt::Display>(err: T) -> Self {
         Error::Generic(err.to_string())
     }
 }
@@ -261,48 +261,12 @@ impl Totp {
         self.account_name.as_deref()
     }
 
-    /// Raw signing function.
-    fn sign(&self, input_data: &[u8]) -> Result<TotpValue, Error> {
-        let secret = PKey::hmac(&self.secret)
-            .map_err(|err| Error::Ssl("error instantiating hmac key", err))?;
-
-        let mut signer = Signer::new(self.algorithm.into(), &secret)
-            .map_err(|err| Error::Ssl("error instantiating hmac signer", err))?;
-
-        signer
-            .update(input_data)
-            .map_err(|err| Error::Ssl("error updating hmac", err))?;
-
-        let hmac = signer
-            .sign_to_vec()
-            .map_err(|err| Error::Ssl("error finishing hmac", err))?;
-
-        let byte_offset = usize::from(
-            hmac.last()
-                .ok_or_else(|| format_err!("error calculating hmac (too short)"))?
-                & 0xF,
-        );
-
-        let value = u32::from_be_bytes(
-            TryFrom::try_from(
-                hmac.get(byte_offset..(byte_offset + 4))
-                    .ok_or_else(|| format_err!("error finalizing hmac (too short)"))?,
-            )
-            .unwrap(),
-        ) & 0x7fffffff;
-
-        Ok(TotpValue {
-            value,
-            digits: u32::from(self.digits),
-        })
-    }
-
     /// Create a HOTP value for a counter.
     ///
     /// This is currently private as for actual counter mode we should have a validate helper
     /// which forces handling of too-low-but-within-range values explicitly!
     fn counter(&self, count: u64) -> Result<TotpValue, Error> {
-        self.sign(&count.to_be_bytes())
+        hotp_value(&self.secret, self.algorithm, self.digits, count)
     }
 
     /// Convert a time stamp into a counter value. This makes it easier and cheaper to check a
@@ -499,6 +463,50 @@ impl Serialize for Totp {
     }
 }
 
+/// Compute the RFC 4226 HOTP value of a counter.
+///
+/// This is shared between the time based [`Totp`] and the counter based
+/// [`Hotp`](crate::hotp::Hotp).
+pub(crate) fn hotp_value(
+    secret: &[u8],
+    algorithm: Algorithm,
+    digits: u8,
+    count: u64,
+) -> Result<TotpValue, Error> {
+    let secret =
+        PKey::hmac(secret).map_err(|err| Error::Ssl("error instantiating hmac key", err))?;
+
+    let mut signer = Signer::new(algorithm.into(), &secret)
+        .map_err(|err| Error::Ssl("error instantiating hmac signer", err))?;
+
+    signer
+        .update(&count.to_be_bytes())
+        .map_err(|err| Error::Ssl("error updating hmac", err))?;
+
+    let hmac = signer
+        .sign_to_vec()
+        .map_err(|err| Error::Ssl("error finishing hmac", err))?;
+
+    let byte_offset = usize::from(
+        hmac.last()
+            .ok_or_else(|| format_err!("error calculating hmac (too short)"))?
+            & 0xF,
+    );
+
+    let value = u32::from_be_bytes(
+        TryFrom::try_from(
+            hmac.get(byte_offset..(byte_offset + 4))
+                .ok_or_else(|| format_err!("error finalizing hmac (too short)"))?,
+        )
+        .unwrap(),
+    ) & 0x7fffffff;
+
+    Ok(TotpValue {
+        value,
+        digits: u32::from(digits),
+    })
+}
+
 /// A HOTP value with a decimal digit limit.
 #[derive(Clone, Copy, Debug)]
 pub struct TotpValue {
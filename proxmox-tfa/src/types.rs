@@ -10,6 +10,8 @@ use proxmox_schema::api;
 pub enum TfaType {
     /// A TOTP entry type.
     Totp,
+    /// A HOTP (event based OATH token) entry type.
+    Hotp,
     /// A U2F token entry.
     U2f,
     /// A Webauthn token entry.